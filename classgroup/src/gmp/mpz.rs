@@ -50,6 +50,8 @@ extern "C" {
     fn __gmpz_get_d(op: mpz_srcptr) -> c_double;
     fn __gmpz_fits_slong_p(op: mpz_srcptr) -> c_long;
     fn __gmpz_sizeinbase(op: mpz_srcptr, base: c_int) -> size_t;
+    fn __gmpz_size(op: mpz_srcptr) -> size_t;
+    fn __gmpz_getlimbn(op: mpz_srcptr, n: size_t) -> mp_limb_t;
     fn __gmpz_cmp(op1: mpz_srcptr, op2: mpz_srcptr) -> c_int;
     fn __gmpz_cmp_ui(op1: mpz_srcptr, op2: c_ulong) -> c_int;
     fn __gmpz_add(rop: mpz_ptr, op1: mpz_srcptr, op2: mpz_srcptr);
@@ -119,12 +121,16 @@ pub struct Mpz {
     mpz: mpz_struct,
 }
 
+/// Human-readable formats get a hex string (`-` prefixed when negative);
+/// binary formats get the sign byte and the big-endian magnitude.
 impl serde::Serialize for Mpz {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
-        // Serialize as bytes instead of hex string for compact representation
+        if serializer.is_human_readable() {
+            return serializer.serialize_str(&self.to_str_radix(16));
+        }
         let bytes: Vec<u8> = self.into();
         let sign = match self.sign() {
             super::sign::Sign::Negative => -1i8,
@@ -146,7 +152,17 @@ impl<'de> serde::Deserialize<'de> for Mpz {
         D: serde::Deserializer<'de>,
     {
         use serde::de::{SeqAccess, Visitor};
-        
+
+        if deserializer.is_human_readable() {
+            let hex = <std::borrow::Cow<'de, str>>::deserialize(deserializer)?;
+            let digits = hex.strip_prefix('-').unwrap_or(&hex);
+            if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(serde::de::Error::custom("invalid hex integer"));
+            }
+            return Mpz::from_str_radix(&hex, 16)
+                .map_err(|_| serde::de::Error::custom("invalid hex integer"));
+        }
+
         struct MpzTupleVisitor;
         
         impl<'de> Visitor<'de> for MpzTupleVisitor {
@@ -242,12 +258,14 @@ impl Mpz {
         unsafe { __gmpz_sizeinbase(&self.mpz, base as c_int) as usize }
     }
 
-    // TODO: fail on an invalid base
     // FIXME: Unfortunately it isn't currently possible to use the fmt::RadixFmt
     //        machinery for a custom type.
+
+    /// Digits are `0-9a-z` up to base 36 and `0-9A-Za-z` above, as
+    /// `from_str_radix` reads them. Panics unless `base` is in `2..=62`.
     pub fn to_str_radix(&self, base: u8) -> String {
         unsafe {
-            assert!(base >= 2 && base <= 36, "invalid base");
+            assert!(base >= 2 && base <= 62, "invalid base");
             // Extra two bytes are for possible minus sign and null terminator
             let len = {
                 let len = __gmpz_sizeinbase(&self.mpz, base as c_int) as usize;
@@ -302,6 +320,100 @@ impl Mpz {
         unsafe { __gmpz_sizeinbase(&self.mpz, 2) as usize }
     }
 
+    /// Length of the big-endian magnitude produced by `Vec::<u8>::from`.
+    #[inline]
+    pub fn byte_length(&self) -> usize {
+        self.bit_length().div_ceil(8)
+    }
+
+    /// Writes the same bytes as `Vec::<u8>::from` limb by limb, without
+    /// allocating.
+    pub fn write_be_bytes<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let limbs = unsafe { __gmpz_size(&self.mpz) };
+        if limbs == 0 {
+            return writer.write_all(&[0]);
+        }
+        for i in (0..limbs).rev() {
+            let bytes = unsafe { __gmpz_getlimbn(&self.mpz, i) }.to_be_bytes();
+            if i == limbs - 1 {
+                let skip = bytes.iter().take_while(|&&b| b == 0).count();
+                writer.write_all(&bytes[skip..])?;
+            } else {
+                writer.write_all(&bytes)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The bytes of `write_be_bytes`, produced lazily.
+    pub fn be_bytes(&self) -> impl Iterator<Item = u8> + '_ {
+        let limbs = unsafe { __gmpz_size(&self.mpz) };
+        let skip = (limbs * size_of::<mp_limb_t>()).saturating_sub(self.byte_length());
+        (0..limbs)
+            .rev()
+            .flat_map(move |i| unsafe { __gmpz_getlimbn(&self.mpz, i) }.to_be_bytes())
+            .skip(skip)
+            .chain((limbs == 0).then_some(0))
+    }
+
+    /// `write_be_bytes` as full `chunk_len`-byte writes and a shorter last
+    /// one, e.g. to fill fixed-size network frames. Only one chunk is
+    /// buffered.
+    pub fn write_bytes_chunked<W: std::io::Write>(
+        &self,
+        writer: &mut W,
+        chunk_len: usize,
+    ) -> std::io::Result<()> {
+        let chunk_len = chunk_len.max(1);
+        let mut chunk = Vec::with_capacity(chunk_len);
+        for byte in self.be_bytes() {
+            chunk.push(byte);
+            if chunk.len() == chunk_len {
+                writer.write_all(&chunk)?;
+                chunk.clear();
+            }
+        }
+        if !chunk.is_empty() {
+            writer.write_all(&chunk)?;
+        }
+        Ok(())
+    }
+
+    /// Magnitude as little-endian 32-bit digits, the layout
+    /// `num_bigint::BigInt::from_slice` takes. The sign is dropped.
+    pub fn to_u32_digits(&self) -> Vec<u32> {
+        let mut digits = vec![0u32; self.bit_length().div_ceil(32)];
+        unsafe {
+            __gmpz_export(
+                digits.as_mut_ptr() as *mut c_void,
+                std::ptr::null_mut(),
+                -1,
+                size_of::<u32>() as size_t,
+                0,
+                0,
+                &self.mpz,
+            );
+        }
+        digits
+    }
+
+    /// Inverse of `to_u32_digits`; the result is non-negative.
+    pub fn from_u32_digits(digits: &[u32]) -> Mpz {
+        unsafe {
+            let mut res = Mpz::new();
+            __gmpz_import(
+                &mut res.mpz,
+                digits.len(),
+                -1,
+                size_of::<u32>() as size_t,
+                0,
+                0,
+                digits.as_ptr() as *const c_void,
+            );
+            res
+        }
+    }
+
     #[inline]
     pub fn compl(&self) -> Mpz {
         unsafe {
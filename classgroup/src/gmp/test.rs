@@ -165,6 +165,27 @@ mod mpz {
         assert!(x.to_str_radix(16) == "ff".to_string());
     }
 
+    #[test]
+    fn test_to_str_radix_62() {
+        let x: Mpz = From::<i64>::from(61 * 62 + 36);
+        assert_eq!(x.to_str_radix(62), "za");
+        assert_eq!((-&x).to_str_radix(62), "-za");
+        let x: Mpz = From::<i64>::from(35);
+        assert_eq!(x.to_str_radix(62), "Z");
+        assert_eq!(x.to_str_radix(36), "z");
+
+        let x: Mpz = FromStr::from_str("-123456789012345678901234567890123456789").unwrap();
+        for base in 2..=62 {
+            assert_eq!(Mpz::from_str_radix(&x.to_str_radix(base), base).unwrap(), x);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_to_str_radix_upper_bound() {
+        let _ = Mpz::one().to_str_radix(63);
+    }
+
     #[test]
     fn test_to_string() {
         let x: Mpz = FromStr::from_str("1234567890").unwrap();
@@ -581,4 +602,46 @@ mod mpz {
         let zero = Mpz::from(-51213);
         assert_eq!(format!("{}", zero), "-51213");
     }
+
+    #[test]
+    fn test_chunked_export() {
+        use std::str::FromStr;
+
+        struct Writes(Vec<Vec<u8>>);
+        impl std::io::Write for Writes {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.push(buf.to_vec());
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let big = Mpz::from_str("-123456789012345678901234567890123456789").unwrap();
+        for x in [Mpz::zero(), Mpz::from(255), Mpz::from(256), big] {
+            let expected: Vec<u8> = (&x).into();
+            assert_eq!(x.be_bytes().collect::<Vec<u8>>(), expected);
+            for chunk_len in [1, 3, 8, 100] {
+                let mut writes = Writes(Vec::new());
+                x.write_bytes_chunked(&mut writes, chunk_len).unwrap();
+                assert_eq!(writes.0.concat(), expected);
+                let (last, full) = writes.0.split_last().unwrap();
+                assert!(full.iter().all(|chunk| chunk.len() == chunk_len));
+                assert!(!last.is_empty() && last.len() <= chunk_len);
+            }
+        }
+    }
+
+    #[test]
+    fn test_serde_human_readable() {
+        use serde::de::value::{Error, StrDeserializer};
+        use serde::Deserialize;
+
+        let hex = |s| Mpz::deserialize(StrDeserializer::<Error>::new(s));
+        assert_eq!(hex("-ff").unwrap(), Mpz::from(-255));
+        assert_eq!(hex("0").unwrap(), Mpz::zero());
+        assert!(hex("").is_err());
+        assert!(hex("0x1").is_err());
+    }
 }
@@ -0,0 +1,95 @@
+//! Resumable `repeated_square`. A long squaring job (a VDF evaluation, a
+//! heavy presign table) keeps its state in a `SquaringCheckpoint`, which
+//! serializes with serde and can be written out between batches and picked
+//! up again after a restart.
+
+use super::GmpClassGroup;
+use crate::gmp::mpz::Mpz;
+use crate::ClassGroup;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SquaringCheckpoint {
+    /// The starting form squared `iterations_done` times.
+    pub element: GmpClassGroup,
+    pub iterations_done: u64,
+}
+
+impl SquaringCheckpoint {
+    pub fn new(element: GmpClassGroup) -> Self {
+        Self {
+            element,
+            iterations_done: 0,
+        }
+    }
+
+    /// The start of `do_compute(discriminant, _)`.
+    pub fn for_discriminant(discriminant: Mpz) -> Self {
+        Self::new(GmpClassGroup::generator_for_discriminant(discriminant))
+    }
+
+    /// Whether `element` is a reduced form of `discriminant`. Run on a
+    /// loaded checkpoint before resuming: it catches a truncated, edited or
+    /// mismatched file, though not a valid form that is the wrong power,
+    /// which only recomputation would.
+    pub fn verify(&self, discriminant: &Mpz) -> bool {
+        self.element.discriminant == *discriminant && self.element.is_reduced_form()
+    }
+
+    /// Squares until `iterations_done` reaches `target`, handing the
+    /// checkpoint to `save` after every `batch` squarings and at the end. A
+    /// failed save stops the run with the state of the last batch.
+    pub fn run_to<E>(
+        &mut self,
+        target: u64,
+        batch: u64,
+        mut save: impl FnMut(&Self) -> Result<(), E>,
+    ) -> Result<(), E> {
+        let batch = batch.max(1);
+        while self.iterations_done < target {
+            let step = batch.min(target - self.iterations_done);
+            self.element.repeated_square(step);
+            self.iterations_done += step;
+            save(self)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::do_compute;
+    use std::str::FromStr;
+
+    #[test]
+    fn checkpoint_resume_test() {
+        let discriminant = Mpz::from_str("-3735928727").unwrap();
+        let expected = do_compute(discriminant.clone(), 100);
+
+        let mut saved = Vec::new();
+        let mut job = SquaringCheckpoint::for_discriminant(discriminant.clone());
+        // interrupted after the second save
+        let interrupted = job.run_to(100, 30, |checkpoint| {
+            saved = bincode::serde::encode_to_vec(checkpoint, bincode::config::standard()).unwrap();
+            match checkpoint.iterations_done {
+                60 => Err(()),
+                _ => Ok(()),
+            }
+        });
+        assert!(interrupted.is_err());
+
+        let (mut resumed, _): (SquaringCheckpoint, usize) =
+            bincode::serde::decode_from_slice(&saved, bincode::config::standard()).unwrap();
+        assert_eq!(resumed.iterations_done, 60);
+        assert!(resumed.verify(&discriminant));
+        resumed.run_to(100, 30, |_| Ok::<(), ()>(())).unwrap();
+        assert_eq!(resumed.element, expected);
+        assert_eq!(resumed.iterations_done, 100);
+
+        let mut tampered = resumed.clone();
+        tampered.element.a += 1u64;
+        assert!(!tampered.verify(&discriminant));
+        assert!(!resumed.verify(&Mpz::from_str("-3735928991").unwrap()));
+    }
+}
@@ -0,0 +1,148 @@
+//! Parameters in the formats of Chia's chiavdf, so discriminants made with
+//! its tooling can be used here and the other way round. chiavdf derives a
+//! discriminant from a challenge with `create_discriminant`, prints it as a
+//! signed decimal, and lays a form out as `a` then `b`, each a big-endian
+//! two's complement integer of `(bits + 16) / 16` bytes, which is
+//! `GmpClassGroup::serialize` at that length. The compressed (BQFC) form
+//! encoding of newer chiavdf versions is not supported. A chiavdf
+//! discriminant is minus a prime, so it serves VDFs; the CL encryption of
+//! `multi_party_ecdsa` needs `-q p` and can only export its forms this way.
+
+use super::GmpClassGroup;
+use crate::gmp::mpz::Mpz;
+use crate::{BigNum, BigNumExt, ClassGroup};
+use sha2::{Digest, Sha256};
+
+/// chiavdf's `CreateDiscriminant`: minus the first prime `p` of
+/// `size_bits` bits, with `p = 7 mod 8`, out of SHA-256 expansions of
+/// `challenge` and a counter. `None` unless `size_bits` is a positive
+/// multiple of 8.
+pub fn create_discriminant(challenge: &[u8], size_bits: usize) -> Option<Mpz> {
+    if size_bits == 0 || !size_bits.is_multiple_of(8) {
+        return None;
+    }
+    let mut sprout = challenge.to_vec();
+    loop {
+        let mut blob = Vec::with_capacity(size_bits / 8);
+        while blob.len() * 8 < size_bits {
+            // big-endian increment, wrapping
+            for byte in sprout.iter_mut().rev() {
+                *byte = byte.wrapping_add(1);
+                if *byte != 0 {
+                    break;
+                }
+            }
+            let hash = Sha256::digest(&sprout);
+            let take = (size_bits / 8 - blob.len()).min(hash.len());
+            blob.extend_from_slice(&hash[..take]);
+        }
+        let mut p = Mpz::from(&blob[..]);
+        for bit in [0, 1, 2, size_bits - 1] {
+            p.setbit(bit);
+        }
+        if BigNum::probab_prime(&p, 50) {
+            return Some(-p);
+        }
+    }
+}
+
+/// The discriminant as chiavdf prints it, e.g. `-1447...`.
+pub fn discriminant_to_string(discriminant: &Mpz) -> String {
+    discriminant.to_str_radix(10)
+}
+
+/// Parses a discriminant printed by chiavdf. `None` unless it is a negative
+/// decimal that is `1 mod 4`.
+pub fn parse_discriminant(s: &str) -> Option<Mpz> {
+    let digits = s.strip_prefix('-')?;
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let discriminant = Mpz::from_str_radix(s, 10).ok()?;
+    match discriminant.frem_u32(4) {
+        1 => Some(discriminant),
+        _ => None,
+    }
+}
+
+/// Bytes of each of `a` and `b` in a serialized form.
+pub fn int_size(discriminant: &Mpz) -> usize {
+    (GmpClassGroup::size_in_bits(discriminant) + 16) >> 4
+}
+
+/// `form` in chiavdf's layout, reduced first as chiavdf does.
+pub fn serialize_form(form: &GmpClassGroup) -> Vec<u8> {
+    let mut form = form.clone();
+    form.reduce();
+    let mut buf = vec![0u8; 2 * int_size(&form.discriminant)];
+    form.serialize(&mut buf).expect("a reduced form fits the chiavdf size");
+    buf
+}
+
+/// The form of `discriminant` in chiavdf's layout. `None` for the wrong
+/// length or anything but a reduced form.
+pub fn deserialize_form(bytes: &[u8], discriminant: &Mpz) -> Option<GmpClassGroup> {
+    if *discriminant >= Mpz::zero() || bytes.len() != 2 * int_size(discriminant) {
+        return None;
+    }
+    let (a, b) = bytes.split_at(bytes.len() / 2);
+    let (a, b) = (super::ffi::import_obj(a), super::ffi::import_obj(b));
+    let four_a = Mpz::from(4u64) * &a;
+    if a <= Mpz::zero() || !(&b * &b - discriminant).mod_floor(&four_a).is_zero() {
+        return None;
+    }
+    let form = GmpClassGroup::from_ab_discriminant(a, b, discriminant.clone());
+    match form.is_reduced_form() {
+        true => Some(form),
+        false => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn create_discriminant_test() {
+        let discriminant = create_discriminant(b"challenge", 512).unwrap();
+        assert_eq!(create_discriminant(b"challenge", 512), Some(discriminant.clone()));
+        assert_ne!(create_discriminant(b"challengf", 512), Some(discriminant.clone()));
+        let p = -discriminant.clone();
+        assert_eq!(p.bit_length(), 512);
+        assert!(BigNum::probab_prime(&p, 50));
+        assert_eq!(discriminant.frem_u32(8), 1);
+        assert_eq!(create_discriminant(b"challenge", 100), None);
+        assert_eq!(create_discriminant(b"challenge", 0), None);
+
+        let printed = discriminant_to_string(&discriminant);
+        assert!(printed.starts_with('-'));
+        assert_eq!(parse_discriminant(&printed), Some(discriminant));
+        for bad in ["", "-", "7", "-5", "-0x17", "- 7", "-7a"] {
+            assert_eq!(parse_discriminant(bad), None, "{}", bad);
+        }
+        assert_eq!(parse_discriminant("-7"), Some(-Mpz::from(7u64)));
+    }
+
+    #[test]
+    fn serialize_form_test() {
+        let discriminant = create_discriminant(&[0xaa], 512).unwrap();
+        assert_eq!(int_size(&discriminant), 33);
+        let mut form = GmpClassGroup::generator_for_discriminant(discriminant.clone());
+        form.pow(Mpz::from(1_000_003u64));
+        let bytes = serialize_form(&form);
+        assert_eq!(bytes.len(), 66);
+        assert_eq!(deserialize_form(&bytes, &discriminant), Some(form.clone()));
+        // one of a form and its inverse has a negative b
+        let mut inverse = form.clone();
+        inverse.inverse();
+        inverse.reduce();
+        let inverse_bytes = serialize_form(&inverse);
+        assert_eq!((bytes[33] | inverse_bytes[33]) & 0x80, 0x80);
+        assert_eq!(deserialize_form(&inverse_bytes, &discriminant), Some(inverse));
+
+        assert_eq!(deserialize_form(&bytes[1..], &discriminant), None);
+        let other = create_discriminant(&[0xab], 512).unwrap();
+        assert_eq!(deserialize_form(&bytes, &other), None);
+        assert_eq!(deserialize_form(&[0u8; 66], &discriminant), None);
+    }
+}
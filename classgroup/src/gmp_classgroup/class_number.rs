@@ -0,0 +1,91 @@
+//! Size estimate of the class group of an imaginary quadratic discriminant,
+//! from the analytic class number formula
+//! `h(D) = sqrt(|D|) / pi * L(1, chi_D)` for `D < -4`, with the Euler
+//! product of `L(1, chi_D)` truncated to the primes below `EULER_PRODUCT_BOUND`.
+//! Good to a fraction of a bit, which is all a sanity check on a generated
+//! or imported discriminant needs.
+
+use crate::gmp::mpz::Mpz;
+use crate::BigNumExt;
+
+const EULER_PRODUCT_BOUND: u32 = 1 << 16;
+
+/// The Kronecker symbol `(D / p)` for a prime `p`, from `D mod p`.
+fn kronecker(discriminant: &Mpz, p: u32) -> f64 {
+    let residue = discriminant.frem_u32(if p == 2 { 8 } else { p }) as u64;
+    if p == 2 {
+        return match residue {
+            1 | 7 => 1.0,
+            3 | 5 => -1.0,
+            _ => 0.0,
+        };
+    }
+    if residue == 0 {
+        return 0.0;
+    }
+    // Euler's criterion
+    let p = p as u64;
+    let (mut base, mut exponent, mut legendre) = (residue, (p - 1) / 2, 1u64);
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            legendre = legendre * base % p;
+        }
+        base = base * base % p;
+        exponent >>= 1;
+    }
+    if legendre == 1 {
+        1.0
+    } else {
+        -1.0
+    }
+}
+
+fn primes_below(bound: u32) -> impl Iterator<Item = u32> {
+    let mut composite = vec![false; bound as usize];
+    (2..bound).filter(move |&n| {
+        if composite[n as usize] {
+            return false;
+        }
+        for multiple in (n as usize * n as usize..bound as usize).step_by(n as usize) {
+            composite[multiple] = true;
+        }
+        true
+    })
+}
+
+/// `log2 h(D)`; `None` unless `D < -4` is a discriminant (`D = 0, 1 mod 4`).
+pub fn log2_class_number(discriminant: &Mpz) -> Option<f64> {
+    if *discriminant >= Mpz::from(-4) || !matches!(discriminant.frem_u32(4), 0 | 1) {
+        return None;
+    }
+    let magnitude = discriminant.abs();
+    let shift = magnitude.bit_length().saturating_sub(64);
+    let log2_magnitude = f64::from(&(&magnitude >> shift)).log2() + shift as f64;
+    let log_l = primes_below(EULER_PRODUCT_BOUND)
+        .map(|p| -(1.0 - kronecker(discriminant, p) / p as f64).ln())
+        .sum::<f64>();
+    Some(log2_magnitude / 2.0 - std::f64::consts::PI.log2() + log_l / std::f64::consts::LN_2)
+}
+
+/// Estimated bit size of the class number of `discriminant`.
+pub fn estimate_class_number(discriminant: &Mpz) -> Option<usize> {
+    log2_class_number(discriminant).map(|bits| bits.ceil().max(1.0) as usize)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn class_number_estimate_test() {
+        // h(-23) = 3, h(-1000003) = 105, h(-10000007) = 3660
+        for (discriminant, class_number) in [(-23i64, 3f64), (-1000003, 105.0), (-10000007, 3660.0)] {
+            let estimate = log2_class_number(&Mpz::from(discriminant)).unwrap();
+            assert!((estimate - class_number.log2()).abs() < 0.5, "{}", discriminant);
+        }
+        assert_eq!(estimate_class_number(&Mpz::from(-1000003)), Some(7));
+        assert_eq!(estimate_class_number(&Mpz::from(-3)), None);
+        assert_eq!(estimate_class_number(&Mpz::from(-6)), None);
+        assert_eq!(estimate_class_number(&Mpz::from(5)), None);
+    }
+}
@@ -19,13 +19,17 @@ use serde::{Deserialize, Serialize};
 use std::{
     borrow::Borrow,
     cell::RefCell,
+    fmt,
     mem::swap,
     ops::{Mul, MulAssign},
 };
+pub mod checkpoint;
+pub mod chiavdf;
+pub mod class_number;
 mod congruence;
 pub(super) mod ffi;
 
-#[derive(PartialEq, PartialOrd, Eq, Ord, Hash, Debug, Clone, Deserialize, Serialize)]
+#[derive(PartialEq, PartialOrd, Eq, Ord, Hash, Clone, Deserialize, Serialize)]
 pub struct GmpClassGroup {
     pub a: Mpz,
     pub b: Mpz,
@@ -33,6 +37,45 @@ pub struct GmpClassGroup {
     pub discriminant: Mpz,
 }
 
+/// Hex digits of `n`, with the middle elided past 16 digits.
+fn abbreviated_hex(n: &Mpz) -> String {
+    let hex = n.to_str_radix(16);
+    let digits = hex.trim_start_matches('-');
+    if digits.len() <= 16 {
+        return hex;
+    }
+    let sign = if hex.starts_with('-') { "-" } else { "" };
+    format!("{}{}…{}", sign, &digits[..8], &digits[digits.len() - 4..])
+}
+
+/// `(a≈…, b≈…, |D|=n bits)`; `c` follows from the discriminant.
+impl fmt::Display for GmpClassGroup {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "(a≈{}, b≈{}, |D|={} bits)",
+            abbreviated_hex(&self.a),
+            abbreviated_hex(&self.b),
+            self.discriminant.bit_length()
+        )
+    }
+}
+
+/// The `Display` form; `{:#?}` prints every field in full.
+impl fmt::Debug for GmpClassGroup {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !f.alternate() {
+            return write!(f, "GmpClassGroup{}", self);
+        }
+        f.debug_struct("GmpClassGroup")
+            .field("a", &self.a)
+            .field("b", &self.b)
+            .field("c", &self.c)
+            .field("discriminant", &self.discriminant)
+            .finish()
+    }
+}
+
 #[derive(PartialEq, PartialOrd, Eq, Ord, Clone, Hash, Debug)]
 pub struct Ctx {
     negative_a: Mpz,
@@ -69,6 +112,50 @@ impl GmpClassGroup {
         (self.a, self.b)
     }
 
+    /// The form with the given hex `a` and `b` (`-` prefixed when negative),
+    /// reduced. `None` unless both parse and `(b^2 - D) / 4a` is an integer
+    /// with `a > 0` and `D < 0`.
+    pub fn from_ab_hex(a_hex: &str, b_hex: &str, discriminant: &Mpz) -> Option<Self> {
+        fn parse(hex: &str) -> Option<Mpz> {
+            let digits = hex.strip_prefix('-').unwrap_or(hex);
+            if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return None;
+            }
+            Mpz::from_str_radix(hex, 16).ok()
+        }
+        let (a, b) = (parse(a_hex)?, parse(b_hex)?);
+        let four_a = Mpz::from(4u64) * &a;
+        if a <= Mpz::zero()
+            || *discriminant >= Mpz::zero()
+            || !(&b * &b - discriminant).mod_floor(&four_a).is_zero()
+        {
+            return None;
+        }
+        let mut form = Self::from_ab_discriminant(a, b, discriminant.clone());
+        form.reduce();
+        Some(form)
+    }
+
+    /// `a` and `b` in hex, the inverse of `from_ab_hex` on reduced forms.
+    pub fn to_ab_hex(&self) -> (String, String) {
+        (self.a.to_str_radix(16), self.b.to_str_radix(16))
+    }
+
+    /// Whether `self` is a reduced form of a negative discriminant:
+    /// `b^2 - 4ac = D`, `|b| <= a <= c`, and `b >= 0` if `|b| = a` or
+    /// `a = c`. Unlike `assert_valid`, checked in release builds too.
+    pub fn is_reduced_form(&self) -> bool {
+        let Self { a, b, c, discriminant } = self;
+        let four_ac: Mpz = Mpz::from(4u64) * a * c;
+        let b_abs = b.abs();
+        *discriminant < Mpz::zero()
+            && b * b - four_ac == *discriminant
+            && *a > Mpz::zero()
+            && b_abs <= *a
+            && a <= c
+            && (*b >= Mpz::zero() || (b_abs != *a && a != c))
+    }
+
     fn inner_multiply(&mut self, rhs: &Self, ctx: &mut Ctx) {
         self.assert_valid();
         rhs.assert_valid();
@@ -428,6 +515,15 @@ impl ClassGroup for GmpClassGroup {
         a_vec
     }
 
+    fn to_bytes_len(&self) -> usize {
+        self.a.byte_length() + self.b.byte_length()
+    }
+
+    fn hash_into<W: std::io::Write>(&self, hasher: &mut W) -> std::io::Result<()> {
+        self.a.write_be_bytes(hasher)?;
+        self.b.write_be_bytes(hasher)
+    }
+
     fn from_bytes(bytearray: &[u8], discriminant: Self::BigNum) -> Self {
         let len = (ffi::size_in_bits(&discriminant) + 16) >> 4;
         let a = ffi::import_obj(&bytearray[..len]);
@@ -617,6 +713,53 @@ mod test {
         s.pow(r);
         println!("s= {:?}", s);
     }
+
+    #[test]
+    fn format_test() {
+        use std::str::FromStr;
+        let s = GmpClassGroup {
+            a: Mpz::from_str("340282366920938463463374607431768211507").unwrap(),
+            b: Mpz::from(-255),
+            c: Mpz::from(1),
+            discriminant: Mpz::from(-3),
+        };
+        assert_eq!(s.to_string(), "(a≈10000000…0033, b≈-ff, |D|=2 bits)");
+        assert_eq!(format!("{:?}", s), format!("GmpClassGroup{}", s));
+        assert!(format!("{:#?}", s).contains("340282366920938463463374607431768211507"));
+    }
+
+    #[test]
+    fn ab_hex_test() {
+        let discriminant = Mpz::from(-0xdead_beefi64);
+        let mut form = GmpClassGroup::new(16.into(), (-23).into(), 5837_3892.into(), discriminant.clone());
+        form.reduce();
+        let (a_hex, b_hex) = form.to_ab_hex();
+        assert_eq!(GmpClassGroup::from_ab_hex(&a_hex, &b_hex, &discriminant), Some(form));
+        // unreduced input comes back reduced
+        let reduced = GmpClassGroup::from_ab_hex("10", "-17", &discriminant).unwrap();
+        assert!(reduced.is_reduced_form());
+
+        for (a, b) in [("0", "1"), ("-10", "-17"), ("10", "-16"), ("1g", "1"), ("", "1"), ("10", " 17")] {
+            assert_eq!(GmpClassGroup::from_ab_hex(a, b, &discriminant), None);
+        }
+    }
+
+    #[test]
+    fn hash_into_matches_to_bytes() {
+        use std::str::FromStr;
+        let s = GmpClassGroup {
+            a: Mpz::from_str("340282366920938463463374607431768211507").unwrap(),
+            b: Mpz::from_str("-18446744073709551629").unwrap(),
+            c: Mpz::from(1),
+            discriminant: Mpz::from(-3),
+        };
+        for x in [s.clone(), GmpClassGroup { b: 0.into(), ..s }] {
+            let mut streamed = Vec::new();
+            x.hash_into(&mut streamed).unwrap();
+            assert_eq!(streamed, x.to_bytes());
+            assert_eq!(x.to_bytes_len(), streamed.len());
+        }
+    }
     #[test]
     fn thread_test() {
         use std::str::FromStr;
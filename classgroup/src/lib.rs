@@ -18,6 +18,8 @@ pub mod gmp;
 
 pub mod gmp_classgroup;
 pub use self::gmp_classgroup::{
+    checkpoint::SquaringCheckpoint,
+    class_number::estimate_class_number,
     do_compute,
     ffi::{export_obj, import_obj},
 };
@@ -69,6 +71,17 @@ pub trait ClassGroup:
 
     fn to_bytes(&self) -> Vec<u8>;
 
+    /// Length of `self.to_bytes()`.
+    fn to_bytes_len(&self) -> usize {
+        self.to_bytes().len()
+    }
+
+    /// Streams `self.to_bytes()` into `hasher`. Implementations should
+    /// override this to avoid materializing the byte vector.
+    fn hash_into<W: std::io::Write>(&self, hasher: &mut W) -> std::io::Result<()> {
+        hasher.write_all(&self.to_bytes())
+    }
+
     /// Computes the identity element of `Self` for a given discriminant.
     ///
     /// If the discriminant is not valid, the result is unspecified.
@@ -166,6 +179,33 @@ pub trait ClassGroup:
     /// Exponentiation
     fn pow(&mut self, exponent: Self::BigNum);
 
+    /// Exponentiation by a possibly negative exponent. A negative exponent
+    /// inverts `self` once and then raises it to `|exponent|`.
+    fn pow_signed(&mut self, exponent: Self::BigNum) {
+        if exponent < Self::BigNum::zero() {
+            self.inverse();
+            self.pow(-exponent);
+        } else {
+            self.pow(exponent);
+        }
+    }
+
+    /// `pow_signed` with the exponent first reduced modulo `order_multiple`,
+    /// which must be a multiple of the order of `self` (e.g. `q` for
+    /// elements of a subgroup of order `q`). An upper bound on the order is
+    /// not enough: reducing by it gives a different element.
+    fn pow_mod_order_hint(&mut self, exponent: Self::BigNum, order_multiple: Option<&Self::BigNum>) {
+        match order_multiple {
+            Some(modulus) => {
+                // exponent^1 mod modulus, which is non-negative
+                let mut reduced = Self::BigNum::zero();
+                reduced.mod_powm(&exponent, &Self::BigNum::one(), modulus);
+                self.pow(reduced);
+            }
+            None => self.pow_signed(exponent),
+        }
+    }
+
     /// Deserialization
     fn deserialize(buf: &[u8], discriminant: Self::BigNum) -> Self;
 }
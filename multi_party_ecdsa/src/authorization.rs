@@ -0,0 +1,69 @@
+//! Dual control. An operator's offline approval key signs each signing
+//! request, i.e. a joint key and a message hash, and party one checks the
+//! approval before it takes part; see `party_one::Sign::with_authorization`.
+
+use crate::utilities::error::MulEcdsaError;
+use k256::ecdsa::signature::hazmat::{PrehashSigner, PrehashVerifier};
+use k256::ecdsa::{SigningKey, VerifyingKey};
+use k256::elliptic_curve::sec1::ToEncodedPoint;
+use k256::{ProjectivePoint, Scalar};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// What the approval key signs: the joint key's
+/// `KeyStore::public_key_fingerprint` and the message hash to be signed.
+pub fn request_hash(key_id: &[u8; 32], message_hash: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(b"SigningRequest");
+    hasher.update(key_id);
+    hasher.update((message_hash.len() as u64).to_be_bytes());
+    hasher.update(message_hash);
+    hasher.finalize().into()
+}
+
+/// An ECDSA signature by the approval key over a `request_hash`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RequestAuthorization {
+    pub request_hash: [u8; 32],
+    pub r: Scalar,
+    pub s: Scalar,
+}
+
+impl RequestAuthorization {
+    /// Run by the operator, away from both parties.
+    pub fn approve(
+        approval_key: &Scalar,
+        key_id: &[u8; 32],
+        message_hash: &[u8],
+    ) -> Result<Self, MulEcdsaError> {
+        let request_hash = request_hash(key_id, message_hash);
+        let signing_key =
+            SigningKey::from_bytes(&approval_key.to_bytes()).map_err(|_| MulEcdsaError::ZeroScalar)?;
+        let signature: k256::ecdsa::Signature = signing_key
+            .sign_prehash(&request_hash)
+            .map_err(|_| MulEcdsaError::Unauthorized)?;
+        Ok(Self {
+            request_hash,
+            r: *signature.r(),
+            s: *signature.s(),
+        })
+    }
+
+    /// Whether `approver` authorized signing `message_hash` with key `key_id`.
+    pub fn verify(
+        &self,
+        approver: &ProjectivePoint,
+        key_id: &[u8; 32],
+        message_hash: &[u8],
+    ) -> Result<(), MulEcdsaError> {
+        if self.request_hash != request_hash(key_id, message_hash) {
+            return Err(MulEcdsaError::Unauthorized);
+        }
+        let signature = k256::ecdsa::Signature::from_scalars(self.r.to_bytes(), self.s.to_bytes())
+            .map_err(|_| MulEcdsaError::Unauthorized)?;
+        let key = VerifyingKey::from_encoded_point(&approver.to_affine().to_encoded_point(false))
+            .map_err(|_| MulEcdsaError::InvalidPublicKey)?;
+        key.verify_prehash(&self.request_hash, &signature)
+            .map_err(|_| MulEcdsaError::Unauthorized)
+    }
+}
@@ -0,0 +1,335 @@
+//! Key generation ceremony reports. After keygen each party writes a JSON
+//! report of what was generated and how, signed with its operator's key,
+//! for auditors to archive. `check_key_store` later ties an archived report
+//! to the key store in use.
+
+use crate::codec::WIRE_VERSION;
+use crate::shared::KeyStore;
+use crate::utilities::address::{compressed_sec1_hex, fingerprint};
+use crate::utilities::error::MulEcdsaError;
+use crate::utilities::parse::{parse_digest, parse_point, parse_scalar, Encoding};
+use crate::utilities::ProtocolParams;
+use k256::ecdsa::signature::hazmat::{PrehashSigner, PrehashVerifier};
+use k256::ecdsa::{SigningKey, VerifyingKey};
+use k256::elliptic_curve::sec1::ToEncodedPoint;
+use k256::{ProjectivePoint, Scalar};
+use sha2::{Digest, Sha256};
+use std::convert::TryFrom;
+use std::fmt::Write;
+
+pub const SOFTWARE_VERSION: &str = concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION"));
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CeremonyReport {
+    /// 1 or 2.
+    pub party: u8,
+    pub computational_security: usize,
+    pub statistical_security: usize,
+    pub public_key: ProjectivePoint,
+    /// The reporting party's share.
+    pub public_share: ProjectivePoint,
+    pub counterparty_share: ProjectivePoint,
+    pub keygen_transcript_hash: Option<[u8; 32]>,
+    pub software_version: String,
+    pub wire_version: u16,
+    /// Seconds since the Unix epoch.
+    pub started_at: u64,
+    pub finished_at: u64,
+}
+
+/// A report with its operator's ECDSA signature over `report_hash`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SignedCeremonyReport {
+    pub report: CeremonyReport,
+    pub signer: ProjectivePoint,
+    pub r: Scalar,
+    pub s: Scalar,
+}
+
+/// A field of the flat JSON object a report is written as.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Value {
+    Str(String),
+    Num(u64),
+    Null,
+}
+
+impl CeremonyReport {
+    /// For the key store `party` just generated, with the default
+    /// parameters the parties run at.
+    pub fn new(party: u8, key_store: &KeyStore, started_at: u64, finished_at: u64) -> Self {
+        let params = ProtocolParams::default();
+        Self {
+            party,
+            computational_security: params.computational_security,
+            statistical_security: params.statistical_security,
+            public_key: key_store.public_signing_key,
+            public_share: key_store.public_share,
+            counterparty_share: key_store.counterparty_share,
+            keygen_transcript_hash: key_store.keygen_transcript_hash,
+            software_version: SOFTWARE_VERSION.to_string(),
+            wire_version: WIRE_VERSION,
+            started_at,
+            finished_at,
+        }
+    }
+
+    fn fields(&self) -> Vec<(&'static str, Value)> {
+        vec![
+            ("party", Value::Num(self.party as u64)),
+            ("computational_security", Value::Num(self.computational_security as u64)),
+            ("statistical_security", Value::Num(self.statistical_security as u64)),
+            ("public_key", Value::Str(compressed_sec1_hex(&self.public_key))),
+            ("key_id", Value::Str(hex::encode(fingerprint(&self.public_key)))),
+            ("public_share", Value::Str(compressed_sec1_hex(&self.public_share))),
+            (
+                "counterparty_share",
+                Value::Str(compressed_sec1_hex(&self.counterparty_share)),
+            ),
+            (
+                "keygen_transcript_hash",
+                self.keygen_transcript_hash
+                    .map_or(Value::Null, |hash| Value::Str(hex::encode(hash))),
+            ),
+            ("software_version", Value::Str(self.software_version.clone())),
+            ("wire_version", Value::Num(self.wire_version as u64)),
+            ("started_at", Value::Num(self.started_at)),
+            ("finished_at", Value::Num(self.finished_at)),
+        ]
+    }
+
+    pub fn to_json(&self) -> String {
+        write_json(&self.fields())
+    }
+
+    /// What the operator signs.
+    pub fn report_hash(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(b"CeremonyReport");
+        hasher.update(self.to_json());
+        hasher.finalize().into()
+    }
+
+    pub fn sign(self, operator_key: &Scalar) -> Result<SignedCeremonyReport, MulEcdsaError> {
+        let signing_key = SigningKey::from_bytes(&operator_key.to_bytes()).map_err(|_| MulEcdsaError::ZeroScalar)?;
+        let signature: k256::ecdsa::Signature = signing_key
+            .sign_prehash(&self.report_hash())
+            .map_err(|_| MulEcdsaError::InvalidCeremonyReport)?;
+        Ok(SignedCeremonyReport {
+            report: self,
+            signer: ProjectivePoint::GENERATOR * operator_key,
+            r: *signature.r(),
+            s: *signature.s(),
+        })
+    }
+}
+
+impl SignedCeremonyReport {
+    /// The report's fields followed by `signer` and `signature`, `r || s`.
+    pub fn to_json(&self) -> String {
+        let mut fields = self.report.fields();
+        fields.push(("signer", Value::Str(compressed_sec1_hex(&self.signer))));
+        fields.push((
+            "signature",
+            Value::Str(hex::encode([self.r.to_bytes(), self.s.to_bytes()].concat())),
+        ));
+        write_json(&fields)
+    }
+
+    /// Parses what `to_json` wrote; the signature is not checked here.
+    pub fn from_json(json: &str) -> Result<Self, MulEcdsaError> {
+        let fields = read_json(json).ok_or(MulEcdsaError::InvalidCeremonyReport)?;
+        let get = |name: &str| {
+            fields
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value)
+                .ok_or(MulEcdsaError::InvalidCeremonyReport)
+        };
+        let num = |name: &str| match get(name)? {
+            Value::Num(n) => Ok(*n),
+            _ => Err(MulEcdsaError::InvalidCeremonyReport),
+        };
+        let string = |name: &str| match get(name)? {
+            Value::Str(s) => Ok(s.as_str()),
+            _ => Err(MulEcdsaError::InvalidCeremonyReport),
+        };
+        let point =
+            |name: &str| parse_point(string(name)?, Encoding::Hex).map_err(|_| MulEcdsaError::InvalidCeremonyReport);
+        let scalar = |hex: &str| parse_scalar(hex, Encoding::Hex).map_err(|_| MulEcdsaError::InvalidCeremonyReport);
+        let keygen_transcript_hash = match get("keygen_transcript_hash")? {
+            Value::Null => None,
+            Value::Str(s) => Some(parse_digest(s, Encoding::Hex).map_err(|_| MulEcdsaError::InvalidCeremonyReport)?),
+            Value::Num(_) => return Err(MulEcdsaError::InvalidCeremonyReport),
+        };
+        let signature = string("signature")?;
+        if signature.len() != 128 || !signature.is_ascii() {
+            return Err(MulEcdsaError::InvalidCeremonyReport);
+        }
+        let signed = Self {
+            report: CeremonyReport {
+                party: narrow(num("party")?)?,
+                computational_security: narrow(num("computational_security")?)?,
+                statistical_security: narrow(num("statistical_security")?)?,
+                public_key: point("public_key")?,
+                public_share: point("public_share")?,
+                counterparty_share: point("counterparty_share")?,
+                keygen_transcript_hash,
+                software_version: string("software_version")?.to_string(),
+                wire_version: narrow(num("wire_version")?)?,
+                started_at: num("started_at")?,
+                finished_at: num("finished_at")?,
+            },
+            signer: point("signer")?,
+            r: scalar(&signature[..64])?,
+            s: scalar(&signature[64..])?,
+        };
+        // nothing but the fields `to_json` writes, and a `key_id` matching
+        // the public key
+        if signed.to_json() != write_json(&fields) {
+            return Err(MulEcdsaError::InvalidCeremonyReport);
+        }
+        Ok(signed)
+    }
+
+    /// Whether `operator` signed the report.
+    pub fn verify(&self, operator: &ProjectivePoint) -> Result<(), MulEcdsaError> {
+        if self.signer != *operator {
+            return Err(MulEcdsaError::InvalidCeremonyReport);
+        }
+        let signature = k256::ecdsa::Signature::from_scalars(self.r.to_bytes(), self.s.to_bytes())
+            .map_err(|_| MulEcdsaError::InvalidCeremonyReport)?;
+        let key = VerifyingKey::from_encoded_point(&operator.to_affine().to_encoded_point(false))
+            .map_err(|_| MulEcdsaError::InvalidPublicKey)?;
+        key.verify_prehash(&self.report.report_hash(), &signature)
+            .map_err(|_| MulEcdsaError::InvalidCeremonyReport)
+    }
+
+    /// Whether the report describes the key held in `key_store`.
+    pub fn check_key_store(&self, key_store: &KeyStore) -> Result<(), MulEcdsaError> {
+        let report = &self.report;
+        match report.public_key == key_store.public_signing_key
+            && report.public_share == key_store.public_share
+            && report.counterparty_share == key_store.counterparty_share
+            && report.keygen_transcript_hash == key_store.keygen_transcript_hash
+        {
+            true => Ok(()),
+            false => Err(MulEcdsaError::InvalidCeremonyReport),
+        }
+    }
+}
+
+fn narrow<T: TryFrom<u64>>(n: u64) -> Result<T, MulEcdsaError> {
+    T::try_from(n).map_err(|_| MulEcdsaError::InvalidCeremonyReport)
+}
+
+fn write_json<K: AsRef<str>>(fields: &[(K, Value)]) -> String {
+    let mut json = String::from("{\n");
+    for (i, (key, value)) in fields.iter().enumerate() {
+        let key = key.as_ref();
+        let separator = if i + 1 < fields.len() { "," } else { "" };
+        let _ = match value {
+            Value::Str(s) => writeln!(json, "  \"{}\": \"{}\"{}", key, s, separator),
+            Value::Num(n) => writeln!(json, "  \"{}\": {}{}", key, n, separator),
+            Value::Null => writeln!(json, "  \"{}\": null{}", key, separator),
+        };
+    }
+    json.push('}');
+    json
+}
+
+/// Reads a flat object of strings without escapes, unsigned integers and
+/// nulls, which is all a report contains.
+fn read_json(json: &str) -> Option<Vec<(String, Value)>> {
+    fn string(rest: &str) -> Option<(String, &str)> {
+        let rest = rest.trim_start().strip_prefix('"')?;
+        let end = rest.find('"')?;
+        let value = &rest[..end];
+        match value.contains('\\') || value.chars().any(char::is_control) {
+            true => None,
+            false => Some((value.to_string(), &rest[end + 1..])),
+        }
+    }
+
+    let mut rest = json.trim().strip_prefix('{')?.strip_suffix('}')?;
+    let mut fields = Vec::new();
+    while !rest.trim().is_empty() {
+        if !fields.is_empty() {
+            rest = rest.trim_start().strip_prefix(',')?;
+        }
+        let (key, after_key) = string(rest)?;
+        rest = after_key.trim_start().strip_prefix(':')?.trim_start();
+        let value = if let Some(after) = rest.strip_prefix("null") {
+            rest = after;
+            Value::Null
+        } else if rest.starts_with('"') {
+            let (value, after) = string(rest)?;
+            rest = after;
+            Value::Str(value)
+        } else {
+            let end = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+            let value = rest[..end].parse().ok()?;
+            rest = &rest[end..];
+            Value::Num(value)
+        };
+        fields.push((key, value));
+    }
+    Some(fields)
+}
+
+#[cfg(not(feature = "verify-only"))]
+#[test]
+fn ceremony_report_test() {
+    use crate::utilities::rng::ProtocolRng;
+    use k256::elliptic_curve::Field;
+
+    let x1 = Scalar::random(&mut ProtocolRng);
+    let p2 = ProjectivePoint::GENERATOR * Scalar::random(&mut ProtocolRng);
+    let mut key_store = KeyStore::new(x1, p2, ProjectivePoint::GENERATOR * x1 + p2).unwrap();
+    key_store.keygen_transcript_hash = Some([9u8; 32]);
+    let operator_key = Scalar::random(&mut ProtocolRng);
+    let operator = ProjectivePoint::GENERATOR * operator_key;
+
+    let signed = CeremonyReport::new(1, &key_store, 1_700_000_000, 1_700_000_042)
+        .sign(&operator_key)
+        .unwrap();
+    let json = signed.to_json();
+    assert!(json.contains(&format!(
+        "\"key_id\": \"{}\"",
+        hex::encode(key_store.public_key_fingerprint())
+    )));
+    let parsed = SignedCeremonyReport::from_json(&json).unwrap();
+    assert_eq!(parsed, signed);
+    parsed.verify(&operator).unwrap();
+    parsed.check_key_store(&key_store).unwrap();
+
+    assert_eq!(parsed.verify(&p2), Err(MulEcdsaError::InvalidCeremonyReport));
+    let mut other = key_store.clone();
+    other.keygen_transcript_hash = None;
+    assert_eq!(
+        parsed.check_key_store(&other),
+        Err(MulEcdsaError::InvalidCeremonyReport)
+    );
+    // an edited report no longer verifies, a malformed one does not parse
+    let edited = json.replace("\"finished_at\": 1700000042", "\"finished_at\": 1700000043");
+    assert_eq!(
+        SignedCeremonyReport::from_json(&edited).unwrap().verify(&operator),
+        Err(MulEcdsaError::InvalidCeremonyReport)
+    );
+    // 128 bytes, with a two-byte character across the split
+    let start = json.find("\"signature\": \"").unwrap() + 14;
+    let signature = &json[start..start + 128];
+    let multibyte = format!("{}\u{e9}{}", &signature[..63], &signature[65..]);
+    for broken in [
+        json.replace(signature, &multibyte),
+        json.replace("\"party\": 1", "\"party\": \"1\""),
+        json.replace("\"key_id\": \"", "\"key_id\": \"00"),
+        json.replace("\n}", ",\n  \"extra\": 1\n}"),
+        json[..json.len() - 1].to_string(),
+    ] {
+        assert_eq!(
+            SignedCeremonyReport::from_json(&broken),
+            Err(MulEcdsaError::InvalidCeremonyReport)
+        );
+    }
+}
@@ -0,0 +1,79 @@
+//! Where the crate reads the time: session expiry, audit entries and the
+//! timestamp in `Hello`. `SystemClock` is the default; tests and
+//! simulations move a `ManualClock` by hand.
+
+use crate::shared::unix_time;
+use crate::utilities::error::MulEcdsaError;
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// How far a peer's clock may be from ours, in seconds, unless configured
+/// otherwise.
+pub const DEFAULT_ALLOWED_SKEW: u64 = 300;
+
+pub trait Clock: Debug + Send + Sync {
+    /// Seconds since the Unix epoch.
+    fn now(&self) -> u64;
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        unix_time()
+    }
+}
+
+/// Stands still until `set` or `advance`.
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    now: AtomicU64,
+}
+
+impl ManualClock {
+    pub fn new(now: u64) -> Self {
+        Self {
+            now: AtomicU64::new(now),
+        }
+    }
+
+    pub fn set(&self, now: u64) {
+        self.now.store(now, Ordering::SeqCst);
+    }
+
+    pub fn advance(&self, secs: u64) {
+        self.now.fetch_add(secs, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> u64 {
+        self.now.load(Ordering::SeqCst)
+    }
+}
+
+/// `ClockSkew` if `peer_time` is more than `allowed_skew` seconds away from
+/// `clock`, in either direction.
+pub fn check_skew(clock: &dyn Clock, peer_time: u64, allowed_skew: u64) -> Result<(), MulEcdsaError> {
+    match clock.now().abs_diff(peer_time) <= allowed_skew {
+        true => Ok(()),
+        false => Err(MulEcdsaError::ClockSkew),
+    }
+}
+
+#[test]
+fn check_skew_test() {
+    let clock = ManualClock::new(1_700_000_000);
+    check_skew(&clock, 1_700_000_000 - DEFAULT_ALLOWED_SKEW, DEFAULT_ALLOWED_SKEW).unwrap();
+    check_skew(&clock, 1_700_000_000 + DEFAULT_ALLOWED_SKEW, DEFAULT_ALLOWED_SKEW).unwrap();
+    assert_eq!(
+        check_skew(&clock, 1_700_000_000 + 301, 300),
+        Err(MulEcdsaError::ClockSkew)
+    );
+    clock.advance(2);
+    assert_eq!(check_skew(&clock, 1_700_000_000, 1), Err(MulEcdsaError::ClockSkew));
+    check_skew(&clock, 1_700_000_000, 2).unwrap();
+    clock.set(0);
+    assert_eq!(check_skew(&clock, u64::MAX, 300), Err(MulEcdsaError::ClockSkew));
+}
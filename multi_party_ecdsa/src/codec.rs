@@ -0,0 +1,514 @@
+//! Wire encoding of protocol messages. Every message is length-checked
+//! against a per-kind cap before it is decoded, so a peer cannot make the
+//! decoder allocate for an oversized big integer, and the sizes seen are
+//! recorded for monitoring. Length prefixes inside a message are bounded
+//! too: bincode allocates a byte string or vector of the claimed length
+//! before reading it, so decoding runs with `DECODE_CLAIM_LIMIT`. With the
+//! `compression` feature the encoded bytes can be zstd-compressed, if both
+//! parties offer it in their `Hello`.
+
+use crate::clock::{check_skew, Clock};
+use crate::utilities::error::MulEcdsaError;
+use bincode::config::standard;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Most bytes the containers of one received message may claim, summed;
+/// well above any honest message under the default caps.
+pub const DECODE_CLAIM_LIMIT: usize = 1 << 20;
+
+/// Version of the message encoding, exchanged in `Hello`.
+pub const WIRE_VERSION: u16 = 2;
+
+/// The messages of one signing session, in protocol order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum MessageKind {
+    /// P2 -> P1: commitment to party two's nonce share.
+    NonceCom,
+    /// P1 -> P2: MtA ciphertext with its CL proof.
+    MtaFirst,
+    /// P2 -> P1: MtA response, with or without the MtAwc proof.
+    MtaSecond,
+    /// P1 -> P2
+    MtaConsistency,
+    /// P1 -> P2
+    PartyOneNonce,
+    /// P2 -> P1: opening of the nonce commitment.
+    PartyTwoNonce,
+    /// P2 -> P1
+    PartialSig,
+    /// Either direction, in place of the next message; see `AbortMsg`.
+    Abort,
+    /// Either direction, before anything else; see `Hello`.
+    Hello,
+    /// Either direction, at any point; see `CancelMsg`.
+    Cancel,
+}
+
+/// Maximum encoded length per message kind, in bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SizeCaps {
+    pub nonce_com: usize,
+    pub mta_first: usize,
+    pub mta_second: usize,
+    pub mta_consistency: usize,
+    pub party_one_nonce: usize,
+    pub party_two_nonce: usize,
+    pub partial_sig: usize,
+    pub abort: usize,
+    pub hello: usize,
+    pub cancel: usize,
+}
+
+impl Default for SizeCaps {
+    /// Roughly four times the sizes observed with the default parameters.
+    fn default() -> Self {
+        Self {
+            nonce_com: 1024,
+            mta_first: 16 * 1024,
+            mta_second: 16 * 1024,
+            mta_consistency: 1024,
+            party_one_nonce: 1024,
+            party_two_nonce: 1024,
+            partial_sig: 128,
+            abort: 128,
+            hello: 64,
+            cancel: 64,
+        }
+    }
+}
+
+impl SizeCaps {
+    /// The largest cap of any kind.
+    pub fn max(&self) -> usize {
+        [
+            self.nonce_com,
+            self.mta_first,
+            self.mta_second,
+            self.mta_consistency,
+            self.party_one_nonce,
+            self.party_two_nonce,
+            self.partial_sig,
+            self.abort,
+            self.hello,
+            self.cancel,
+        ]
+        .iter()
+        .copied()
+        .max()
+        .unwrap_or(0)
+    }
+
+    pub fn cap(&self, kind: MessageKind) -> usize {
+        match kind {
+            MessageKind::NonceCom => self.nonce_com,
+            MessageKind::MtaFirst => self.mta_first,
+            MessageKind::MtaSecond => self.mta_second,
+            MessageKind::MtaConsistency => self.mta_consistency,
+            MessageKind::PartyOneNonce => self.party_one_nonce,
+            MessageKind::PartyTwoNonce => self.party_two_nonce,
+            MessageKind::PartialSig => self.partial_sig,
+            MessageKind::Abort => self.abort,
+            MessageKind::Hello => self.hello,
+            MessageKind::Cancel => self.cancel,
+        }
+    }
+}
+
+/// What is applied to a message after bincode.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Compression {
+    #[default]
+    None,
+    /// Needs the `compression` feature.
+    Zstd,
+}
+
+/// Sent first by both parties; the session uses the compression both of
+/// them offer.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hello {
+    pub version: u16,
+    pub compression: Vec<Compression>,
+    /// The sender's time, in seconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+impl Hello {
+    /// This build's version and compressions, stamped with `clock`.
+    pub fn local(clock: &dyn Clock) -> Self {
+        let mut compression = vec![Compression::None];
+        if cfg!(feature = "compression") {
+            compression.push(Compression::Zstd);
+        }
+        Self {
+            version: WIRE_VERSION,
+            compression,
+            timestamp: clock.now(),
+        }
+    }
+
+    /// `ClockSkew` if the peer's `timestamp` is more than `allowed_skew`
+    /// seconds from `clock`, usually `DEFAULT_ALLOWED_SKEW`. Timestamps the
+    /// peer sends later can then be trusted to that margin.
+    pub fn check_timestamp(&self, clock: &dyn Clock, allowed_skew: u64) -> Result<(), MulEcdsaError> {
+        check_skew(clock, self.timestamp, allowed_skew)
+    }
+
+    /// Zstd if both sides offer it, so both arrive at the same answer.
+    pub fn negotiate(&self, peer: &Hello) -> Result<Compression, MulEcdsaError> {
+        if self.version != peer.version {
+            return Err(MulEcdsaError::InvalidProtocolParams);
+        }
+        let both = |c| self.compression.contains(&c) && peer.compression.contains(&c);
+        Ok(match both(Compression::Zstd) {
+            true => Compression::Zstd,
+            false => Compression::None,
+        })
+    }
+}
+
+/// Sent by a party whose check of `round` failed, so the counterparty can
+/// drop the session instead of waiting for a message that will not come.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AbortMsg {
+    pub session: [u8; 32],
+    pub round: MessageKind,
+    pub reason: MulEcdsaError,
+}
+
+/// Sent by a party that gives up on the session, e.g. because the user
+/// declined on their device, after it called `cancel` on its `Sign`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CancelMsg {
+    pub session: [u8; 32],
+}
+
+/// Why a received message did not decode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeFailure {
+    /// Longer than the cap for its kind.
+    TooLarge { len: usize, cap: usize },
+    /// A length prefix inside it claims more than `DECODE_CLAIM_LIMIT`.
+    ClaimTooLarge,
+    /// Ends in the middle of a field.
+    Truncated,
+    /// Bytes left over after the message.
+    TrailingBytes,
+    /// Rejected by bincode or by the message's `Deserialize` impl.
+    Malformed,
+}
+
+/// A decode failure and the kind of message it happened on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DecodeError {
+    pub kind: MessageKind,
+    pub failure: DecodeFailure,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} message: ", self.kind)?;
+        match self.failure {
+            DecodeFailure::TooLarge { len, cap } => write!(f, "{} bytes over the cap of {}", len, cap),
+            DecodeFailure::ClaimTooLarge => write!(f, "length prefix over the decode limit"),
+            DecodeFailure::Truncated => write!(f, "truncated"),
+            DecodeFailure::TrailingBytes => write!(f, "trailing bytes"),
+            DecodeFailure::Malformed => write!(f, "malformed"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl From<DecodeError> for MulEcdsaError {
+    fn from(e: DecodeError) -> Self {
+        match e.failure {
+            DecodeFailure::TooLarge { .. } | DecodeFailure::ClaimTooLarge => {
+                MulEcdsaError::MessageTooLarge
+            }
+            _ => MulEcdsaError::SerializeFailed,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SizeStats {
+    pub count: u64,
+    /// As sent or received, after compression.
+    pub total_bytes: u64,
+    pub max_bytes: usize,
+    /// Before compression; of received messages, only those that
+    /// decompressed.
+    pub raw_bytes: u64,
+}
+
+impl SizeStats {
+    fn observe(&mut self, len: usize) {
+        self.count += 1;
+        self.total_bytes += len as u64;
+        self.max_bytes = self.max_bytes.max(len);
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Codec {
+    /// Applies to the bytes before compression.
+    pub caps: SizeCaps,
+    pub compression: Compression,
+    /// Sizes of encoded messages, per kind.
+    pub sent: BTreeMap<MessageKind, SizeStats>,
+    /// Sizes of received messages, per kind, including rejected ones.
+    pub received: BTreeMap<MessageKind, SizeStats>,
+}
+
+impl Codec {
+    pub fn new(caps: SizeCaps) -> Self {
+        Self {
+            caps,
+            ..Self::default()
+        }
+    }
+
+    /// Usually the outcome of `Hello::negotiate`.
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// `Hello`, `Abort` and `Cancel` are never compressed, so they are
+    /// readable before and regardless of the negotiation.
+    fn compression_for(&self, kind: MessageKind) -> Compression {
+        match kind {
+            MessageKind::Hello | MessageKind::Abort | MessageKind::Cancel => Compression::None,
+            _ => self.compression,
+        }
+    }
+
+    pub fn encode<T: Serialize>(
+        &mut self,
+        kind: MessageKind,
+        msg: &T,
+    ) -> Result<Vec<u8>, MulEcdsaError> {
+        let raw = bincode::serde::encode_to_vec(msg, standard())
+            .map_err(|_| MulEcdsaError::SerializeFailed)?;
+        if raw.len() > self.caps.cap(kind) {
+            let stats = self.sent.entry(kind).or_default();
+            stats.observe(raw.len());
+            stats.raw_bytes += raw.len() as u64;
+            return Err(MulEcdsaError::MessageTooLarge);
+        }
+        let bytes = compress(self.compression_for(kind), &raw)?;
+        let stats = self.sent.entry(kind).or_default();
+        stats.observe(bytes.len());
+        stats.raw_bytes += raw.len() as u64;
+        Ok(bytes)
+    }
+
+    /// Rejects messages over the cap before decoding and messages with
+    /// trailing bytes after it.
+    pub fn decode<T: DeserializeOwned>(
+        &mut self,
+        kind: MessageKind,
+        bytes: &[u8],
+    ) -> Result<T, MulEcdsaError> {
+        self.try_decode(kind, bytes).map_err(MulEcdsaError::from)
+    }
+
+    /// `decode` with the reason for a failure.
+    pub fn try_decode<T: DeserializeOwned>(
+        &mut self,
+        kind: MessageKind,
+        bytes: &[u8],
+    ) -> Result<T, DecodeError> {
+        use bincode::error::DecodeError as Bincode;
+
+        let fail = |failure| DecodeError { kind, failure };
+        self.received.entry(kind).or_default().observe(bytes.len());
+        let cap = self.caps.cap(kind);
+        if bytes.len() > cap {
+            return Err(fail(DecodeFailure::TooLarge {
+                len: bytes.len(),
+                cap,
+            }));
+        }
+        let raw = decompress(self.compression_for(kind), bytes, cap)
+            .ok_or_else(|| fail(DecodeFailure::Malformed))?;
+        self.received.entry(kind).or_default().raw_bytes += raw.len() as u64;
+        let bytes = &raw[..];
+        let config = standard().with_limit::<DECODE_CLAIM_LIMIT>();
+        let (msg, read) = bincode::serde::decode_from_slice(bytes, config).map_err(|e| {
+            fail(match e {
+                Bincode::LimitExceeded => DecodeFailure::ClaimTooLarge,
+                Bincode::UnexpectedEnd { .. } => DecodeFailure::Truncated,
+                _ => DecodeFailure::Malformed,
+            })
+        })?;
+        if read != bytes.len() {
+            return Err(fail(DecodeFailure::TrailingBytes));
+        }
+        Ok(msg)
+    }
+}
+
+fn compress(compression: Compression, raw: &[u8]) -> Result<Vec<u8>, MulEcdsaError> {
+    match compression {
+        Compression::None => Ok(raw.to_vec()),
+        #[cfg(feature = "compression")]
+        Compression::Zstd => zstd::bulk::compress(raw, 0).map_err(|_| MulEcdsaError::SerializeFailed),
+        #[cfg(not(feature = "compression"))]
+        Compression::Zstd => Err(MulEcdsaError::InvalidProtocolParams),
+    }
+}
+
+/// `None` if `bytes` do not decompress to at most `cap` bytes.
+fn decompress(compression: Compression, bytes: &[u8], cap: usize) -> Option<Cow<'_, [u8]>> {
+    match compression {
+        Compression::None => Some(Cow::Borrowed(bytes)),
+        #[cfg(feature = "compression")]
+        Compression::Zstd => zstd::bulk::decompress(bytes, cap).ok().map(Cow::Owned),
+        #[cfg(not(feature = "compression"))]
+        Compression::Zstd => {
+            let _ = cap;
+            None
+        }
+    }
+}
+
+#[cfg(not(feature = "verify-only"))]
+#[test]
+fn codec_caps_test() {
+    use k256::elliptic_curve::Field;
+    use k256::Scalar;
+    use crate::utilities::rng::ProtocolRng;
+
+    let mut codec = Codec::default();
+    let s = Scalar::random(&mut ProtocolRng);
+    let bytes = codec.encode(MessageKind::PartialSig, &s).unwrap();
+    assert_eq!(
+        codec
+            .decode::<Scalar>(MessageKind::PartialSig, &bytes)
+            .unwrap(),
+        s
+    );
+
+    let huge = vec![0u8; codec.caps.partial_sig + 1];
+    assert_eq!(
+        codec.decode::<Scalar>(MessageKind::PartialSig, &huge),
+        Err(MulEcdsaError::MessageTooLarge)
+    );
+    let stats = codec.received[&MessageKind::PartialSig];
+    assert_eq!(stats.count, 2);
+    assert_eq!(stats.max_bytes, huge.len());
+
+    // a length prefix claiming far more data than the cap allows
+    let mut codec = Codec::new(SizeCaps {
+        mta_first: 64,
+        ..SizeCaps::default()
+    });
+    let forged = bincode::serde::encode_to_vec(vec![0u8; 1 << 20], standard()).unwrap();
+    assert_eq!(
+        codec.decode::<Vec<u8>>(MessageKind::MtaFirst, &forged),
+        Err(MulEcdsaError::MessageTooLarge)
+    );
+    assert_eq!(
+        codec.decode::<Vec<u8>>(MessageKind::MtaFirst, &forged[..16]),
+        Err(MulEcdsaError::SerializeFailed)
+    );
+}
+
+#[test]
+fn codec_claim_limit_test() {
+    let mut codec = Codec::default();
+    // nine bytes announcing a string of 2^60 bytes
+    let mut forged = vec![0xfd];
+    forged.extend_from_slice(&(1u64 << 60).to_le_bytes());
+    assert_eq!(
+        codec.try_decode::<String>(MessageKind::Abort, &forged),
+        Err(DecodeError {
+            kind: MessageKind::Abort,
+            failure: DecodeFailure::ClaimTooLarge
+        })
+    );
+    assert_eq!(
+        codec.decode::<String>(MessageKind::Abort, &forged),
+        Err(MulEcdsaError::MessageTooLarge)
+    );
+
+    let mut bytes = codec.encode(MessageKind::Abort, &"abort".to_string()).unwrap();
+    bytes.push(0);
+    let err = codec.try_decode::<String>(MessageKind::Abort, &bytes).unwrap_err();
+    assert_eq!(err.failure, DecodeFailure::TrailingBytes);
+    assert_eq!(err.to_string(), "Abort message: trailing bytes");
+    assert_eq!(
+        codec.try_decode::<String>(MessageKind::Abort, &bytes[..3]).unwrap_err().failure,
+        DecodeFailure::Truncated
+    );
+}
+
+#[test]
+fn compression_negotiation_test() {
+    use crate::clock::{ManualClock, DEFAULT_ALLOWED_SKEW};
+
+    let clock = ManualClock::new(1_700_000_000);
+    let local = Hello::local(&clock);
+    let plain = Hello {
+        version: WIRE_VERSION,
+        compression: vec![Compression::None],
+        timestamp: clock.now() + DEFAULT_ALLOWED_SKEW,
+    };
+    assert_eq!(local.negotiate(&plain), Ok(Compression::None));
+    assert_eq!(plain.negotiate(&local), Ok(Compression::None));
+    let newer = Hello {
+        version: WIRE_VERSION + 1,
+        ..local.clone()
+    };
+    assert_eq!(local.negotiate(&newer), Err(MulEcdsaError::InvalidProtocolParams));
+
+    let mut codec = Codec::default();
+    let bytes = codec.encode(MessageKind::Hello, &local).unwrap();
+    assert_eq!(codec.decode::<Hello>(MessageKind::Hello, &bytes).unwrap(), local);
+    let stats = codec.sent[&MessageKind::Hello];
+    assert_eq!(stats.raw_bytes, stats.total_bytes);
+
+    plain.check_timestamp(&clock, DEFAULT_ALLOWED_SKEW).unwrap();
+    clock.advance(2 * DEFAULT_ALLOWED_SKEW + 1);
+    assert_eq!(
+        plain.check_timestamp(&clock, DEFAULT_ALLOWED_SKEW),
+        Err(MulEcdsaError::ClockSkew)
+    );
+    plain.check_timestamp(&clock, DEFAULT_ALLOWED_SKEW + 1).unwrap();
+}
+
+#[cfg(feature = "compression")]
+#[test]
+fn zstd_compression_test() {
+    let local = Hello::local(&crate::clock::SystemClock);
+    assert_eq!(local.negotiate(&local), Ok(Compression::Zstd));
+
+    let mut sender = Codec::default().with_compression(Compression::Zstd);
+    let mut receiver = Codec::default().with_compression(Compression::Zstd);
+    let msg = vec![7u8; 4096];
+    let bytes = sender.encode(MessageKind::MtaFirst, &msg).unwrap();
+    assert_eq!(receiver.decode::<Vec<u8>>(MessageKind::MtaFirst, &bytes).unwrap(), msg);
+    let stats = sender.sent[&MessageKind::MtaFirst];
+    assert!(stats.total_bytes < stats.raw_bytes);
+    assert_eq!(receiver.received[&MessageKind::MtaFirst], stats);
+    // aborts stay readable without the negotiation
+    let abort = sender.encode(MessageKind::Abort, &"abort".to_string()).unwrap();
+    assert_eq!(Codec::default().decode::<String>(MessageKind::Abort, &abort).unwrap(), "abort");
+
+    // a frame that inflates past the cap is rejected, not decompressed
+    let mut small = Codec::new(SizeCaps {
+        mta_first: 1024,
+        ..SizeCaps::default()
+    })
+    .with_compression(Compression::Zstd);
+    assert_eq!(
+        small.try_decode::<Vec<u8>>(MessageKind::MtaFirst, &bytes).unwrap_err().failure,
+        DecodeFailure::Malformed
+    );
+}
@@ -0,0 +1,201 @@
+//! One local share signing with several remote co-signers that hold the
+//! same counterparty share, such as a primary server and a hot standby.
+//! Each co-signer runs its own independent two-party session, so the same
+//! message may be in flight with all of them; a `CoSignerSet` makes sure
+//! only one of those sessions releases a result per message, for
+//! `release_ttl` after the first one did. Party one releases its final
+//! signature, party two its partial one, which is all a co-signer needs to
+//! finish. Share the set behind a `Mutex` between the sessions' threads.
+
+use crate::clock::{Clock, SystemClock};
+use crate::party_one;
+use crate::party_two;
+use crate::shared::KeyStore;
+use crate::utilities::error::MulEcdsaError;
+use crate::utilities::signature::Signature;
+use k256::Scalar;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+#[derive(Clone, Debug)]
+pub struct CoSignerSet {
+    pub key_store: KeyStore,
+    /// Names of the co-signers, such as their addresses.
+    co_signers: BTreeSet<String>,
+    /// Sessions in flight and when they started, by message hash.
+    sessions: BTreeMap<[u8; 32], BTreeMap<String, u64>>,
+    /// The co-signer whose session released the result and when, by
+    /// message hash.
+    released: BTreeMap<[u8; 32], (String, u64)>,
+    pub clock: Arc<dyn Clock>,
+    /// Seconds after which a session may no longer release its result.
+    pub session_timeout: Option<u64>,
+    /// Seconds a released message is remembered and refused; a day unless
+    /// set.
+    pub release_ttl: u64,
+}
+
+impl CoSignerSet {
+    pub fn new<I: IntoIterator<Item = String>>(key_store: KeyStore, co_signers: I) -> Result<Self, MulEcdsaError> {
+        key_store.validate()?;
+        Ok(Self {
+            key_store,
+            co_signers: co_signers.into_iter().collect(),
+            sessions: BTreeMap::new(),
+            released: BTreeMap::new(),
+            clock: Arc::new(SystemClock),
+            session_timeout: None,
+            release_ttl: 24 * 60 * 60,
+        })
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn with_session_timeout(mut self, secs: u64) -> Self {
+        self.session_timeout = Some(secs);
+        self
+    }
+
+    pub fn with_release_ttl(mut self, secs: u64) -> Self {
+        self.release_ttl = secs;
+        self
+    }
+
+    /// Starts a party one session with `co_signer` for `message_hash`,
+    /// bound to `session_id`.
+    pub fn party_one_sign(
+        &mut self,
+        co_signer: &str,
+        session_id: &[u8],
+        message_hash: &[u8; 32],
+    ) -> Result<party_one::Sign, MulEcdsaError> {
+        self.begin(co_signer, message_hash)?;
+        Ok(party_one::Sign::new(self.key_store.clone())?.for_session(session_id, message_hash))
+    }
+
+    /// Starts a party two session with `co_signer` for `message_hash`,
+    /// bound to `session_id`.
+    pub fn party_two_sign(
+        &mut self,
+        co_signer: &str,
+        session_id: &[u8],
+        message_hash: &[u8; 32],
+    ) -> Result<party_two::Sign, MulEcdsaError> {
+        self.begin(co_signer, message_hash)?;
+        Ok(party_two::Sign::new(self.key_store.clone())?.for_session(session_id, message_hash))
+    }
+
+    /// Party one's last step: the signature, unless another co-signer's
+    /// session released one for this message first.
+    pub fn finish_party_one(
+        &mut self,
+        co_signer: &str,
+        sign: &party_one::Sign,
+        s_2: &Scalar,
+        message_hash: &[u8; 32],
+    ) -> Result<Signature, MulEcdsaError> {
+        let signature = sign
+            .online_sign(s_2, message_hash)
+            .and_then(|signature| self.release(co_signer, message_hash).map(|_| signature));
+        self.end(co_signer, message_hash);
+        signature
+    }
+
+    /// Party two's last step: the partial signature, unless another
+    /// co-signer's session released one for this message first.
+    pub fn finish_party_two(
+        &mut self,
+        co_signer: &str,
+        sign: &mut party_two::Sign,
+        message_hash: &[u8; 32],
+    ) -> Result<Scalar, MulEcdsaError> {
+        let s_2 = sign
+            .online_sign(message_hash)
+            .and_then(|s_2| self.release(co_signer, message_hash).map(|_| s_2));
+        self.end(co_signer, message_hash);
+        s_2
+    }
+
+    /// Forgets a session that failed or was given up; the message can
+    /// still be signed with the other co-signers.
+    pub fn end(&mut self, co_signer: &str, message_hash: &[u8; 32]) {
+        if let Some(open) = self.sessions.get_mut(message_hash) {
+            open.remove(co_signer);
+            if open.is_empty() {
+                self.sessions.remove(message_hash);
+            }
+        }
+    }
+
+    /// The co-signers with a session in flight for `message_hash`.
+    pub fn sessions(&self, message_hash: &[u8; 32]) -> Vec<&str> {
+        self.sessions
+            .get(message_hash)
+            .map_or_else(Vec::new, |open| open.keys().map(String::as_str).collect())
+    }
+
+    /// Ends every session past the timeout; returns how many there were.
+    pub fn expire(&mut self) -> usize {
+        let before: usize = self.sessions.values().map(BTreeMap::len).sum();
+        let (now, timeout) = (self.clock.now(), self.session_timeout);
+        for open in self.sessions.values_mut() {
+            open.retain(|_, started| !is_expired(*started, now, timeout));
+        }
+        self.sessions.retain(|_, open| !open.is_empty());
+        before - self.sessions.values().map(BTreeMap::len).sum::<usize>()
+    }
+
+    /// The co-signer whose session released the result for `message_hash`.
+    pub fn released(&self, message_hash: &[u8; 32]) -> Option<&str> {
+        self.released.get(message_hash).map(|(co_signer, _)| co_signer.as_str())
+    }
+
+    /// Drops the released messages older than `release_ttl`, which may then
+    /// be signed again.
+    pub fn purge(&mut self) {
+        let (now, ttl) = (self.clock.now(), self.release_ttl);
+        self.released.retain(|_, (_, at)| now.saturating_sub(*at) < ttl);
+    }
+
+    fn begin(&mut self, co_signer: &str, message_hash: &[u8; 32]) -> Result<(), MulEcdsaError> {
+        self.purge();
+        if !self.co_signers.contains(co_signer) {
+            return Err(MulEcdsaError::CounterpartyMismatch);
+        }
+        if self.released.contains_key(message_hash) {
+            return Err(MulEcdsaError::SignatureConflict);
+        }
+        let now = self.clock.now();
+        self.sessions
+            .entry(*message_hash)
+            .or_default()
+            .insert(co_signer.to_string(), now);
+        Ok(())
+    }
+
+    /// The first session to get here for a message wins, unless it has
+    /// expired.
+    fn release(&mut self, co_signer: &str, message_hash: &[u8; 32]) -> Result<(), MulEcdsaError> {
+        let started = self
+            .sessions
+            .get(message_hash)
+            .and_then(|open| open.get(co_signer).copied())
+            .ok_or(MulEcdsaError::SignatureConflict)?;
+        if self.released.contains_key(message_hash) {
+            return Err(MulEcdsaError::SignatureConflict);
+        }
+        let now = self.clock.now();
+        if is_expired(started, now, self.session_timeout) {
+            return Err(MulEcdsaError::SessionExpired);
+        }
+        self.released.insert(*message_hash, (co_signer.to_string(), now));
+        Ok(())
+    }
+}
+
+fn is_expired(started: u64, now: u64, timeout: Option<u64>) -> bool {
+    timeout.is_some_and(|timeout| now.saturating_sub(started) >= timeout)
+}
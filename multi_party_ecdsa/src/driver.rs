@@ -0,0 +1,315 @@
+//! Bounded plumbing for a service running many sessions: a `SessionRouter`
+//! hands each received message to its session's inbox, and a `WorkerPool`
+//! runs the CL computation of all sessions on a fixed number of threads.
+//! Every queue has a capacity. A new session or job that does not fit is
+//! refused with `Overloaded` right away, while the rounds of sessions
+//! already running wait for room with `submit_blocking`, so a burst of
+//! sign requests is shed instead of piling up in memory. A `CryptoPool`
+//! keeps the class group work, which blocks for tens of milliseconds a
+//! step, off the threads serving the network.
+
+use crate::utilities::error::MulEcdsaError;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+type Job<J, R> = (J, SyncSender<R>);
+
+/// Runs `handler` on a fixed number of threads, taking jobs from a queue of
+/// fixed capacity. Dropping the pool finishes the queued jobs and joins the
+/// threads.
+pub struct WorkerPool<J, R> {
+    jobs: Option<SyncSender<Job<J, R>>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl<J: Send + 'static, R: Send + 'static> WorkerPool<J, R> {
+    pub fn new<F>(workers: usize, queue: usize, handler: F) -> Self
+    where
+        F: Fn(J) -> R + Send + Sync + 'static,
+    {
+        let (jobs, queued) = sync_channel::<Job<J, R>>(queue);
+        let queued = Arc::new(Mutex::new(queued));
+        let handler = Arc::new(handler);
+        let workers = (0..workers.max(1))
+            .map(|_| {
+                let queued = queued.clone();
+                let handler = handler.clone();
+                thread::spawn(move || loop {
+                    let job = match queued.lock() {
+                        Ok(queued) => queued.recv(),
+                        Err(_) => return,
+                    };
+                    match job {
+                        Ok((job, reply)) => {
+                            let _ = reply.send(handler(job));
+                        }
+                        Err(_) => return,
+                    }
+                })
+            })
+            .collect();
+        Self {
+            jobs: Some(jobs),
+            workers,
+        }
+    }
+
+    /// Queues `job` for a new session; `Overloaded` if the queue is full.
+    /// The result arrives on the returned receiver.
+    pub fn submit(&self, job: J) -> Result<Receiver<R>, MulEcdsaError> {
+        let (reply, result) = sync_channel(1);
+        self.sender()?.try_send((job, reply)).map_err(|e| match e {
+            TrySendError::Full(_) => MulEcdsaError::Overloaded,
+            TrySendError::Disconnected(_) => MulEcdsaError::TransportFailed,
+        })?;
+        Ok(result)
+    }
+
+    /// Queues `job` of a running session, waiting while the queue is full.
+    pub fn submit_blocking(&self, job: J) -> Result<Receiver<R>, MulEcdsaError> {
+        let (reply, result) = sync_channel(1);
+        self.sender()?
+            .send((job, reply))
+            .map_err(|_| MulEcdsaError::TransportFailed)?;
+        Ok(result)
+    }
+
+    fn sender(&self) -> Result<&SyncSender<Job<J, R>>, MulEcdsaError> {
+        self.jobs.as_ref().ok_or(MulEcdsaError::TransportFailed)
+    }
+}
+
+impl<J, R> Drop for WorkerPool<J, R> {
+    fn drop(&mut self) {
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// A `WorkerPool` of closures, for the blocking protocol steps of every
+/// session: the CL encryptions, decryptions and proofs of keygen, MtA and
+/// refresh. Its threads draw from `OsRng` even inside a `SeededRun`.
+pub struct CryptoPool {
+    pool: WorkerPool<Box<dyn FnOnce() + Send>, ()>,
+}
+
+impl CryptoPool {
+    /// `threads` workers sharing a queue of `queue` steps.
+    pub fn new(threads: usize, queue: usize) -> Self {
+        Self {
+            pool: WorkerPool::new(threads, queue, |step: Box<dyn FnOnce() + Send>| step()),
+        }
+    }
+
+    /// Runs `step` of a new session on the pool; `Overloaded` if the queue
+    /// is full.
+    pub fn run<T, F>(&self, step: F) -> Result<Receiver<T>, MulEcdsaError>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let (reply, result) = sync_channel(1);
+        self.pool.submit(Box::new(move || {
+            let _ = reply.send(step());
+        }))?;
+        Ok(result)
+    }
+
+    /// Runs `step` of a running session on the pool, waiting while the
+    /// queue is full.
+    pub fn run_blocking<T, F>(&self, step: F) -> Result<Receiver<T>, MulEcdsaError>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let (reply, result) = sync_channel(1);
+        self.pool.submit_blocking(Box::new(move || {
+            let _ = reply.send(step());
+        }))?;
+        Ok(result)
+    }
+
+    /// `run_blocking` for a session that may be cancelled: if `cancel` is
+    /// set before the pool gets to `step`, the step is dropped unrun and the
+    /// receiver gets `Cancelled`.
+    pub fn run_cancellable<T, F>(
+        &self,
+        cancel: &CancelToken,
+        step: F,
+    ) -> Result<Receiver<Result<T, MulEcdsaError>>, MulEcdsaError>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        cancel.check()?;
+        let cancel = cancel.clone();
+        self.run_blocking(move || cancel.check().map(|_| step()))
+    }
+}
+
+/// Shared between a session and the pool steps it queued.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// `Cancelled` once `cancel` was called.
+    pub fn check(&self) -> Result<(), MulEcdsaError> {
+        match self.is_cancelled() {
+            true => Err(MulEcdsaError::Cancelled),
+            false => Ok(()),
+        }
+    }
+}
+
+impl Default for CryptoPool {
+    /// A thread per core, each with four steps queued.
+    fn default() -> Self {
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        Self::new(threads, 4 * threads)
+    }
+}
+
+/// Received messages by session id, each session with an inbox of fixed
+/// capacity, and at most `max_sessions` sessions at a time.
+#[derive(Debug)]
+pub struct SessionRouter<M> {
+    pub max_sessions: usize,
+    /// Messages a session may have waiting.
+    pub inbox: usize,
+    sessions: HashMap<[u8; 32], SyncSender<M>>,
+}
+
+impl<M> SessionRouter<M> {
+    pub fn new(max_sessions: usize, inbox: usize) -> Self {
+        Self {
+            max_sessions,
+            inbox,
+            sessions: HashMap::new(),
+        }
+    }
+
+    /// The inbox of a new session; `Overloaded` when `max_sessions` are
+    /// open. Opening an id again replaces its session, whose inbox then
+    /// disconnects.
+    pub fn open(&mut self, session: [u8; 32]) -> Result<Receiver<M>, MulEcdsaError> {
+        if !self.sessions.contains_key(&session) && self.sessions.len() >= self.max_sessions {
+            return Err(MulEcdsaError::Overloaded);
+        }
+        let (sender, inbox) = sync_channel(self.inbox);
+        self.sessions.insert(session, sender);
+        Ok(inbox)
+    }
+
+    /// Queues `msg` for its session without waiting. A full inbox means the
+    /// peer is ahead of the protocol or the session is stuck: `Overloaded`,
+    /// and the message is dropped.
+    pub fn route(&mut self, session: &[u8; 32], msg: M) -> Result<(), MulEcdsaError> {
+        let sender = self.sessions.get(session).ok_or(MulEcdsaError::UnknownSession)?;
+        match sender.try_send(msg) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => Err(MulEcdsaError::Overloaded),
+            Err(TrySendError::Disconnected(_)) => {
+                self.sessions.remove(session);
+                Err(MulEcdsaError::UnknownSession)
+            }
+        }
+    }
+
+    pub fn close(&mut self, session: &[u8; 32]) {
+        self.sessions.remove(session);
+    }
+
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+}
+
+#[test]
+fn worker_pool_test() {
+    // the only worker blocks until released, so the queue fills up
+    let (release, gate) = sync_channel::<()>(0);
+    let gate = Mutex::new(gate);
+    let pool = WorkerPool::new(1, 1, move |n: u64| {
+        gate.lock().unwrap().recv().unwrap();
+        n * 2
+    });
+    let first = pool.submit(1).unwrap();
+    // wait until the worker took the first job off the queue
+    while pool.submit(0).map(drop).is_err() {
+        thread::yield_now();
+    }
+    assert_eq!(pool.submit(3).unwrap_err(), MulEcdsaError::Overloaded);
+    release.send(()).unwrap();
+    assert_eq!(first.recv().unwrap(), 2);
+    release.send(()).unwrap();
+    let third = pool.submit_blocking(3).unwrap();
+    release.send(()).unwrap();
+    assert_eq!(third.recv().unwrap(), 6);
+}
+
+#[test]
+fn session_router_test() {
+    let mut router = SessionRouter::new(2, 1);
+    let a = router.open([1; 32]).unwrap();
+    let _b = router.open([2; 32]).unwrap();
+    assert_eq!(router.open([3; 32]).unwrap_err(), MulEcdsaError::Overloaded);
+
+    router.route(&[1; 32], 7u8).unwrap();
+    assert_eq!(router.route(&[1; 32], 8), Err(MulEcdsaError::Overloaded));
+    assert_eq!(a.recv().unwrap(), 7);
+    router.route(&[1; 32], 9).unwrap();
+    assert_eq!(router.route(&[3; 32], 1), Err(MulEcdsaError::UnknownSession));
+
+    // a session whose task is gone frees its slot
+    drop(a);
+    router.close(&[2; 32]);
+    assert_eq!(router.route(&[1; 32], 1), Err(MulEcdsaError::UnknownSession));
+    assert!(router.is_empty());
+    router.open([3; 32]).unwrap();
+}
+
+#[test]
+fn cancel_token_test() {
+    // the only worker is busy until released, so the second step waits
+    let pool = CryptoPool::new(1, 2);
+    let (release, gate) = sync_channel::<()>(0);
+    let busy = pool.run(move || gate.recv().unwrap()).unwrap();
+    let cancel = CancelToken::new();
+    let step = pool.run_cancellable(&cancel, || 42).unwrap();
+    cancel.cancel();
+    release.send(()).unwrap();
+    busy.recv().unwrap();
+    assert_eq!(step.recv().unwrap(), Err(MulEcdsaError::Cancelled));
+    assert_eq!(
+        pool.run_cancellable(&cancel, || 42).unwrap_err(),
+        MulEcdsaError::Cancelled
+    );
+    assert_eq!(
+        pool.run_cancellable(&CancelToken::new(), || 42)
+            .unwrap()
+            .recv()
+            .unwrap(),
+        Ok(42)
+    );
+}
@@ -0,0 +1,333 @@
+//! Feeds arbitrary but well-typed messages into the round handlers of
+//! party one and party two. Scalars, points, integers and class group
+//! elements are built from the fuzzer's bytes, zero, the identity and
+//! forms of the wrong discriminant included; a handler may reject any of
+//! them, but must not panic. `fuzz/` holds a cargo-fuzz target over
+//! `run_rounds`, and `fuzz_rounds_test` runs a batch of random inputs.
+
+use crate::mta::{self, MTASecondRoundMsg};
+use crate::party_one;
+use crate::party_two;
+use crate::shared::{KeyGenPartyOneMsg, KeyGenPartyTwoMsg, KeyStore, MtaConsistencyMsg, NonceKEMsg};
+use crate::utilities::cl_dl_proof::CLDLProof;
+use crate::utilities::cl_key_proof::ClKeyProof;
+use crate::utilities::cl_proof::{CLProof, CLState, MTAFirstRoundMsg};
+use crate::utilities::class_group::{CLGroup, Ciphertext};
+use crate::utilities::dl_com_zk::{CommWitness, DLCommitments};
+use crate::utilities::equality_proof::EqualityProof;
+use crate::utilities::k256_helpers::{scalar_mod_order, DLogProof};
+use crate::utilities::mta_wc_proof::MtAwcProof;
+use arbitrary::{Arbitrary, Result, Unstructured};
+use classgroup::gmp::mpz::Mpz;
+use classgroup::gmp_classgroup::GmpClassGroup;
+use k256::{ProjectivePoint, Scalar};
+use num_bigint::{BigInt, Sign};
+use std::sync::OnceLock;
+
+/// One message for one handler, applied to the parties in the state the
+/// previous rounds left them in.
+#[derive(Clone, Debug)]
+pub enum Round {
+    /// `party_one::Sign::get_nonce_com`
+    PartyOneNonceCom(DLCommitments),
+    /// `mta::PartyOne::handle_receive_msg`
+    PartyOneMtaSecond(Ciphertext),
+    /// `mta::PartyOne::handle_receive_msg_wc`, against the first round
+    /// party one sent.
+    PartyOneMtaSecondWc(Box<MTASecondRoundMsg>, ProjectivePoint),
+    /// `party_one::Sign::verify_nonce_ke_msg`
+    PartyOneNonceKe(CommWitness),
+    /// `party_one::Sign::online_sign`
+    PartyOneSign(Scalar, Vec<u8>),
+    /// `party_one::KeyGen::verify_send_key_msg`
+    PartyOneKeyGen(KeyGenPartyTwoMsg),
+    /// `mta::PartyTwo::receive_and_send_msg`
+    PartyTwoMtaFirst(Box<MTAFirstRoundMsg>),
+    /// `party_two::Sign::verify_generate_mta_consistency`
+    PartyTwoMtaConsistency(Scalar, MtaConsistencyMsg),
+    /// `party_two::Sign::verify_send_nonce_ke_msg`
+    PartyTwoNonceKe(NonceKEMsg),
+    /// `party_two::Sign::online_sign`
+    PartyTwoSign(Vec<u8>),
+    /// `party_two::KeyGen::get_key_com_send_key_msg`, then `verify_key_msg`.
+    PartyTwoKeyGen(DLCommitments, Box<KeyGenPartyOneMsg>),
+}
+
+/// Both parties at the start of a session, with a fixed CL key pair.
+#[derive(Clone, Debug)]
+pub struct Parties {
+    pub party_one_sign: party_one::Sign,
+    pub party_two_sign: party_two::Sign,
+    pub mta_party_one: mta::PartyOne,
+    pub mta_party_two: mta::PartyTwo,
+    pub first_round: MTAFirstRoundMsg,
+    pub party_one_keygen: party_one::KeyGen,
+    pub party_two_keygen: party_two::KeyGen,
+}
+
+impl Parties {
+    /// Built once; the CL key generation dominates a single round.
+    pub fn fixture() -> Self {
+        static FIXTURE: OnceLock<Parties> = OnceLock::new();
+        FIXTURE
+            .get_or_init(|| {
+                let x1 = Scalar::from(3u64);
+                let x2 = Scalar::from(5u64);
+                let p1 = ProjectivePoint::GENERATOR * x1;
+                let p2 = ProjectivePoint::GENERATOR * x2;
+                let party_one_sign =
+                    party_one::Sign::new(KeyStore::new(x1, p2, p1 + p2).expect("valid key store")).expect("new");
+                let party_two_sign =
+                    party_two::Sign::new(KeyStore::new(x2, p1, p1 + p2).expect("valid key store")).expect("new");
+                let mta_party_one = mta::PartyOne::new(party_one_sign.reshared_secret_share);
+                let first_round = mta_party_one.generate_send_msg(&mta_party_one.cl_pub_key);
+                Parties {
+                    mta_party_two: mta::PartyTwo::new(party_two_sign.nonce_secret_share),
+                    party_one_sign,
+                    party_two_sign,
+                    mta_party_one,
+                    first_round,
+                    party_one_keygen: party_one::KeyGen::new(),
+                    party_two_keygen: party_two::KeyGen::new(),
+                }
+            })
+            .clone()
+    }
+
+    /// Hands `round` to its handler; whether it accepts is of no concern.
+    pub fn apply(&mut self, round: &Round) {
+        match round {
+            Round::PartyOneNonceCom(msg) => self.party_one_sign.get_nonce_com(msg),
+            Round::PartyOneMtaSecond(c_a) => {
+                let cl_priv_key = self.mta_party_one.cl_priv_key.clone();
+                let _ = self.mta_party_one.handle_receive_msg(&cl_priv_key, c_a);
+            }
+            Round::PartyOneMtaSecondWc(msg, a_pub) => {
+                let cl_priv_key = self.mta_party_one.cl_priv_key.clone();
+                let _ = self
+                    .mta_party_one
+                    .handle_receive_msg_wc(&cl_priv_key, &self.first_round.state, msg, a_pub);
+            }
+            Round::PartyOneNonceKe(witness) => {
+                let _ = self.party_one_sign.verify_nonce_ke_msg(witness);
+            }
+            Round::PartyOneSign(s_2, message) => {
+                let _ = self.party_one_sign.online_sign(s_2, message);
+            }
+            Round::PartyOneKeyGen(msg) => {
+                let _ = self.party_one_keygen.verify_send_key_msg(msg);
+            }
+            Round::PartyTwoMtaFirst(msg) => {
+                let _ = self.mta_party_two.receive_and_send_msg(*msg.clone());
+            }
+            Round::PartyTwoMtaConsistency(t_b, msg) => {
+                let _ = self.party_two_sign.verify_generate_mta_consistency(*t_b, msg);
+            }
+            Round::PartyTwoNonceKe(msg) => {
+                let _ = self.party_two_sign.verify_send_nonce_ke_msg(msg);
+            }
+            Round::PartyTwoSign(message) => {
+                let _ = self.party_two_sign.online_sign(message);
+            }
+            Round::PartyTwoKeyGen(key_com, msg) => {
+                self.party_two_keygen.get_key_com_send_key_msg(key_com);
+                let _ = self.party_two_keygen.verify_key_msg(msg);
+            }
+        }
+    }
+}
+
+/// The fuzz target: rounds decoded from `data`, applied to fresh parties.
+pub fn run_rounds(data: &[u8]) {
+    let mut u = Unstructured::new(data);
+    let Ok(rounds) = u
+        .arbitrary_iter::<Round>()
+        .and_then(|rounds| rounds.collect::<Result<Vec<_>>>())
+    else {
+        return;
+    };
+    let mut parties = Parties::fixture();
+    for round in &rounds {
+        parties.apply(round);
+    }
+}
+
+impl<'a> Arbitrary<'a> for Round {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(match u.int_in_range(0..=10)? {
+            0 => Round::PartyOneNonceCom(dl_commitments(u)?),
+            1 => Round::PartyOneMtaSecond(ciphertext(u)?),
+            2 => Round::PartyOneMtaSecondWc(
+                Box::new(MTASecondRoundMsg {
+                    c_a: ciphertext(u)?,
+                    proof: MtAwcProof {
+                        t1: element(u)?,
+                        t2: element(u)?,
+                        t3: point(u)?,
+                        z_a: mpz(u)?,
+                        z_alpha: mpz(u)?,
+                        z_rho: mpz(u)?,
+                    },
+                }),
+                point(u)?,
+            ),
+            3 => Round::PartyOneNonceKe(comm_witness(u)?),
+            4 => Round::PartyOneSign(scalar(u)?, u.arbitrary()?),
+            5 => Round::PartyOneKeyGen(KeyGenPartyTwoMsg {
+                public_share: point(u)?,
+                dl_proof: dlog_proof(u)?,
+            }),
+            6 => Round::PartyTwoMtaFirst(Box::new(MTAFirstRoundMsg {
+                proof: CLProof {
+                    t1: element(u)?,
+                    t2: element(u)?,
+                    u1: mpz(u)?,
+                    u2: mpz(u)?,
+                },
+                state: CLState {
+                    cipher: ciphertext(u)?,
+                    cl_pub_key: element(u)?,
+                },
+            })),
+            7 => Round::PartyTwoMtaConsistency(
+                scalar(u)?,
+                MtaConsistencyMsg {
+                    reshared_public_share: point(u)?,
+                    r1: scalar(u)?,
+                    cc: scalar(u)?,
+                    public_key: point(u)?,
+                },
+            ),
+            8 => Round::PartyTwoNonceKe(NonceKEMsg {
+                nonce_public_key: point(u)?,
+                dl_proof: dlog_proof(u)?,
+            }),
+            9 => Round::PartyTwoSign(u.arbitrary()?),
+            _ => Round::PartyTwoKeyGen(
+                dl_commitments(u)?,
+                Box::new(KeyGenPartyOneMsg {
+                    witness: comm_witness(u)?,
+                    cl_pub_key: element(u)?,
+                    cl_key_proof: ClKeyProof {
+                        t: element(u)?,
+                        z: mpz(u)?,
+                    },
+                    encrypted_share: ciphertext(u)?,
+                    cldl_proof: CLDLProof {
+                        proof: EqualityProof {
+                            t1: element(u)?,
+                            t2: element(u)?,
+                            t3: point(u)?,
+                            u1: mpz(u)?,
+                            u2: mpz(u)?,
+                        },
+                    },
+                }),
+            ),
+        })
+    }
+}
+
+/// Any scalar, zero and small values more often than chance would give.
+fn scalar(u: &mut Unstructured) -> Result<Scalar> {
+    Ok(match u.int_in_range(0..=3)? {
+        0 => Scalar::ZERO,
+        1 => Scalar::from(u.arbitrary::<u64>()?),
+        _ => scalar_mod_order(&BigInt::from_bytes_be(Sign::Plus, &u.arbitrary::<[u8; 32]>()?)),
+    })
+}
+
+/// A multiple of the generator, the identity when the scalar is zero.
+fn point(u: &mut Unstructured) -> Result<ProjectivePoint> {
+    Ok(ProjectivePoint::GENERATOR * scalar(u)?)
+}
+
+fn bigint(u: &mut Unstructured) -> Result<BigInt> {
+    let sign = if u.arbitrary()? { Sign::Minus } else { Sign::Plus };
+    Ok(BigInt::from_bytes_be(sign, u.arbitrary::<&[u8]>()?))
+}
+
+fn mpz(u: &mut Unstructured) -> Result<Mpz> {
+    let magnitude = Mpz::from(u.arbitrary::<&[u8]>()?);
+    Ok(if u.arbitrary()? { -magnitude } else { magnitude })
+}
+
+/// A valid element of the group, or `(a, b, c)` for the group's
+/// discriminant or another one, reduced or not.
+fn element(u: &mut Unstructured) -> Result<GmpClassGroup> {
+    static GQ: OnceLock<GmpClassGroup> = OnceLock::new();
+    let gq = || GQ.get_or_init(|| CLGroup::new().gq).clone();
+    Ok(match u.int_in_range(0..=2)? {
+        0 => gq(),
+        1 => GmpClassGroup {
+            a: mpz(u)?,
+            b: mpz(u)?,
+            c: mpz(u)?,
+            discriminant: gq().discriminant,
+        },
+        _ => GmpClassGroup {
+            a: mpz(u)?,
+            b: mpz(u)?,
+            c: mpz(u)?,
+            discriminant: mpz(u)?,
+        },
+    })
+}
+
+fn ciphertext(u: &mut Unstructured) -> Result<Ciphertext> {
+    Ok(Ciphertext {
+        c1: element(u)?,
+        c2: element(u)?,
+    })
+}
+
+fn dlog_proof(u: &mut Unstructured) -> Result<DLogProof<ProjectivePoint>> {
+    Ok(DLogProof {
+        pk_t_rand_commitment: point(u)?,
+        challenge_response: scalar(u)?,
+    })
+}
+
+fn dl_commitments(u: &mut Unstructured) -> Result<DLCommitments> {
+    Ok(DLCommitments {
+        pk_commitment: bigint(u)?,
+        zk_pok_commitment: bigint(u)?,
+    })
+}
+
+fn comm_witness(u: &mut Unstructured) -> Result<CommWitness> {
+    Ok(CommWitness {
+        pk_commitment_blind_factor: bigint(u)?,
+        zk_pok_blind_factor: bigint(u)?,
+        public_share: point(u)?,
+        d_log_proof: dlog_proof(u)?,
+    })
+}
+
+#[test]
+fn fuzz_rounds_test() {
+    use crate::utilities::rng::ProtocolRng;
+    use rand::RngCore;
+
+    for _ in 0..200 {
+        let mut data = vec![0u8; 1024];
+        ProtocolRng.fill_bytes(&mut data);
+        run_rounds(&data);
+    }
+
+    // a reduced form of another discriminant used to reach the class group
+    // arithmetic, which assumes matching discriminants
+    let mut parties = Parties::fixture();
+    let mut c_a = parties.first_round.state.cipher.clone();
+    c_a.c1 = GmpClassGroup {
+        a: Mpz::from(1u64),
+        b: Mpz::from(1u64),
+        c: Mpz::from(1u64),
+        discriminant: -Mpz::from(3u64),
+    };
+    let cl_priv_key = parties.mta_party_one.cl_priv_key.clone();
+    assert_eq!(
+        parties.mta_party_one.handle_receive_msg(&cl_priv_key, &c_a),
+        Err(crate::utilities::error::MulEcdsaError::VrfyClassGroupFailed)
+    );
+}
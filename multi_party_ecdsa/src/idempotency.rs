@@ -0,0 +1,119 @@
+//! Idempotent signing requests. A client names each request with an id of
+//! its choosing; submitting the same id and message again returns the
+//! signature of the first run instead of starting another session, which
+//! would consume a nonce and could lead to a second broadcast of the same
+//! transaction.
+
+use crate::clock::{Clock, SystemClock};
+use crate::utilities::error::MulEcdsaError;
+use crate::utilities::signature::Signature;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RequestState {
+    /// A session is running.
+    Running,
+    Signed(Box<Signature>),
+}
+
+#[derive(Clone, Debug)]
+struct Entry {
+    message_hash: Vec<u8>,
+    state: RequestState,
+    /// When the request was first seen.
+    at: u64,
+}
+
+/// Requests by id. Share it behind a `Mutex` between the sessions.
+#[derive(Clone, Debug)]
+pub struct RequestCache {
+    entries: HashMap<String, Entry>,
+    pub clock: Arc<dyn Clock>,
+    /// Seconds a request id is remembered; a day unless set.
+    pub ttl: u64,
+}
+
+impl Default for RequestCache {
+    fn default() -> Self {
+        Self {
+            entries: HashMap::new(),
+            clock: Arc::new(SystemClock),
+            ttl: 24 * 60 * 60,
+        }
+    }
+}
+
+impl RequestCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn with_ttl(mut self, secs: u64) -> Self {
+        self.ttl = secs;
+        self
+    }
+
+    /// Call before starting a session for `request_id`. `None` means go
+    /// ahead and report back with `complete` or `abandon`; a signature means
+    /// the request was signed before. `RequestInProgress` if a session for
+    /// it is still running, `RequestIdReused` if the id came with another
+    /// message.
+    pub fn begin(&mut self, request_id: &str, message_hash: &[u8]) -> Result<Option<Signature>, MulEcdsaError> {
+        self.purge();
+        match self.entries.get(request_id) {
+            Some(entry) if entry.message_hash != message_hash => Err(MulEcdsaError::RequestIdReused),
+            Some(Entry {
+                state: RequestState::Running,
+                ..
+            }) => Err(MulEcdsaError::RequestInProgress),
+            Some(Entry {
+                state: RequestState::Signed(signature),
+                ..
+            }) => Ok(Some(signature.as_ref().clone())),
+            None => {
+                let entry = Entry {
+                    message_hash: message_hash.to_vec(),
+                    state: RequestState::Running,
+                    at: self.clock.now(),
+                };
+                self.entries.insert(request_id.to_string(), entry);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Records the signature of a request started with `begin`.
+    pub fn complete(&mut self, request_id: &str, signature: &Signature) -> Result<(), MulEcdsaError> {
+        match self.entries.get_mut(request_id) {
+            Some(entry) if entry.state == RequestState::Running => {
+                entry.state = RequestState::Signed(Box::new(signature.clone()));
+                Ok(())
+            }
+            _ => Err(MulEcdsaError::UnknownSession),
+        }
+    }
+
+    /// Forgets a request whose session failed, so that it can be retried.
+    pub fn abandon(&mut self, request_id: &str) {
+        if matches!(self.entries.get(request_id), Some(entry) if entry.state == RequestState::Running) {
+            self.entries.remove(request_id);
+        }
+    }
+
+    pub fn state(&self, request_id: &str) -> Option<&RequestState> {
+        self.entries.get(request_id).map(|entry| &entry.state)
+    }
+
+    /// Drops the requests older than `ttl`, except running ones.
+    pub fn purge(&mut self) {
+        let (now, ttl) = (self.clock.now(), self.ttl);
+        self.entries
+            .retain(|_, entry| entry.state == RequestState::Running || now.saturating_sub(entry.at) < ttl);
+    }
+}
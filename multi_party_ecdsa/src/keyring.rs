@@ -0,0 +1,117 @@
+//! Many joint keys in one process. A `KeyRing` maps each key's id, the
+//! fingerprint of its joint public key, to its store, who holds the other
+//! share and its refresh policy, and hands out signing sessions for one key
+//! at a time. It serializes as a whole for persistence.
+
+use crate::party_one;
+use crate::party_two;
+use crate::shared::{KeyStore, RefreshPolicy};
+use crate::utilities::error::MulEcdsaError;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// `KeyStore::public_key_fingerprint` of the key.
+pub type KeyId = [u8; 32];
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct KeyRingEntry {
+    pub key_store: KeyStore,
+    /// Free-form description of the other share's holder, such as its
+    /// address or customer id.
+    pub counterparty: String,
+    pub policy: RefreshPolicy,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct KeyRing {
+    entries: BTreeMap<KeyId, KeyRingEntry>,
+}
+
+impl KeyRing {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a key, replacing the entry of the same key if there is one.
+    pub fn insert(&mut self, entry: KeyRingEntry) -> KeyId {
+        let id = entry.key_store.public_key_fingerprint();
+        self.entries.insert(id, entry);
+        id
+    }
+
+    pub fn get(&self, id: &KeyId) -> Result<&KeyRingEntry, MulEcdsaError> {
+        self.entries.get(id).ok_or(MulEcdsaError::UnknownKey)
+    }
+
+    pub fn get_mut(&mut self, id: &KeyId) -> Result<&mut KeyRingEntry, MulEcdsaError> {
+        self.entries.get_mut(id).ok_or(MulEcdsaError::UnknownKey)
+    }
+
+    /// Ids of all keys, in order.
+    pub fn list_keys(&self) -> Vec<KeyId> {
+        self.entries.keys().copied().collect()
+    }
+
+    pub fn delete_key(&mut self, id: &KeyId) -> Result<KeyRingEntry, MulEcdsaError> {
+        self.entries.remove(id).ok_or(MulEcdsaError::UnknownKey)
+    }
+
+    /// A party one session for key `id`, under the key's refresh policy.
+    pub fn party_one_sign(&self, id: &KeyId, now: u64) -> Result<party_one::Sign, MulEcdsaError> {
+        let entry = self.get(id)?;
+        party_one::Sign::with_refresh_policy(entry.key_store.clone(), &entry.policy, now)
+    }
+
+    /// A party two session for key `id`, under the key's refresh policy.
+    pub fn party_two_sign(&self, id: &KeyId, now: u64) -> Result<party_two::Sign, MulEcdsaError> {
+        let entry = self.get(id)?;
+        party_two::Sign::with_refresh_policy(entry.key_store.clone(), &entry.policy, now)
+    }
+
+    /// Counts a completed signature with key `id`.
+    pub fn record_signature(&mut self, id: &KeyId) -> Result<(), MulEcdsaError> {
+        self.get_mut(id)?.key_store.record_signature();
+        Ok(())
+    }
+}
+
+#[test]
+fn key_ring_test() {
+    use crate::utilities::rng::ProtocolRng;
+    use bincode::config::standard;
+    use k256::elliptic_curve::Field;
+    use k256::{ProjectivePoint, Scalar};
+
+    let entry = |counterparty: &str, policy| {
+        let (x1, x2) = (Scalar::random(&mut ProtocolRng), Scalar::random(&mut ProtocolRng));
+        let p2 = ProjectivePoint::GENERATOR * x2;
+        KeyRingEntry {
+            key_store: KeyStore::new(x1, p2, ProjectivePoint::GENERATOR * x1 + p2).unwrap(),
+            counterparty: counterparty.to_string(),
+            policy,
+        }
+    };
+    let mut ring = KeyRing::new();
+    let a = ring.insert(entry("customer-a", RefreshPolicy::default().with_max_signatures(1)));
+    let b = ring.insert(entry("customer-b", RefreshPolicy::default()));
+    let mut ids = vec![a, b];
+    ids.sort();
+    assert_eq!(ring.list_keys(), ids);
+
+    let now = crate::shared::unix_time();
+    ring.party_one_sign(&a, now).unwrap();
+    ring.record_signature(&a).unwrap();
+    assert_eq!(ring.party_one_sign(&a, now).unwrap_err(), MulEcdsaError::RefreshRequired);
+    // the other key is routed separately
+    ring.party_one_sign(&b, now).unwrap();
+
+    let bytes = bincode::serde::encode_to_vec(&ring, standard()).unwrap();
+    let (mut ring, _): (KeyRing, usize) = bincode::serde::decode_from_slice(&bytes, standard()).unwrap();
+    assert_eq!(ring.get(&a).unwrap().counterparty, "customer-a");
+    assert_eq!(ring.get(&a).unwrap().key_store.usage.signatures, 1);
+
+    assert_eq!(ring.delete_key(&b).unwrap().counterparty, "customer-b");
+    assert_eq!(ring.list_keys(), vec![a]);
+    assert_eq!(ring.party_two_sign(&b, now).unwrap_err(), MulEcdsaError::UnknownKey);
+    assert_eq!(ring.delete_key(&b).unwrap_err(), MulEcdsaError::UnknownKey);
+}
@@ -1,8 +1,47 @@
+#[cfg(not(any(feature = "rng", feature = "verify-only")))]
+compile_error!("the parties and provers need the `rng` feature; only a `verify-only` build can go without it");
+
 pub mod utilities;
+pub mod authorization;
+pub mod ceremony;
+pub mod clock;
+pub mod codec;
+#[cfg(not(feature = "verify-only"))]
+pub mod cosigner;
+pub mod driver;
+#[cfg(all(any(test, feature = "fuzzing"), not(feature = "verify-only")))]
+pub mod fuzz;
+pub mod idempotency;
+#[cfg(not(feature = "verify-only"))]
+pub mod keyring;
+#[cfg(not(feature = "verify-only"))]
+pub mod migration;
 pub mod mta;
+#[cfg(not(feature = "verify-only"))]
+pub mod nonce_registry;
+pub mod observer;
+pub mod offline;
+#[cfg(not(feature = "verify-only"))]
+pub mod options;
+#[cfg(all(any(test, feature = "test-utils"), not(feature = "verify-only")))]
+pub mod malicious;
+#[cfg(not(feature = "verify-only"))]
 pub mod party_one;
+#[cfg(not(feature = "verify-only"))]
 pub mod party_two;
+#[cfg(not(feature = "verify-only"))]
+pub mod presign;
+#[cfg(not(feature = "verify-only"))]
+pub mod refresh;
+#[cfg(not(feature = "verify-only"))]
+pub mod secret_store;
+#[cfg(not(feature = "verify-only"))]
+pub mod self_test;
 pub mod shared;
+#[cfg(not(feature = "verify-only"))]
+pub mod simulation;
+pub mod tweak;
+pub mod watcher;
 
-#[cfg(test)]
-mod test;
\ No newline at end of file
+#[cfg(all(test, not(feature = "verify-only")))]
+mod test;
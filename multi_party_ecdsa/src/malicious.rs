@@ -0,0 +1,275 @@
+//! Deviating parties for negative tests. Each wrapper runs the honest party
+//! and then applies the deviations switched on in its knobs to the messages
+//! it sends. A correct counterparty must reject every one of them.
+
+use crate::mta;
+use crate::party_one;
+use crate::party_two;
+use crate::shared::*;
+use crate::utilities::class_group::*;
+use crate::utilities::cl_proof::MTAFirstRoundMsg;
+use crate::utilities::dl_com_zk::*;
+use crate::utilities::error::MulEcdsaError;
+use crate::utilities::k256_helpers::DLogProof;
+use classgroup::gmp_classgroup::GmpClassGroup;
+use k256::elliptic_curve::Field;
+use k256::{ProjectivePoint, Scalar};
+use crate::utilities::rng::ProtocolRng;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PartyOneKnobs {
+    /// Offset `cc` in the MtA consistency message.
+    pub wrong_mta_consistency: bool,
+    /// Send a DLog proof for a point other than the announced nonce.
+    pub wrong_nonce_proof: bool,
+    /// Push the CL proof response u1 past its range bound.
+    pub out_of_range_cl_proof: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct PartyOne {
+    pub sign: party_one::Sign,
+    pub knobs: PartyOneKnobs,
+}
+
+impl PartyOne {
+    pub fn new(sign: party_one::Sign, knobs: PartyOneKnobs) -> Self {
+        Self { sign, knobs }
+    }
+
+    /// Reuse the nonce of an earlier session. Neither party can detect this
+    /// within one session; it exercises external nonce registries.
+    pub fn reuse_nonce_from(&mut self, earlier: &party_one::Sign) {
+        self.sign.nonce_secret_share = earlier.nonce_secret_share;
+        self.sign.nonce_public_share = earlier.nonce_public_share;
+        self.sign.dl_proof =
+            DLogProof::prove_in_context(&earlier.nonce_secret_share, &self.sign.session_context);
+    }
+
+    pub fn generate_mta_first_round(
+        &self,
+        mta: &mta::PartyOne,
+        group: &CLGroup,
+        cl_pub_key: &GmpClassGroup,
+    ) -> MTAFirstRoundMsg {
+        let mut msg = mta.generate_send_msg(cl_pub_key);
+        if self.knobs.out_of_range_cl_proof {
+            let bound = group
+                .params
+                .proof_response_bound(&mpz_to_bigint(group.stilde.clone()));
+            msg.proof.u1 = msg.proof.u1.clone() + &bigint_to_mpz(bound);
+        }
+        msg
+    }
+
+    pub fn generate_mta_consistency(&self, t_b: Scalar) -> MtaConsistencyMsg {
+        let mut msg = self.sign.generate_mta_consistency(t_b);
+        if self.knobs.wrong_mta_consistency {
+            msg.cc += Scalar::ONE;
+        }
+        msg
+    }
+
+    pub fn generate_nonce_ke_msg(&mut self) -> NonceKEMsg {
+        let mut msg = self.sign.generate_nonce_ke_msg();
+        if self.knobs.wrong_nonce_proof {
+            msg.dl_proof =
+                DLogProof::prove_in_context(&Scalar::random(&mut ProtocolRng), &self.sign.session_context);
+        }
+        msg
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PartyTwoKnobs {
+    /// Commit to one nonce and open another.
+    pub wrong_commitment: bool,
+    /// Run the MtA on a scalar other than the committed nonce share.
+    pub wrong_mta_input: bool,
+    /// Push the MtAwc response z_a past its range bound.
+    pub out_of_range_mta_wc_proof: bool,
+    /// Offset the partial signature.
+    pub wrong_partial_signature: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct PartyTwo {
+    pub sign: party_two::Sign,
+    pub knobs: PartyTwoKnobs,
+}
+
+impl PartyTwo {
+    pub fn new(sign: party_two::Sign, knobs: PartyTwoKnobs) -> Self {
+        Self { sign, knobs }
+    }
+
+    /// See `PartyOne::reuse_nonce_from`.
+    pub fn reuse_nonce_from(&mut self, earlier: &party_two::Sign) {
+        self.sign.nonce_secret_share = earlier.nonce_secret_share;
+        self.sign.nonce_public_share = earlier.nonce_public_share;
+        self.sign.dl_com_zk_com = earlier.dl_com_zk_com.clone();
+    }
+
+    pub fn generate_nonce_com(&self) -> DLCommitments {
+        match self.knobs.wrong_commitment {
+            true => {
+                let other = Scalar::random(&mut ProtocolRng);
+                DLComZK::new(
+                    &other,
+                    &(ProjectivePoint::GENERATOR * other),
+                    &self.sign.dl_com_zk_com.context,
+                )
+                .commitments
+            }
+            false => self.sign.generate_nonce_com(),
+        }
+    }
+
+    /// The MtA responder for this party's nonce share.
+    pub fn mta_party(&self) -> mta::PartyTwo {
+        match self.knobs.wrong_mta_input {
+            true => mta::PartyTwo::new(Scalar::random(&mut ProtocolRng)),
+            false => mta::PartyTwo::new(self.sign.nonce_secret_share),
+        }
+    }
+
+    pub fn receive_and_send_msg_wc(
+        &self,
+        mta: &mut mta::PartyTwo,
+        group: &CLGroup,
+        msg: MTAFirstRoundMsg,
+    ) -> Result<mta::MTASecondRoundMsg, String> {
+        let mut reply = mta.receive_and_send_msg_wc(msg)?;
+        if self.knobs.out_of_range_mta_wc_proof {
+            let bound = mpz_to_bigint(q())
+                * num_bigint::BigInt::from(2i32).pow(
+                    (group.params.computational_security + 2 * group.params.slack_bits) as u32,
+                );
+            reply.proof.z_a = reply.proof.z_a.clone() + &bigint_to_mpz(bound);
+        }
+        Ok(reply)
+    }
+
+    /// An honest party two aborts here when its MtA input was not its nonce
+    /// share; a deviating one carries on so that party one's checks are
+    /// exercised.
+    pub fn verify_generate_mta_consistency(
+        &mut self,
+        t_a: Scalar,
+        msg: &MtaConsistencyMsg,
+    ) -> Result<(), String> {
+        if !self.knobs.wrong_mta_input {
+            return self.sign.verify_generate_mta_consistency(t_a, msg);
+        }
+        self.sign.reshared_secret_share = self.sign.secret_store.share_minus(&(t_a + msg.cc));
+        self.sign.r1_rec = msg.r1;
+        Ok(())
+    }
+
+    pub fn online_sign(&mut self, message_bytes: &[u8]) -> Result<Scalar, MulEcdsaError> {
+        let s_2 = self.sign.online_sign(message_bytes)?;
+        match self.knobs.wrong_partial_signature {
+            true => Ok(s_2 + Scalar::ONE),
+            false => Ok(s_2),
+        }
+    }
+}
+
+#[cfg(test)]
+fn run(one_knobs: PartyOneKnobs, two_knobs: PartyTwoKnobs) -> Result<(), MulEcdsaError> {
+    let x1 = Scalar::random(&mut ProtocolRng);
+    let x2 = Scalar::random(&mut ProtocolRng);
+    let (p1, p2) = (ProjectivePoint::GENERATOR * x1, ProjectivePoint::GENERATOR * x2);
+    let key = |x, other| KeyStore::new(x, other, p1 + p2).unwrap();
+    let mut one = PartyOne::new(party_one::Sign::new_strict(key(x1, p2)).unwrap(), one_knobs);
+    let mut two = PartyTwo::new(party_two::Sign::new(key(x2, p1)).unwrap(), two_knobs);
+    let message_hash = [7u8; 32];
+
+    one.sign.get_nonce_com(&two.generate_nonce_com());
+    let group = CLGroup::new();
+    let (cl_priv_key, cl_pub_key) = group.keygen();
+    let mut mta_one = mta::PartyOne::new(one.sign.reshared_secret_share);
+    let mut mta_two = two.mta_party();
+    let first = one.generate_mta_first_round(&mta_one, &group, &cl_pub_key);
+    let second = two
+        .receive_and_send_msg_wc(&mut mta_two, &group, first.clone())
+        .map_err(|_| MulEcdsaError::VrfyCLProofFailed)?;
+    mta_one.handle_receive_msg(&cl_priv_key, &second.c_a)?;
+    one.sign.set_mta_wc_msg(&first.state, &second);
+    two.verify_generate_mta_consistency(mta_two.t_a, &one.generate_mta_consistency(mta_one.t_b))
+        .map_err(|_| MulEcdsaError::VrfyMtaConsistencyFailed)?;
+    let witness = two
+        .sign
+        .verify_send_nonce_ke_msg(&one.generate_nonce_ke_msg())
+        .map_err(|_| MulEcdsaError::VrfyDlogFailed)?;
+    one.sign.verify_nonce_ke_msg(&witness)?;
+    one.sign
+        .online_sign(&two.online_sign(&message_hash)?, &message_hash)
+        .map(|_| ())
+}
+
+#[test]
+fn malicious_party_test() {
+    run(PartyOneKnobs::default(), PartyTwoKnobs::default()).unwrap();
+
+    let one_deviations = [
+        (
+            PartyOneKnobs {
+                wrong_mta_consistency: true,
+                ..Default::default()
+            },
+            MulEcdsaError::VrfyMtaConsistencyFailed,
+        ),
+        (
+            PartyOneKnobs {
+                wrong_nonce_proof: true,
+                ..Default::default()
+            },
+            MulEcdsaError::VrfyDlogFailed,
+        ),
+        (
+            PartyOneKnobs {
+                out_of_range_cl_proof: true,
+                ..Default::default()
+            },
+            MulEcdsaError::VrfyCLProofFailed,
+        ),
+    ];
+    for (knobs, expected) in one_deviations {
+        assert_eq!(run(knobs, PartyTwoKnobs::default()), Err(expected));
+    }
+
+    let two_deviations = [
+        (
+            PartyTwoKnobs {
+                wrong_commitment: true,
+                ..Default::default()
+            },
+            MulEcdsaError::OpenDLCommFailed,
+        ),
+        (
+            PartyTwoKnobs {
+                wrong_mta_input: true,
+                ..Default::default()
+            },
+            MulEcdsaError::VrfyMtAwcProofFailed,
+        ),
+        (
+            PartyTwoKnobs {
+                out_of_range_mta_wc_proof: true,
+                ..Default::default()
+            },
+            MulEcdsaError::VrfyMtAwcProofFailed,
+        ),
+        (
+            PartyTwoKnobs {
+                wrong_partial_signature: true,
+                ..Default::default()
+            },
+            MulEcdsaError::VrfyMultiECDSAFailed,
+        ),
+    ];
+    for (knobs, expected) in two_deviations {
+        assert_eq!(run(PartyOneKnobs::default(), knobs), Err(expected));
+    }
+}
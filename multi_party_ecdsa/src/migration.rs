@@ -0,0 +1,179 @@
+//! Moving party two's share to a new device. The new device announces a CL
+//! key, the old device encrypts its share under it with a CLDL proof that
+//! party one checks against the recorded counterparty share, and party one
+//! then refreshes both shares so the copy left on the old device no longer
+//! fits.
+
+use crate::shared::KeyStore;
+use crate::utilities::cl_dl_proof::*;
+use crate::utilities::cl_key_proof::ClKeyProof;
+use crate::utilities::class_group::{CLGroup, Ciphertext};
+use crate::utilities::error::MulEcdsaError;
+use classgroup::gmp::mpz::Mpz;
+use classgroup::gmp_classgroup::GmpClassGroup;
+use k256::elliptic_curve::Field;
+use k256::{ProjectivePoint, Scalar};
+use crate::utilities::rng::ProtocolRng;
+use serde::{Deserialize, Serialize};
+
+/// New device -> old device and party one.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DeviceKeyMsg {
+    pub cl_pub_key: GmpClassGroup,
+    pub cl_key_proof: ClKeyProof,
+}
+
+/// Old device -> new device and party one.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ShareTransferMsg {
+    pub encrypted_share: Ciphertext,
+    /// `encrypted_share` encrypts the discrete log of party two's public share.
+    pub cldl_proof: CLDLProof,
+}
+
+/// Party one -> new device: the refresh offset, which the old device cannot
+/// read.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RefreshMsg {
+    pub encrypted_offset: Ciphertext,
+}
+
+#[derive(Clone, Debug)]
+pub struct NewDevice {
+    pub cl_priv_key: Mpz,
+    pub cl_pub_key: GmpClassGroup,
+}
+
+impl NewDevice {
+    pub fn new() -> Self {
+        let (cl_priv_key, cl_pub_key) = CLGroup::new().keygen();
+        Self {
+            cl_priv_key,
+            cl_pub_key,
+        }
+    }
+
+    pub fn generate_key_msg(&self) -> DeviceKeyMsg {
+        DeviceKeyMsg {
+            cl_pub_key: self.cl_pub_key.clone(),
+            cl_key_proof: ClKeyProof::prove(&CLGroup::new(), &self.cl_priv_key, &self.cl_pub_key),
+        }
+    }
+
+    /// Builds the refreshed key store. `key_store` is the old device's; only
+    /// its public part is used.
+    pub fn import(
+        &self,
+        key_store: &KeyStore,
+        transfer: &ShareTransferMsg,
+        refresh: &RefreshMsg,
+    ) -> Result<KeyStore, MulEcdsaError> {
+        let group = CLGroup::new();
+        let share = CLGroup::decrypt(&group, &self.cl_priv_key, &transfer.encrypted_share)?;
+        if ProjectivePoint::GENERATOR * share != key_store.public_share {
+            return Err(MulEcdsaError::InvalidKeyStore);
+        }
+        let offset = CLGroup::decrypt(&group, &self.cl_priv_key, &refresh.encrypted_offset)?;
+        let mut refreshed = KeyStore::new(
+            share - offset,
+            key_store.counterparty_share + ProjectivePoint::GENERATOR * offset,
+            key_store.public_signing_key,
+        )?;
+        refreshed.counterparty_cl_pub_key = key_store.counterparty_cl_pub_key.clone();
+        refreshed.keygen_transcript_hash = key_store.keygen_transcript_hash;
+        Ok(refreshed)
+    }
+}
+
+impl Default for NewDevice {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Run by the old device.
+pub fn export_share(
+    key_store: &KeyStore,
+    device: &DeviceKeyMsg,
+) -> Result<ShareTransferMsg, MulEcdsaError> {
+    let group = CLGroup::new();
+    device.cl_key_proof.verify(&group, &device.cl_pub_key)?;
+    let (encrypted_share, r) = CLGroup::encrypt(&group, &device.cl_pub_key, key_store.secret_share);
+    let statement = CLDLState {
+        cipher: encrypted_share.clone(),
+        cl_pub_key: device.cl_pub_key.clone(),
+        dl_pub: key_store.public_share,
+    };
+    let witness = CLDLWit {
+        dl_priv: key_store.secret_share,
+        r,
+    };
+    Ok(ShareTransferMsg {
+        encrypted_share,
+        cldl_proof: CLDLProof::prove(&group, witness, statement),
+    })
+}
+
+/// Run by party one: checks that the transfer carries the recorded
+/// counterparty share and returns the refresh message with party one's
+/// refreshed key store. Afterwards the old device's share is rejected by
+/// `KeyStore::check_counterparty`.
+pub fn refresh_for_device(
+    key_store: &KeyStore,
+    device: &DeviceKeyMsg,
+    transfer: &ShareTransferMsg,
+) -> Result<(RefreshMsg, KeyStore), MulEcdsaError> {
+    let group = CLGroup::new();
+    device.cl_key_proof.verify(&group, &device.cl_pub_key)?;
+    let statement = CLDLState {
+        cipher: transfer.encrypted_share.clone(),
+        cl_pub_key: device.cl_pub_key.clone(),
+        dl_pub: key_store.counterparty_share,
+    };
+    transfer.cldl_proof.verify(&group, statement)?;
+
+    let offset = Scalar::random(&mut ProtocolRng);
+    let (encrypted_offset, _) = CLGroup::encrypt(&group, &device.cl_pub_key, offset);
+    let mut refreshed = KeyStore::new(
+        key_store.secret_share + offset,
+        key_store.counterparty_share - ProjectivePoint::GENERATOR * offset,
+        key_store.public_signing_key,
+    )?;
+    refreshed.counterparty_cl_pub_key = key_store.counterparty_cl_pub_key.clone();
+    refreshed.keygen_transcript_hash = key_store.keygen_transcript_hash;
+    Ok((RefreshMsg { encrypted_offset }, refreshed))
+}
+
+#[test]
+fn device_migration_test() {
+    let x1 = Scalar::random(&mut ProtocolRng);
+    let x2 = Scalar::random(&mut ProtocolRng);
+    let (p1, p2) = (ProjectivePoint::GENERATOR * x1, ProjectivePoint::GENERATOR * x2);
+    let party_one_key = KeyStore::new(x1, p2, p1 + p2).unwrap();
+    let old_device_key = KeyStore::new(x2, p1, p1 + p2).unwrap();
+
+    let device = NewDevice::new();
+    let device_msg = device.generate_key_msg();
+    let transfer = export_share(&old_device_key, &device_msg).unwrap();
+
+    // a share other than the recorded one is refused
+    let other = KeyStore::new(x1 + x2, -p2, p1).unwrap();
+    let forged = export_share(&other, &device_msg).unwrap();
+    assert_eq!(
+        refresh_for_device(&party_one_key, &device_msg, &forged).unwrap_err(),
+        MulEcdsaError::VrfyCLDLProofFailed
+    );
+
+    let (refresh, party_one_key) =
+        refresh_for_device(&party_one_key, &device_msg, &transfer).unwrap();
+    let new_device_key = device.import(&old_device_key, &transfer, &refresh).unwrap();
+    assert_eq!(party_one_key.secret_share + new_device_key.secret_share, x1 + x2);
+    assert_eq!(new_device_key.public_signing_key, p1 + p2);
+    party_one_key
+        .check_counterparty(&new_device_key.public_share, None)
+        .unwrap();
+    assert_eq!(
+        party_one_key.check_counterparty(&old_device_key.public_share, None),
+        Err(MulEcdsaError::CounterpartyMismatch)
+    );
+}
@@ -1,72 +1,250 @@
+use crate::utilities::cl_key_proof::ClKeyProtocol;
 use crate::utilities::cl_proof::*;
 use crate::utilities::class_group::*;
+use crate::utilities::error::MulEcdsaError;
+use crate::utilities::mta_wc_proof::*;
+use crate::utilities::sigma::{self, And, Transcript};
+#[cfg(not(feature = "verify-only"))]
+use crate::utilities::ProtocolParams;
 use classgroup::gmp::mpz::Mpz;
 use classgroup::gmp_classgroup::GmpClassGroup;
-use k256::Scalar;
-use k256::elliptic_curve::Field;
-use rand::rngs::OsRng;
+use k256::ProjectivePoint;
+#[cfg(not(feature = "verify-only"))]
+use k256::{elliptic_curve::Field, Scalar};
+#[cfg(not(feature = "verify-only"))]
+use crate::utilities::rng::ProtocolRng;
+use serde::{Deserialize, Serialize};
 
+#[cfg(not(feature = "verify-only"))]
 #[derive(Clone, Debug)]
 pub struct PartyOne {
     pub b: Scalar,
     pub t_b: Scalar,
     pub cl_pub_key: GmpClassGroup,
     pub cl_priv_key: Mpz,
+    /// Used when decrypting the responder's ciphertext.
+    pub options: ClOptions,
+    /// For the CL key and every proof; the counterparty must use the same.
+    pub params: ProtocolParams,
 }
 
+/// Precomputation for repeated MtA under one CL public key, such as party
+/// one's key towards a given counterparty: the power tables of `gq` and of
+/// the key, used by the encryption and the `CLProof` of every session.
+/// Only the secret changes between sessions. Clones share the tables.
+#[cfg(not(feature = "verify-only"))]
+#[derive(Clone, Debug)]
+pub struct MtaContext {
+    pub cl_pub_key: GmpClassGroup,
+    pub group: CLGroup,
+}
+
+#[cfg(not(feature = "verify-only"))]
 #[derive(Clone, Debug)]
 pub struct PartyTwo {
     pub a: Scalar,
     pub t_a: Scalar,
+    /// See `PartyOne::params`.
+    pub params: ProtocolParams,
+}
+
+/// Second MtA message with check: the responder's ciphertext plus a proof
+/// that the scalar it multiplied by is the discrete log of a known point.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MTASecondRoundMsg {
+    pub c_a: Ciphertext,
+    pub proof: MtAwcProof,
+}
+
+impl MTASecondRoundMsg {
+    pub fn verify(
+        &self,
+        group: &CLGroup,
+        first_round: &CLState,
+        a_pub: &ProjectivePoint,
+    ) -> Result<(), MulEcdsaError> {
+        let statement = MtAwcState {
+            c_b: first_round.cipher.clone(),
+            c_a: self.c_a.clone(),
+            cl_pub_key: first_round.cl_pub_key.clone(),
+            a_pub: *a_pub,
+        };
+        self.proof.verify(group, &statement)
+    }
+}
+
+/// Replaces the encryptor's CL key pair. Proves knowledge of both the
+/// retiring and the new secret key under one challenge, so only the holder of
+/// the old key can announce its successor.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CLKeyRotationMsg {
+    pub new_cl_pub_key: GmpClassGroup,
+    pub t_old: GmpClassGroup,
+    pub t_new: GmpClassGroup,
+    pub z_old: Mpz,
+    pub z_new: Mpz,
+}
+
+impl CLKeyRotationMsg {
+    pub fn verify(&self, group: &CLGroup, old_cl_pub_key: &GmpClassGroup) -> Result<(), MulEcdsaError> {
+        group.check_elements(&[old_cl_pub_key, &self.new_cl_pub_key, &self.t_old, &self.t_new])?;
+        let protocol = And(ClKeyProtocol { group }, ClKeyProtocol { group });
+        let statement = (old_cl_pub_key.clone(), self.new_cl_pub_key.clone());
+        let commitment = (self.t_old.clone(), self.t_new.clone());
+        let response = (self.z_old.clone(), self.z_new.clone());
+        match sigma::verify(
+            &protocol,
+            &Transcript::new(b"CLKeyRotation"),
+            &statement,
+            &commitment,
+            &response,
+        ) {
+            true => Ok(()),
+            false => Err(MulEcdsaError::VrfyCLKeyProofFailed),
+        }
+    }
 }
 
+#[cfg(not(feature = "verify-only"))]
 impl PartyOne {
     pub fn new(b: Scalar) -> Self {
-        let group = CLGroup::new();
+        Self::new_with_params(b, ProtocolParams::default())
+    }
+
+    pub fn new_with_params(b: Scalar, params: ProtocolParams) -> Self {
+        let group = CLGroup::new_with_params(params);
         let (cl_priv_key, cl_pub_key) = group.keygen();
         Self {
             b,
-            t_b: Scalar::random(&mut OsRng),
+            t_b: Scalar::random(&mut ProtocolRng),
             cl_pub_key,
             cl_priv_key,
+            options: ClOptions::default(),
+            params,
+        }
+    }
+
+    fn group(&self) -> CLGroup {
+        CLGroup::new_with_params(self.params)
+    }
+
+    pub fn with_options(mut self, options: ClOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Switches to a fresh CL key pair. The MtA share `b` is untouched; the
+    /// returned message lets the counterparty accept the new public key.
+    pub fn rotate_cl_key(&mut self) -> CLKeyRotationMsg {
+        let group = self.group();
+        let (new_cl_priv_key, new_cl_pub_key) = group.keygen();
+        let protocol = And(ClKeyProtocol { group: &group }, ClKeyProtocol { group: &group });
+        let statement = (self.cl_pub_key.clone(), new_cl_pub_key.clone());
+        let witness = (self.cl_priv_key.clone(), new_cl_priv_key.clone());
+        let ((t_old, t_new), (z_old, z_new)) = sigma::prove(
+            &protocol,
+            &Transcript::new(b"CLKeyRotation"),
+            &statement,
+            &witness,
+        );
+        self.cl_priv_key = new_cl_priv_key;
+        self.cl_pub_key = new_cl_pub_key.clone();
+        CLKeyRotationMsg {
+            new_cl_pub_key,
+            t_old,
+            t_new,
+            z_old,
+            z_new,
         }
     }
 
     pub fn generate_send_msg(&self, cl_pk: &GmpClassGroup) -> MTAFirstRoundMsg {
-        let group = CLGroup::new();
-        let (c_b, r) = CLGroup::encrypt(&group, cl_pk, &self.b);
+        self.first_round(&self.group(), cl_pk)
+    }
+
+    /// `generate_send_msg` under `context`'s key, with its tables.
+    pub fn generate_send_msg_in(&self, context: &MtaContext) -> MTAFirstRoundMsg {
+        self.first_round(&context.group, &context.cl_pub_key)
+    }
+
+    fn first_round(&self, group: &CLGroup, cl_pk: &GmpClassGroup) -> MTAFirstRoundMsg {
+        let (c_b, r) = CLGroup::encrypt(group, cl_pk, self.b);
         let witness = CLWit { x: self.b, r };
         let statement = CLState {
             cipher: c_b,
             cl_pub_key: cl_pk.clone(),
         };
-        let cl_proof = CLProof::prove(&group, witness, statement.clone());
+        let cl_proof = CLProof::prove(group, witness, statement.clone());
         MTAFirstRoundMsg {
             proof: cl_proof,
             state: statement,
         }
     }
 
-    pub fn handle_receive_msg(&mut self, cl_sk: &Mpz, c_a: &Ciphertext) {
-        let group = CLGroup::new();
-        self.t_b = CLGroup::decrypt(&group, cl_sk, c_a);
+    pub fn handle_receive_msg(&mut self, cl_sk: &Mpz, c_a: &Ciphertext) -> Result<(), MulEcdsaError> {
+        let group = self.group().with_options(self.options);
+        self.t_b = CLGroup::decrypt(&group, cl_sk, c_a)?;
+        Ok(())
+    }
+
+    /// MtAwc counterpart of `handle_receive_msg`: checks that the responder's
+    /// input is the discrete log of `a_pub` before decrypting.
+    pub fn handle_receive_msg_wc(
+        &mut self,
+        cl_sk: &Mpz,
+        first_round: &CLState,
+        msg: &MTASecondRoundMsg,
+        a_pub: &ProjectivePoint,
+    ) -> Result<(), MulEcdsaError> {
+        msg.verify(&self.group(), first_round, a_pub)?;
+        self.handle_receive_msg(cl_sk, &msg.c_a)
+    }
+}
+
+#[cfg(not(feature = "verify-only"))]
+impl MtaContext {
+    pub fn new(cl_pub_key: GmpClassGroup) -> Self {
+        Self::new_with_params(cl_pub_key, ProtocolParams::default())
+    }
+
+    pub fn new_with_params(cl_pub_key: GmpClassGroup, params: ProtocolParams) -> Self {
+        Self {
+            cl_pub_key,
+            group: CLGroup::new_with_params(params).with_prover_context(ProverContext::new()),
+        }
+    }
+
+    /// Moves to the key announced by a `CLKeyRotationMsg`, keeping the table
+    /// of `gq` and dropping the one of the old key.
+    pub fn rekey(&mut self, new_cl_pub_key: GmpClassGroup) {
+        if let Some(prover_context) = &self.group.prover_context {
+            prover_context.forget(&self.cl_pub_key);
+        }
+        self.cl_pub_key = new_cl_pub_key;
     }
 }
 
+#[cfg(not(feature = "verify-only"))]
 impl PartyTwo {
     pub fn new(a: Scalar) -> Self {
         Self {
             a,
-            t_a: Scalar::random(&mut OsRng),
+            t_a: Scalar::random(&mut ProtocolRng),
+            params: ProtocolParams::default(),
         }
     }
 
+    pub fn with_params(mut self, params: ProtocolParams) -> Self {
+        self.params = params;
+        self
+    }
+
     pub fn receive_and_send_msg(
         &mut self,
         mta_msg: MTAFirstRoundMsg,
     ) -> Result<Ciphertext, String> {
-        let group = CLGroup::new();
-        let alpha_tag = Scalar::random(&mut OsRng);
+        let group = CLGroup::new_with_params(self.params);
+        let alpha_tag = Scalar::random(&mut ProtocolRng);
         let alpha = -alpha_tag;
         self.t_a = alpha;
 
@@ -74,9 +252,49 @@ impl PartyTwo {
         mta_msg.proof
             .verify(&group, mta_msg.state.clone())
             .map_err(|_| "verify cl encryption dl proof failed")?;
-        let encrypted_alpha_tag = CLGroup::encrypt(&group, &mta_msg.state.cl_pub_key, &alpha_tag);
-        let a_scal_c_b = CLGroup::eval_scal(&mta_msg.state.cipher, into_mpz(&self.a));
-        let c_a = CLGroup::eval_sum(&a_scal_c_b, &encrypted_alpha_tag.0);
+        let (c_a, _) = CLGroup::eval_affine(
+            &group,
+            &mta_msg.state.cipher,
+            &self.a,
+            &alpha_tag,
+            &mta_msg.state.cl_pub_key,
+        );
         return Ok(c_a);
     }
+
+    /// MtAwc variant of `receive_and_send_msg`, additionally proving that
+    /// the scalar `a` matches `a * G`.
+    pub fn receive_and_send_msg_wc(
+        &mut self,
+        mta_msg: MTAFirstRoundMsg,
+    ) -> Result<MTASecondRoundMsg, String> {
+        let group = CLGroup::new_with_params(self.params);
+        let alpha_tag = Scalar::random(&mut ProtocolRng);
+        self.t_a = -alpha_tag;
+
+        mta_msg.proof
+            .verify(&group, mta_msg.state.clone())
+            .map_err(|_| "verify cl encryption dl proof failed")?;
+        let (c_a, rho) = CLGroup::eval_affine(
+            &group,
+            &mta_msg.state.cipher,
+            &self.a,
+            &alpha_tag,
+            &mta_msg.state.cl_pub_key,
+        );
+
+        let statement = MtAwcState {
+            c_b: mta_msg.state.cipher,
+            c_a: c_a.clone(),
+            cl_pub_key: mta_msg.state.cl_pub_key,
+            a_pub: ProjectivePoint::GENERATOR * self.a,
+        };
+        let witness = MtAwcWit {
+            a: self.a,
+            alpha: alpha_tag,
+            rho,
+        };
+        let proof = MtAwcProof::prove(&group, &witness, &statement);
+        Ok(MTASecondRoundMsg { c_a, proof })
+    }
 }
@@ -0,0 +1,125 @@
+//! Records every nonce point `R` a key has signed with, so that a session
+//! restored from a stale state (VM snapshot, backup) cannot reuse one.
+
+use crate::utilities::address::public_key_sec1;
+use crate::utilities::error::MulEcdsaError;
+use k256::ProjectivePoint;
+use std::collections::HashSet;
+use std::convert::TryInto;
+use std::fmt::Debug;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Key fingerprint followed by the compressed `R`.
+const RECORD_LEN: usize = 32 + 33;
+
+pub trait NonceRegistry: Debug + Send {
+    /// Records `r` for `key_id`, failing with `NonceReuse` if it was
+    /// recorded before.
+    fn record(&mut self, key_id: &[u8; 32], r: &ProjectivePoint) -> Result<(), MulEcdsaError>;
+}
+
+fn record_bytes(key_id: &[u8; 32], r: &ProjectivePoint) -> [u8; RECORD_LEN] {
+    let mut record = [0u8; RECORD_LEN];
+    record[..32].copy_from_slice(key_id);
+    record[32..].copy_from_slice(&public_key_sec1(r, true));
+    record
+}
+
+/// Forgets everything when dropped; for tests and short-lived processes.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryNonceRegistry {
+    seen: HashSet<[u8; RECORD_LEN]>,
+}
+
+impl NonceRegistry for MemoryNonceRegistry {
+    fn record(&mut self, key_id: &[u8; 32], r: &ProjectivePoint) -> Result<(), MulEcdsaError> {
+        match self.seen.insert(record_bytes(key_id, r)) {
+            true => Ok(()),
+            false => Err(MulEcdsaError::NonceReuse),
+        }
+    }
+}
+
+/// Append-only file of fixed-size records, synced before `record` returns.
+#[derive(Debug)]
+pub struct FileNonceRegistry {
+    pub path: PathBuf,
+    file: File,
+    seen: HashSet<[u8; RECORD_LEN]>,
+}
+
+impl FileNonceRegistry {
+    /// Opens or creates the registry at `path`. A file whose length is not
+    /// a whole number of records is rejected rather than repaired.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, MulEcdsaError> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)
+            .map_err(|_| MulEcdsaError::NonceRegistryFailed)?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)
+            .map_err(|_| MulEcdsaError::NonceRegistryFailed)?;
+        if bytes.len() % RECORD_LEN != 0 {
+            return Err(MulEcdsaError::NonceRegistryFailed);
+        }
+        let seen = bytes
+            .chunks_exact(RECORD_LEN)
+            .map(|chunk| chunk.try_into().unwrap())
+            .collect();
+        Ok(Self { path, file, seen })
+    }
+}
+
+impl NonceRegistry for FileNonceRegistry {
+    fn record(&mut self, key_id: &[u8; 32], r: &ProjectivePoint) -> Result<(), MulEcdsaError> {
+        let record = record_bytes(key_id, r);
+        if self.seen.contains(&record) {
+            return Err(MulEcdsaError::NonceReuse);
+        }
+        self.file
+            .write_all(&record)
+            .and_then(|_| self.file.sync_data())
+            .map_err(|_| MulEcdsaError::NonceRegistryFailed)?;
+        self.seen.insert(record);
+        Ok(())
+    }
+}
+
+#[test]
+fn file_nonce_registry_test() {
+    use k256::elliptic_curve::Field;
+    use k256::Scalar;
+    use crate::utilities::rng::ProtocolRng;
+
+    let path = std::env::temp_dir().join(format!("nonce_registry_{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let key_id = [1u8; 32];
+    let r = ProjectivePoint::GENERATOR * Scalar::random(&mut ProtocolRng);
+
+    let mut registry = FileNonceRegistry::open(&path).unwrap();
+    registry.record(&key_id, &r).unwrap();
+    registry.record(&[2u8; 32], &r).unwrap();
+    assert_eq!(registry.record(&key_id, &r), Err(MulEcdsaError::NonceReuse));
+    drop(registry);
+
+    // survives a restart
+    let mut registry = FileNonceRegistry::open(&path).unwrap();
+    assert_eq!(registry.record(&key_id, &r), Err(MulEcdsaError::NonceReuse));
+
+    std::fs::OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .unwrap()
+        .write_all(&[0u8; 3])
+        .unwrap();
+    assert_eq!(
+        FileNonceRegistry::open(&path).unwrap_err(),
+        MulEcdsaError::NonceRegistryFailed
+    );
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,113 @@
+//! Per-round performance events for integrators' telemetry. A driver of the
+//! protocol times each round with a `RoundTimer` and hands the resulting
+//! `RoundEvent` to a `ProtocolObserver`; `Simulation` does this when given
+//! one.
+
+use crate::codec::MessageKind;
+use std::fmt::Debug;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RoundEvent {
+    /// The message the round produced; `None` for the final step that
+    /// yields the signature.
+    pub round: Option<MessageKind>,
+    pub wall_time: Duration,
+    /// Of the calling thread only, so work on helper threads (parallel
+    /// proofs) is not included; `None` where the platform has no thread
+    /// clock.
+    pub cpu_time: Option<Duration>,
+    /// Encoded bytes received during the round.
+    pub bytes_in: usize,
+    /// Encoded bytes of the message sent at its end.
+    pub bytes_out: usize,
+}
+
+pub trait ProtocolObserver: Debug + Send {
+    fn on_round(&mut self, event: &RoundEvent);
+}
+
+/// Keeps every event, for tests and batch reports.
+impl ProtocolObserver for Vec<RoundEvent> {
+    fn on_round(&mut self, event: &RoundEvent) {
+        self.push(*event);
+    }
+}
+
+#[cfg(unix)]
+fn thread_cpu_time() -> Option<Duration> {
+    let mut time = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: `time` is a valid, writable timespec.
+    match unsafe { libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, &mut time) } {
+        0 => Some(Duration::new(time.tv_sec as u64, time.tv_nsec as u32)),
+        _ => None,
+    }
+}
+
+#[cfg(not(unix))]
+fn thread_cpu_time() -> Option<Duration> {
+    None
+}
+
+/// Measures consecutive rounds: `finish` ends one and starts the next.
+#[derive(Clone, Copy, Debug)]
+pub struct RoundTimer {
+    wall_start: Instant,
+    cpu_start: Option<Duration>,
+    bytes_in: usize,
+}
+
+impl Default for RoundTimer {
+    fn default() -> Self {
+        Self::start()
+    }
+}
+
+impl RoundTimer {
+    pub fn start() -> Self {
+        Self {
+            wall_start: Instant::now(),
+            cpu_start: thread_cpu_time(),
+            bytes_in: 0,
+        }
+    }
+
+    pub fn received(&mut self, bytes: usize) {
+        self.bytes_in += bytes;
+    }
+
+    pub fn finish(&mut self, round: Option<MessageKind>, bytes_out: usize) -> RoundEvent {
+        let cpu_now = thread_cpu_time();
+        let event = RoundEvent {
+            round,
+            wall_time: self.wall_start.elapsed(),
+            cpu_time: cpu_now.zip(self.cpu_start).map(|(now, start)| now.saturating_sub(start)),
+            bytes_in: self.bytes_in,
+            bytes_out,
+        };
+        *self = Self::start();
+        event
+    }
+}
+
+#[test]
+fn round_timer_test() {
+    let mut timer = RoundTimer::start();
+    timer.received(10);
+    timer.received(5);
+    let mut spin = 0u64;
+    for i in 0..1_000_000u64 {
+        spin = spin.wrapping_add(std::hint::black_box(i));
+    }
+    std::hint::black_box(spin);
+    let event = timer.finish(Some(MessageKind::NonceCom), 7);
+    assert_eq!((event.bytes_in, event.bytes_out), (15, 7));
+    assert!(event.wall_time > Duration::ZERO);
+    #[cfg(unix)]
+    assert!(event.cpu_time.unwrap() <= event.wall_time + Duration::from_millis(1));
+    // the next round starts from zero
+    assert_eq!(timer.finish(None, 0).bytes_in, 0);
+}
@@ -0,0 +1,203 @@
+//! File-based message exchange, for a party whose share lives on an
+//! air-gapped machine. Each message of a session is one file in a directory
+//! that is carried between the machines (USB stick), named after the
+//! session and round so either side can tell what it has been handed.
+//! `to_chunks` splits a message into short text chunks for QR codes instead.
+
+use crate::codec::{AbortMsg, CancelMsg, Codec, MessageKind, SizeCaps};
+use crate::utilities::error::MulEcdsaError;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::PathBuf;
+
+const CHUNK_PREFIX: &str = "MPC";
+
+pub struct OfflineExchange {
+    pub dir: PathBuf,
+    pub session: [u8; 32],
+    pub codec: Codec,
+}
+
+impl OfflineExchange {
+    pub fn new<P: Into<PathBuf>>(dir: P, session: [u8; 32]) -> Self {
+        Self {
+            dir: dir.into(),
+            session,
+            codec: Codec::default(),
+        }
+    }
+
+    /// The file holding the message of `kind`.
+    pub fn path(&self, kind: MessageKind) -> PathBuf {
+        self.dir
+            .join(format!("{}-{}-{:?}.msg", hex::encode(self.session), kind as u8, kind))
+    }
+
+    /// Writes to a temporary file first, so a reader never sees half a
+    /// message.
+    pub fn write<T: Serialize>(&mut self, kind: MessageKind, msg: &T) -> Result<PathBuf, MulEcdsaError> {
+        let bytes = self.codec.encode(kind, msg)?;
+        let path = self.path(kind);
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, &bytes)
+            .and_then(|_| fs::rename(&tmp, &path))
+            .map_err(|_| MulEcdsaError::TransportFailed)?;
+        Ok(path)
+    }
+
+    /// `None` until the message of `kind` has been carried over. An abort
+    /// or cancel written by the counterparty ends the session.
+    pub fn read<T: DeserializeOwned>(&mut self, kind: MessageKind) -> Result<Option<T>, MulEcdsaError> {
+        if let Some(bytes) = self.read_bytes(MessageKind::Abort)? {
+            let _: AbortMsg = self.codec.decode(MessageKind::Abort, &bytes)?;
+            return Err(MulEcdsaError::PeerAborted);
+        }
+        if let Some(bytes) = self.read_bytes(MessageKind::Cancel)? {
+            let _: CancelMsg = self.codec.decode(MessageKind::Cancel, &bytes)?;
+            return Err(MulEcdsaError::PeerCancelled);
+        }
+        match self.read_bytes(kind)? {
+            Some(bytes) => self.codec.decode(kind, &bytes).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Writes an abort for `round` and returns `reason`.
+    pub fn abort(&mut self, round: MessageKind, reason: MulEcdsaError) -> MulEcdsaError {
+        let msg = AbortMsg {
+            session: self.session,
+            round,
+            reason: reason.clone(),
+        };
+        let _ = self.write(MessageKind::Abort, &msg);
+        reason
+    }
+
+    /// Writes a cancel for the session; call `cancel` on the `Sign` too.
+    pub fn cancel(&mut self) -> MulEcdsaError {
+        let msg = CancelMsg { session: self.session };
+        let _ = self.write(MessageKind::Cancel, &msg);
+        MulEcdsaError::Cancelled
+    }
+
+    fn read_bytes(&self, kind: MessageKind) -> Result<Option<Vec<u8>>, MulEcdsaError> {
+        match fs::read(self.path(kind)) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(_) => Err(MulEcdsaError::TransportFailed),
+        }
+    }
+}
+
+/// Splits `bytes` into chunks of at most `chunk_len` bytes, each encoded as
+/// `MPC:<checksum>:<index>/<count>:<data>` in upper-case hex, which fits a
+/// QR code's alphanumeric mode.
+pub fn to_chunks(bytes: &[u8], chunk_len: usize) -> Vec<String> {
+    let checksum = hex::encode_upper(&Sha256::digest(bytes)[..4]);
+    let chunks: Vec<&[u8]> = bytes.chunks(chunk_len.max(1)).collect();
+    chunks
+        .iter()
+        .enumerate()
+        .map(|(i, chunk)| {
+            format!(
+                "{}:{}:{}/{}:{}",
+                CHUNK_PREFIX,
+                checksum,
+                i + 1,
+                chunks.len(),
+                hex::encode_upper(chunk)
+            )
+        })
+        .collect()
+}
+
+/// Reassembles `to_chunks` output given in any order. Fails on a missing,
+/// foreign or corrupted chunk, and on a count more chunks of the first
+/// one's length than any message within `SizeCaps::default()` needs.
+pub fn from_chunks<S: AsRef<str>>(chunks: &[S]) -> Result<Vec<u8>, MulEcdsaError> {
+    let mut checksum = None;
+    let mut parts: Vec<Option<Vec<u8>>> = Vec::new();
+    for chunk in chunks {
+        let fields: Vec<&str> = chunk.as_ref().split(':').collect();
+        let (sum, position, data) = match fields[..] {
+            [CHUNK_PREFIX, sum, position, data] => (sum, position, data),
+            _ => return Err(MulEcdsaError::TransportFailed),
+        };
+        if *checksum.get_or_insert(sum) != sum {
+            return Err(MulEcdsaError::TransportFailed);
+        }
+        let (index, count) = position
+            .split_once('/')
+            .and_then(|(i, n)| Some((i.parse::<usize>().ok()?, n.parse::<usize>().ok()?)))
+            .ok_or(MulEcdsaError::TransportFailed)?;
+        if parts.is_empty() {
+            if count > SizeCaps::default().max().div_ceil((data.len() / 2).max(1)) {
+                return Err(MulEcdsaError::TransportFailed);
+            }
+            parts.resize(count, None);
+        }
+        if count != parts.len() || index == 0 || index > count {
+            return Err(MulEcdsaError::TransportFailed);
+        }
+        parts[index - 1] = Some(hex::decode(data).map_err(|_| MulEcdsaError::TransportFailed)?);
+    }
+    let bytes = parts
+        .into_iter()
+        .collect::<Option<Vec<_>>>()
+        .ok_or(MulEcdsaError::TransportFailed)?
+        .concat();
+    match checksum {
+        Some(sum) if hex::encode_upper(&Sha256::digest(&bytes)[..4]) == sum => Ok(bytes),
+        _ => Err(MulEcdsaError::TransportFailed),
+    }
+}
+
+#[cfg(not(feature = "verify-only"))]
+#[test]
+fn offline_exchange_test() {
+    use k256::elliptic_curve::Field;
+    use k256::Scalar;
+    use crate::utilities::rng::ProtocolRng;
+
+    let dir = std::env::temp_dir().join(format!("offline_exchange_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let mut one = OfflineExchange::new(&dir, [7u8; 32]);
+    let mut two = OfflineExchange::new(&dir, [7u8; 32]);
+
+    let s = Scalar::random(&mut ProtocolRng);
+    assert_eq!(one.read::<Scalar>(MessageKind::PartialSig).unwrap(), None);
+    two.write(MessageKind::PartialSig, &s).unwrap();
+    assert_eq!(one.read::<Scalar>(MessageKind::PartialSig).unwrap(), Some(s));
+    // another session's files are not picked up
+    let mut other = OfflineExchange::new(&dir, [8u8; 32]);
+    assert_eq!(other.read::<Scalar>(MessageKind::PartialSig).unwrap(), None);
+
+    one.abort(MessageKind::PartialSig, MulEcdsaError::ZeroScalar);
+    assert_eq!(two.read::<Scalar>(MessageKind::PartialSig), Err(MulEcdsaError::PeerAborted));
+    assert_eq!(other.cancel(), MulEcdsaError::Cancelled);
+    assert_eq!(
+        OfflineExchange::new(&dir, [8u8; 32]).read::<Scalar>(MessageKind::PartialSig),
+        Err(MulEcdsaError::PeerCancelled)
+    );
+    fs::remove_dir_all(&dir).unwrap();
+
+    let bytes: Vec<u8> = (0..=255).collect();
+    let mut chunks = to_chunks(&bytes, 100);
+    assert_eq!(chunks.len(), 3);
+    chunks.reverse();
+    assert_eq!(from_chunks(&chunks).unwrap(), bytes);
+    assert_eq!(from_chunks(&chunks[1..]), Err(MulEcdsaError::TransportFailed));
+    let mut tampered = chunks.clone();
+    let last = tampered[0].pop().unwrap();
+    tampered[0].push(if last == '0' { '1' } else { '0' });
+    assert_eq!(from_chunks(&tampered), Err(MulEcdsaError::TransportFailed));
+    let mut mixed = chunks.clone();
+    mixed[0] = to_chunks(&[1, 2, 3], 100).remove(0);
+    assert_eq!(from_chunks(&mixed), Err(MulEcdsaError::TransportFailed));
+    // the count is checked before anything is allocated for it
+    for huge in ["MPC:00000000:1/18446744073709551615:00", "MPC:00000000:1/16385:00"] {
+        assert_eq!(from_chunks(&[huge]), Err(MulEcdsaError::TransportFailed));
+    }
+}
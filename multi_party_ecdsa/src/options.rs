@@ -0,0 +1,377 @@
+//! Session options, set through a builder and checked once in `build`
+//! rather than mid-protocol. Options added later get a default and a
+//! builder method, so existing callers keep compiling.
+
+use crate::mta;
+use crate::nonce_registry::NonceRegistry;
+use crate::party_one;
+use crate::party_two;
+use crate::shared::{KeyStore, ProtocolProfile};
+use crate::utilities::class_group::CLGroup;
+use crate::utilities::error::MulEcdsaError;
+use crate::utilities::signature::{LowSPolicy, MessageDigest};
+use crate::utilities::ProtocolParams;
+use k256::Scalar;
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+fn check_timeout(timeout: Option<Duration>) -> Result<(), MulEcdsaError> {
+    match timeout {
+        Some(timeout) if timeout.is_zero() => Err(MulEcdsaError::InvalidProtocolParams),
+        _ => Ok(()),
+    }
+}
+
+fn set_stream_timeout(stream: &TcpStream, timeout: Option<Duration>) -> Result<(), MulEcdsaError> {
+    stream
+        .set_read_timeout(timeout)
+        .and_then(|_| stream.set_write_timeout(timeout))
+        .map_err(|_| MulEcdsaError::TransportFailed)
+}
+
+#[derive(Clone, Debug)]
+pub struct SignOptions {
+    pub profile: ProtocolProfile,
+    /// Hashes the message into the digest the parties sign.
+    pub digest: MessageDigest,
+    pub low_s: LowSPolicy,
+    /// For the CL work of the session; both parties must use the same.
+    pub params: ProtocolParams,
+    /// For the transport carrying the session, per message; see
+    /// `set_stream_timeout`.
+    pub timeout: Option<Duration>,
+    pub nonce_registry: Option<Arc<Mutex<dyn NonceRegistry>>>,
+    /// See `party_two::Sign::for_session`.
+    pub session_id: Option<Vec<u8>>,
+    /// See `party_two::Sign::for_labeled_session`.
+    pub label: Option<String>,
+    /// Seeds `ProtocolRng` in `run`.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub seed: Option<u64>,
+}
+
+impl SignOptions {
+    pub fn builder() -> SignOptionsBuilder {
+        SignOptionsBuilder::default()
+    }
+
+    pub fn message_hash(&self, message: &[u8]) -> [u8; 32] {
+        self.digest.digest(message)
+    }
+
+    /// A party one session for signing `message`.
+    pub fn party_one(&self, key_store: KeyStore, message: &[u8]) -> Result<party_one::Sign, MulEcdsaError> {
+        let mut sign = party_one::Sign::new(key_store)?
+            .with_profile(self.profile)
+            .with_params(self.params)
+            .with_low_s(self.low_s);
+        if let Some(registry) = &self.nonce_registry {
+            sign = sign.with_nonce_registry(registry.clone());
+        }
+        let message_hash = self.message_hash(message);
+        Ok(match (&self.session_id, &self.label) {
+            (Some(id), Some(label)) => sign.for_labeled_session(id, &message_hash, label),
+            (Some(id), None) => sign.for_session(id, &message_hash),
+            _ => sign,
+        })
+    }
+
+    /// A party two session for signing `message`.
+    pub fn party_two(&self, key_store: KeyStore, message: &[u8]) -> Result<party_two::Sign, MulEcdsaError> {
+        let mut sign = party_two::Sign::new(key_store)?.with_profile(self.profile);
+        if let Some(registry) = &self.nonce_registry {
+            sign = sign.with_nonce_registry(registry.clone());
+        }
+        let message_hash = self.message_hash(message);
+        Ok(match (&self.session_id, &self.label) {
+            (Some(id), Some(label)) => sign.for_labeled_session(id, &message_hash, label),
+            (Some(id), None) => sign.for_session(id, &message_hash),
+            _ => sign,
+        })
+    }
+
+    /// The group for the MtA, with the profile's `ClOptions`.
+    pub fn cl_group(&self) -> CLGroup {
+        CLGroup::new_with_params(self.params).with_options(self.profile.cl_options())
+    }
+
+    /// Party one's side of the MtA, multiplying `b`.
+    pub fn mta_party_one(&self, b: Scalar) -> mta::PartyOne {
+        mta::PartyOne::new_with_params(b, self.params).with_options(self.profile.cl_options())
+    }
+
+    /// Party two's side of the MtA, multiplying `a`.
+    pub fn mta_party_two(&self, a: Scalar) -> mta::PartyTwo {
+        mta::PartyTwo::new(a).with_params(self.params)
+    }
+
+    /// Makes reads and writes on `stream` fail after `timeout`, or never if
+    /// none is set.
+    pub fn set_stream_timeout(&self, stream: &TcpStream) -> Result<(), MulEcdsaError> {
+        set_stream_timeout(stream, self.timeout)
+    }
+
+    /// Runs `f`, with `ProtocolRng` seeded if a seed is set.
+    pub fn run<T>(&self, f: impl FnOnce() -> T) -> T {
+        #[cfg(any(test, feature = "test-utils"))]
+        if let Some(seed) = self.seed {
+            return crate::utilities::rng::SeededRun::new(seed).run(f);
+        }
+        f()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SignOptionsBuilder {
+    options: SignOptions,
+    security_level: usize,
+}
+
+impl Default for SignOptionsBuilder {
+    fn default() -> Self {
+        Self {
+            options: SignOptions {
+                profile: ProtocolProfile::default(),
+                digest: MessageDigest::Sha256,
+                low_s: LowSPolicy::default(),
+                params: ProtocolParams::default(),
+                timeout: None,
+                nonce_registry: None,
+                session_id: None,
+                label: None,
+                #[cfg(any(test, feature = "test-utils"))]
+                seed: None,
+            },
+            security_level: ProtocolParams::default().computational_security,
+        }
+    }
+}
+
+impl SignOptionsBuilder {
+    pub fn profile(mut self, profile: ProtocolProfile) -> Self {
+        self.options.profile = profile;
+        self
+    }
+
+    pub fn strict(self) -> Self {
+        self.profile(ProtocolProfile::Strict)
+    }
+
+    pub fn digest(mut self, digest: MessageDigest) -> Self {
+        self.options.digest = digest;
+        self
+    }
+
+    pub fn low_s(mut self, low_s: LowSPolicy) -> Self {
+        self.options.low_s = low_s;
+        self
+    }
+
+    /// Computational security in bits, see `ProtocolParams::with_security_level`.
+    pub fn security_level(mut self, bits: usize) -> Self {
+        self.security_level = bits;
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.options.timeout = Some(timeout);
+        self
+    }
+
+    pub fn nonce_registry(mut self, registry: Arc<Mutex<dyn NonceRegistry>>) -> Self {
+        self.options.nonce_registry = Some(registry);
+        self
+    }
+
+    pub fn session_id(mut self, session_id: &[u8]) -> Self {
+        self.options.session_id = Some(session_id.to_vec());
+        self
+    }
+
+    pub fn label(mut self, label: &str) -> Self {
+        self.options.label = Some(label.to_string());
+        self
+    }
+
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.options.seed = Some(seed);
+        self
+    }
+
+    /// Fails on an unsupported security level, a zero timeout, a label
+    /// without a session id, or a strict profile without the session id and
+    /// nonce registry it requires.
+    pub fn build(self) -> Result<SignOptions, MulEcdsaError> {
+        let mut options = self.options;
+        options.params = ProtocolParams::with_security_level(self.security_level)?;
+        check_timeout(options.timeout)?;
+        if options.session_id.is_none() && (options.label.is_some() || options.profile.is_strict()) {
+            return Err(MulEcdsaError::MissingSessionBinding);
+        }
+        if options.profile.is_strict() && options.nonce_registry.is_none() {
+            return Err(MulEcdsaError::MissingNonceRegistry);
+        }
+        Ok(options)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct KeygenOptions {
+    /// See `party_one::KeyGen::new_parallel`.
+    pub parallel: bool,
+    /// See `party_one::KeyGen::params`.
+    pub params: ProtocolParams,
+    /// See `SignOptions::timeout`.
+    pub timeout: Option<Duration>,
+    /// Seeds `ProtocolRng` in `run`.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub seed: Option<u64>,
+}
+
+impl KeygenOptions {
+    pub fn builder() -> KeygenOptionsBuilder {
+        KeygenOptionsBuilder::default()
+    }
+
+    pub fn party_one(&self) -> party_one::KeyGen {
+        party_one::KeyGen::new_with_params(self.params, self.parallel)
+    }
+
+    pub fn party_two(&self) -> party_two::KeyGen {
+        let keygen = match self.parallel {
+            true => party_two::KeyGen::new_parallel(),
+            false => party_two::KeyGen::new(),
+        };
+        keygen.with_params(self.params)
+    }
+
+    /// See `SignOptions::set_stream_timeout`.
+    pub fn set_stream_timeout(&self, stream: &TcpStream) -> Result<(), MulEcdsaError> {
+        set_stream_timeout(stream, self.timeout)
+    }
+
+    /// See `SignOptions::run`.
+    pub fn run<T>(&self, f: impl FnOnce() -> T) -> T {
+        #[cfg(any(test, feature = "test-utils"))]
+        if let Some(seed) = self.seed {
+            return crate::utilities::rng::SeededRun::new(seed).run(f);
+        }
+        f()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct KeygenOptionsBuilder {
+    options: KeygenOptions,
+    security_level: usize,
+}
+
+impl Default for KeygenOptionsBuilder {
+    fn default() -> Self {
+        Self {
+            options: KeygenOptions {
+                parallel: false,
+                params: ProtocolParams::default(),
+                timeout: None,
+                #[cfg(any(test, feature = "test-utils"))]
+                seed: None,
+            },
+            security_level: ProtocolParams::default().computational_security,
+        }
+    }
+}
+
+impl KeygenOptionsBuilder {
+    pub fn parallel(mut self, parallel: bool) -> Self {
+        self.options.parallel = parallel;
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.options.timeout = Some(timeout);
+        self
+    }
+
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.options.seed = Some(seed);
+        self
+    }
+
+    /// See `SignOptionsBuilder::security_level`.
+    pub fn security_level(mut self, bits: usize) -> Self {
+        self.security_level = bits;
+        self
+    }
+
+    pub fn build(self) -> Result<KeygenOptions, MulEcdsaError> {
+        let mut options = self.options;
+        options.params = ProtocolParams::with_security_level(self.security_level)?;
+        check_timeout(options.timeout)?;
+        Ok(options)
+    }
+}
+
+#[test]
+fn options_builder_test() {
+    use crate::nonce_registry::MemoryNonceRegistry;
+
+    let registry: Arc<Mutex<dyn NonceRegistry>> = Arc::new(Mutex::new(MemoryNonceRegistry::default()));
+    let options = SignOptions::builder()
+        .strict()
+        .digest(MessageDigest::Keccak256)
+        .session_id(b"session")
+        .label("withdrawal-batch-2024-06")
+        .nonce_registry(registry.clone())
+        .timeout(Duration::from_secs(30))
+        .security_level(192)
+        .low_s(LowSPolicy::Preserve)
+        .build()
+        .unwrap();
+    assert_eq!(options.message_hash(b"msg"), MessageDigest::Keccak256.digest(b"msg"));
+    assert!(options.cl_group().options.blind_decryption);
+    assert_eq!(options.cl_group().params.computational_security, 192);
+    assert_eq!(options.mta_party_two(Scalar::ONE).params, options.params);
+    let key_store = {
+        use k256::elliptic_curve::Field;
+        use k256::{ProjectivePoint, Scalar};
+        let (x1, x2) = (Scalar::random(&mut crate::utilities::rng::ProtocolRng), Scalar::ONE);
+        KeyStore::new(x1, ProjectivePoint::GENERATOR, ProjectivePoint::GENERATOR * (x1 + x2)).unwrap()
+    };
+    let sign = options.party_one(key_store, b"msg").unwrap();
+    assert!(sign.strict && sign.nonce_registry.is_some());
+    assert_eq!((sign.params, sign.low_s), (options.params, LowSPolicy::Preserve));
+    assert_eq!(sign.label.as_deref(), Some("withdrawal-batch-2024-06"));
+
+    for (builder, err) in [
+        (SignOptions::builder().strict().nonce_registry(registry.clone()), MulEcdsaError::MissingSessionBinding),
+        (SignOptions::builder().strict().session_id(b"session"), MulEcdsaError::MissingNonceRegistry),
+        (SignOptions::builder().label("label"), MulEcdsaError::MissingSessionBinding),
+        (SignOptions::builder().timeout(Duration::ZERO), MulEcdsaError::InvalidProtocolParams),
+        (SignOptions::builder().security_level(100), MulEcdsaError::InvalidProtocolParams),
+    ] {
+        assert_eq!(builder.build().unwrap_err(), err);
+    }
+
+    let keygen = KeygenOptions::builder().parallel(true).security_level(112).seed(7).build().unwrap();
+    let (a, b) = (keygen.run(|| keygen.party_two()), keygen.run(|| keygen.party_two()));
+    assert!(a.parallel);
+    assert_eq!(a.params.computational_security, 112);
+    assert_eq!(a.secret_share, b.secret_share);
+    for builder in [
+        KeygenOptions::builder().timeout(Duration::ZERO),
+        KeygenOptions::builder().security_level(256),
+    ] {
+        assert_eq!(builder.build().unwrap_err(), MulEcdsaError::InvalidProtocolParams);
+    }
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    options.set_stream_timeout(&stream).unwrap();
+    assert_eq!(stream.read_timeout().unwrap(), Some(Duration::from_secs(30)));
+    assert_eq!(stream.write_timeout().unwrap(), Some(Duration::from_secs(30)));
+    keygen.set_stream_timeout(&stream).unwrap();
+    assert_eq!(stream.read_timeout().unwrap(), None);
+}
@@ -1,14 +1,23 @@
 use crate::utilities::dl_com_zk::*;
 use crate::utilities::signature::*;
 use crate::utilities::error::MulEcdsaError;
-use crate::utilities::k256_helpers::DLogProof;
+use crate::utilities::k256_helpers::{check_not_identity, check_not_zero, is_low_s, reduce_bytes_to_scalar, DLogProof};
 use k256::{Scalar, ProjectivePoint};
 use k256::elliptic_curve::Field;
-use crate::utilities::class_group::{scalar_to_bigint, scalar_from_bigint};
-use num_bigint::BigInt;
-use rand::rngs::OsRng;
-use std::cmp;
+use crate::utilities::rng::ProtocolRng;
 use crate::shared::*;
+use crate::mta::MTASecondRoundMsg;
+use crate::utilities::class_group::CLGroup;
+use crate::utilities::ProtocolParams;
+use crate::utilities::cl_proof::CLState;
+use crate::utilities::cl_dl_proof::*;
+use crate::utilities::cl_key_proof::ClKeyProof;
+use classgroup::gmp::mpz::Mpz;
+use classgroup::gmp_classgroup::GmpClassGroup;
+use crate::secret_store::*;
+use crate::authorization::RequestAuthorization;
+use crate::nonce_registry::NonceRegistry;
+use std::sync::{Arc, Mutex};
 
 #[derive(Clone, Debug)]
 pub struct Sign {
@@ -16,46 +25,166 @@ pub struct Sign {
     pub reshared_secret_share: Scalar,
     pub reshared_public_share: ProjectivePoint,
     pub key_store: KeyStore,
+    pub secret_store: Arc<dyn SecretStore>,
     pub nonce_secret_share: Scalar,
     pub nonce_public_share: ProjectivePoint,
     pub r1: Scalar,
     pub r_x: Scalar,
+    pub r_point: ProjectivePoint,
     pub dl_proof: DLogProof<ProjectivePoint>,
+    /// Strict mode requires an MtAwc proof binding party two's MtA input to
+    /// its nonce share.
+    pub strict: bool,
+    pub mta_wc_check: Option<(CLState, MTASecondRoundMsg)>,
+    /// Checked and updated as soon as `R` is known.
+    pub nonce_registry: Option<Arc<Mutex<dyn NonceRegistry>>>,
+    /// Expected in party two's nonce commitment, see `for_session`.
+    pub session_context: [u8; 32],
+    /// See `for_labeled_session`.
+    pub label: Option<String>,
+    /// Set by `with_authorization`; `online_sign` refuses any other message.
+    pub authorized_message: Option<Vec<u8>>,
+    pub profile: ProtocolProfile,
+    /// For checking the MtAwc proof; see `mta::PartyOne::params`.
+    pub params: ProtocolParams,
+    pub low_s: LowSPolicy,
+    /// Set by `cancel`.
+    pub cancelled: bool,
 }
 
 impl Sign {
+    /// Every constructor takes the key store and validates it, so no round
+    /// can run without one.
     pub fn new(key_store: KeyStore) -> Result<Self, MulEcdsaError> {
-        let reshared_secret_share = Scalar::random(&mut OsRng);
+        key_store.validate()?;
+        let secret_store = Arc::new(SoftwareSecretStore::new(key_store.secret_share));
+        Self::with_secret_store(key_store, secret_store)
+    }
+
+    /// `new`, refusing a key that `policy` says must be refreshed first.
+    pub fn with_refresh_policy(
+        key_store: KeyStore,
+        policy: &RefreshPolicy,
+        now: u64,
+    ) -> Result<Self, MulEcdsaError> {
+        key_store.check_refresh(policy, now)?;
+        Self::new(key_store)
+    }
+
+    /// Signs with the share held in `secret_store`; only the public part of
+    /// `key_store` is used, and its `secret_share` is zeroed so the `Sign`
+    /// keeps no copy of the share outside the store.
+    pub fn with_secret_store(
+        mut key_store: KeyStore,
+        secret_store: Arc<dyn SecretStore>,
+    ) -> Result<Self, MulEcdsaError> {
+        check_secret_store(secret_store.as_ref(), &key_store)?;
+        key_store.secret_share = Scalar::ZERO;
+        let reshared_secret_share = Scalar::random(&mut ProtocolRng);
         let reshared_public_share = ProjectivePoint::GENERATOR * reshared_secret_share;
         
-        let nonce_secret_share = Scalar::random(&mut OsRng);
+        let nonce_secret_share = Scalar::random(&mut ProtocolRng);
         let nonce_public_share = ProjectivePoint::GENERATOR * nonce_secret_share;
-        let dl_proof = DLogProof::<ProjectivePoint>::prove(&nonce_secret_share);
+        let dl_proof = DLogProof::<ProjectivePoint>::prove_in_context(&nonce_secret_share, &[0u8; 32]);
         
         let ret = Self {
             dl_com_zk_com_rec: DLCommitments::default(),
             reshared_secret_share,
             reshared_public_share,
             key_store,
+            secret_store,
             nonce_secret_share,
             nonce_public_share,
-            r1: Scalar::random(&mut OsRng),
-            r_x: Scalar::random(&mut OsRng),
+            r1: Scalar::random(&mut ProtocolRng),
+            r_x: Scalar::random(&mut ProtocolRng),
+            r_point: ProjectivePoint::IDENTITY,
             dl_proof,
+            strict: false,
+            mta_wc_check: None,
+            nonce_registry: None,
+            session_context: [0u8; 32],
+            label: None,
+            authorized_message: None,
+            profile: ProtocolProfile::Fast,
+            params: ProtocolParams::default(),
+            low_s: LowSPolicy::Normalize,
+            cancelled: false,
         };
         Ok(ret)
     }
 
+    pub fn new_strict(key_store: KeyStore) -> Result<Self, MulEcdsaError> {
+        let mut ret = Self::new(key_store)?;
+        ret.strict = true;
+        Ok(ret)
+    }
+
+    /// Also sets `strict` from the profile.
+    pub fn with_profile(mut self, profile: ProtocolProfile) -> Self {
+        self.profile = profile;
+        self.strict = profile.is_strict();
+        self
+    }
+
+    pub fn with_nonce_registry(mut self, registry: Arc<Mutex<dyn NonceRegistry>>) -> Self {
+        self.nonce_registry = Some(registry);
+        self
+    }
+
+    pub fn with_params(mut self, params: ProtocolParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    pub fn with_low_s(mut self, low_s: LowSPolicy) -> Self {
+        self.low_s = low_s;
+        self
+    }
+
+    /// See `party_two::Sign::for_session`.
+    pub fn for_session(self, session_id: &[u8], message_hash: &[u8]) -> Self {
+        self.with_session_context(session_context(session_id, message_hash))
+    }
+
+    /// Requires `approver`'s `authorization` to sign `message_hash` with this
+    /// key, checked before any signing round, and then signs nothing else.
+    pub fn with_authorization(
+        mut self,
+        approver: &ProjectivePoint,
+        authorization: &RequestAuthorization,
+        message_hash: &[u8],
+    ) -> Result<Self, MulEcdsaError> {
+        authorization.verify(approver, &self.key_store.public_key_fingerprint(), message_hash)?;
+        self.authorized_message = Some(message_hash.to_vec());
+        Ok(self)
+    }
+
+    /// See `party_two::Sign::for_labeled_session`.
+    pub fn for_labeled_session(mut self, session_id: &[u8], message_hash: &[u8], label: &str) -> Self {
+        self.label = Some(label.to_string());
+        self.with_session_context(labeled_session_context(session_id, message_hash, label))
+    }
+
+    /// The nonce proof is bound to the session as well.
+    fn with_session_context(mut self, context: [u8; 32]) -> Self {
+        self.session_context = context;
+        self.dl_proof = DLogProof::prove_in_context(&self.nonce_secret_share, &context);
+        self
+    }
+
+    /// Keep the MtAwc message until party two's nonce point is opened.
+    pub fn set_mta_wc_msg(&mut self, first_round: &CLState, msg: &MTASecondRoundMsg) {
+        self.mta_wc_check = Some((first_round.clone(), msg.clone()));
+    }
+
     pub fn get_nonce_com(&mut self, dl_com_zk_com_rec: &DLCommitments) {
         self.dl_com_zk_com_rec = (*dl_com_zk_com_rec).clone();
     }
 
     pub fn generate_mta_consistency(&self, t_a: Scalar) -> MtaConsistencyMsg {
-        let cc: Scalar = t_a
-            + self.reshared_secret_share * self.r1
-            - self
-                .key_store
-                .secret_share;
+        let cc: Scalar = -self
+            .secret_store
+            .share_minus(&(t_a + self.reshared_secret_share * self.r1));
         MtaConsistencyMsg {
             reshared_public_share: self.reshared_public_share,
             r1: self.r1,
@@ -72,45 +201,205 @@ impl Sign {
     }
 
     pub fn verify_nonce_ke_msg(&mut self, nonce_ke_rec: &CommWitness) -> Result<(), MulEcdsaError> {
-        DLComZK::verify(&self.dl_com_zk_com_rec, nonce_ke_rec)?;
+        self.profile
+            .check_nonce_setup(&self.session_context, self.nonce_registry.is_some())?;
+        check_not_identity(&nonce_ke_rec.public_share)?;
+        DLComZK::verify(&self.dl_com_zk_com_rec, nonce_ke_rec, &self.session_context)?;
         DLogProof::verify(&nonce_ke_rec.d_log_proof, &nonce_ke_rec.public_share).map_err(|_| MulEcdsaError::VrfyDlogFailed)?;
+        match &self.mta_wc_check {
+            Some((first_round, msg)) => {
+                msg.verify(&CLGroup::new_with_params(self.params), first_round, &nonce_ke_rec.public_share)?
+            }
+            None if self.strict => return Err(MulEcdsaError::MissingMtAwcProof),
+            None => {}
+        }
         
         let r = nonce_ke_rec.public_share * self.nonce_secret_share
             + ProjectivePoint::GENERATOR * (self.nonce_secret_share * self.r1);
+        check_not_identity(&r)?;
+        if let Some(registry) = &self.nonce_registry {
+            registry
+                .lock()
+                .map_err(|_| MulEcdsaError::NonceRegistryFailed)?
+                .record(&self.key_store.public_key_fingerprint(), &r)?;
+        }
         
         // Get x-coordinate
         use k256::elliptic_curve::sec1::ToEncodedPoint;
         let affine = r.to_affine();
         let encoded = affine.to_encoded_point(false);
         let x_bytes = encoded.x().ok_or(MulEcdsaError::XcoorNone)?;
-        let x_bigint = BigInt::from_bytes_be(num_bigint::Sign::Plus, x_bytes);
         
-        self.r_x = scalar_from_bigint(&x_bigint);
+        self.r_x = reduce_bytes_to_scalar(x_bytes).0;
+        check_not_zero(&self.r_x)?;
+        self.r_point = r;
         Ok(())
     }
 
+    /// Ends the session midway: zeroes the nonce and reshared shares, the
+    /// MtA state and this copy of the key share, and swaps `secret_store`
+    /// for an empty one, after which `online_sign` fails with `Cancelled`.
+    /// Party two is told with a `CancelMsg`.
+    pub fn cancel(&mut self) {
+        self.cancelled = true;
+        self.nonce_secret_share = Scalar::ZERO;
+        self.reshared_secret_share = Scalar::ZERO;
+        self.r1 = Scalar::ZERO;
+        self.mta_wc_check = None;
+        self.key_store.secret_share = Scalar::ZERO;
+        self.secret_store = Arc::new(SoftwareSecretStore::new(Scalar::ZERO));
+    }
+
+    /// The signature, checked against the key; `s` is low unless `low_s`
+    /// is `Preserve`.
     pub fn online_sign(&self, s2_rec: &Scalar, message_bytes: &[u8]) -> Result<Signature, MulEcdsaError> {
+        if self.cancelled {
+            return Err(MulEcdsaError::Cancelled);
+        }
+        if matches!(&self.authorized_message, Some(authorized) if authorized != message_bytes) {
+            return Err(MulEcdsaError::Unauthorized);
+        }
+        check_not_zero(s2_rec)?;
         let s_tag = self.nonce_secret_share.invert().unwrap_or(Scalar::ZERO)
             * (*s2_rec + self.r_x * self.reshared_secret_share);
+        check_not_zero(&s_tag)?;
         
-        // secp256k1 order
-        let q = BigInt::parse_bytes(b"115792089237316195423570985008687907852837564279074904382605163141518161494337", 10).unwrap();
-        let s_tag_bigint = scalar_to_bigint(&s_tag);
-        let s = cmp::min(s_tag_bigint.clone(), &q - &s_tag_bigint);
+        // negating s negates the point the verifier recomputes
+        let (s, r_point) = if is_low_s(&s_tag) {
+            (s_tag, self.r_point)
+        } else {
+            (-s_tag, -self.r_point)
+        };
         
         let signature = Signature {
             r: self.r_x,
-            s: scalar_from_bigint(&s),
+            s,
+            r_point,
         };
 
-        // Process the message to sign - convert bytes to BigInt
-        let message_bigint = BigInt::from_bytes_be(num_bigint::Sign::Plus, message_bytes);
-        let message = scalar_from_bigint(&message_bigint);
+        let message = reduce_bytes_to_scalar(message_bytes).0;
 
         signature.verify(
             &self.key_store.public_signing_key,
             &message,
         )?;
+        if self.low_s == LowSPolicy::Preserve && s != s_tag {
+            return Ok(Signature {
+                s: s_tag,
+                r_point: self.r_point,
+                ..signature
+            });
+        }
         return Ok(signature);
     }
 }
+
+/// Party one of the XAX21 key generation. Party one commits to its share
+/// before seeing party two's, then opens it together with the share
+/// encrypted under its own CL key.
+#[derive(Clone, Debug)]
+pub struct KeyGen {
+    pub secret_share: Scalar,
+    pub public_share: ProjectivePoint,
+    pub dl_com_zk: DLComZK,
+    pub cl_priv_key: Mpz,
+    pub cl_pub_key: GmpClassGroup,
+    /// Run the CL work on a second thread, next to the EC work.
+    pub parallel: bool,
+    /// For the CL key and proofs; party two must use the same.
+    pub params: ProtocolParams,
+}
+
+impl KeyGen {
+    pub fn new() -> Self {
+        Self::new_with_params(ProtocolParams::default(), false)
+    }
+
+    /// Like `new`, but the CL key pair is generated alongside the share and
+    /// its commitment, and `verify_send_key_msg` proves on two threads.
+    pub fn new_parallel() -> Self {
+        Self::new_with_params(ProtocolParams::default(), true)
+    }
+
+    /// `new`, or `new_parallel` if `parallel`, with the CL work at `params`.
+    pub fn new_with_params(params: ProtocolParams, parallel: bool) -> Self {
+        let ((secret_share, public_share, dl_com_zk), (cl_priv_key, cl_pub_key)) = join(
+            parallel,
+            || {
+                let secret_share = Scalar::random(&mut ProtocolRng);
+                let public_share = ProjectivePoint::GENERATOR * secret_share;
+                let dl_com_zk = DLComZK::new(&secret_share, &public_share, &[0u8; 32]);
+                (secret_share, public_share, dl_com_zk)
+            },
+            || CLGroup::new_with_params(params).keygen(),
+        );
+        Self {
+            secret_share,
+            public_share,
+            dl_com_zk,
+            cl_priv_key,
+            cl_pub_key,
+            parallel,
+            params,
+        }
+    }
+
+    pub fn generate_key_com(&self) -> DLCommitments {
+        self.dl_com_zk.commitments.clone()
+    }
+
+    /// Checks party two's share and returns the final message along with
+    /// party one's key store.
+    pub fn verify_send_key_msg(
+        &self,
+        msg: &KeyGenPartyTwoMsg,
+    ) -> Result<(KeyGenPartyOneMsg, KeyStore), MulEcdsaError> {
+        msg.dl_proof
+            .verify(&msg.public_share)
+            .map_err(|_| MulEcdsaError::VrfyDlogFailed)?;
+        let group = CLGroup::new_with_params(self.params);
+        let ((encrypted_share, cldl_proof), cl_key_proof) = join(
+            self.parallel,
+            || {
+                let (encrypted_share, r) =
+                    CLGroup::encrypt(&group, &self.cl_pub_key, self.secret_share);
+                let statement = CLDLState {
+                    cipher: encrypted_share.clone(),
+                    cl_pub_key: self.cl_pub_key.clone(),
+                    dl_pub: self.public_share,
+                };
+                let witness = CLDLWit {
+                    dl_priv: self.secret_share,
+                    r,
+                };
+                (encrypted_share, CLDLProof::prove(&group, witness, statement))
+            },
+            || ClKeyProof::prove(&group, &self.cl_priv_key, &self.cl_pub_key),
+        );
+        let reply = KeyGenPartyOneMsg {
+            witness: self.dl_com_zk.witness.clone(),
+            cl_pub_key: self.cl_pub_key.clone(),
+            cl_key_proof,
+            encrypted_share,
+            cldl_proof,
+        };
+
+        let mut key_store = KeyStore::new(
+            self.secret_share,
+            msg.public_share,
+            self.public_share + msg.public_share,
+        )?;
+        key_store.keygen_transcript_hash = Some(keygen_transcript_hash(
+            &self.dl_com_zk.commitments,
+            msg,
+            &reply,
+        )?);
+        Ok((reply, key_store))
+    }
+}
+
+impl Default for KeyGen {
+    fn default() -> Self {
+        Self::new()
+    }
+}
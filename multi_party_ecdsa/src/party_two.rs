@@ -1,12 +1,16 @@
 use crate::utilities::dl_com_zk::*;
 use crate::utilities::error::MulEcdsaError;
-use crate::utilities::k256_helpers::DLogProof;
+use crate::utilities::k256_helpers::{check_not_identity, check_not_zero, reduce_bytes_to_scalar, DLogProof};
 use k256::{Scalar, ProjectivePoint};
 use k256::elliptic_curve::Field;
-use crate::utilities::class_group::scalar_from_bigint;
-use num_bigint::BigInt;
-use rand::rngs::OsRng;
+use crate::utilities::rng::ProtocolRng;
 use crate::shared::*;
+use crate::utilities::class_group::{CLGroup, Ciphertext};
+use crate::utilities::ProtocolParams;
+use crate::utilities::cl_dl_proof::CLDLState;
+use crate::secret_store::*;
+use crate::nonce_registry::NonceRegistry;
+use std::sync::{Arc, Mutex};
 
 #[derive(Clone, Debug)]
 pub struct Sign {
@@ -14,31 +18,113 @@ pub struct Sign {
     pub nonce_public_share: ProjectivePoint,
     pub dl_com_zk_com: DLComZK,
     pub key_store: KeyStore,
+    pub secret_store: Arc<dyn SecretStore>,
     pub reshared_secret_share: Scalar,
     pub r1_rec: Scalar,
     pub r_x: Scalar,
     pub msg_set: bool,
+    /// See `for_labeled_session`.
+    pub label: Option<String>,
+    /// The message hash given to `for_session`; `online_sign` signs
+    /// nothing else.
+    pub session_message: Option<Vec<u8>>,
+    /// Checked and updated before the nonce commitment is opened.
+    pub nonce_registry: Option<Arc<Mutex<dyn NonceRegistry>>>,
+    pub profile: ProtocolProfile,
+    /// Set by `cancel`.
+    pub cancelled: bool,
+    /// Set by `online_sign`; the nonce signs one message only.
+    pub signed: bool,
 }
 
 impl Sign {
     pub fn new(key_store: KeyStore) -> Result<Self, MulEcdsaError> {
-        let nonce_secret_share = Scalar::random(&mut OsRng);
+        key_store.validate()?;
+        let secret_store = Arc::new(SoftwareSecretStore::new(key_store.secret_share));
+        Self::with_secret_store(key_store, secret_store)
+    }
+
+    /// `new`, refusing a key that `policy` says must be refreshed first.
+    pub fn with_refresh_policy(
+        key_store: KeyStore,
+        policy: &RefreshPolicy,
+        now: u64,
+    ) -> Result<Self, MulEcdsaError> {
+        key_store.check_refresh(policy, now)?;
+        Self::new(key_store)
+    }
+
+    /// See `party_one::Sign::with_secret_store`.
+    pub fn with_secret_store(
+        mut key_store: KeyStore,
+        secret_store: Arc<dyn SecretStore>,
+    ) -> Result<Self, MulEcdsaError> {
+        check_secret_store(secret_store.as_ref(), &key_store)?;
+        key_store.secret_share = Scalar::ZERO;
+        let nonce_secret_share = Scalar::random(&mut ProtocolRng);
         let nonce_public_share = ProjectivePoint::GENERATOR * nonce_secret_share;
-        let dl_com_zk_com = DLComZK::new(&nonce_secret_share, &nonce_public_share);
+        let dl_com_zk_com = DLComZK::new(&nonce_secret_share, &nonce_public_share, &[0u8; 32]);
         
         let ret = Self {
             nonce_secret_share,
             nonce_public_share,
             dl_com_zk_com: dl_com_zk_com,
             key_store,
-            reshared_secret_share: Scalar::random(&mut OsRng),
-            r1_rec: Scalar::random(&mut OsRng),
-            r_x: Scalar::random(&mut OsRng),
+            secret_store,
+            reshared_secret_share: Scalar::random(&mut ProtocolRng),
+            r1_rec: Scalar::random(&mut ProtocolRng),
+            // set by `verify_send_nonce_ke_msg`, see `Presignature::party_two`
+            r_x: Scalar::ZERO,
             msg_set: false,
+            label: None,
+            session_message: None,
+            nonce_registry: None,
+            profile: ProtocolProfile::Fast,
+            cancelled: false,
+            signed: false,
         };
         Ok(ret)
     }
 
+    pub fn with_profile(mut self, profile: ProtocolProfile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    pub fn with_nonce_registry(mut self, registry: Arc<Mutex<dyn NonceRegistry>>) -> Self {
+        self.nonce_registry = Some(registry);
+        self
+    }
+
+    /// Binds the nonce commitment to `session_id` and `message_hash`; party
+    /// one must call its `for_session` with the same values. Without it the
+    /// commitment is bound to no session.
+    pub fn for_session(mut self, session_id: &[u8], message_hash: &[u8]) -> Self {
+        self.dl_com_zk_com = DLComZK::new(
+            &self.nonce_secret_share,
+            &self.nonce_public_share,
+            &session_context(session_id, message_hash),
+        );
+        self.session_message = Some(message_hash.to_vec());
+        self
+    }
+
+    /// `for_session` with a label naming what is being signed, e.g.
+    /// "withdrawal-batch-2024-06". Party one must call its
+    /// `for_labeled_session` with the same label; the label is bound into
+    /// the nonce commitment and party one's nonce proof, and a watcher with
+    /// the same label records it in its transcript.
+    pub fn for_labeled_session(mut self, session_id: &[u8], message_hash: &[u8], label: &str) -> Self {
+        self.dl_com_zk_com = DLComZK::new(
+            &self.nonce_secret_share,
+            &self.nonce_public_share,
+            &labeled_session_context(session_id, message_hash, label),
+        );
+        self.label = Some(label.to_string());
+        self.session_message = Some(message_hash.to_vec());
+        self
+    }
+
     pub fn generate_nonce_com(&self) -> DLCommitments {
         self.dl_com_zk_com.commitments.clone()
     }
@@ -48,6 +134,12 @@ impl Sign {
         t_b: Scalar,
         mta_consis_rec: &MtaConsistencyMsg,
     ) -> Result<(), String> {
+        check_not_zero(&mta_consis_rec.r1)
+            .and(check_not_zero(&mta_consis_rec.cc))
+            .and(check_not_zero(&(mta_consis_rec.r1 + self.nonce_secret_share)))
+            .and(check_not_identity(&mta_consis_rec.reshared_public_share))
+            .and(check_not_identity(&mta_consis_rec.public_key))
+            .map_err(|e| e.to_string())?;
         if ProjectivePoint::GENERATOR * (t_b + mta_consis_rec.cc)
             != mta_consis_rec.reshared_public_share
                 * (mta_consis_rec.r1 + self.nonce_secret_share)
@@ -56,10 +148,8 @@ impl Sign {
             return Err("Verify Mta Consistency Failed".to_string());
         }
         let reshared_secret_share = self
-            .key_store
-            .secret_share
-            - t_b
-            - mta_consis_rec.cc;
+            .secret_store
+            .share_minus(&(t_b + mta_consis_rec.cc));
         self.reshared_secret_share = reshared_secret_share;
         self.r1_rec = mta_consis_rec.r1;
         Ok(())
@@ -69,27 +159,162 @@ impl Sign {
         &mut self,
         nonce_ke_rec: &NonceKEMsg,
     ) -> Result<CommWitness, String> {
-        DLogProof::verify(&nonce_ke_rec.dl_proof, &nonce_ke_rec.nonce_public_key).map_err(|_| "Verify DLog failed".to_string())?;
+        if self.cancelled {
+            return Err(MulEcdsaError::Cancelled.to_string());
+        }
+        self.profile
+            .check_nonce_setup(&self.dl_com_zk_com.context, self.nonce_registry.is_some())
+            .map_err(|e| e.to_string())?;
+        check_not_identity(&nonce_ke_rec.nonce_public_key).map_err(|e| e.to_string())?;
+        DLogProof::verify_in_context(&nonce_ke_rec.dl_proof, &nonce_ke_rec.nonce_public_key, &self.dl_com_zk_com.context).map_err(|_| "Verify DLog failed".to_string())?;
         let r = nonce_ke_rec.nonce_public_key * (self.r1_rec + self.nonce_secret_share);
+        check_not_identity(&r).map_err(|e| e.to_string())?;
+        if let Some(registry) = &self.nonce_registry {
+            registry
+                .lock()
+                .map_err(|_| MulEcdsaError::NonceRegistryFailed.to_string())?
+                .record(&self.key_store.public_key_fingerprint(), &r)
+                .map_err(|e| e.to_string())?;
+        }
         
         // Get x-coordinate
         use k256::elliptic_curve::sec1::ToEncodedPoint;
         let affine = r.to_affine();
         let encoded = affine.to_encoded_point(false);
         let x_bytes = encoded.x().ok_or("get x coor failed")?;
-        let x_bigint = BigInt::from_bytes_be(num_bigint::Sign::Plus, x_bytes);
         
-        self.r_x = scalar_from_bigint(&x_bigint);
+        self.r_x = reduce_bytes_to_scalar(x_bytes).0;
+        check_not_zero(&self.r_x).map_err(|e| e.to_string())?;
         Ok(self.dl_com_zk_com.witness.clone())
     }
 
-    pub fn online_sign(&self, message_bytes: &[u8]) -> Scalar {
-        // Process the message to sign - convert bytes to BigInt
-        let message_bigint = BigInt::from_bytes_be(num_bigint::Sign::Plus, message_bytes);
-        let message = scalar_from_bigint(&message_bigint);
+    /// See `party_one::Sign::cancel`; the nonce commitment is then never
+    /// opened and no partial signature is made.
+    pub fn cancel(&mut self) {
+        self.cancelled = true;
+        self.nonce_secret_share = Scalar::ZERO;
+        self.reshared_secret_share = Scalar::ZERO;
+        self.r1_rec = Scalar::ZERO;
+        self.key_store.secret_share = Scalar::ZERO;
+        self.secret_store = Arc::new(SoftwareSecretStore::new(Scalar::ZERO));
+    }
+
+    /// The partial signature, once per session: a second call fails with
+    /// `NonceReuse`, and a call before `verify_send_nonce_ke_msg` with
+    /// `MissingMsg`, as either would let party one solve for the nonce. A
+    /// session bound to a message fails with `Unauthorized` on any other.
+    pub fn online_sign(&mut self, message_bytes: &[u8]) -> Result<Scalar, MulEcdsaError> {
+        if self.cancelled {
+            return Err(MulEcdsaError::Cancelled);
+        }
+        if matches!(&self.session_message, Some(bound) if bound != message_bytes) {
+            return Err(MulEcdsaError::Unauthorized);
+        }
+        if self.signed {
+            return Err(MulEcdsaError::NonceReuse);
+        }
+        if self.r_x == Scalar::ZERO {
+            return Err(MulEcdsaError::MissingMsg);
+        }
+        let message = reduce_bytes_to_scalar(message_bytes).0;
 
         let s_2 = (self.r1_rec + self.nonce_secret_share).invert().unwrap_or(Scalar::ZERO)
             * (message + self.r_x * self.reshared_secret_share);
-        return s_2;
+        self.signed = true;
+        Ok(s_2)
+    }
+}
+
+/// Party two of the XAX21 key generation, see `party_one::KeyGen`.
+#[derive(Clone, Debug)]
+pub struct KeyGen {
+    pub secret_share: Scalar,
+    pub public_share: ProjectivePoint,
+    pub key_com_rec: Option<DLCommitments>,
+    pub key_msg: KeyGenPartyTwoMsg,
+    /// Party one's share under party one's CL key, once verified.
+    pub encrypted_share: Option<Ciphertext>,
+    /// Check party one's two CL proofs on separate threads.
+    pub parallel: bool,
+    /// See `party_one::KeyGen::params`.
+    pub params: ProtocolParams,
+}
+
+impl KeyGen {
+    /// Like `new`, with `verify_key_msg` checking the proofs in parallel.
+    pub fn new_parallel() -> Self {
+        Self {
+            parallel: true,
+            ..Self::new()
+        }
+    }
+
+    pub fn new() -> Self {
+        let secret_share = Scalar::random(&mut ProtocolRng);
+        let public_share = ProjectivePoint::GENERATOR * secret_share;
+        Self {
+            secret_share,
+            public_share,
+            key_com_rec: None,
+            key_msg: KeyGenPartyTwoMsg {
+                public_share,
+                dl_proof: DLogProof::prove(&secret_share),
+            },
+            encrypted_share: None,
+            parallel: false,
+            params: ProtocolParams::default(),
+        }
+    }
+
+    pub fn with_params(mut self, params: ProtocolParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    /// Records party one's share commitment; party two's share is only
+    /// sent after it.
+    pub fn get_key_com_send_key_msg(&mut self, key_com_rec: &DLCommitments) -> KeyGenPartyTwoMsg {
+        self.key_com_rec = Some(key_com_rec.clone());
+        self.key_msg.clone()
+    }
+
+    pub fn verify_key_msg(&mut self, msg: &KeyGenPartyOneMsg) -> Result<KeyStore, MulEcdsaError> {
+        let key_com_rec = self.key_com_rec.as_ref().ok_or(MulEcdsaError::MissingMsg)?;
+        DLComZK::verify(key_com_rec, &msg.witness, &[0u8; 32])?;
+        let party_one_share = msg.witness.public_share;
+        msg.witness
+            .d_log_proof
+            .verify(&party_one_share)
+            .map_err(|_| MulEcdsaError::VrfyDlogFailed)?;
+        let group = CLGroup::new_with_params(self.params);
+        let statement = CLDLState {
+            cipher: msg.encrypted_share.clone(),
+            cl_pub_key: msg.cl_pub_key.clone(),
+            dl_pub: party_one_share,
+        };
+        let (key_proof, cldl_proof) = join(
+            self.parallel,
+            || msg.cl_key_proof.verify(&group, &msg.cl_pub_key),
+            || msg.cldl_proof.verify(&group, statement),
+        );
+        key_proof?;
+        cldl_proof?;
+
+        let mut key_store = KeyStore::new(
+            self.secret_share,
+            party_one_share,
+            self.public_share + party_one_share,
+        )?;
+        key_store.counterparty_cl_pub_key = Some(msg.cl_pub_key.clone());
+        key_store.keygen_transcript_hash =
+            Some(keygen_transcript_hash(key_com_rec, &self.key_msg, msg)?);
+        self.encrypted_share = Some(msg.encrypted_share.clone());
+        Ok(key_store)
+    }
+}
+
+impl Default for KeyGen {
+    fn default() -> Self {
+        Self::new()
     }
 }
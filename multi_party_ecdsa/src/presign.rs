@@ -0,0 +1,322 @@
+//! Presignatures: a party's state once the nonce exchange is done, kept to
+//! finish a signature later with `online_sign` alone. Only sessions not
+//! bound to a message with `for_session` make sense to keep.
+//!
+//! `PresignaturePool` keeps them as files in a directory. `take` claims a
+//! presignature by renaming its file before reading it, so two takers never
+//! get the same one, and leaves the renamed file behind so the id cannot be
+//! inserted again. A presignature records the party's public share, which a
+//! refresh changes, so presignatures from before a refresh are refused.
+//! Restoring the directory from a backup brings consumed presignatures
+//! back; a `NonceRegistry` kept elsewhere still catches their reuse.
+//!
+//! A presignature file holds the nonce and reshared secret shares in the
+//! clear, so the directory needs the same care as a key store. On unix the
+//! files are created readable by the owner only.
+
+use crate::party_one;
+use crate::party_two;
+use crate::shared::KeyStore;
+use crate::utilities::error::MulEcdsaError;
+use bincode::config::standard;
+use k256::{ProjectivePoint, Scalar};
+use serde::{Deserialize, Serialize};
+use std::convert::TryInto;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+
+const AVAILABLE: &str = "presig";
+const CONSUMED: &str = "consumed";
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PresignatureShare {
+    PartyOne {
+        nonce_secret_share: Scalar,
+        reshared_secret_share: Scalar,
+        r_x: Scalar,
+        #[serde(with = "crate::utilities::point_serde")]
+        r_point: ProjectivePoint,
+    },
+    PartyTwo {
+        nonce_secret_share: Scalar,
+        reshared_secret_share: Scalar,
+        r1_rec: Scalar,
+        r_x: Scalar,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Presignature {
+    /// `r` of the signature it finishes, which both parties know.
+    pub id: [u8; 32],
+    /// See `KeyStore::public_key_fingerprint`.
+    pub key_id: [u8; 32],
+    /// The party's own public share when the presignature was made.
+    #[serde(with = "crate::utilities::point_serde")]
+    pub public_share: ProjectivePoint,
+    /// Seconds since the Unix epoch.
+    pub expires_at: u64,
+    pub share: PresignatureShare,
+}
+
+impl Presignature {
+    /// Party one's state after `verify_nonce_ke_msg`.
+    pub fn party_one(sign: &party_one::Sign, expires_at: u64) -> Result<Self, MulEcdsaError> {
+        if sign.cancelled || sign.r_point == ProjectivePoint::IDENTITY {
+            return Err(MulEcdsaError::MissingMsg);
+        }
+        Ok(Self::new(
+            &sign.key_store,
+            expires_at,
+            PresignatureShare::PartyOne {
+                nonce_secret_share: sign.nonce_secret_share,
+                reshared_secret_share: sign.reshared_secret_share,
+                r_x: sign.r_x,
+                r_point: sign.r_point,
+            },
+        ))
+    }
+
+    /// Party two's state after `verify_send_nonce_ke_msg`.
+    pub fn party_two(sign: &party_two::Sign, expires_at: u64) -> Result<Self, MulEcdsaError> {
+        if sign.cancelled || sign.r_x == Scalar::ZERO {
+            return Err(MulEcdsaError::MissingMsg);
+        }
+        Ok(Self::new(
+            &sign.key_store,
+            expires_at,
+            PresignatureShare::PartyTwo {
+                nonce_secret_share: sign.nonce_secret_share,
+                reshared_secret_share: sign.reshared_secret_share,
+                r1_rec: sign.r1_rec,
+                r_x: sign.r_x,
+            },
+        ))
+    }
+
+    fn new(key_store: &KeyStore, expires_at: u64, share: PresignatureShare) -> Self {
+        let r_x = match &share {
+            PresignatureShare::PartyOne { r_x, .. } | PresignatureShare::PartyTwo { r_x, .. } => r_x,
+        };
+        Self {
+            id: r_x.to_bytes().into(),
+            key_id: key_store.public_key_fingerprint(),
+            public_share: key_store.public_share,
+            expires_at,
+            share,
+        }
+    }
+
+    /// Fails if the presignature has expired or was made for another key
+    /// or before a refresh of this one.
+    pub fn check(&self, key_store: &KeyStore, now: u64) -> Result<(), MulEcdsaError> {
+        if now >= self.expires_at
+            || self.key_id != key_store.public_key_fingerprint()
+            || self.public_share != key_store.public_share
+        {
+            return Err(MulEcdsaError::PresignatureUnavailable);
+        }
+        Ok(())
+    }
+
+    /// A party one session ready for `online_sign`.
+    pub fn into_party_one_sign(self, key_store: KeyStore, now: u64) -> Result<party_one::Sign, MulEcdsaError> {
+        self.check(&key_store, now)?;
+        let mut sign = party_one::Sign::new(key_store)?;
+        match self.share {
+            PresignatureShare::PartyOne {
+                nonce_secret_share,
+                reshared_secret_share,
+                r_x,
+                r_point,
+            } => {
+                sign.nonce_secret_share = nonce_secret_share;
+                sign.nonce_public_share = ProjectivePoint::GENERATOR * nonce_secret_share;
+                sign.reshared_secret_share = reshared_secret_share;
+                sign.reshared_public_share = ProjectivePoint::GENERATOR * reshared_secret_share;
+                sign.r_x = r_x;
+                sign.r_point = r_point;
+                Ok(sign)
+            }
+            PresignatureShare::PartyTwo { .. } => Err(MulEcdsaError::PresignatureUnavailable),
+        }
+    }
+
+    /// A party two session ready for `online_sign`.
+    pub fn into_party_two_sign(self, key_store: KeyStore, now: u64) -> Result<party_two::Sign, MulEcdsaError> {
+        self.check(&key_store, now)?;
+        let mut sign = party_two::Sign::new(key_store)?;
+        match self.share {
+            PresignatureShare::PartyTwo {
+                nonce_secret_share,
+                reshared_secret_share,
+                r1_rec,
+                r_x,
+            } => {
+                sign.nonce_secret_share = nonce_secret_share;
+                sign.nonce_public_share = ProjectivePoint::GENERATOR * nonce_secret_share;
+                sign.reshared_secret_share = reshared_secret_share;
+                sign.r1_rec = r1_rec;
+                sign.r_x = r_x;
+                Ok(sign)
+            }
+            PresignatureShare::PartyOne { .. } => Err(MulEcdsaError::PresignatureUnavailable),
+        }
+    }
+}
+
+/// A new file at `path` that only the owner can read, replacing a stale
+/// one left by an interrupted insert.
+fn create_private(path: &std::path::Path) -> std::io::Result<File> {
+    let _ = fs::remove_file(path);
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    options.open(path)
+}
+
+#[derive(Clone, Debug)]
+pub struct PresignaturePool {
+    pub dir: PathBuf,
+}
+
+impl PresignaturePool {
+    /// Opens or creates the pool at `dir`.
+    pub fn open<P: Into<PathBuf>>(dir: P) -> Result<Self, MulEcdsaError> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir).map_err(|_| MulEcdsaError::PresignaturePoolFailed)?;
+        Ok(Self { dir })
+    }
+
+    fn path(&self, id: &[u8; 32], state: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", hex::encode(id), state))
+    }
+
+    /// Makes renames in the directory durable.
+    fn sync_dir(&self) -> Result<(), MulEcdsaError> {
+        File::open(&self.dir)
+            .and_then(|dir| dir.sync_all())
+            .map_err(|_| MulEcdsaError::PresignaturePoolFailed)
+    }
+
+    /// Fails with `NonceReuse` if a presignature with the same id was ever
+    /// inserted.
+    pub fn insert(&self, presignature: &Presignature) -> Result<(), MulEcdsaError> {
+        let path = self.path(&presignature.id, AVAILABLE);
+        if path.exists() || self.path(&presignature.id, CONSUMED).exists() {
+            return Err(MulEcdsaError::NonceReuse);
+        }
+        let bytes = bincode::serde::encode_to_vec(presignature, standard())
+            .map_err(|_| MulEcdsaError::PresignaturePoolFailed)?;
+        let tmp = path.with_extension("tmp");
+        create_private(&tmp)
+            .and_then(|mut file| file.write_all(&bytes).and_then(|_| file.sync_all()))
+            .and_then(|_| fs::rename(&tmp, &path))
+            .map_err(|_| MulEcdsaError::PresignaturePoolFailed)?;
+        self.sync_dir()
+    }
+
+    /// Ids of the presignatures not yet taken, expired ones included.
+    pub fn ids(&self) -> Result<Vec<[u8; 32]>, MulEcdsaError> {
+        let mut ids = Vec::new();
+        for entry in fs::read_dir(&self.dir).map_err(|_| MulEcdsaError::PresignaturePoolFailed)? {
+            let path = entry.map_err(|_| MulEcdsaError::PresignaturePoolFailed)?.path();
+            if path.extension().is_some_and(|ext| ext == AVAILABLE) {
+                if let Some(id) = path
+                    .file_stem()
+                    .and_then(|stem| hex::decode(stem.to_str()?).ok())
+                    .and_then(|id| id.try_into().ok())
+                {
+                    ids.push(id);
+                }
+            }
+        }
+        ids.sort();
+        Ok(ids)
+    }
+
+    /// Marks presignature `id` consumed, durably, and only then returns it.
+    /// It is consumed even if it turns out to be unusable.
+    pub fn take(&self, id: &[u8; 32], key_store: &KeyStore, now: u64) -> Result<Presignature, MulEcdsaError> {
+        let consumed = self.path(id, CONSUMED);
+        fs::rename(self.path(id, AVAILABLE), &consumed).map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => MulEcdsaError::PresignatureUnavailable,
+            _ => MulEcdsaError::PresignaturePoolFailed,
+        })?;
+        self.sync_dir()?;
+        let bytes = fs::read(&consumed).map_err(|_| MulEcdsaError::PresignaturePoolFailed)?;
+        let (presignature, _): (Presignature, usize) = bincode::serde::decode_from_slice(&bytes, standard())
+            .map_err(|_| MulEcdsaError::PresignaturePoolFailed)?;
+        if presignature.id != *id {
+            return Err(MulEcdsaError::PresignaturePoolFailed);
+        }
+        presignature.check(key_store, now)?;
+        Ok(presignature)
+    }
+
+    /// Deletes the presignatures of `key_store`'s key that have expired or
+    /// predate its last refresh; run after every refresh. Returns how many
+    /// were deleted.
+    pub fn purge(&self, key_store: &KeyStore, now: u64) -> Result<usize, MulEcdsaError> {
+        let key_id = key_store.public_key_fingerprint();
+        let mut purged = 0;
+        for id in self.ids()? {
+            let path = self.path(&id, AVAILABLE);
+            let bytes = fs::read(&path).map_err(|_| MulEcdsaError::PresignaturePoolFailed)?;
+            let stale = match bincode::serde::decode_from_slice::<Presignature, _>(&bytes, standard()) {
+                Ok((presignature, _)) => presignature.key_id == key_id && presignature.check(key_store, now).is_err(),
+                Err(_) => false,
+            };
+            if stale {
+                fs::remove_file(&path).map_err(|_| MulEcdsaError::PresignaturePoolFailed)?;
+                purged += 1;
+            }
+        }
+        self.sync_dir()?;
+        Ok(purged)
+    }
+}
+
+#[test]
+fn presignature_pool_files_test() {
+    use k256::elliptic_curve::Field;
+    use crate::utilities::rng::ProtocolRng;
+
+    let dir = std::env::temp_dir().join(format!("presignature_pool_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let pool = PresignaturePool::open(&dir).unwrap();
+    let x1 = Scalar::random(&mut ProtocolRng);
+    let p2 = ProjectivePoint::GENERATOR * Scalar::random(&mut ProtocolRng);
+    let key_store = KeyStore::new(x1, p2, ProjectivePoint::GENERATOR * x1 + p2).unwrap();
+    let mut sign = party_one::Sign::new(key_store.clone()).unwrap();
+    assert_eq!(Presignature::party_one(&sign, 10).unwrap_err(), MulEcdsaError::MissingMsg);
+    sign.r_point = ProjectivePoint::GENERATOR * sign.nonce_secret_share;
+    let presignature = Presignature::party_one(&sign, 10).unwrap();
+
+    pool.insert(&presignature).unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let metadata = fs::metadata(pool.path(&presignature.id, AVAILABLE)).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+    }
+    assert_eq!(pool.insert(&presignature), Err(MulEcdsaError::NonceReuse));
+    assert_eq!(pool.ids().unwrap(), vec![presignature.id]);
+    assert_eq!(pool.take(&presignature.id, &key_store, 5).unwrap(), presignature);
+    assert_eq!(
+        pool.take(&presignature.id, &key_store, 5),
+        Err(MulEcdsaError::PresignatureUnavailable)
+    );
+    // consumed ids cannot come back
+    assert_eq!(pool.insert(&presignature), Err(MulEcdsaError::NonceReuse));
+    assert!(pool.ids().unwrap().is_empty());
+    assert_eq!(
+        presignature.clone().into_party_two_sign(key_store.clone(), 5).unwrap_err(),
+        MulEcdsaError::PresignatureUnavailable
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,92 @@
+//! Proactive refresh of the two shares. Party two picks an offset, moves
+//! it from its share to party one's and sends it to party one encrypted
+//! under party one's CL key, with a CLDL proof so party one only ever
+//! decrypts well-formed ciphertexts. The joint key stays the same; shares
+//! taken from before and after the refresh no longer add up to it. Both
+//! refreshed stores start a new `KeyUsage` period.
+
+use crate::shared::KeyStore;
+use crate::utilities::cl_dl_proof::*;
+use crate::utilities::class_group::{CLGroup, Ciphertext};
+use crate::utilities::error::MulEcdsaError;
+use crate::utilities::rng::ProtocolRng;
+use classgroup::gmp::mpz::Mpz;
+use k256::elliptic_curve::Field;
+use k256::{ProjectivePoint, Scalar};
+use serde::{Deserialize, Serialize};
+
+/// Party two -> party one.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RefreshOffsetMsg {
+    pub encrypted_offset: Ciphertext,
+    /// `G` times the offset.
+    #[serde(with = "crate::utilities::point_serde")]
+    pub offset_point: ProjectivePoint,
+    pub cldl_proof: CLDLProof,
+}
+
+/// Run by party two. Returns the message and party two's refreshed store,
+/// which replaces the current one once party one has applied the message;
+/// a message must be applied once only.
+pub fn start_refresh(key_store: &KeyStore) -> Result<(RefreshOffsetMsg, KeyStore), MulEcdsaError> {
+    let cl_pub_key = key_store
+        .counterparty_cl_pub_key
+        .as_ref()
+        .ok_or(MulEcdsaError::CounterpartyMismatch)?;
+    let group = CLGroup::new();
+    let offset = Scalar::random(&mut ProtocolRng);
+    let offset_point = ProjectivePoint::GENERATOR * offset;
+    let (encrypted_offset, r) = CLGroup::encrypt(&group, cl_pub_key, offset);
+    let statement = CLDLState {
+        cipher: encrypted_offset.clone(),
+        cl_pub_key: cl_pub_key.clone(),
+        dl_pub: offset_point,
+    };
+    let cldl_proof = CLDLProof::prove(&group, CLDLWit { dl_priv: offset, r }, statement);
+    let refreshed = refreshed(
+        key_store,
+        key_store.secret_share - offset,
+        key_store.counterparty_share + offset_point,
+    )?;
+    let msg = RefreshOffsetMsg {
+        encrypted_offset,
+        offset_point,
+        cldl_proof,
+    };
+    Ok((msg, refreshed))
+}
+
+/// Run by party one with its CL secret key.
+pub fn apply_refresh(
+    key_store: &KeyStore,
+    cl_priv_key: &Mpz,
+    msg: &RefreshOffsetMsg,
+) -> Result<KeyStore, MulEcdsaError> {
+    let group = CLGroup::new();
+    let statement = CLDLState {
+        cipher: msg.encrypted_offset.clone(),
+        cl_pub_key: group.pk_for_sk(cl_priv_key.clone()),
+        dl_pub: msg.offset_point,
+    };
+    msg.cldl_proof.verify(&group, statement)?;
+    let offset = CLGroup::decrypt(&group, cl_priv_key, &msg.encrypted_offset)?;
+    if ProjectivePoint::GENERATOR * offset != msg.offset_point {
+        return Err(MulEcdsaError::VrfyCLDLProofFailed);
+    }
+    refreshed(
+        key_store,
+        key_store.secret_share + offset,
+        key_store.counterparty_share - msg.offset_point,
+    )
+}
+
+fn refreshed(
+    key_store: &KeyStore,
+    secret_share: Scalar,
+    counterparty_share: ProjectivePoint,
+) -> Result<KeyStore, MulEcdsaError> {
+    let mut refreshed = KeyStore::new(secret_share, counterparty_share, key_store.public_signing_key)?;
+    refreshed.counterparty_cl_pub_key = key_store.counterparty_cl_pub_key.clone();
+    refreshed.keygen_transcript_hash = key_store.keygen_transcript_hash;
+    Ok(refreshed)
+}
@@ -0,0 +1,175 @@
+//! Access to the long-term signing share. The parties only ever need
+//! `share - offset` during signing, so a store that keeps the share inside
+//! an HSM boundary has to implement that single subtraction.
+//! `TokenSecretStore` is that store for a PKCS#11 token or a TPM, over a
+//! `TokenBackend` the deployment implements with its vendor's library.
+
+use crate::shared::KeyStore;
+use crate::utilities::error::MulEcdsaError;
+use k256::{ProjectivePoint, Scalar};
+use std::fmt::Debug;
+
+pub trait SecretStore: Debug + Send + Sync {
+    /// `G * share`.
+    fn public_share(&self) -> ProjectivePoint;
+
+    /// `share - offset`. With a uniformly random offset the result reveals
+    /// nothing about the share.
+    fn share_minus(&self, offset: &Scalar) -> Scalar;
+}
+
+/// The share held in process memory.
+#[derive(Clone, Debug)]
+pub struct SoftwareSecretStore {
+    share: Scalar,
+}
+
+impl SoftwareSecretStore {
+    pub fn new(share: Scalar) -> Self {
+        Self { share }
+    }
+}
+
+impl SecretStore for SoftwareSecretStore {
+    fn public_share(&self) -> ProjectivePoint {
+        ProjectivePoint::GENERATOR * self.share
+    }
+
+    fn share_minus(&self, offset: &Scalar) -> Scalar {
+        self.share - offset
+    }
+}
+
+/// A share's key object on a PKCS#11 token or in a TPM.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyHandle {
+    pub label: String,
+}
+
+/// The device side of `TokenSecretStore`, over a PKCS#11 session or a TPM
+/// context. The share is generated or imported on the device and is never
+/// exported; only its public share and `share - offset` come out.
+pub trait TokenBackend: Debug + Send + Sync {
+    /// The key object labelled `label`, or `UnknownKey`.
+    fn find(&self, label: &str) -> Result<KeyHandle, MulEcdsaError>;
+
+    fn public_share(&self, handle: &KeyHandle) -> Result<ProjectivePoint, MulEcdsaError>;
+
+    /// `share - offset`, computed on the device.
+    fn share_minus(&self, handle: &KeyHandle, offset: &Scalar) -> Result<Scalar, MulEcdsaError>;
+}
+
+/// The share held on a hardware token, reached through `backend`.
+#[derive(Debug)]
+pub struct TokenSecretStore<B: TokenBackend> {
+    backend: B,
+    handle: KeyHandle,
+    public_share: ProjectivePoint,
+}
+
+impl<B: TokenBackend> TokenSecretStore<B> {
+    /// Finds the key object labelled `label` and reads its public share
+    /// once, for `check_secret_store`.
+    pub fn open(backend: B, label: &str) -> Result<Self, MulEcdsaError> {
+        let handle = backend.find(label)?;
+        let public_share = backend.public_share(&handle)?;
+        Ok(Self {
+            backend,
+            handle,
+            public_share,
+        })
+    }
+
+    pub fn handle(&self) -> &KeyHandle {
+        &self.handle
+    }
+}
+
+impl<B: TokenBackend> SecretStore for TokenSecretStore<B> {
+    fn public_share(&self) -> ProjectivePoint {
+        self.public_share
+    }
+
+    /// Zero if the device call fails. Party two's consistency check then
+    /// rejects party one's message, or party one's check of the signature
+    /// fails, so the session ends without a signature.
+    fn share_minus(&self, offset: &Scalar) -> Scalar {
+        self.backend
+            .share_minus(&self.handle, offset)
+            .unwrap_or(Scalar::ZERO)
+    }
+}
+
+/// Checks the public part of `key_store` and that `store` holds the share
+/// behind it. `key_store.secret_share` is not looked at.
+pub fn check_secret_store(
+    store: &dyn SecretStore,
+    key_store: &KeyStore,
+) -> Result<(), MulEcdsaError> {
+    key_store.validate_public()?;
+    match store.public_share() == key_store.public_share {
+        true => Ok(()),
+        false => Err(MulEcdsaError::InvalidKeyStore),
+    }
+}
+
+#[test]
+fn secret_store_test() {
+    use k256::elliptic_curve::Field;
+    use crate::utilities::rng::ProtocolRng;
+
+    let x1 = Scalar::random(&mut ProtocolRng);
+    let x2 = Scalar::random(&mut ProtocolRng);
+    let (p1, p2) = (ProjectivePoint::GENERATOR * x1, ProjectivePoint::GENERATOR * x2);
+    let mut key_store = KeyStore::new(x1, p2, p1 + p2).unwrap();
+    let store = SoftwareSecretStore::new(x1);
+    let offset = Scalar::random(&mut ProtocolRng);
+    assert_eq!(store.share_minus(&offset) + offset, x1);
+
+    // the share may live only in the store
+    key_store.secret_share = Scalar::ZERO;
+    check_secret_store(&store, &key_store).unwrap();
+    assert_eq!(
+        check_secret_store(&SoftwareSecretStore::new(x2), &key_store),
+        Err(MulEcdsaError::InvalidKeyStore)
+    );
+
+    // a token that holds shares by label, standing in for the device
+    #[derive(Debug)]
+    struct Token(Vec<(String, Scalar)>, bool);
+    impl TokenBackend for Token {
+        fn find(&self, label: &str) -> Result<KeyHandle, MulEcdsaError> {
+            match self.0.iter().any(|(l, _)| l == label) {
+                true => Ok(KeyHandle { label: label.to_string() }),
+                false => Err(MulEcdsaError::UnknownKey),
+            }
+        }
+        fn public_share(&self, handle: &KeyHandle) -> Result<ProjectivePoint, MulEcdsaError> {
+            self.share_minus(handle, &Scalar::ZERO).map(|share| ProjectivePoint::GENERATOR * share)
+        }
+        fn share_minus(&self, handle: &KeyHandle, offset: &Scalar) -> Result<Scalar, MulEcdsaError> {
+            match self.0.iter().find(|(l, _)| *l == handle.label) {
+                Some((_, share)) if self.1 => Ok(share - offset),
+                _ => Err(MulEcdsaError::UnknownKey),
+            }
+        }
+    }
+    let token = |up| Token(vec![("share".to_string(), x1)], up);
+    assert_eq!(
+        TokenSecretStore::open(token(true), "other").unwrap_err(),
+        MulEcdsaError::UnknownKey
+    );
+    let store = TokenSecretStore::open(token(true), "share").unwrap();
+    assert_eq!(store.handle().label, "share");
+    assert_eq!(store.share_minus(&offset) + offset, x1);
+    let sign = crate::party_one::Sign::with_secret_store(
+        KeyStore::new(x1, p2, p1 + p2).unwrap(),
+        std::sync::Arc::new(store),
+    )
+    .unwrap();
+    assert_eq!(sign.key_store.secret_share, Scalar::ZERO);
+
+    let mut store = TokenSecretStore::open(token(true), "share").unwrap();
+    store.backend.1 = false;
+    assert_eq!(store.share_minus(&offset), Scalar::ZERO);
+}
@@ -0,0 +1,194 @@
+//! Startup self-test of the local build: the shipped CL proof vectors must
+//! verify, the arithmetic backends must agree, and a loopback key
+//! generation and signing run must succeed. Meant to gate a service before
+//! it accepts traffic.
+
+use crate::simulation::{keygen, Simulation};
+use crate::utilities::cl_dl_proof::{CLDLProof, CLDLState};
+use crate::utilities::cl_proof::{CLProof, CLState};
+use crate::utilities::class_group::{
+    bigint_to_mpz, mpz_to_bigint, mpz_to_scalar, scalar_to_bigint, scalar_to_mpz, CLGroup, Ciphertext, ProverContext,
+};
+use crate::utilities::equality_proof::EqualityProof;
+use crate::utilities::error::MulEcdsaError;
+use crate::utilities::rng::ProtocolRng;
+use classgroup::gmp::mpz::Mpz;
+use classgroup::gmp_classgroup::GmpClassGroup;
+use classgroup::ClassGroup;
+use k256::elliptic_curve::sec1::FromEncodedPoint;
+use k256::elliptic_curve::Field;
+use k256::{AffinePoint, EncodedPoint, ProjectivePoint, Scalar};
+use std::collections::HashMap;
+use std::fmt;
+
+const CL_PROOF_VECTOR: &str = include_str!("../test_vectors/cl_proof.txt");
+const CL_DL_PROOF_VECTOR: &str = include_str!("../test_vectors/cl_dl_proof.txt");
+
+#[derive(Clone, Debug)]
+pub struct SelfTestReport {
+    pub checks: Vec<(&'static str, Result<(), MulEcdsaError>)>,
+}
+
+impl SelfTestReport {
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|(_, result)| result.is_ok())
+    }
+}
+
+impl fmt::Display for SelfTestReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (name, result) in &self.checks {
+            match result {
+                Ok(()) => writeln!(f, "ok      {}", name)?,
+                Err(e) => writeln!(f, "FAILED  {}: {}", name, e)?,
+            }
+        }
+        write!(f, "self-test {}", if self.passed() { "passed" } else { "FAILED" })
+    }
+}
+
+/// Runs every check, continuing past failures so the report is complete.
+pub fn run_self_test() -> SelfTestReport {
+    SelfTestReport {
+        checks: vec![
+            ("CLProof test vector", check_cl_proof_vector(CL_PROOF_VECTOR)),
+            ("CLDLProof test vector", check_cl_dl_proof_vector(CL_DL_PROOF_VECTOR)),
+            ("backend equivalence", check_backends()),
+            ("loopback keygen and sign", check_loopback()),
+        ],
+    }
+}
+
+/// The `name = value` lines of a vector, with the group it was made in.
+struct Vector<'a> {
+    values: HashMap<&'a str, &'a str>,
+    group: CLGroup,
+}
+
+impl<'a> Vector<'a> {
+    fn parse(text: &'a str) -> Result<Self, MulEcdsaError> {
+        let values: HashMap<_, _> = text
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .filter_map(|line| line.split_once(" = "))
+            .collect();
+        let vector = Self {
+            values,
+            group: CLGroup::new(),
+        };
+        if vector.mpz("discriminant")? != *vector.group.gq.discriminant()
+            || vector.element("gq")? != vector.group.gq
+            || vector.get("challenge_bytes")? != vector.group.params.challenge_bytes().to_string()
+        {
+            return Err(MulEcdsaError::InvalidProtocolParams);
+        }
+        Ok(vector)
+    }
+
+    fn get(&self, name: &str) -> Result<&'a str, MulEcdsaError> {
+        self.values.get(name).copied().ok_or(MulEcdsaError::MissingMsg)
+    }
+
+    fn mpz(&self, name: &str) -> Result<Mpz, MulEcdsaError> {
+        Mpz::from_str_radix(self.get(name)?, 16).map_err(|_| MulEcdsaError::FromHexFailed)
+    }
+
+    fn element(&self, name: &str) -> Result<GmpClassGroup, MulEcdsaError> {
+        let a = self.get(&format!("{}.a", name))?;
+        let b = self.get(&format!("{}.b", name))?;
+        GmpClassGroup::from_ab_hex(a, b, self.group.gq.discriminant()).ok_or(MulEcdsaError::FromHexFailed)
+    }
+
+    fn point(&self, name: &str) -> Result<ProjectivePoint, MulEcdsaError> {
+        let bytes = hex::decode(self.get(name)?).map_err(|_| MulEcdsaError::FromHexFailed)?;
+        let encoded = EncodedPoint::from_bytes(bytes).map_err(|_| MulEcdsaError::FromHexFailed)?;
+        Option::<AffinePoint>::from(AffinePoint::from_encoded_point(&encoded))
+            .map(ProjectivePoint::from)
+            .ok_or(MulEcdsaError::FromHexFailed)
+    }
+
+    fn cipher(&self) -> Result<Ciphertext, MulEcdsaError> {
+        Ok(Ciphertext {
+            c1: self.element("c1")?,
+            c2: self.element("c2")?,
+        })
+    }
+}
+
+fn check_cl_proof_vector(text: &str) -> Result<(), MulEcdsaError> {
+    let vector = Vector::parse(text)?;
+    let proof = CLProof {
+        t1: vector.element("t1")?,
+        t2: vector.element("t2")?,
+        u1: vector.mpz("u1")?,
+        u2: vector.mpz("u2")?,
+    };
+    let statement = CLState {
+        cipher: vector.cipher()?,
+        cl_pub_key: vector.element("cl_pub_key")?,
+    };
+    proof.verify(&vector.group, statement)
+}
+
+fn check_cl_dl_proof_vector(text: &str) -> Result<(), MulEcdsaError> {
+    let vector = Vector::parse(text)?;
+    let proof = CLDLProof {
+        proof: EqualityProof {
+            t1: vector.element("t1")?,
+            t2: vector.element("t2")?,
+            t3: vector.point("t3")?,
+            u1: vector.mpz("u1")?,
+            u2: vector.mpz("u2")?,
+        },
+    };
+    let statement = CLDLState {
+        cipher: vector.cipher()?,
+        cl_pub_key: vector.element("cl_pub_key")?,
+        dl_pub: vector.point("dl_pub")?,
+    };
+    proof.verify(&vector.group, statement)
+}
+
+/// The table-based prover exponentiation against plain `pow_signed`, and
+/// the GMP and num-bigint conversions against each other.
+fn check_backends() -> Result<(), MulEcdsaError> {
+    let group = CLGroup::new();
+    let plain = group.clone();
+    let tabled = group.with_prover_context(ProverContext::new());
+    let (exponent, _) = plain.keygen();
+    if tabled.pow(&plain.gq, &exponent) != plain.pow(&plain.gq, &exponent) {
+        return Err(MulEcdsaError::GeneralError);
+    }
+    let x = Scalar::random(&mut ProtocolRng);
+    let as_mpz = scalar_to_mpz(&x);
+    if mpz_to_bigint(as_mpz.clone()) != scalar_to_bigint(&x)
+        || bigint_to_mpz(scalar_to_bigint(&x)) != as_mpz
+        || mpz_to_scalar(&as_mpz) != x
+    {
+        return Err(MulEcdsaError::GeneralError);
+    }
+    Ok(())
+}
+
+/// The two-party key generation, then a full strict-mode session with the
+/// key stores it returned; party one checks the signature.
+fn check_loopback() -> Result<(), MulEcdsaError> {
+    let (key_store_one, key_store_two) = keygen()?;
+    let message_hash = Scalar::random(&mut ProtocolRng).to_bytes();
+    Simulation::new(0)
+        .run_with_keys(&message_hash, key_store_one, key_store_two)
+        .map(|_| ())
+}
+
+#[test]
+fn self_test_test() {
+    let report = run_self_test();
+    assert!(report.passed(), "{}", report);
+    assert_eq!(report.checks.len(), 4);
+    assert!(report.to_string().ends_with("self-test passed"));
+
+    let tampered = CL_PROOF_VECTOR.replacen("u1 = ", "u1 = 1", 1);
+    assert_eq!(check_cl_proof_vector(&tampered), Err(MulEcdsaError::VrfyCLProofFailed));
+    let truncated = CL_DL_PROOF_VECTOR.replace("t3 = ", "t4 = ");
+    assert_eq!(check_cl_dl_proof_vector(&truncated), Err(MulEcdsaError::MissingMsg));
+}
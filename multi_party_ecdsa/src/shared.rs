@@ -1,14 +1,390 @@
+use classgroup::gmp_classgroup::GmpClassGroup;
+use k256::ecdsa::VerifyingKey;
 use k256::{Scalar, ProjectivePoint};
 use serde::{Deserialize, Serialize};
+use crate::mta::CLKeyRotationMsg;
+use crate::utilities::address;
+use crate::utilities::class_group::{CLGroup, Ciphertext, ClOptions};
+use crate::utilities::cl_dl_proof::CLDLProof;
+use crate::utilities::cl_key_proof::ClKeyProof;
+use crate::utilities::dl_com_zk::{CommWitness, DLCommitments};
+use crate::utilities::error::MulEcdsaError;
 use crate::utilities::k256_helpers::DLogProof;
+use sha2::{Digest, Sha256};
 
 #[derive(Clone, Debug)]
 pub struct KeyStore {
     pub secret_share: Scalar,
     pub public_share: ProjectivePoint,
+    /// The other party's public share.
+    pub counterparty_share: ProjectivePoint,
     pub public_signing_key: ProjectivePoint,
+    /// The other party's CL public key, if it holds one.
+    pub counterparty_cl_pub_key: Option<GmpClassGroup>,
+    /// Hash of the keygen transcript; `None` for stores migrated from v1.
+    pub keygen_transcript_hash: Option<[u8; 32]>,
+    pub usage: KeyUsage,
 }
 
+/// Signatures made with a key since its last refresh, and when that was.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyUsage {
+    pub signatures: u64,
+    /// Seconds since the Unix epoch: keygen or the last refresh. 0 for
+    /// stores written before usage was tracked, which a `max_age` policy
+    /// therefore refreshes first.
+    pub last_refresh: u64,
+}
+
+/// When a key has to go through `refresh` before it signs again. Without
+/// limits, which is the default, it never has to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RefreshPolicy {
+    pub max_signatures: Option<u64>,
+    /// In seconds.
+    pub max_age: Option<u64>,
+}
+
+impl RefreshPolicy {
+    pub fn with_max_signatures(mut self, max_signatures: u64) -> Self {
+        self.max_signatures = Some(max_signatures);
+        self
+    }
+
+    pub fn with_max_age_days(mut self, days: u64) -> Self {
+        self.max_age = Some(days * 24 * 60 * 60);
+        self
+    }
+
+    pub fn is_due(&self, usage: &KeyUsage, now: u64) -> bool {
+        self.max_signatures
+            .is_some_and(|max| usage.signatures >= max)
+            || self
+                .max_age
+                .is_some_and(|max| now.saturating_sub(usage.last_refresh) >= max)
+    }
+}
+
+/// Seconds since the Unix epoch.
+pub fn unix_time() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// Which side of the protocol a key store belongs to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Party {
+    One,
+    Two,
+}
+
+impl KeyStore {
+    pub fn new(
+        secret_share: Scalar,
+        counterparty_share: ProjectivePoint,
+        public_signing_key: ProjectivePoint,
+    ) -> Result<Self, MulEcdsaError> {
+        let key_store = Self {
+            secret_share,
+            public_share: ProjectivePoint::GENERATOR * secret_share,
+            counterparty_share,
+            public_signing_key,
+            counterparty_cl_pub_key: None,
+            keygen_transcript_hash: None,
+            usage: KeyUsage {
+                signatures: 0,
+                last_refresh: unix_time(),
+            },
+        };
+        key_store.validate()?;
+        Ok(key_store)
+    }
+
+    /// Counts a completed signature; persist the store afterwards.
+    pub fn record_signature(&mut self) {
+        self.usage.signatures += 1;
+    }
+
+    /// `RefreshRequired` once `policy` says the key is due for a refresh.
+    pub fn check_refresh(&self, policy: &RefreshPolicy, now: u64) -> Result<(), MulEcdsaError> {
+        match policy.is_due(&self.usage, now) {
+            true => Err(MulEcdsaError::RefreshRequired),
+            false => Ok(()),
+        }
+    }
+
+    /// Checks that `public_share` matches `secret_share` and that the two
+    /// public shares add up to `public_signing_key`.
+    pub fn validate(&self) -> Result<(), MulEcdsaError> {
+        if bool::from(self.secret_share.is_zero())
+            || self.public_share != ProjectivePoint::GENERATOR * self.secret_share
+        {
+            return Err(MulEcdsaError::InvalidKeyStore);
+        }
+        self.validate_public()
+    }
+
+    /// The checks of `validate` that do not involve `secret_share`.
+    pub fn validate_public(&self) -> Result<(), MulEcdsaError> {
+        if self.public_share == ProjectivePoint::IDENTITY
+            || self.counterparty_share == ProjectivePoint::IDENTITY
+            || self.public_signing_key == ProjectivePoint::IDENTITY
+            || self.public_signing_key != self.public_share + self.counterparty_share
+        {
+            return Err(MulEcdsaError::InvalidKeyStore);
+        }
+        Ok(())
+    }
+
+    /// SHA-256 of the compressed joint public key.
+    pub fn public_key_fingerprint(&self) -> [u8; 32] {
+        address::fingerprint(&self.public_signing_key)
+    }
+
+    /// The joint public key for verifying the parties' signatures with k256
+    /// or anything that takes one.
+    pub fn verifying_key(&self) -> Result<VerifyingKey, MulEcdsaError> {
+        VerifyingKey::from_sec1_bytes(&self.public_key_sec1(false))
+            .map_err(|_| MulEcdsaError::InvalidKeyStore)
+    }
+
+    pub fn public_key_sec1(&self, compressed: bool) -> Vec<u8> {
+        address::public_key_sec1(&self.public_signing_key, compressed)
+    }
+
+    pub fn compressed_public_key_hex(&self) -> String {
+        address::compressed_sec1_hex(&self.public_signing_key)
+    }
+
+    pub fn p2wpkh_address(&self, hrp: &str) -> Result<String, MulEcdsaError> {
+        address::p2wpkh_address(&self.public_signing_key, hrp)
+    }
+
+    pub fn ethereum_address(&self) -> String {
+        address::ethereum_address(&self.public_signing_key)
+    }
+
+    /// The store for the joint key plus `t G`. Party one adds `t` to its
+    /// share, party two to its record of party one's; both must use the same
+    /// `t`, which `tweak::TweakConfirmMsg` checks before either store is used.
+    pub fn tweak_add(&self, t: Scalar, party: Party) -> Result<KeyStore, MulEcdsaError> {
+        let offset = ProjectivePoint::GENERATOR * t;
+        let mut tweaked = self.clone();
+        match party {
+            Party::One => {
+                tweaked.secret_share += t;
+                tweaked.public_share += offset;
+            }
+            Party::Two => tweaked.counterparty_share += offset,
+        }
+        tweaked.public_signing_key += offset;
+        tweaked.validate()?;
+        Ok(tweaked)
+    }
+
+    /// Accepts the counterparty's new CL public key after checking the
+    /// rotation proof against the recorded one.
+    pub fn rotate_counterparty_cl_key(
+        &mut self,
+        group: &CLGroup,
+        msg: &CLKeyRotationMsg,
+    ) -> Result<(), MulEcdsaError> {
+        let old = self
+            .counterparty_cl_pub_key
+            .as_ref()
+            .ok_or(MulEcdsaError::CounterpartyMismatch)?;
+        msg.verify(group, old)?;
+        self.counterparty_cl_pub_key = Some(msg.new_cl_pub_key.clone());
+        Ok(())
+    }
+
+    /// Checks that a peer presents the public data recorded at keygen.
+    pub fn check_counterparty(
+        &self,
+        public_share: &ProjectivePoint,
+        cl_pub_key: Option<&GmpClassGroup>,
+    ) -> Result<(), MulEcdsaError> {
+        if *public_share != self.counterparty_share
+            || cl_pub_key != self.counterparty_cl_pub_key.as_ref()
+        {
+            return Err(MulEcdsaError::CounterpartyMismatch);
+        }
+        Ok(())
+    }
+}
+
+/// The optional signing checks as one switch. `Strict` turns on all of
+/// them:
+/// - party one requires the MtAwc proof;
+/// - the MtA initiator decrypts with `ClOptions::blind_decryption`;
+/// - both parties refuse to derive R unless the nonce commitment is bound
+///   to a session (`for_session`) and a nonce registry is set.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ProtocolProfile {
+    #[default]
+    Fast,
+    Strict,
+}
+
+impl ProtocolProfile {
+    pub fn is_strict(self) -> bool {
+        self == ProtocolProfile::Strict
+    }
+
+    pub fn cl_options(self) -> ClOptions {
+        ClOptions {
+            blind_decryption: self.is_strict(),
+        }
+    }
+
+    /// The session binding and nonce registry checks of `Strict`.
+    pub fn check_nonce_setup(
+        self,
+        session_context: &[u8; 32],
+        has_nonce_registry: bool,
+    ) -> Result<(), MulEcdsaError> {
+        if !self.is_strict() {
+            return Ok(());
+        }
+        if *session_context == [0u8; 32] {
+            return Err(MulEcdsaError::MissingSessionBinding);
+        }
+        if !has_nonce_registry {
+            return Err(MulEcdsaError::MissingNonceRegistry);
+        }
+        Ok(())
+    }
+}
+
+/// Key store layout before the counterparty data was recorded.
+#[derive(Serialize, Deserialize)]
+struct KeyStoreV1 {
+    secret_share: Scalar,
+    public_share: Vec<u8>,
+    public_signing_key: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct KeyStoreV2 {
+    secret_share: Scalar,
+    public_share: Vec<u8>,
+    counterparty_share: Vec<u8>,
+    public_signing_key: Vec<u8>,
+    counterparty_cl_pub_key: Option<GmpClassGroup>,
+    keygen_transcript_hash: Option<[u8; 32]>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct KeyStoreV3 {
+    secret_share: Scalar,
+    public_share: Vec<u8>,
+    counterparty_share: Vec<u8>,
+    public_signing_key: Vec<u8>,
+    counterparty_cl_pub_key: Option<GmpClassGroup>,
+    keygen_transcript_hash: Option<[u8; 32]>,
+    usage: KeyUsage,
+}
+
+/// On-disk format. New variants are appended so older files keep decoding.
+#[derive(Serialize, Deserialize)]
+enum VersionedKeyStore {
+    V1(KeyStoreV1),
+    V2(KeyStoreV2),
+    V3(KeyStoreV3),
+}
+
+fn point_to_bytes(point: &ProjectivePoint) -> Vec<u8> {
+    use k256::elliptic_curve::sec1::ToEncodedPoint;
+    point.to_affine().to_encoded_point(true).as_bytes().to_vec()
+}
+
+fn point_from_bytes(bytes: &[u8]) -> Option<ProjectivePoint> {
+    use k256::elliptic_curve::sec1::FromEncodedPoint;
+    let encoded = k256::EncodedPoint::from_bytes(bytes).ok()?;
+    let affine: Option<k256::AffinePoint> =
+        k256::AffinePoint::from_encoded_point(&encoded).into();
+    affine.map(ProjectivePoint::from)
+}
+
+impl Serialize for KeyStore {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        VersionedKeyStore::V3(KeyStoreV3 {
+            secret_share: self.secret_share,
+            public_share: point_to_bytes(&self.public_share),
+            counterparty_share: point_to_bytes(&self.counterparty_share),
+            public_signing_key: point_to_bytes(&self.public_signing_key),
+            counterparty_cl_pub_key: self.counterparty_cl_pub_key.clone(),
+            keygen_transcript_hash: self.keygen_transcript_hash,
+            usage: self.usage,
+        })
+        .serialize(serializer)
+    }
+}
+
+/// Accepts every known version, migrates it to the current layout and runs
+/// `KeyStore::validate`, so a corrupted file fails to load.
+impl<'de> Deserialize<'de> for KeyStore {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        use serde::de;
+        let point = |bytes: &[u8]| {
+            point_from_bytes(bytes).ok_or_else(|| de::Error::custom("invalid point encoding"))
+        };
+
+        let key_store = match VersionedKeyStore::deserialize(deserializer)? {
+            VersionedKeyStore::V1(v1) => {
+                let public_share = point(&v1.public_share)?;
+                let public_signing_key = point(&v1.public_signing_key)?;
+                KeyStore {
+                    secret_share: v1.secret_share,
+                    public_share,
+                    counterparty_share: public_signing_key - public_share,
+                    public_signing_key,
+                    counterparty_cl_pub_key: None,
+                    keygen_transcript_hash: None,
+                    usage: KeyUsage::default(),
+                }
+            }
+            VersionedKeyStore::V2(v2) => KeyStore {
+                secret_share: v2.secret_share,
+                public_share: point(&v2.public_share)?,
+                counterparty_share: point(&v2.counterparty_share)?,
+                public_signing_key: point(&v2.public_signing_key)?,
+                counterparty_cl_pub_key: v2.counterparty_cl_pub_key,
+                keygen_transcript_hash: v2.keygen_transcript_hash,
+                usage: KeyUsage::default(),
+            },
+            VersionedKeyStore::V3(v3) => KeyStore {
+                secret_share: v3.secret_share,
+                public_share: point(&v3.public_share)?,
+                counterparty_share: point(&v3.counterparty_share)?,
+                public_signing_key: point(&v3.public_signing_key)?,
+                counterparty_cl_pub_key: v3.counterparty_cl_pub_key,
+                keygen_transcript_hash: v3.keygen_transcript_hash,
+                usage: v3.usage,
+            },
+        };
+        key_store.validate().map_err(de::Error::custom)?;
+        Ok(key_store)
+    }
+}
+
+/// Party one -> party two after the MtA. With `a` party one's reshared
+/// share and `t_a` its MtA output, `cc = t_a + a * r1 - x1`. Party two
+/// checks `G * (t_b + cc) == A * (r1 + k2) - X1` with its own output `t_b`,
+/// which holds exactly when `t_a + t_b == a * k2`. Only party two runs the
+/// check, so it catches an additive error by party one, in any profile,
+/// before party two's partial signature is sent. Nothing here checks party
+/// two: its MtA response is bound to its nonce only by the MtAwc proof,
+/// which party one requires in `Strict` alone, and an error in party two's
+/// own output shows up as an invalid signature in party one's
+/// `online_sign`.
 #[derive(Clone, Debug)]
 pub struct MtaConsistencyMsg {
     pub reshared_public_share: ProjectivePoint,
@@ -24,7 +400,7 @@ impl Serialize for MtaConsistencyMsg {
     {
         use serde::ser::SerializeStruct;
         use k256::elliptic_curve::sec1::ToEncodedPoint;
-        let mut state = serializer.serialize_struct("MtaConsistencyMsg", 3)?;
+        let mut state = serializer.serialize_struct("MtaConsistencyMsg", 4)?;
         // Serialize ProjectivePoint as compressed bytes (33 bytes)
         let point_bytes = self.reshared_public_share.to_affine().to_encoded_point(true);
         state.serialize_field("reshared_public_share", point_bytes.as_bytes())?;
@@ -94,7 +470,7 @@ impl Serialize for NonceKEMsg {
     {
         use serde::ser::SerializeStruct;
         use k256::elliptic_curve::sec1::ToEncodedPoint;
-        let mut state = serializer.serialize_struct("NonceKEMsg", 2)?;
+        let mut state = serializer.serialize_struct("NonceKEMsg", 3)?;
         // Serialize nonce_public_key as compressed bytes
         let nonce_bytes = self.nonce_public_key.to_affine().to_encoded_point(true);
         state.serialize_field("nonce_public_key", nonce_bytes.as_bytes())?;
@@ -154,3 +530,98 @@ impl<'de> Deserialize<'de> for NonceKEMsg {
         })
     }
 }
+
+/// Keygen, party two -> party one: party two's share with its DLog proof.
+/// Sent after party one's share commitment.
+#[derive(Clone, Debug)]
+pub struct KeyGenPartyTwoMsg {
+    pub public_share: ProjectivePoint,
+    pub dl_proof: DLogProof<ProjectivePoint>,
+}
+
+impl Serialize for KeyGenPartyTwoMsg {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        (
+            point_to_bytes(&self.public_share),
+            point_to_bytes(&self.dl_proof.pk_t_rand_commitment),
+            self.dl_proof.challenge_response,
+        )
+            .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyGenPartyTwoMsg {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        use serde::de;
+        let (public_share, pk_t_rand_commitment, challenge_response): (Vec<u8>, Vec<u8>, Scalar) =
+            Deserialize::deserialize(deserializer)?;
+        let point = |bytes: &[u8]| {
+            point_from_bytes(bytes).ok_or_else(|| de::Error::custom("invalid point encoding"))
+        };
+        Ok(KeyGenPartyTwoMsg {
+            public_share: point(&public_share)?,
+            dl_proof: DLogProof {
+                pk_t_rand_commitment: point(&pk_t_rand_commitment)?,
+                challenge_response,
+            },
+        })
+    }
+}
+
+/// Keygen, party one -> party two: the opening of party one's share
+/// commitment, party one's CL public key and the share encrypted under it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct KeyGenPartyOneMsg {
+    pub witness: CommWitness,
+    pub cl_pub_key: GmpClassGroup,
+    pub cl_key_proof: ClKeyProof,
+    pub encrypted_share: Ciphertext,
+    /// `encrypted_share` encrypts the discrete log of `witness.public_share`.
+    pub cldl_proof: CLDLProof,
+}
+
+/// Runs `a` and `b`, on two threads when `parallel` is set. Threads other
+/// than the caller's draw from `OsRng` even inside a `SeededRun`.
+#[cfg(not(feature = "verify-only"))]
+pub(crate) fn join<A: Send, B: Send>(
+    parallel: bool,
+    a: impl FnOnce() -> A + Send,
+    b: impl FnOnce() -> B + Send,
+) -> (A, B) {
+    if !parallel {
+        return (a(), b());
+    }
+    std::thread::scope(|scope| {
+        let b = scope.spawn(b);
+        let a = a();
+        (a, b.join().unwrap_or_else(|e| std::panic::resume_unwind(e)))
+    })
+}
+
+/// SHA-256 over the encoded keygen messages, in protocol order. Both
+/// parties record it as `KeyStore::keygen_transcript_hash`.
+pub fn keygen_transcript_hash(
+    com: &DLCommitments,
+    party_two_msg: &KeyGenPartyTwoMsg,
+    party_one_msg: &KeyGenPartyOneMsg,
+) -> Result<[u8; 32], MulEcdsaError> {
+    let encode = |hasher: &mut Sha256, bytes: Result<Vec<u8>, _>| {
+        let bytes = bytes.map_err(|_| MulEcdsaError::SerializeFailed)?;
+        hasher.update((bytes.len() as u64).to_be_bytes());
+        hasher.update(&bytes);
+        Ok::<_, MulEcdsaError>(())
+    };
+    let config = bincode::config::standard();
+    let mut hasher = Sha256::new();
+    hasher.update(b"XAX21KeyGen");
+    encode(&mut hasher, bincode::serde::encode_to_vec(com, config))?;
+    encode(&mut hasher, bincode::serde::encode_to_vec(party_two_msg, config))?;
+    encode(&mut hasher, bincode::serde::encode_to_vec(party_one_msg, config))?;
+    Ok(hasher.finalize().into())
+}
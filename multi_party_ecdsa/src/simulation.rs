@@ -0,0 +1,459 @@
+//! In-process signing runs over an in-memory transport with fault injection,
+//! for negative testing. Faults are placed per round; the bit flipped by
+//! `Fault::Corrupt` comes from a seeded RNG so failures reproduce. In tests
+//! and with `test-utils`, each run is also a `SeededRun` drawn from that RNG,
+//! so the keys, the session id and both parties replay from the seed too.
+
+use crate::codec::{AbortMsg, Codec, MessageKind};
+use crate::mta;
+use crate::observer::{ProtocolObserver, RoundTimer};
+use crate::party_one;
+use crate::party_two;
+use crate::shared::KeyStore;
+use crate::utilities::class_group::CLGroup;
+use crate::utilities::dl_com_zk::CommWitness;
+use crate::utilities::error::MulEcdsaError;
+use crate::utilities::signature::Signature;
+use k256::elliptic_curve::Field;
+use k256::{ProjectivePoint, Scalar};
+use crate::utilities::rng::ProtocolRng;
+#[cfg(any(test, feature = "test-utils"))]
+use crate::utilities::rng::SeededRun;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+fn to_party_one(round: MessageKind) -> bool {
+    matches!(
+        round,
+        MessageKind::NonceCom
+            | MessageKind::MtaSecond
+            | MessageKind::PartyTwoNonce
+            | MessageKind::PartialSig
+    )
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    Drop,
+    Duplicate,
+    /// Deliver after the sender's next message.
+    Reorder,
+    /// Flip one bit of the serialized message.
+    Corrupt,
+}
+
+struct Envelope {
+    round: MessageKind,
+    bytes: Vec<u8>,
+}
+
+#[derive(Default)]
+struct Channel {
+    queue: VecDeque<Envelope>,
+    held: Option<Envelope>,
+}
+
+pub struct Simulation {
+    pub codec: Codec,
+    /// Id of the current run, carried in abort messages.
+    pub session: [u8; 32],
+    rng: StdRng,
+    faults: Vec<(MessageKind, Fault)>,
+    to_one: Channel,
+    to_two: Channel,
+    /// Gets a `RoundEvent` per message sent and one for the signature. Both
+    /// parties run on the calling thread, so a round's times cover the work
+    /// of both since the previous message.
+    pub observer: Option<Arc<Mutex<dyn ProtocolObserver>>>,
+    timer: RoundTimer,
+    /// Time spent, over all runs, in the steps doing class group work: CL
+    /// key generation, the MtA and party one's check of the MtAwc proof.
+    pub class_group_time: Duration,
+}
+
+impl Simulation {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            codec: Codec::default(),
+            session: [0u8; 32],
+            rng: StdRng::seed_from_u64(seed),
+            faults: Vec::new(),
+            to_one: Channel::default(),
+            to_two: Channel::default(),
+            observer: None,
+            timer: RoundTimer::start(),
+            class_group_time: Duration::ZERO,
+        }
+    }
+
+    pub fn with_observer(mut self, observer: Arc<Mutex<dyn ProtocolObserver>>) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    fn end_round(&mut self, round: Option<MessageKind>, bytes_out: usize) {
+        let event = self.timer.finish(round, bytes_out);
+        if let Some(observer) = &self.observer {
+            if let Ok(mut observer) = observer.lock() {
+                observer.on_round(&event);
+            }
+        }
+    }
+
+    /// Runs `f`, counting its time as class group work.
+    fn class_group<T>(&mut self, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let out = f();
+        self.class_group_time += start.elapsed();
+        out
+    }
+
+    pub fn with_fault(mut self, round: MessageKind, fault: Fault) -> Self {
+        self.faults.push((round, fault));
+        self
+    }
+
+    fn send<T: Serialize>(&mut self, round: MessageKind, msg: &T) -> Result<(), MulEcdsaError> {
+        let mut bytes = self.codec.encode(round, msg)?;
+        self.end_round(Some(round), bytes.len());
+        let fault = self
+            .faults
+            .iter()
+            .find(|(r, _)| *r == round)
+            .map(|(_, fault)| *fault);
+        if fault == Some(Fault::Corrupt) {
+            let i = self.rng.gen_range(0..bytes.len());
+            bytes[i] ^= 1 << self.rng.gen_range(0..8);
+        }
+
+        let channel = match to_party_one(round) {
+            true => &mut self.to_one,
+            false => &mut self.to_two,
+        };
+        let envelope = Envelope { round, bytes };
+        match fault {
+            Some(Fault::Drop) => {}
+            Some(Fault::Duplicate) => {
+                channel.queue.push_back(Envelope {
+                    round,
+                    bytes: envelope.bytes.clone(),
+                });
+                channel.queue.push_back(envelope);
+            }
+            Some(Fault::Reorder) => {
+                channel.held = Some(envelope);
+                return Ok(());
+            }
+            _ => channel.queue.push_back(envelope),
+        }
+        if let Some(held) = channel.held.take() {
+            channel.queue.push_back(held);
+        }
+        Ok(())
+    }
+
+    /// Takes the next message for the receiver of `round`; anything other
+    /// than that round at the head of the queue counts as missing, and an
+    /// abort from the sender ends the session.
+    fn recv<T: DeserializeOwned>(&mut self, round: MessageKind) -> Result<T, MulEcdsaError> {
+        let channel = match to_party_one(round) {
+            true => &mut self.to_one,
+            false => &mut self.to_two,
+        };
+        let envelope = channel.queue.pop_front();
+        if let Some(envelope) = &envelope {
+            self.timer.received(envelope.bytes.len());
+        }
+        match envelope {
+            Some(envelope) if envelope.round == MessageKind::Abort => {
+                let _: AbortMsg = self.codec.decode(MessageKind::Abort, &envelope.bytes)?;
+                Err(MulEcdsaError::PeerAborted)
+            }
+            Some(envelope) if envelope.round == round => self
+                .codec
+                .decode(round, &envelope.bytes)
+                .map_err(|e| self.abort(round, e)),
+            _ => Err(self.abort(round, MulEcdsaError::MissingMsg)),
+        }
+    }
+
+    /// Sends an abort from the receiver of `round` back to its sender and
+    /// returns `reason`.
+    fn abort(&mut self, round: MessageKind, reason: MulEcdsaError) -> MulEcdsaError {
+        let msg = AbortMsg {
+            session: self.session,
+            round,
+            reason: reason.clone(),
+        };
+        if let Ok(bytes) = self.codec.encode(MessageKind::Abort, &msg) {
+            let channel = match to_party_one(round) {
+                true => &mut self.to_two,
+                false => &mut self.to_one,
+            };
+            channel.queue.push_back(Envelope {
+                round: MessageKind::Abort,
+                bytes,
+            });
+        }
+        reason
+    }
+
+    /// Runs one strict-mode signing session on fresh keys.
+    pub fn run(&mut self, message_hash: &[u8]) -> Result<Signature, MulEcdsaError> {
+        self.seeded(|sim| {
+            let x1 = Scalar::random(&mut ProtocolRng);
+            let x2 = Scalar::random(&mut ProtocolRng);
+            let p1 = ProjectivePoint::GENERATOR * x1;
+            let p2 = ProjectivePoint::GENERATOR * x2;
+            sim.run_session(
+                message_hash,
+                KeyStore::new(x1, p2, p1 + p2)?,
+                KeyStore::new(x2, p1, p1 + p2)?,
+            )
+        })
+    }
+
+    /// `run` with the parties' key stores from an earlier key generation,
+    /// e.g. `keygen`.
+    pub fn run_with_keys(
+        &mut self,
+        message_hash: &[u8],
+        key_store_one: KeyStore,
+        key_store_two: KeyStore,
+    ) -> Result<Signature, MulEcdsaError> {
+        self.seeded(|sim| sim.run_session(message_hash, key_store_one, key_store_two))
+    }
+
+    fn seeded<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        #[cfg(any(test, feature = "test-utils"))]
+        {
+            let run = SeededRun::new(self.rng.gen());
+            run.run(|| f(self))
+        }
+        #[cfg(not(any(test, feature = "test-utils")))]
+        f(self)
+    }
+
+    fn run_session(
+        &mut self,
+        message_hash: &[u8],
+        key_store_one: KeyStore,
+        key_store_two: KeyStore,
+    ) -> Result<Signature, MulEcdsaError> {
+        let session_id = Scalar::random(&mut ProtocolRng).to_bytes();
+        self.session.copy_from_slice(&session_id);
+        self.timer = RoundTimer::start();
+        let mut one = party_one::Sign::new_strict(key_store_one)?
+            .for_session(&session_id, message_hash);
+        let mut two = party_two::Sign::new(key_store_two)?
+            .for_session(&session_id, message_hash);
+
+        self.send(MessageKind::NonceCom, &two.generate_nonce_com())?;
+        one.get_nonce_com(&self.recv(MessageKind::NonceCom)?);
+
+        let (cl_priv_key, cl_pub_key) = self.class_group(|| CLGroup::new().keygen());
+        let mut mta_one = self.class_group(|| mta::PartyOne::new(one.reshared_secret_share));
+        let mut mta_two = mta::PartyTwo::new(two.nonce_secret_share);
+        let first_round = self.class_group(|| mta_one.generate_send_msg(&cl_pub_key));
+        self.send(MessageKind::MtaFirst, &first_round)?;
+
+        let first_round_rec = self.recv(MessageKind::MtaFirst)?;
+        let second_round = self
+            .class_group(|| mta_two.receive_and_send_msg_wc(first_round_rec))
+            .map_err(|_| self.abort(MessageKind::MtaFirst, MulEcdsaError::VrfyCLProofFailed))?;
+        self.send(MessageKind::MtaSecond, &second_round)?;
+
+        let second_round: mta::MTASecondRoundMsg = self.recv(MessageKind::MtaSecond)?;
+        self.class_group(|| mta_one.handle_receive_msg(&cl_priv_key, &second_round.c_a))
+            .map_err(|e| self.abort(MessageKind::MtaSecond, e))?;
+        one.set_mta_wc_msg(&first_round.state, &second_round);
+        self.send(
+            MessageKind::MtaConsistency,
+            &one.generate_mta_consistency(mta_one.t_b),
+        )?;
+        self.send(MessageKind::PartyOneNonce, &one.generate_nonce_ke_msg())?;
+
+        two.verify_generate_mta_consistency(mta_two.t_a, &self.recv(MessageKind::MtaConsistency)?)
+            .map_err(|_| {
+                self.abort(MessageKind::MtaConsistency, MulEcdsaError::VrfyMtaConsistencyFailed)
+            })?;
+        let witness = two
+            .verify_send_nonce_ke_msg(&self.recv(MessageKind::PartyOneNonce)?)
+            .map_err(|_| self.abort(MessageKind::PartyOneNonce, MulEcdsaError::VrfyDlogFailed))?;
+        self.send(MessageKind::PartyTwoNonce, &witness)?;
+
+        let witness: CommWitness = self.recv(MessageKind::PartyTwoNonce)?;
+        self.class_group(|| one.verify_nonce_ke_msg(&witness))
+            .map_err(|e| self.abort(MessageKind::PartyTwoNonce, e))?;
+        let s_2 = two.online_sign(message_hash)?;
+        self.send(MessageKind::PartialSig, &s_2)?;
+        let s_2: Scalar = self.recv(MessageKind::PartialSig)?;
+        let signature = one.online_sign(&s_2, message_hash)?;
+        self.end_round(None, 0);
+        Ok(signature)
+    }
+}
+
+/// Timings and message sizes of repeated honest runs.
+#[derive(Clone, Debug)]
+pub struct BenchReport {
+    /// Per-run latency of `Simulation::run`, sorted.
+    pub latencies: Vec<Duration>,
+    /// Per-run latency of a key generation, both parties in process, sorted.
+    pub keygen_latencies: Vec<Duration>,
+    /// Of the signing runs' total, the time in class group steps; see
+    /// `Simulation::class_group_time`.
+    pub class_group: Duration,
+    /// The rest of the signing runs' total: curve arithmetic, hashing and
+    /// encoding.
+    pub curve: Duration,
+    pub codec: Codec,
+}
+
+/// Nearest-rank percentile of `sorted`, `p` in `0..=100`.
+fn nearest_rank(sorted: &[Duration], p: u32) -> Duration {
+    let rank = (sorted.len() * p as usize).div_ceil(100);
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+impl BenchReport {
+    /// Nearest-rank percentile of `latencies`, `p` in `0..=100`.
+    pub fn percentile(&self, p: u32) -> Duration {
+        nearest_rank(&self.latencies, p)
+    }
+
+    /// `percentile` of `keygen_latencies`.
+    pub fn keygen_percentile(&self, p: u32) -> Duration {
+        nearest_rank(&self.keygen_latencies, p)
+    }
+}
+
+/// Both key generation parties on the calling thread, returning party
+/// one's and party two's key stores. The codec has no key generation kinds,
+/// so the messages are passed unencoded.
+pub fn keygen() -> Result<(KeyStore, KeyStore), MulEcdsaError> {
+    let party_one = party_one::KeyGen::new();
+    let mut party_two = party_two::KeyGen::new();
+    let key_msg = party_two.get_key_com_send_key_msg(&party_one.generate_key_com());
+    let (msg, key_store_one) = party_one.verify_send_key_msg(&key_msg)?;
+    Ok((key_store_one, party_two.verify_key_msg(&msg)?))
+}
+
+/// Runs `runs` key generations and honest sessions, the sessions through
+/// one codec so the size statistics cover all of them. Fails with
+/// `InvalidInput` if `runs` is zero.
+pub fn bench(runs: usize, message_hash: &[u8]) -> Result<BenchReport, MulEcdsaError> {
+    if runs == 0 {
+        return Err(MulEcdsaError::InvalidInput);
+    }
+    let mut sim = Simulation::new(0);
+    let mut latencies = Vec::with_capacity(runs);
+    let mut keygen_latencies = Vec::with_capacity(runs);
+    for _ in 0..runs {
+        let start = Instant::now();
+        keygen()?;
+        keygen_latencies.push(start.elapsed());
+
+        let start = Instant::now();
+        sim.run(message_hash)?;
+        latencies.push(start.elapsed());
+    }
+    latencies.sort();
+    keygen_latencies.sort();
+    let total: Duration = latencies.iter().sum();
+    Ok(BenchReport {
+        latencies,
+        keygen_latencies,
+        class_group: sim.class_group_time,
+        curve: total.saturating_sub(sim.class_group_time),
+        codec: sim.codec,
+    })
+}
+
+#[test]
+fn bench_test() {
+    let report = bench(2, &[0x42u8; 32]).unwrap();
+    assert!(report.percentile(50) <= report.percentile(100));
+    assert_eq!(report.percentile(100), report.latencies[1]);
+    assert!(report.codec.sent.values().all(|stats| stats.count == 2));
+    assert_eq!(report.keygen_latencies.len(), 2);
+    assert!(report.keygen_percentile(50) <= report.keygen_percentile(100));
+    assert!(report.class_group > Duration::ZERO && report.curve > Duration::ZERO);
+    assert_eq!(report.class_group + report.curve, report.latencies.iter().sum());
+    assert_eq!(bench(0, &[0x42u8; 32]).unwrap_err(), MulEcdsaError::InvalidInput);
+}
+
+#[test]
+fn simulation_test() {
+    let message_hash = [0x42u8; 32];
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let mut honest = Simulation::new(0).with_observer(events.clone());
+    honest.run(&message_hash).unwrap();
+    assert_eq!(honest.codec.received.len(), 7);
+    assert_eq!(honest.codec.sent, honest.codec.received);
+    let events = events.lock().unwrap();
+    assert_eq!(events.len(), 8);
+    assert_eq!(events[0].round, Some(MessageKind::NonceCom));
+    assert_eq!(events[7].round, None);
+    let sent: usize = events.iter().map(|event| event.bytes_out).sum();
+    let received: usize = events.iter().map(|event| event.bytes_in).sum();
+    assert_eq!(sent as u64, honest.codec.sent.values().map(|stats| stats.total_bytes).sum::<u64>());
+    assert_eq!(sent, received);
+
+    let transport_faults = [
+        (MessageKind::NonceCom, Fault::Drop),
+        (MessageKind::PartialSig, Fault::Drop),
+        (MessageKind::NonceCom, Fault::Duplicate),
+        (MessageKind::MtaConsistency, Fault::Reorder),
+    ];
+    for (round, fault) in transport_faults {
+        let result = Simulation::new(0)
+            .with_fault(round, fault)
+            .run(&message_hash);
+        assert_eq!(
+            result.unwrap_err(),
+            MulEcdsaError::MissingMsg,
+            "{:?}",
+            (round, fault)
+        );
+    }
+
+    // class group rounds are left out: corrupted forms can trip assertions
+    // in the classgroup crate instead of failing verification
+    for round in [
+        MessageKind::NonceCom,
+        MessageKind::MtaConsistency,
+        MessageKind::PartyOneNonce,
+        MessageKind::PartyTwoNonce,
+        MessageKind::PartialSig,
+    ] {
+        let result = Simulation::new(7)
+            .with_fault(round, Fault::Corrupt)
+            .run(&message_hash);
+        assert!(result.is_err(), "{:?}", round);
+    }
+}
+
+#[test]
+fn abort_test() {
+    let mut sim = Simulation::new(7).with_fault(MessageKind::PartyOneNonce, Fault::Corrupt);
+    let reason = sim.run(&[0x42u8; 32]).unwrap_err();
+
+    // party two rejected party one's nonce message and told party one
+    let envelope = sim.to_one.queue.back().unwrap();
+    assert_eq!(envelope.round, MessageKind::Abort);
+    let abort: AbortMsg = sim.codec.decode(MessageKind::Abort, &envelope.bytes).unwrap();
+    assert_eq!(abort.session, sim.session);
+    assert_eq!(abort.round, MessageKind::PartyOneNonce);
+    assert_eq!(abort.reason, reason);
+    sim.to_one.queue.retain(|e| e.round == MessageKind::Abort);
+    assert_eq!(
+        sim.recv::<Scalar>(MessageKind::PartyTwoNonce),
+        Err(MulEcdsaError::PeerAborted)
+    );
+}
@@ -3,18 +3,55 @@ use crate::utilities::class_group::*;
 use k256::Scalar;
 use k256::elliptic_curve::Field;
 use k256::elliptic_curve::PrimeField;
-use rand::rngs::OsRng;
+use crate::utilities::rng::ProtocolRng;
 use sha2::Digest;
-use k256::ecdsa::{VerifyingKey, signature::Verifier}; 
+use k256::ecdsa::signature::Verifier;
 use k256::elliptic_curve::sec1::ToEncodedPoint;
 use k256::ecdsa::signature::Signature;
 use bincode::config::standard;
 use crate::shared::*;
 
+/// Both parties' key stores for a fresh random key.
+fn key_pair() -> (KeyStore, KeyStore) {
+    let x1 = Scalar::random(&mut ProtocolRng);
+    let x2 = Scalar::random(&mut ProtocolRng);
+    let p1 = k256::ProjectivePoint::GENERATOR * x1;
+    let p2 = k256::ProjectivePoint::GENERATOR * x2;
+    (KeyStore::new(x1, p2, p1 + p2).unwrap(), KeyStore::new(x2, p1, p1 + p2).unwrap())
+}
+
+/// Takes a session through the nonce commitment, the MtA and party two's
+/// consistency check, stopping before the nonce exchange.
+fn run_mta(party_one_sign: &mut party_one::Sign, party_two_sign: &mut party_two::Sign) {
+    party_one_sign.get_nonce_com(&party_two_sign.generate_nonce_com());
+    let (cl_priv_key, cl_pub_key) = CLGroup::new().keygen();
+    let mut mta_party_one = mta::PartyOne::new(party_one_sign.reshared_secret_share);
+    let mut mta_party_two = mta::PartyTwo::new(party_two_sign.nonce_secret_share);
+    let first_round = mta_party_one.generate_send_msg(&cl_pub_key);
+    let second_round = mta_party_two.receive_and_send_msg(first_round).unwrap();
+    mta_party_one.handle_receive_msg(&cl_priv_key, &second_round).unwrap();
+    party_two_sign
+        .verify_generate_mta_consistency(
+            mta_party_two.t_a,
+            &party_one_sign.generate_mta_consistency(mta_party_one.t_b),
+        )
+        .unwrap();
+}
+
+/// `run_mta` and then the nonce exchange, leaving both sides ready for
+/// `online_sign`.
+fn run_presign(party_one_sign: &mut party_one::Sign, party_two_sign: &mut party_two::Sign) {
+    run_mta(party_one_sign, party_two_sign);
+    let witness = party_two_sign
+        .verify_send_nonce_ke_msg(&party_one_sign.generate_nonce_ke_msg())
+        .unwrap();
+    party_one_sign.verify_nonce_ke_msg(&witness).unwrap();
+}
+
 #[test]
 fn mta_test() {
-    let a = Scalar::random(&mut OsRng);
-    let b = Scalar::random(&mut OsRng);
+    let a = Scalar::random(&mut ProtocolRng);
+    let b = Scalar::random(&mut ProtocolRng);
     let group = CLGroup::new();
     let (cl_priv_key, cl_pub_key) = group.keygen();
     let mut mta_party_one = mta::PartyOne::new(a);
@@ -23,10 +60,43 @@ fn mta_test() {
     let mta_second_round_msg = mta_party_two
         .receive_and_send_msg(mta_msg)
         .unwrap();
-    mta_party_one.handle_receive_msg(&cl_priv_key, &mta_second_round_msg);
+    mta_party_one.handle_receive_msg(&cl_priv_key, &mta_second_round_msg).unwrap();
     assert_eq!(a * b, mta_party_two.t_a + mta_party_one.t_b);
 }
 
+#[test]
+fn mta_context_test() {
+    let mut mta_party_one = mta::PartyOne::new(Scalar::ONE);
+    let mut context = mta::MtaContext::new(mta_party_one.cl_pub_key.clone());
+    for _ in 0..2 {
+        // a fresh secret each session, under the same key
+        let a = Scalar::random(&mut ProtocolRng);
+        let b = Scalar::random(&mut ProtocolRng);
+        mta_party_one.b = a;
+        let mut mta_party_two = mta::PartyTwo::new(b);
+        let c_a = mta_party_two
+            .receive_and_send_msg(mta_party_one.generate_send_msg_in(&context))
+            .unwrap();
+        let cl_priv_key = mta_party_one.cl_priv_key.clone();
+        mta_party_one.handle_receive_msg(&cl_priv_key, &c_a).unwrap();
+        assert_eq!(a * b, mta_party_two.t_a + mta_party_one.t_b);
+    }
+    let prover_context = context.group.prover_context.clone().unwrap();
+    assert_eq!(prover_context.cached_bases(), 2);
+
+    let rotation = mta_party_one.rotate_cl_key();
+    context.rekey(rotation.new_cl_pub_key);
+    assert_eq!(prover_context.cached_bases(), 1);
+    let mut mta_party_two = mta::PartyTwo::new(Scalar::ONE);
+    let c_a = mta_party_two
+        .receive_and_send_msg(mta_party_one.generate_send_msg_in(&context))
+        .unwrap();
+    let cl_priv_key = mta_party_one.cl_priv_key.clone();
+    mta_party_one.handle_receive_msg(&cl_priv_key, &c_a).unwrap();
+    assert_eq!(mta_party_one.b, mta_party_two.t_a + mta_party_one.t_b);
+    assert_eq!(prover_context.cached_bases(), 2);
+}
+
 #[test]
 fn party_two_test() {
     // Import secret key
@@ -38,7 +108,7 @@ fn party_two_test() {
     let secret_key = Scalar::from_repr(sk_array.into()).unwrap();
     
     // Split secret key into two shares: x1 and x2 where x1 + x2 = secret_key
-    let x1 = Scalar::random(&mut OsRng);  // party_one's share
+    let x1 = Scalar::random(&mut ProtocolRng);  // party_one's share
     let x2 = secret_key - x1;              // party_two's share
     
     // Create public shares from the secret shares
@@ -49,17 +119,8 @@ fn party_two_test() {
     let public_signing_key = party_one_public_share + party_two_public_share;
     
     // Create KeyStore for both parties
-    let party_one_key = KeyStore {
-        secret_share: x1,
-        public_share: party_one_public_share,
-        public_signing_key,
-    };
-    
-    let party_two_key = KeyStore {
-        secret_share: x2,
-        public_share: party_two_public_share,
-        public_signing_key,
-    };
+    let party_one_key = KeyStore::new(x1, party_two_public_share, public_signing_key).unwrap();
+    let party_two_key = KeyStore::new(x2, party_one_public_share, public_signing_key).unwrap();
     // println!("party_one_key = {:?}", party_one_key);
     // println!("party_two_key = {:?}", party_two_key);
 
@@ -107,7 +168,7 @@ fn party_two_test() {
     let (mta_second_round_msg_deserialized, _): (utilities::class_group::Ciphertext, usize) = 
         bincode::serde::decode_from_slice(&mta_second_round_msg_serialized, standard()).unwrap();
 
-    mta_party_one.handle_receive_msg(&cl_priv_key, &mta_second_round_msg_deserialized);
+    mta_party_one.handle_receive_msg(&cl_priv_key, &mta_second_round_msg_deserialized).unwrap();
     let mta_consistency_msg = party_one_sign.generate_mta_consistency(mta_party_one.t_b);
 
     // P1 -> P2: mta_consistency_msg
@@ -142,7 +203,7 @@ fn party_two_test() {
         .verify_nonce_ke_msg(&party_two_nonce_ke_msg_deserialized)
         .unwrap();
 
-    let s_2 = party_two_sign.online_sign(&message_hash);
+    let s_2 = party_two_sign.online_sign(&message_hash).unwrap();
 
     // P2 -> P1: s_2
     let s_2_serialized = bincode::serde::encode_to_vec(&s_2, standard()).unwrap();
@@ -152,6 +213,14 @@ fn party_two_test() {
 
     let signature = party_one_sign.online_sign(&s_2_deserialized, &message_hash).unwrap();
 
+    // R satisfies s * R = m * G + r * P for the final signature
+    let m = utilities::class_group::scalar_from_bigint(&num_bigint::BigInt::from_bytes_be(num_bigint::Sign::Plus, &message_hash)).unwrap();
+    assert_eq!(
+        signature.r_point * signature.s,
+        k256::ProjectivePoint::GENERATOR * m + party_one_sign.key_store.public_signing_key * signature.r
+    );
+    assert!(signature.recovery_id() < 2);
+
     let elapsed_time = start_time.elapsed();
     println!("Signing time: {} ms", elapsed_time.as_millis());
     
@@ -164,14 +233,1219 @@ fn party_two_test() {
     
     let k256_sig = k256::ecdsa::Signature::from_bytes(&signature).unwrap();
     
-    // Get the public key in the right format
-    let public_key_point = party_one_sign.key_store.public_signing_key;
-    let affine = public_key_point.to_affine();
-    let encoded = affine.to_encoded_point(false);
-    let verifying_key = VerifyingKey::from_sec1_bytes(encoded.as_bytes()).unwrap();
-    println!("Public key (hex): {}", hex::encode(encoded.as_bytes()));
+    let verifying_key = party_one_sign.key_store.verifying_key().unwrap();
+    println!("Public key (hex): {}", hex::encode(party_one_sign.key_store.public_key_sec1(false)));
     
     // Verify signature with k256
     let k256_verify_result = verifying_key.verify(message, &k256_sig);
     println!("k256 native verification result: {:?}", k256_verify_result);
 }
+
+#[test]
+fn mta_wc_test() {
+    let a = Scalar::random(&mut ProtocolRng);
+    let b = Scalar::random(&mut ProtocolRng);
+    let group = CLGroup::new();
+    let (cl_priv_key, cl_pub_key) = group.keygen();
+    let mut mta_party_one = mta::PartyOne::new(a);
+    let mut mta_party_two = mta::PartyTwo::new(b);
+    let mta_msg = mta_party_one.generate_send_msg(&cl_pub_key);
+    let first_round = mta_msg.state.clone();
+    let second_round = mta_party_two.receive_and_send_msg_wc(mta_msg).unwrap();
+
+    let b_pub = k256::ProjectivePoint::GENERATOR * b;
+    assert!(mta_party_one
+        .handle_receive_msg_wc(&cl_priv_key, &first_round, &second_round, &(b_pub + b_pub))
+        .is_err());
+    mta_party_one
+        .handle_receive_msg_wc(&cl_priv_key, &first_round, &second_round, &b_pub)
+        .unwrap();
+    assert_eq!(a * b, mta_party_two.t_a + mta_party_one.t_b);
+}
+
+#[test]
+fn strict_sign_test() {
+    let (party_one_key, party_two_key) = key_pair();
+    let message_hash = sha2::Sha256::digest(b"strict").to_vec();
+
+    let clock = std::sync::Arc::new(clock::ManualClock::new(1_700_000_000));
+    let mut watcher = watcher::Watcher::new(party_one_key.public_share, party_two_key.public_share)
+        .with_clock(clock.clone());
+    let mut party_one_sign = party_one::Sign::new_strict(party_one_key).unwrap();
+    let mut party_two_sign = party_two::Sign::new(party_two_key).unwrap();
+    let nonce_com = party_two_sign.generate_nonce_com();
+    watcher.observe_nonce_com(&nonce_com).unwrap();
+    party_one_sign.get_nonce_com(&nonce_com);
+
+    let group = CLGroup::new();
+    let (cl_priv_key, cl_pub_key) = group.keygen();
+    let mut mta_party_one = mta::PartyOne::new(party_one_sign.reshared_secret_share);
+    let mut mta_party_two = mta::PartyTwo::new(party_two_sign.nonce_secret_share);
+    let mta_first_round_msg = mta_party_one.generate_send_msg(&cl_pub_key);
+    let first_round = mta_first_round_msg.state.clone();
+    watcher.observe_mta_first_round(&mta_first_round_msg).unwrap();
+    let mta_second_round_msg = mta_party_two
+        .receive_and_send_msg_wc(mta_first_round_msg)
+        .unwrap();
+    mta_party_one
+        .handle_receive_msg(&cl_priv_key, &mta_second_round_msg.c_a)
+        .unwrap();
+    party_one_sign.set_mta_wc_msg(&first_round, &mta_second_round_msg);
+    watcher.observe_mta_second_round(&mta_second_round_msg).unwrap();
+
+    let mta_consistency_msg = party_one_sign.generate_mta_consistency(mta_party_one.t_b);
+    party_two_sign
+        .verify_generate_mta_consistency(mta_party_two.t_a, &mta_consistency_msg)
+        .unwrap();
+    watcher.observe_mta_consistency(&mta_consistency_msg).unwrap();
+    let nonce_ke_msg = party_one_sign.generate_nonce_ke_msg();
+    watcher.observe_party_one_nonce(&nonce_ke_msg).unwrap();
+    let witness = party_two_sign.verify_send_nonce_ke_msg(&nonce_ke_msg).unwrap();
+    watcher.observe_party_two_nonce(&witness).unwrap();
+
+    // strict mode refuses to continue without the MtAwc proof
+    let mut without_proof = party_one_sign.clone();
+    without_proof.mta_wc_check = None;
+    assert_eq!(
+        without_proof.verify_nonce_ke_msg(&witness),
+        Err(utilities::error::MulEcdsaError::MissingMtAwcProof)
+    );
+
+    party_one_sign.verify_nonce_ke_msg(&witness).unwrap();
+    let s_2 = party_two_sign.online_sign(&message_hash).unwrap();
+    let signature = party_one_sign.online_sign(&s_2, &message_hash).unwrap();
+    watcher.observe_signature(&signature, &message_hash).unwrap();
+    assert!(watcher.is_clean());
+
+    use utilities::signature::MessageDigest;
+    let public_key = party_one_sign.key_store.public_signing_key;
+    let digest: [u8; 32] = sha2::Sha256::digest(b"strict").into();
+    signature.verify_prehashed(&public_key, &digest).unwrap();
+    signature.verify_msg(&public_key, b"strict", MessageDigest::Sha256).unwrap();
+    // the digest is not the message: hashing it again fails
+    assert!(signature.verify_msg(&public_key, &digest, MessageDigest::Sha256).is_err());
+    assert!(signature.verify_msg(&public_key, b"strict", MessageDigest::Keccak256).is_err());
+
+    // an auditor re-verifies the session from the encoded transcript
+    let bytes = bincode::serde::encode_to_vec(&watcher.transcript, standard()).unwrap();
+    let (mut transcript, _): (watcher::SigningTranscript, usize) =
+        bincode::serde::decode_from_slice(&bytes, standard()).unwrap();
+    let (p1, p2) = (watcher.party_one_share, watcher.party_two_share);
+    transcript.verify(p1, p2).unwrap();
+    assert_eq!(transcript.verify(p2, p1), Err(utilities::error::MulEcdsaError::CounterpartyMismatch));
+    transcript.message_hash = Some(b"other".to_vec());
+    assert!(transcript.verify(p1, p2).is_err());
+    transcript.signature = None;
+    assert_eq!(transcript.verify(p1, p2), Err(utilities::error::MulEcdsaError::MissingMsg));
+
+    // the watcher logs, rather than hides, a failed check
+    clock.advance(5);
+    assert!(watcher.observe_signature(&signature, b"other").is_err());
+    assert!(!watcher.is_clean());
+    assert_eq!(watcher.audit_log.len(), 8);
+    assert_eq!(watcher.audit_log[0].at, 1_700_000_000);
+    assert_eq!(watcher.audit_log[7].at, 1_700_000_005);
+}
+
+#[test]
+fn security_level_test() {
+    use crate::utilities::ProtocolParams;
+
+    for level in [112, 192] {
+        let params = ProtocolParams::with_security_level(level).unwrap();
+        let party_one_keygen = party_one::KeyGen::new_with_params(params, false);
+        let mut party_two_keygen = party_two::KeyGen::new().with_params(params);
+        let key_com = party_one_keygen.generate_key_com();
+        let party_two_msg = party_two_keygen.get_key_com_send_key_msg(&key_com);
+        let (party_one_msg, party_one_key) =
+            party_one_keygen.verify_send_key_msg(&party_two_msg).unwrap();
+        // a party two at the default level rejects the proofs
+        let mut other = party_two::KeyGen::new();
+        other.get_key_com_send_key_msg(&key_com);
+        assert!(other.verify_key_msg(&party_one_msg).is_err());
+        let party_two_key = party_two_keygen.verify_key_msg(&party_one_msg).unwrap();
+
+        let message_hash = [0x42u8; 32];
+        let (p1, p2) = (party_one_key.public_share, party_two_key.public_share);
+        let mut watcher = watcher::Watcher::new(p1, p2).with_params(params);
+        let mut party_one_sign = party_one::Sign::new_strict(party_one_key).unwrap().with_params(params);
+        let mut party_two_sign = party_two::Sign::new(party_two_key).unwrap();
+        let nonce_com = party_two_sign.generate_nonce_com();
+        watcher.observe_nonce_com(&nonce_com).unwrap();
+        party_one_sign.get_nonce_com(&nonce_com);
+
+        let mut mta_party_one = mta::PartyOne::new_with_params(party_one_sign.reshared_secret_share, params);
+        let mut mta_party_two = mta::PartyTwo::new(party_two_sign.nonce_secret_share).with_params(params);
+        let first_round = mta_party_one.generate_send_msg(&mta_party_one.cl_pub_key);
+        watcher.observe_mta_first_round(&first_round).unwrap();
+        let state = first_round.state.clone();
+        let second_round = mta_party_two.receive_and_send_msg_wc(first_round).unwrap();
+        let cl_priv_key = mta_party_one.cl_priv_key.clone();
+        mta_party_one.handle_receive_msg(&cl_priv_key, &second_round.c_a).unwrap();
+        party_one_sign.set_mta_wc_msg(&state, &second_round);
+        watcher.observe_mta_second_round(&second_round).unwrap();
+
+        let consistency = party_one_sign.generate_mta_consistency(mta_party_one.t_b);
+        watcher.observe_mta_consistency(&consistency).unwrap();
+        party_two_sign
+            .verify_generate_mta_consistency(mta_party_two.t_a, &consistency)
+            .unwrap();
+        let nonce_ke_msg = party_one_sign.generate_nonce_ke_msg();
+        watcher.observe_party_one_nonce(&nonce_ke_msg).unwrap();
+        let witness = party_two_sign.verify_send_nonce_ke_msg(&nonce_ke_msg).unwrap();
+        watcher.observe_party_two_nonce(&witness).unwrap();
+        party_one_sign.verify_nonce_ke_msg(&witness).unwrap();
+        let s_2 = party_two_sign.online_sign(&message_hash).unwrap();
+        let signature = party_one_sign.online_sign(&s_2, &message_hash).unwrap();
+        watcher.observe_signature(&signature, &message_hash).unwrap();
+        assert!(watcher.is_clean());
+
+        watcher.transcript.verify_with_params(p1, p2, params).unwrap();
+        assert!(watcher.transcript.verify(p1, p2).is_err());
+    }
+}
+
+#[test]
+fn low_s_policy_test() {
+    use crate::utilities::k256_helpers::is_low_s;
+    use crate::utilities::signature::LowSPolicy;
+    let (key_store_one, key_store_two) = key_pair();
+    let mut party_one_sign = party_one::Sign::new(key_store_one).unwrap();
+    let mut party_two_sign = party_two::Sign::new(key_store_two).unwrap();
+    run_presign(&mut party_one_sign, &mut party_two_sign);
+    let message = [9u8; 32];
+    let s_2 = party_two_sign.online_sign(&message).unwrap();
+    let preserving = party_one_sign.clone().with_low_s(LowSPolicy::Preserve);
+
+    let normalized = party_one_sign.online_sign(&s_2, &message).unwrap();
+    let preserved = preserving.online_sign(&s_2, &message).unwrap();
+    assert!(is_low_s(&normalized.s));
+    assert_eq!(preserved.r, normalized.r);
+    if is_low_s(&preserved.s) {
+        assert_eq!(preserved, normalized);
+    } else {
+        assert_eq!((-preserved.s, -preserved.r_point), (normalized.s, normalized.r_point));
+    }
+}
+
+#[test]
+fn key_store_validate_test() {
+    let (mut key_store, _) = key_pair();
+    let (x1, p2, public_signing_key) =
+        (key_store.secret_share, key_store.counterparty_share, key_store.public_signing_key);
+
+    key_store.secret_share += Scalar::ONE;
+    assert_eq!(key_store.validate(), Err(utilities::error::MulEcdsaError::InvalidKeyStore));
+    assert!(party_one::Sign::new(key_store).is_err());
+    assert_eq!(
+        KeyStore::new(x1, p2 + k256::ProjectivePoint::GENERATOR, public_signing_key).unwrap_err(),
+        utilities::error::MulEcdsaError::InvalidKeyStore
+    );
+}
+
+#[test]
+fn key_store_serde_test() {
+    let (mut key_store, other) = key_pair();
+    let (x1, x2) = (key_store.secret_share, other.secret_share);
+    let (p1, public_signing_key) = (key_store.public_share, key_store.public_signing_key);
+    key_store.keygen_transcript_hash = Some([7u8; 32]);
+    key_store.record_signature();
+
+    let bytes = bincode::serde::encode_to_vec(&key_store, standard()).unwrap();
+    let (decoded, _): (KeyStore, usize) =
+        bincode::serde::decode_from_slice(&bytes, standard()).unwrap();
+    assert_eq!(decoded.counterparty_share, key_store.counterparty_share);
+    assert_eq!(decoded.keygen_transcript_hash, Some([7u8; 32]));
+    assert_eq!(decoded.usage, key_store.usage);
+    decoded
+        .check_counterparty(&key_store.counterparty_share, None)
+        .unwrap();
+    assert!(decoded.check_counterparty(&p1, None).is_err());
+
+    // a v1 file: variant 0 holding (secret_share, public_share, public_signing_key)
+    #[derive(serde::Serialize)]
+    enum Legacy {
+        V1(Scalar, Vec<u8>, Vec<u8>),
+    }
+    let compressed = |p: k256::ProjectivePoint| p.to_affine().to_encoded_point(true).as_bytes().to_vec();
+    let legacy = Legacy::V1(x1, compressed(p1), compressed(public_signing_key));
+    let bytes = bincode::serde::encode_to_vec(&legacy, standard()).unwrap();
+    let (migrated, _): (KeyStore, usize) =
+        bincode::serde::decode_from_slice(&bytes, standard()).unwrap();
+    assert_eq!(migrated.counterparty_share, key_store.counterparty_share);
+    assert_eq!(migrated.keygen_transcript_hash, None);
+    assert_eq!(migrated.usage, KeyUsage::default());
+
+    let corrupt = Legacy::V1(x2, compressed(p1), compressed(public_signing_key));
+    let bytes = bincode::serde::encode_to_vec(&corrupt, standard()).unwrap();
+    assert!(bincode::serde::decode_from_slice::<KeyStore, _>(&bytes, standard()).is_err());
+}
+
+#[test]
+fn cl_key_rotation_test() {
+    let group = CLGroup::new();
+    let mut mta_party_one = mta::PartyOne::new(Scalar::random(&mut ProtocolRng));
+    let (_, mut key_store) = key_pair();
+    let x2 = key_store.secret_share;
+    key_store.counterparty_cl_pub_key = Some(mta_party_one.cl_pub_key.clone());
+
+    let msg = mta_party_one.rotate_cl_key();
+    key_store.rotate_counterparty_cl_key(&group, &msg).unwrap();
+    assert_eq!(key_store.counterparty_cl_pub_key, Some(mta_party_one.cl_pub_key.clone()));
+    assert_eq!(key_store.secret_share, x2);
+
+    // replaying the same rotation against the new key fails
+    assert!(key_store.rotate_counterparty_cl_key(&group, &msg).is_err());
+
+    // MtA runs under the rotated key
+    let b = mta_party_one.b;
+    let a = Scalar::random(&mut ProtocolRng);
+    let mut mta_party_two = mta::PartyTwo::new(a);
+    let first = mta_party_one.generate_send_msg(&msg.new_cl_pub_key);
+    let c_a = mta_party_two.receive_and_send_msg(first).unwrap();
+    let cl_priv_key = mta_party_one.cl_priv_key.clone();
+    mta_party_one.handle_receive_msg(&cl_priv_key, &c_a).unwrap();
+    assert_eq!(a * b, mta_party_two.t_a + mta_party_one.t_b);
+}
+
+#[test]
+fn nonce_registry_test() {
+    use crate::nonce_registry::{MemoryNonceRegistry, NonceRegistry};
+    use std::sync::{Arc, Mutex};
+
+    let (party_one_key, party_two_key) = key_pair();
+    let registry: Arc<Mutex<dyn NonceRegistry>> = Arc::new(Mutex::new(MemoryNonceRegistry::default()));
+    let mut one = party_one::Sign::new(party_one_key)
+        .unwrap()
+        .with_nonce_registry(registry.clone());
+    let two = party_two::Sign::new(party_two_key).unwrap();
+    one.get_nonce_com(&two.generate_nonce_com());
+
+    // a session restored from a snapshot taken before R was derived
+    let mut restored = one.clone();
+    one.verify_nonce_ke_msg(&two.dl_com_zk_com.witness).unwrap();
+    assert_eq!(
+        restored.verify_nonce_ke_msg(&two.dl_com_zk_com.witness),
+        Err(utilities::error::MulEcdsaError::NonceReuse)
+    );
+}
+
+#[test]
+fn nonce_com_session_binding_test() {
+    let (party_one_key, party_two_key) = key_pair();
+    let message_hash = [3u8; 32];
+    let two = party_two::Sign::new(party_two_key.clone())
+        .unwrap()
+        .for_session(b"session 1", &message_hash);
+
+    let mut same = party_one::Sign::new(party_one_key.clone())
+        .unwrap()
+        .for_session(b"session 1", &message_hash);
+    same.get_nonce_com(&two.generate_nonce_com());
+    same.verify_nonce_ke_msg(&two.dl_com_zk_com.witness).unwrap();
+
+    // the same commitment and opening replayed into another session
+    let mut other = party_one::Sign::new(party_one_key.clone())
+        .unwrap()
+        .for_session(b"session 2", &message_hash);
+    other.get_nonce_com(&two.generate_nonce_com());
+    assert_eq!(
+        other.verify_nonce_ke_msg(&two.dl_com_zk_com.witness),
+        Err(utilities::error::MulEcdsaError::OpenDLCommFailed)
+    );
+
+    // party two signs only the message the session was bound to
+    let mut one = party_one::Sign::new(party_one_key)
+        .unwrap()
+        .for_session(b"session 3", &message_hash);
+    let mut two = party_two::Sign::new(party_two_key)
+        .unwrap()
+        .for_session(b"session 3", &message_hash);
+    run_presign(&mut one, &mut two);
+    assert_eq!(
+        two.online_sign(&[4u8; 32]),
+        Err(utilities::error::MulEcdsaError::Unauthorized)
+    );
+    let s_2 = two.online_sign(&message_hash).unwrap();
+    one.online_sign(&s_2, &message_hash)
+        .unwrap()
+        .verify_prehashed(&one.key_store.public_signing_key, &message_hash)
+        .unwrap();
+}
+
+#[test]
+fn labeled_session_test() {
+    let (party_one_key, party_two_key) = key_pair();
+    let (p1, p2) = (party_one_key.public_share, party_two_key.public_share);
+    let message_hash = [3u8; 32];
+    let label = "withdrawal-batch-2024-06";
+    let one = |label| {
+        party_one::Sign::new(party_one_key.clone())
+            .unwrap()
+            .for_labeled_session(b"session", &message_hash, label)
+    };
+    let mut two = party_two::Sign::new(party_two_key)
+        .unwrap()
+        .for_labeled_session(b"session", &message_hash, label);
+    let mut watcher = watcher::Watcher::new(p1, p2).for_labeled_session(b"session", &message_hash, label);
+
+    let mut same = one(label);
+    same.get_nonce_com(&two.generate_nonce_com());
+    watcher.observe_nonce_com(&two.generate_nonce_com()).unwrap();
+    let nonce_ke_msg = same.generate_nonce_ke_msg();
+    watcher.observe_party_one_nonce(&nonce_ke_msg).unwrap();
+    let witness = two.verify_send_nonce_ke_msg(&nonce_ke_msg).unwrap();
+    watcher.observe_party_two_nonce(&witness).unwrap();
+    same.verify_nonce_ke_msg(&witness).unwrap();
+    assert_eq!(same.label.as_deref(), Some(label));
+    assert_eq!(watcher.transcript.label.as_deref(), Some(label));
+
+    // a different label is caught by both parties and by the watcher
+    let mut relabeled = one("withdrawal-batch-2024-07");
+    relabeled.get_nonce_com(&two.generate_nonce_com());
+    let nonce_ke_msg = relabeled.generate_nonce_ke_msg();
+    assert!(two.verify_send_nonce_ke_msg(&nonce_ke_msg).is_err());
+    assert_eq!(
+        relabeled.verify_nonce_ke_msg(&witness),
+        Err(utilities::error::MulEcdsaError::OpenDLCommFailed)
+    );
+    assert_eq!(
+        watcher.observe_party_one_nonce(&nonce_ke_msg),
+        Err(utilities::error::MulEcdsaError::VrfyDlogFailed)
+    );
+    // and a labeled session is not an unlabeled one
+    let mut unlabeled = party_one::Sign::new(party_one_key.clone())
+        .unwrap()
+        .for_session(b"session", &message_hash);
+    unlabeled.get_nonce_com(&two.generate_nonce_com());
+    assert!(unlabeled.verify_nonce_ke_msg(&witness).is_err());
+}
+
+#[test]
+fn request_authorization_test() {
+    use crate::authorization::RequestAuthorization;
+    use utilities::error::MulEcdsaError;
+
+    let (key_store, _) = key_pair();
+    let p1 = key_store.public_share;
+    let key_id = key_store.public_key_fingerprint();
+    let approval_key = Scalar::random(&mut ProtocolRng);
+    let approver = k256::ProjectivePoint::GENERATOR * approval_key;
+    let message_hash = [5u8; 32];
+
+    let authorization = RequestAuthorization::approve(&approval_key, &key_id, &message_hash).unwrap();
+    let bytes = bincode::serde::encode_to_vec(&authorization, standard()).unwrap();
+    let (decoded, _): (RequestAuthorization, usize) =
+        bincode::serde::decode_from_slice(&bytes, standard()).unwrap();
+    decoded.verify(&approver, &key_id, &message_hash).unwrap();
+
+    let sign = party_one::Sign::new(key_store.clone())
+        .unwrap()
+        .with_authorization(&approver, &authorization, &message_hash)
+        .unwrap();
+    assert_eq!(sign.authorized_message.as_deref(), Some(&message_hash[..]));
+    // approved for another message, by another key, or for another joint key
+    for (approver, message_hash) in [(approver, [6u8; 32]), (p1, message_hash)] {
+        assert_eq!(
+            party_one::Sign::new(key_store.clone())
+                .unwrap()
+                .with_authorization(&approver, &authorization, &message_hash)
+                .unwrap_err(),
+            MulEcdsaError::Unauthorized
+        );
+    }
+    assert_eq!(authorization.verify(&approver, &[0u8; 32], &message_hash), Err(MulEcdsaError::Unauthorized));
+    // an authorized session still signs nothing else
+    assert_eq!(
+        sign.online_sign(&Scalar::ONE, &[6u8; 32]).unwrap_err(),
+        MulEcdsaError::Unauthorized
+    );
+}
+
+#[test]
+fn keygen_test() {
+    let party_one_keygen = party_one::KeyGen::new();
+    let mut party_two_keygen = party_two::KeyGen::new();
+    let key_com = party_one_keygen.generate_key_com();
+    let party_two_msg = party_two_keygen.get_key_com_send_key_msg(&key_com);
+    let (party_one_msg, party_one_key) =
+        party_one_keygen.verify_send_key_msg(&party_two_msg).unwrap();
+
+    // a ciphertext of some other share does not pass the CLDL proof
+    let mut forged = party_one_msg.clone();
+    forged.encrypted_share = CLGroup::encrypt(
+        &CLGroup::new(),
+        &party_one_msg.cl_pub_key,
+        Scalar::random(&mut ProtocolRng),
+    )
+    .0;
+    assert_eq!(
+        party_two_keygen.clone().verify_key_msg(&forged).unwrap_err(),
+        utilities::error::MulEcdsaError::VrfyCLDLProofFailed
+    );
+
+    let party_two_key = party_two_keygen.verify_key_msg(&party_one_msg).unwrap();
+    assert_eq!(party_one_key.public_signing_key, party_two_key.public_signing_key);
+    assert_eq!(party_one_key.keygen_transcript_hash, party_two_key.keygen_transcript_hash);
+    assert_eq!(
+        party_two_key.counterparty_cl_pub_key,
+        Some(party_one_keygen.cl_pub_key.clone())
+    );
+    let group = CLGroup::new();
+    assert_eq!(
+        CLGroup::decrypt(
+            &group,
+            &party_one_keygen.cl_priv_key,
+            party_two_keygen.encrypted_share.as_ref().unwrap()
+        )
+        .unwrap(),
+        party_one_keygen.secret_share
+    );
+
+    // the generated key stores sign
+    let message_hash = [0x42u8; 32];
+    let mut party_one_sign = party_one::Sign::new_strict(party_one_key).unwrap();
+    let mut party_two_sign = party_two::Sign::new(party_two_key).unwrap();
+    party_one_sign.get_nonce_com(&party_two_sign.generate_nonce_com());
+    let (cl_priv_key, cl_pub_key) = group.keygen();
+    let mut mta_party_one = mta::PartyOne::new(party_one_sign.reshared_secret_share);
+    let mut mta_party_two = mta::PartyTwo::new(party_two_sign.nonce_secret_share);
+    let first_round = mta_party_one.generate_send_msg(&cl_pub_key);
+    let second_round = mta_party_two
+        .receive_and_send_msg_wc(first_round.clone())
+        .unwrap();
+    mta_party_one
+        .handle_receive_msg(&cl_priv_key, &second_round.c_a)
+        .unwrap();
+    party_one_sign.set_mta_wc_msg(&first_round.state, &second_round);
+    party_two_sign
+        .verify_generate_mta_consistency(
+            mta_party_two.t_a,
+            &party_one_sign.generate_mta_consistency(mta_party_one.t_b),
+        )
+        .unwrap();
+    let witness = party_two_sign
+        .verify_send_nonce_ke_msg(&party_one_sign.generate_nonce_ke_msg())
+        .unwrap();
+    party_one_sign.verify_nonce_ke_msg(&witness).unwrap();
+    let s_2 = party_two_sign.online_sign(&message_hash).unwrap();
+    party_one_sign.online_sign(&s_2, &message_hash).unwrap();
+}
+
+#[test]
+fn parallel_keygen_test() {
+    let party_one_keygen = party_one::KeyGen::new_parallel();
+    let mut party_two_keygen = party_two::KeyGen::new_parallel();
+    let party_two_msg =
+        party_two_keygen.get_key_com_send_key_msg(&party_one_keygen.generate_key_com());
+    let (party_one_msg, party_one_key) =
+        party_one_keygen.verify_send_key_msg(&party_two_msg).unwrap();
+
+    let mut forged = party_one_msg.clone();
+    // a proof for some other CL key
+    forged.cl_key_proof = party_one::KeyGen::new()
+        .verify_send_key_msg(&party_two_msg)
+        .unwrap()
+        .0
+        .cl_key_proof;
+    assert_eq!(
+        party_two_keygen.clone().verify_key_msg(&forged).unwrap_err(),
+        utilities::error::MulEcdsaError::VrfyCLKeyProofFailed
+    );
+
+    let party_two_key = party_two_keygen.verify_key_msg(&party_one_msg).unwrap();
+    assert_eq!(party_one_key.public_signing_key, party_two_key.public_signing_key);
+    assert_eq!(party_one_key.keygen_transcript_hash, party_two_key.keygen_transcript_hash);
+}
+
+fn send_frame<T: serde::Serialize>(stream: &mut std::net::TcpStream, msg: &T) {
+    use std::io::Write;
+    let bytes = bincode::serde::encode_to_vec(msg, standard()).unwrap();
+    stream.write_all(&(bytes.len() as u32).to_be_bytes()).unwrap();
+    stream.write_all(&bytes).unwrap();
+}
+
+fn recv_frame<T: serde::de::DeserializeOwned>(stream: &mut std::net::TcpStream) -> T {
+    use std::io::Read;
+    let mut len = [0u8; 4];
+    stream.read_exact(&mut len).unwrap();
+    let mut bytes = vec![0u8; u32::from_be_bytes(len) as usize];
+    stream.read_exact(&mut bytes).unwrap();
+    bincode::serde::decode_from_slice(&bytes, standard()).unwrap().0
+}
+
+/// Keygen and one signature with the parties on two threads talking over
+/// localhost TCP, each message length-prefixed.
+#[test]
+fn two_party_tcp_test() {
+    let message = b"two party over tcp";
+    let message_hash = sha2::Sha256::digest(message).to_vec();
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let hash = message_hash.clone();
+    let party_two = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut keygen = party_two::KeyGen::new();
+        let key_com = recv_frame(&mut stream);
+        send_frame(&mut stream, &keygen.get_key_com_send_key_msg(&key_com));
+        let key_store = keygen.verify_key_msg(&recv_frame(&mut stream)).unwrap();
+
+        let mut sign = party_two::Sign::new(key_store).unwrap();
+        send_frame(&mut stream, &sign.generate_nonce_com());
+        let mut mta = mta::PartyTwo::new(sign.nonce_secret_share);
+        let reply = mta.receive_and_send_msg_wc(recv_frame(&mut stream)).unwrap();
+        send_frame(&mut stream, &reply);
+        sign.verify_generate_mta_consistency(mta.t_a, &recv_frame(&mut stream))
+            .unwrap();
+        let witness = sign.verify_send_nonce_ke_msg(&recv_frame(&mut stream)).unwrap();
+        send_frame(&mut stream, &witness);
+        send_frame(&mut stream, &sign.online_sign(&hash).unwrap());
+    });
+
+    let mut stream = std::net::TcpStream::connect(addr).unwrap();
+    let keygen = party_one::KeyGen::new();
+    send_frame(&mut stream, &keygen.generate_key_com());
+    let (key_msg, key_store) = keygen.verify_send_key_msg(&recv_frame(&mut stream)).unwrap();
+    send_frame(&mut stream, &key_msg);
+
+    let mut sign = party_one::Sign::new_strict(key_store).unwrap();
+    sign.get_nonce_com(&recv_frame(&mut stream));
+    let group = CLGroup::new();
+    let (cl_priv_key, cl_pub_key) = group.keygen();
+    let mut mta = mta::PartyOne::new(sign.reshared_secret_share);
+    let first_round = mta.generate_send_msg(&cl_pub_key);
+    send_frame(&mut stream, &first_round);
+    let second_round: mta::MTASecondRoundMsg = recv_frame(&mut stream);
+    mta.handle_receive_msg(&cl_priv_key, &second_round.c_a).unwrap();
+    sign.set_mta_wc_msg(&first_round.state, &second_round);
+    send_frame(&mut stream, &sign.generate_mta_consistency(mta.t_b));
+    send_frame(&mut stream, &sign.generate_nonce_ke_msg());
+    sign.verify_nonce_ke_msg(&recv_frame(&mut stream)).unwrap();
+    let s_2: Scalar = recv_frame(&mut stream);
+    let signature = sign.online_sign(&s_2, &message_hash).unwrap();
+    party_two.join().unwrap();
+
+    let bytes = [signature.r.to_bytes(), signature.s.to_bytes()].concat();
+    let k256_sig = k256::ecdsa::Signature::from_bytes(&bytes).unwrap();
+    let verifying_key = sign.key_store.verifying_key().unwrap();
+    verifying_key.verify(message, &k256_sig).unwrap();
+    assert_eq!(
+        verifying_key.to_encoded_point(true).as_bytes(),
+        &sign.key_store.public_key_sec1(true)[..]
+    );
+}
+
+#[test]
+fn protocol_profile_test() {
+    use crate::nonce_registry::{MemoryNonceRegistry, NonceRegistry};
+    use std::sync::{Arc, Mutex};
+    use utilities::error::MulEcdsaError;
+
+    let (party_one_key, party_two_key) = key_pair();
+    let registry = || -> Arc<Mutex<dyn NonceRegistry>> {
+        Arc::new(Mutex::new(MemoryNonceRegistry::default()))
+    };
+    let strict = ProtocolProfile::Strict;
+    assert!(strict.cl_options().blind_decryption);
+
+    let mut one = party_one::Sign::new(party_one_key)
+        .unwrap()
+        .with_profile(strict);
+    assert!(one.strict);
+    let mut two = party_two::Sign::new(party_two_key)
+        .unwrap()
+        .with_profile(strict);
+    let witness = two.dl_com_zk_com.witness.clone();
+    assert_eq!(
+        one.clone().verify_nonce_ke_msg(&witness),
+        Err(MulEcdsaError::MissingSessionBinding)
+    );
+    assert_eq!(
+        two.clone().verify_send_nonce_ke_msg(&one.generate_nonce_ke_msg()).unwrap_err(),
+        MulEcdsaError::MissingSessionBinding.to_string()
+    );
+
+    one = one.for_session(b"session", &[1u8; 32]);
+    two = two.for_session(b"session", &[1u8; 32]);
+    assert_eq!(
+        one.clone().verify_nonce_ke_msg(&witness),
+        Err(MulEcdsaError::MissingNonceRegistry)
+    );
+    assert_eq!(
+        two.clone().verify_send_nonce_ke_msg(&one.generate_nonce_ke_msg()).unwrap_err(),
+        MulEcdsaError::MissingNonceRegistry.to_string()
+    );
+
+    // with both in place only the MtAwc proof is missing
+    let mut one = one.with_nonce_registry(registry());
+    let mut two = two.with_nonce_registry(registry());
+    one.get_nonce_com(&two.generate_nonce_com());
+    two.verify_send_nonce_ke_msg(&one.generate_nonce_ke_msg()).unwrap();
+    assert_eq!(
+        one.verify_nonce_ke_msg(&two.dl_com_zk_com.witness),
+        Err(MulEcdsaError::MissingMtAwcProof)
+    );
+}
+
+#[test]
+fn seeded_run_reproduces_signing_test() {
+    use crate::simulation::Simulation;
+    use crate::utilities::rng::SeededRun;
+
+    let message_hash = [0x42u8; 32];
+    let seed = SeededRun::from_env().seed;
+    let sign = |seed| {
+        let mut sim = Simulation::new(seed);
+        (sim.run(&message_hash).unwrap(), sim.session)
+    };
+    let signature = sign(seed);
+    assert_eq!(sign(seed), signature);
+    // the simulation's seed alone decides, even inside another seeded run
+    assert_eq!(SeededRun::new(seed.wrapping_add(1)).run(|| sign(seed)), signature);
+    assert_ne!(sign(seed.wrapping_add(1)), signature);
+}
+
+#[test]
+fn zero_and_identity_rejection_test() {
+    use utilities::error::MulEcdsaError;
+    use k256::ProjectivePoint;
+
+    let (party_one_key, party_two_key) = key_pair();
+    assert_eq!(
+        KeyStore::new(party_one_key.secret_share, ProjectivePoint::IDENTITY, party_one_key.public_share)
+            .unwrap_err(),
+        MulEcdsaError::InvalidKeyStore
+    );
+
+    let mut one = party_one::Sign::new(party_one_key).unwrap();
+    let two = party_two::Sign::new(party_two_key).unwrap();
+    one.get_nonce_com(&two.generate_nonce_com());
+
+    let mut consistency = one.generate_mta_consistency(Scalar::random(&mut ProtocolRng));
+    consistency.r1 = Scalar::ZERO;
+    assert_eq!(
+        two.clone()
+            .verify_generate_mta_consistency(Scalar::ZERO, &consistency)
+            .unwrap_err(),
+        MulEcdsaError::ZeroScalar.to_string()
+    );
+    consistency.r1 = -two.nonce_secret_share;
+    assert_eq!(
+        two.clone()
+            .verify_generate_mta_consistency(Scalar::ZERO, &consistency)
+            .unwrap_err(),
+        MulEcdsaError::ZeroScalar.to_string()
+    );
+
+    let mut nonce_msg = one.generate_nonce_ke_msg();
+    nonce_msg.nonce_public_key = ProjectivePoint::IDENTITY;
+    assert_eq!(
+        two.clone().verify_send_nonce_ke_msg(&nonce_msg).unwrap_err(),
+        MulEcdsaError::IdentityPoint.to_string()
+    );
+
+    let mut witness = two.dl_com_zk_com.witness.clone();
+    witness.public_share = ProjectivePoint::IDENTITY;
+    assert_eq!(
+        one.clone().verify_nonce_ke_msg(&witness),
+        Err(MulEcdsaError::IdentityPoint)
+    );
+    assert_eq!(
+        one.online_sign(&Scalar::ZERO, &[0x42u8; 32]).unwrap_err(),
+        MulEcdsaError::ZeroScalar
+    );
+}
+
+#[test]
+fn signing_msg_wire_format_test() {
+    use crate::utilities::k256_helpers::DLogProof;
+
+    fn encode<T: serde::Serialize>(value: &T) -> Vec<u8> {
+        bincode::serde::encode_to_vec(value, standard()).unwrap()
+    }
+    fn point(p: &k256::ProjectivePoint) -> Vec<u8> {
+        encode(&p.to_affine().to_encoded_point(true).as_bytes())
+    }
+
+    let g = k256::ProjectivePoint::GENERATOR;
+    let msg = MtaConsistencyMsg {
+        reshared_public_share: g,
+        r1: Scalar::from(2u64),
+        cc: Scalar::from(3u64),
+        public_key: g * Scalar::from(4u64),
+    };
+    // exhaustive, so a new field does not compile until it is placed below
+    let MtaConsistencyMsg { reshared_public_share, r1, cc, public_key } = &msg;
+    let expected = [point(reshared_public_share), encode(r1), encode(cc), point(public_key)].concat();
+    assert_eq!(encode(&msg), expected);
+    let (decoded, _): (MtaConsistencyMsg, usize) =
+        bincode::serde::decode_from_slice(&expected, standard()).unwrap();
+    assert_eq!(decoded.public_key, msg.public_key);
+
+    let msg = NonceKEMsg {
+        nonce_public_key: g,
+        dl_proof: DLogProof {
+            pk_t_rand_commitment: g * Scalar::from(5u64),
+            challenge_response: Scalar::from(6u64),
+        },
+    };
+    let NonceKEMsg {
+        nonce_public_key,
+        dl_proof: DLogProof { pk_t_rand_commitment, challenge_response },
+    } = &msg;
+    let expected = [point(nonce_public_key), point(pk_t_rand_commitment), encode(challenge_response)].concat();
+    assert_eq!(encode(&msg), expected);
+    let (decoded, _): (NonceKEMsg, usize) =
+        bincode::serde::decode_from_slice(&expected, standard()).unwrap();
+    assert_eq!(decoded.dl_proof.pk_t_rand_commitment, msg.dl_proof.pk_t_rand_commitment);
+}
+
+#[test]
+fn key_refresh_test() {
+    use crate::refresh::{apply_refresh, start_refresh, RefreshOffsetMsg};
+    use crate::utilities::error::MulEcdsaError;
+
+    let party_one_keygen = party_one::KeyGen::new();
+    let mut party_two_keygen = party_two::KeyGen::new();
+    let party_two_msg = party_two_keygen.get_key_com_send_key_msg(&party_one_keygen.generate_key_com());
+    let (party_one_msg, party_one_key) =
+        party_one_keygen.verify_send_key_msg(&party_two_msg).unwrap();
+    let mut party_two_key = party_two_keygen.verify_key_msg(&party_one_msg).unwrap();
+
+    let now = unix_time();
+    let policy = RefreshPolicy::default().with_max_signatures(2).with_max_age_days(30);
+    party_two_key.check_refresh(&policy, now).unwrap();
+    party_two_key.record_signature();
+    party_two_key.record_signature();
+    assert_eq!(
+        party_two::Sign::with_refresh_policy(party_two_key.clone(), &policy, now).unwrap_err(),
+        MulEcdsaError::RefreshRequired
+    );
+    let later = now + 30 * 24 * 60 * 60;
+    assert_eq!(
+        party_one_key.check_refresh(&policy, later),
+        Err(MulEcdsaError::RefreshRequired)
+    );
+
+    let (msg, party_two_refreshed) = start_refresh(&party_two_key).unwrap();
+    let bytes = bincode::serde::encode_to_vec(&msg, standard()).unwrap();
+    let (msg, _): (RefreshOffsetMsg, usize) =
+        bincode::serde::decode_from_slice(&bytes, standard()).unwrap();
+
+    // the offset must be the one the proof is about
+    let mut forged = msg.clone();
+    forged.offset_point = -forged.offset_point;
+    assert!(apply_refresh(&party_one_key, &party_one_keygen.cl_priv_key, &forged).is_err());
+
+    let party_one_refreshed =
+        apply_refresh(&party_one_key, &party_one_keygen.cl_priv_key, &msg).unwrap();
+    assert_eq!(
+        party_one_refreshed.secret_share + party_two_refreshed.secret_share,
+        party_one_key.secret_share + party_two_key.secret_share
+    );
+    assert_ne!(party_one_refreshed.secret_share, party_one_key.secret_share);
+    party_one_refreshed
+        .check_counterparty(&party_two_refreshed.public_share, None)
+        .unwrap();
+    assert_eq!(party_two_refreshed.usage.signatures, 0);
+    party_two::Sign::with_refresh_policy(party_two_refreshed, &policy, now).unwrap();
+    party_one::Sign::with_refresh_policy(party_one_refreshed, &policy, now).unwrap();
+
+    // party one holds no counterparty CL key to refresh towards
+    assert_eq!(
+        start_refresh(&party_one_key).unwrap_err(),
+        MulEcdsaError::CounterpartyMismatch
+    );
+}
+
+#[test]
+fn key_tweak_test() {
+    use crate::tweak::{taproot_key_store, taproot_tweak, TweakConfirmMsg};
+    use crate::utilities::error::MulEcdsaError;
+
+    let (party_one_key, party_two_key) = key_pair();
+
+    let t = Scalar::random(&mut ProtocolRng);
+    let party_one_tweaked = party_one_key.tweak_add(t, Party::One).unwrap();
+    let party_two_tweaked = party_two_key.tweak_add(t, Party::Two).unwrap();
+    TweakConfirmMsg::new(&party_two_tweaked).verify(&party_one_tweaked).unwrap();
+    TweakConfirmMsg::new(&party_one_tweaked).verify(&party_two_tweaked).unwrap();
+    assert_eq!(
+        k256::ProjectivePoint::GENERATOR * (party_one_tweaked.secret_share + party_two_tweaked.secret_share),
+        party_one_key.public_signing_key + k256::ProjectivePoint::GENERATOR * t
+    );
+    assert_eq!(party_two_tweaked.secret_share, party_two_key.secret_share);
+
+    // a peer that applied another tweak, or the same role twice, is caught
+    let other = party_two_key.tweak_add(t + Scalar::ONE, Party::Two).unwrap();
+    assert_eq!(
+        TweakConfirmMsg::new(&other).verify(&party_one_tweaked),
+        Err(MulEcdsaError::CounterpartyMismatch)
+    );
+    let both_one = party_two_key.tweak_add(t, Party::One).unwrap();
+    assert!(TweakConfirmMsg::new(&both_one).verify(&party_one_tweaked).is_err());
+
+    let merkle_root = [7u8; 32];
+    let party_one_taproot = taproot_key_store(&party_one_key, Some(&merkle_root), Party::One).unwrap();
+    let party_two_taproot = taproot_key_store(&party_two_key, Some(&merkle_root), Party::Two).unwrap();
+    TweakConfirmMsg::new(&party_two_taproot).verify(&party_one_taproot).unwrap();
+    TweakConfirmMsg::new(&party_one_taproot).verify(&party_two_taproot).unwrap();
+    let internal_key = match party_one_key.public_key_sec1(true)[0] {
+        0x02 => party_one_key.public_signing_key,
+        _ => -party_one_key.public_signing_key,
+    };
+    let tweak = taproot_tweak(&internal_key, Some(&merkle_root)).unwrap();
+    assert_eq!(
+        party_one_taproot.public_signing_key,
+        internal_key + k256::ProjectivePoint::GENERATOR * tweak
+    );
+    party_one::Sign::new(party_one_taproot).unwrap();
+    party_two::Sign::new(party_two_taproot).unwrap();
+}
+
+#[test]
+fn presignature_pool_test() {
+    use crate::presign::{Presignature, PresignaturePool};
+    use utilities::error::MulEcdsaError;
+
+    let (party_one_key, party_two_key) = key_pair();
+
+    // the offline phase, with no message yet
+    let mut party_one_sign = party_one::Sign::new(party_one_key.clone()).unwrap();
+    let mut party_two_sign = party_two::Sign::new(party_two_key.clone()).unwrap();
+    for missing in [
+        Presignature::party_one(&party_one_sign, unix_time() + 60),
+        Presignature::party_two(&party_two_sign, unix_time() + 60),
+    ] {
+        assert_eq!(missing.unwrap_err(), MulEcdsaError::MissingMsg);
+    }
+    run_presign(&mut party_one_sign, &mut party_two_sign);
+
+    let now = unix_time();
+    let dir = std::env::temp_dir().join(format!("presignature_pool_test_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let party_one_pool = PresignaturePool::open(dir.join("one")).unwrap();
+    let party_two_pool = PresignaturePool::open(dir.join("two")).unwrap();
+    let party_one_presig = Presignature::party_one(&party_one_sign, now + 60).unwrap();
+    let party_two_presig = Presignature::party_two(&party_two_sign, now + 60).unwrap();
+    assert_eq!(party_one_presig.id, party_two_presig.id);
+    let (mut cancelled_one, mut cancelled_two) = (party_one_sign.clone(), party_two_sign.clone());
+    cancelled_one.cancel();
+    cancelled_two.cancel();
+    assert_eq!(Presignature::party_one(&cancelled_one, now + 60).unwrap_err(), MulEcdsaError::MissingMsg);
+    assert_eq!(Presignature::party_two(&cancelled_two, now + 60).unwrap_err(), MulEcdsaError::MissingMsg);
+    party_one_pool.insert(&party_one_presig).unwrap();
+    party_two_pool.insert(&party_two_presig).unwrap();
+    drop((party_one_sign, party_two_sign));
+
+    // later, after a restart, the message arrives
+    let id = party_two_pool.ids().unwrap()[0];
+    let message_hash = [0x42u8; 32];
+    let s_2 = party_two_pool
+        .take(&id, &party_two_key, now)
+        .and_then(|presig| presig.into_party_two_sign(party_two_key.clone(), now))
+        .unwrap()
+        .online_sign(&message_hash)
+        .unwrap();
+    let signature = party_one_pool
+        .take(&id, &party_one_key, now)
+        .and_then(|presig| presig.into_party_one_sign(party_one_key.clone(), now))
+        .unwrap()
+        .online_sign(&s_2, &message_hash)
+        .unwrap();
+    signature
+        .verify_prehashed(&party_one_key.public_signing_key, &message_hash)
+        .unwrap();
+    assert_eq!(
+        party_one_pool.take(&id, &party_one_key, now).unwrap_err(),
+        MulEcdsaError::PresignatureUnavailable
+    );
+
+    // expired, or made before a refresh
+    let offset = Scalar::random(&mut ProtocolRng);
+    let refreshed = KeyStore::new(
+        party_one_key.secret_share + offset,
+        party_one_key.counterparty_share - k256::ProjectivePoint::GENERATOR * offset,
+        party_one_key.public_signing_key,
+    )
+    .unwrap();
+    for (expires_at, key_store) in [(now, &party_one_key), (now + 60, &refreshed)] {
+        let mut presig = party_one_presig.clone();
+        presig.id = Scalar::random(&mut ProtocolRng).to_bytes().into();
+        presig.expires_at = expires_at;
+        party_one_pool.insert(&presig).unwrap();
+        assert_eq!(party_one_pool.purge(key_store, now).unwrap(), 1);
+        assert_eq!(
+            presig.into_party_one_sign(key_store.clone(), now).unwrap_err(),
+            MulEcdsaError::PresignatureUnavailable
+        );
+    }
+    assert!(party_one_pool.ids().unwrap().is_empty());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn co_signer_set_test() {
+    use crate::cosigner::CoSignerSet;
+    use utilities::error::MulEcdsaError;
+
+    let (party_one_key, party_two_key) = key_pair();
+    let mut set = CoSignerSet::new(party_one_key.clone(), ["primary".to_string(), "standby".to_string()]).unwrap();
+    let message_hash = [0x42u8; 32];
+
+    // runs a session up to party two's partial signature
+    let run = |party_one_sign: &mut party_one::Sign, session_id: &[u8], message_hash: &[u8; 32]| {
+        let mut party_two_sign = party_two::Sign::new(party_two_key.clone())
+            .unwrap()
+            .for_session(session_id, message_hash);
+        run_presign(party_one_sign, &mut party_two_sign);
+        party_two_sign.online_sign(message_hash).unwrap()
+    };
+
+    assert_eq!(
+        set.party_one_sign("unknown", b"session 0", &message_hash).unwrap_err(),
+        MulEcdsaError::CounterpartyMismatch
+    );
+    let mut primary = set.party_one_sign("primary", b"session 1", &message_hash).unwrap();
+    let mut standby = set.party_one_sign("standby", b"session 2", &message_hash).unwrap();
+    assert_eq!(set.sessions(&message_hash), vec!["primary", "standby"]);
+
+    // the standby answers first; the primary's late result is withheld
+    let s_2 = run(&mut standby, b"session 2", &message_hash);
+    let signature = set.finish_party_one("standby", &standby, &s_2, &message_hash).unwrap();
+    signature
+        .verify_prehashed(&party_one_key.public_signing_key, &message_hash)
+        .unwrap();
+    assert_eq!(set.released(&message_hash), Some("standby"));
+    let s_2 = run(&mut primary, b"session 1", &message_hash);
+    assert_eq!(
+        set.finish_party_one("primary", &primary, &s_2, &message_hash).unwrap_err(),
+        MulEcdsaError::SignatureConflict
+    );
+    assert!(set.sessions(&message_hash).is_empty());
+    assert_eq!(
+        set.party_one_sign("primary", b"session 3", &message_hash).unwrap_err(),
+        MulEcdsaError::SignatureConflict
+    );
+
+    // a session that is given up leaves the message to the other co-signer
+    let other_hash = [0x43u8; 32];
+    set.party_one_sign("primary", b"session 4", &other_hash).unwrap();
+    set.end("primary", &other_hash);
+    assert!(set.sessions(&other_hash).is_empty());
+    assert_eq!(set.released(&other_hash), None);
+
+    // and so does one that runs past the timeout
+    let clock = std::sync::Arc::new(clock::ManualClock::new(1_700_000_000));
+    let mut set = set.with_clock(clock.clone()).with_session_timeout(30);
+    let mut late = set.party_one_sign("primary", b"session 5", &other_hash).unwrap();
+    clock.advance(20);
+    set.party_one_sign("standby", b"session 6", &other_hash).unwrap();
+    clock.advance(10);
+    let s_2 = run(&mut late, b"session 5", &other_hash);
+    assert_eq!(
+        set.finish_party_one("primary", &late, &s_2, &other_hash).unwrap_err(),
+        MulEcdsaError::SessionExpired
+    );
+    assert_eq!(set.sessions(&other_hash), vec!["standby"]);
+    clock.advance(20);
+    assert_eq!(set.expire(), 1);
+    assert!(set.sessions(&other_hash).is_empty());
+
+    // a released message is refused for `release_ttl` only
+    let mut set = CoSignerSet::new(party_one_key.clone(), ["primary".to_string()])
+        .unwrap()
+        .with_clock(clock.clone())
+        .with_release_ttl(60);
+    let mut party_one_sign = set.party_one_sign("primary", b"session 9", &other_hash).unwrap();
+    let s_2 = run(&mut party_one_sign, b"session 9", &other_hash);
+    set.finish_party_one("primary", &party_one_sign, &s_2, &other_hash).unwrap();
+    clock.advance(59);
+    assert_eq!(
+        set.party_one_sign("primary", b"session 10", &other_hash).unwrap_err(),
+        MulEcdsaError::SignatureConflict
+    );
+    clock.advance(1);
+    set.purge();
+    assert_eq!(set.released(&other_hash), None);
+    set.party_one_sign("primary", b"session 10", &other_hash).unwrap();
+
+    // on party two's side, a session that fails to sign releases nothing
+    let mut set = CoSignerSet::new(party_two_key.clone(), ["primary".to_string(), "standby".to_string()]).unwrap();
+    let mut failed = set.party_two_sign("primary", b"session 7", &message_hash).unwrap();
+    let mut party_two_sign = set.party_two_sign("standby", b"session 8", &message_hash).unwrap();
+    failed.cancel();
+    assert_eq!(
+        set.finish_party_two("primary", &mut failed, &message_hash).unwrap_err(),
+        MulEcdsaError::Cancelled
+    );
+    assert_eq!(set.sessions(&message_hash), vec!["standby"]);
+    assert_eq!(set.released(&message_hash), None);
+
+    // so the standby still completes the message
+    let mut party_one_sign = party_one::Sign::new(party_one_key.clone())
+        .unwrap()
+        .for_session(b"session 8", &message_hash);
+    run_presign(&mut party_one_sign, &mut party_two_sign);
+    let s_2 = set.finish_party_two("standby", &mut party_two_sign, &message_hash).unwrap();
+    assert_eq!(set.released(&message_hash), Some("standby"));
+    party_one_sign
+        .online_sign(&s_2, &message_hash)
+        .unwrap()
+        .verify_prehashed(&party_one_key.public_signing_key, &message_hash)
+        .unwrap();
+}
+
+#[test]
+fn crypto_pool_test() {
+    use crate::driver::CryptoPool;
+
+    // the MtA of a signing session, with every CL step on the pool
+    let pool = CryptoPool::new(2, 4);
+    let group = CLGroup::new();
+    let (cl_priv_key, cl_pub_key) = group.keygen();
+    let a = Scalar::random(&mut ProtocolRng);
+    let b = Scalar::random(&mut ProtocolRng);
+    let mta_party_one = mta::PartyOne::new(a);
+    let (mta_party_one, first_round) = pool
+        .run(move || {
+            let msg = mta_party_one.generate_send_msg(&cl_pub_key);
+            (mta_party_one, msg)
+        })
+        .unwrap()
+        .recv()
+        .unwrap();
+    let (mta_party_two, second_round) = pool
+        .run_blocking(move || {
+            let mut mta_party_two = mta::PartyTwo::new(b);
+            let msg = mta_party_two.receive_and_send_msg(first_round);
+            (mta_party_two, msg)
+        })
+        .unwrap()
+        .recv()
+        .unwrap();
+    let second_round = second_round.unwrap();
+    let mta_party_one = pool
+        .run_blocking(move || {
+            let mut mta_party_one = mta_party_one;
+            mta_party_one
+                .handle_receive_msg(&cl_priv_key, &second_round)
+                .map(|_| mta_party_one)
+        })
+        .unwrap()
+        .recv()
+        .unwrap()
+        .unwrap();
+    assert_eq!(a * b, mta_party_two.t_a + mta_party_one.t_b);
+}
+
+#[test]
+fn cancel_sign_test() {
+    use crate::codec::{CancelMsg, Codec, MessageKind};
+    use utilities::error::MulEcdsaError;
+
+    let (party_one_key, party_two_key) = key_pair();
+    let mut party_one_sign = party_one::Sign::new(party_one_key).unwrap();
+    let mut party_two_sign = party_two::Sign::new(party_two_key).unwrap();
+    run_mta(&mut party_one_sign, &mut party_two_sign);
+    let nonce_ke_msg = party_one_sign.generate_nonce_ke_msg();
+
+    // the user declines on party two's device before the nonce is opened
+    party_two_sign.cancel();
+    assert_eq!(party_two_sign.nonce_secret_share, Scalar::ZERO);
+    assert_eq!(party_two_sign.reshared_secret_share, Scalar::ZERO);
+    assert_eq!(party_two_sign.r1_rec, Scalar::ZERO);
+    assert_eq!(party_two_sign.key_store.secret_share, Scalar::ZERO);
+    assert_eq!(party_two_sign.secret_store.public_share(), k256::ProjectivePoint::IDENTITY);
+    assert_eq!(
+        party_two_sign.verify_send_nonce_ke_msg(&nonce_ke_msg).unwrap_err(),
+        MulEcdsaError::Cancelled.to_string()
+    );
+    assert_eq!(party_two_sign.online_sign(&[0x42u8; 32]), Err(MulEcdsaError::Cancelled));
+    let mut codec = Codec::default();
+    let bytes = codec.encode(MessageKind::Cancel, &CancelMsg { session: [7u8; 32] }).unwrap();
+
+    // party one gets the cancel in place of the opening and stops too
+    let msg: CancelMsg = codec.decode(MessageKind::Cancel, &bytes).unwrap();
+    assert_eq!(msg.session, [7u8; 32]);
+    party_one_sign.cancel();
+    assert_eq!(party_one_sign.nonce_secret_share, Scalar::ZERO);
+    assert_eq!(party_one_sign.key_store.secret_share, Scalar::ZERO);
+    assert_eq!(party_one_sign.secret_store.public_share(), k256::ProjectivePoint::IDENTITY);
+    assert_eq!(
+        party_one_sign.online_sign(&Scalar::ONE, &[0x42u8; 32]),
+        Err(MulEcdsaError::Cancelled)
+    );
+}
+
+#[test]
+fn party_two_online_sign_once_test() {
+    use crate::presign::Presignature;
+    use utilities::error::MulEcdsaError;
+
+    let (party_one_key, party_two_key) = key_pair();
+    let mut party_one_sign = party_one::Sign::new(party_one_key).unwrap();
+    let mut party_two_sign = party_two::Sign::new(party_two_key.clone()).unwrap();
+    run_mta(&mut party_one_sign, &mut party_two_sign);
+    // before the nonce exchange s_2 would give away the nonce share
+    assert_eq!(party_two_sign.online_sign(&[0x42u8; 32]), Err(MulEcdsaError::MissingMsg));
+
+    let witness = party_two_sign
+        .verify_send_nonce_ke_msg(&party_one_sign.generate_nonce_ke_msg())
+        .unwrap();
+    party_one_sign.verify_nonce_ke_msg(&witness).unwrap();
+    let now = unix_time();
+    let presignature = Presignature::party_two(&party_two_sign, now + 60).unwrap();
+    let s_2 = party_two_sign.online_sign(&[0x42u8; 32]).unwrap();
+    party_one_sign.online_sign(&s_2, &[0x42u8; 32]).unwrap();
+    assert_eq!(party_two_sign.online_sign(&[0x43u8; 32]), Err(MulEcdsaError::NonceReuse));
+
+    // a restored session signs once as well
+    let mut restored = presignature.into_party_two_sign(party_two_key, now).unwrap();
+    restored.online_sign(&[0x42u8; 32]).unwrap();
+    assert_eq!(restored.online_sign(&[0x43u8; 32]), Err(MulEcdsaError::NonceReuse));
+}
+
+#[test]
+fn idempotent_request_test() {
+    use crate::clock::ManualClock;
+    use crate::idempotency::{RequestCache, RequestState};
+    use crate::simulation::Simulation;
+    use utilities::error::MulEcdsaError;
+
+    let clock = std::sync::Arc::new(ManualClock::new(1_700_000_000));
+    let mut cache = RequestCache::new().with_clock(clock.clone()).with_ttl(60);
+    let message_hash = [0x42u8; 32];
+
+    assert_eq!(cache.begin("req-1", &message_hash), Ok(None));
+    assert_eq!(
+        cache.begin("req-1", &message_hash),
+        Err(MulEcdsaError::RequestInProgress)
+    );
+    let signature = Simulation::new(7).run(&message_hash).unwrap();
+    cache.complete("req-1", &signature).unwrap();
+
+    // a retry gets the same signature without another session
+    assert_eq!(cache.begin("req-1", &message_hash), Ok(Some(signature.clone())));
+    assert_eq!(
+        cache.begin("req-1", &[0x43u8; 32]),
+        Err(MulEcdsaError::RequestIdReused)
+    );
+    assert_eq!(
+        cache.complete("req-1", &signature),
+        Err(MulEcdsaError::UnknownSession)
+    );
+
+    // a failed session can be retried under the same id
+    assert_eq!(cache.begin("req-2", &message_hash), Ok(None));
+    cache.abandon("req-2");
+    assert_eq!(cache.state("req-2"), None);
+    assert_eq!(cache.begin("req-2", &message_hash), Ok(None));
+
+    // finished requests are forgotten after the ttl, running ones are not
+    clock.advance(60);
+    cache.purge();
+    assert_eq!(cache.state("req-1"), None);
+    assert_eq!(cache.state("req-2"), Some(&RequestState::Running));
+}
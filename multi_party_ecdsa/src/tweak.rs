@@ -0,0 +1,100 @@
+//! Tweaking the joint key by `t G`, for instance into the BIP341 output key
+//! of a Taproot output whose internal key is the two-party key. Both parties
+//! apply the same tweak with `KeyStore::tweak_add` (or `taproot_key_store`)
+//! and exchange `TweakConfirmMsg`s; neither uses its tweaked store until the
+//! peer's message checks out.
+
+use crate::shared::{KeyStore, Party};
+use crate::utilities::error::MulEcdsaError;
+use k256::elliptic_curve::sec1::ToEncodedPoint;
+use k256::elliptic_curve::PrimeField;
+use k256::{ProjectivePoint, Scalar};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Either party -> the other, after tweaking.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TweakConfirmMsg {
+    /// The sender's public share after the tweak.
+    #[serde(with = "crate::utilities::point_serde")]
+    pub public_share: ProjectivePoint,
+    #[serde(with = "crate::utilities::point_serde")]
+    pub public_signing_key: ProjectivePoint,
+}
+
+impl TweakConfirmMsg {
+    pub fn new(tweaked: &KeyStore) -> Self {
+        Self {
+            public_share: tweaked.public_share,
+            public_signing_key: tweaked.public_signing_key,
+        }
+    }
+
+    /// Checks the peer ended up with the same key and the share this party
+    /// expects of it, that is, that both applied the same tweak.
+    pub fn verify(&self, tweaked: &KeyStore) -> Result<(), MulEcdsaError> {
+        if self.public_share != tweaked.counterparty_share || self.public_signing_key != tweaked.public_signing_key {
+            return Err(MulEcdsaError::CounterpartyMismatch);
+        }
+        Ok(())
+    }
+}
+
+/// The BIP341 tweak `hash_TapTweak(x(P) || merkle_root)` of `internal_key`;
+/// `None` for an output without a script path.
+pub fn taproot_tweak(internal_key: &ProjectivePoint, merkle_root: Option<&[u8; 32]>) -> Result<Scalar, MulEcdsaError> {
+    let tag = Sha256::digest(b"TapTweak");
+    let mut hasher = Sha256::new();
+    hasher.update(tag);
+    hasher.update(tag);
+    hasher.update(&internal_key.to_affine().to_encoded_point(true).as_bytes()[1..]);
+    if let Some(merkle_root) = merkle_root {
+        hasher.update(merkle_root);
+    }
+    Option::from(Scalar::from_repr(hasher.finalize())).ok_or(MulEcdsaError::InvalidTweak)
+}
+
+/// The store for the BIP341 output key of the joint key. BIP341 takes the
+/// internal key with an even y, so when the joint key's is odd both parties
+/// first negate everything they hold.
+pub fn taproot_key_store(
+    key_store: &KeyStore,
+    merkle_root: Option<&[u8; 32]>,
+    party: Party,
+) -> Result<KeyStore, MulEcdsaError> {
+    let mut internal = key_store.clone();
+    if has_odd_y(&key_store.public_signing_key) {
+        internal.secret_share = -internal.secret_share;
+        internal.public_share = -internal.public_share;
+        internal.counterparty_share = -internal.counterparty_share;
+        internal.public_signing_key = -internal.public_signing_key;
+    }
+    let t = taproot_tweak(&internal.public_signing_key, merkle_root)?;
+    internal.tweak_add(t, party)
+}
+
+fn has_odd_y(point: &ProjectivePoint) -> bool {
+    point.to_affine().to_encoded_point(true).as_bytes()[0] == 0x03
+}
+
+#[test]
+fn taproot_tweak_test() {
+    use crate::utilities::parse::{parse_point, Encoding};
+
+    // the first key-path-only case of the BIP341 wallet test vectors
+    let internal_key = parse_point(
+        "02d6889cb081036e0faefa3a35157ad71086b123b2b144b649798b494c300a961d",
+        Encoding::Hex,
+    )
+    .unwrap();
+    let t = taproot_tweak(&internal_key, None).unwrap();
+    assert_eq!(
+        hex::encode(t.to_bytes()),
+        "b86e7be8f39bab32a6f2c0443abbc210f0edac0e2c53d501b36b64437d9c6c70"
+    );
+    let output_key = (internal_key + ProjectivePoint::GENERATOR * t).to_affine();
+    assert_eq!(
+        hex::encode(&output_key.to_encoded_point(true).as_bytes()[1..]),
+        "53a1f6e454df1aa2776a2814a721372d6258050de330b3c6d10ee8f4e0dda343"
+    );
+}
@@ -0,0 +1,169 @@
+use crate::utilities::error::MulEcdsaError;
+use bech32::{ToBase32, Variant};
+use k256::elliptic_curve::sec1::ToEncodedPoint;
+use k256::ProjectivePoint;
+use ripemd::Ripemd160;
+use sha2::{Digest, Sha256};
+use sha3::Keccak256;
+
+const BASE58_ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// SEC1 encoding of a public key, compressed (33 bytes) or uncompressed (65 bytes).
+pub fn public_key_sec1(public_key: &ProjectivePoint, compressed: bool) -> Vec<u8> {
+    public_key
+        .to_affine()
+        .to_encoded_point(compressed)
+        .as_bytes()
+        .to_vec()
+}
+
+/// Hex string of the compressed SEC1 encoding.
+pub fn compressed_sec1_hex(public_key: &ProjectivePoint) -> String {
+    hex::encode(public_key_sec1(public_key, true))
+}
+
+/// SHA-256 of the compressed SEC1 encoding, used to compare keys out-of-band.
+pub fn fingerprint(public_key: &ProjectivePoint) -> [u8; 32] {
+    Sha256::digest(public_key_sec1(public_key, true)).into()
+}
+
+/// `fingerprint` in base58check, for identifiers that have to be typed or
+/// read out.
+pub fn fingerprint_base58check(public_key: &ProjectivePoint) -> String {
+    base58check_encode(&fingerprint(public_key))
+}
+
+/// Base58 of `payload` followed by the first four bytes of its double
+/// SHA-256, as in Bitcoin's legacy addresses and WIF keys.
+pub fn base58check_encode(payload: &[u8]) -> String {
+    let mut bytes = payload.to_vec();
+    bytes.extend_from_slice(&Sha256::digest(Sha256::digest(payload))[..4]);
+    // little-endian base 58 digits of the big-endian bytes
+    let mut digits: Vec<u8> = Vec::new();
+    for &byte in &bytes {
+        let mut carry = byte as u32;
+        for digit in digits.iter_mut() {
+            carry += (*digit as u32) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+    let zeros = bytes.iter().take_while(|&&byte| byte == 0).count();
+    std::iter::repeat_n(b'1', zeros)
+        .chain(digits.iter().rev().map(|&digit| BASE58_ALPHABET[digit as usize]))
+        .map(char::from)
+        .collect()
+}
+
+/// The payload of a `base58check_encode` string; `InvalidInput` for a
+/// character outside the alphabet or a wrong checksum.
+pub fn base58check_decode(s: &str) -> Result<Vec<u8>, MulEcdsaError> {
+    let mut bytes: Vec<u8> = Vec::new();
+    for c in s.bytes() {
+        let mut carry = BASE58_ALPHABET
+            .iter()
+            .position(|&a| a == c)
+            .ok_or(MulEcdsaError::InvalidInput)? as u32;
+        for byte in bytes.iter_mut() {
+            carry += *byte as u32 * 58;
+            *byte = carry as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.push(carry as u8);
+            carry >>= 8;
+        }
+    }
+    let zeros = s.bytes().take_while(|&c| c == b'1').count();
+    bytes.extend(std::iter::repeat_n(0, zeros));
+    bytes.reverse();
+    if bytes.len() < 4 {
+        return Err(MulEcdsaError::InvalidInput);
+    }
+    let (payload, checksum) = bytes.split_at(bytes.len() - 4);
+    if Sha256::digest(Sha256::digest(payload))[..4] != *checksum {
+        return Err(MulEcdsaError::InvalidInput);
+    }
+    Ok(payload.to_vec())
+}
+
+/// RIPEMD160(SHA256(compressed key)), as used by Bitcoin.
+pub fn hash160(public_key: &ProjectivePoint) -> [u8; 20] {
+    let sha = Sha256::digest(public_key_sec1(public_key, true));
+    Ripemd160::digest(sha).into()
+}
+
+/// Bech32 segwit v0 P2WPKH address; `hrp` is "bc" for mainnet, "tb" for testnet.
+pub fn p2wpkh_address(public_key: &ProjectivePoint, hrp: &str) -> Result<String, MulEcdsaError> {
+    let mut data = vec![bech32::u5::try_from_u8(0).map_err(|_| MulEcdsaError::GenerateAddressFailed)?];
+    data.extend(hash160(public_key).to_base32());
+    bech32::encode(hrp, data, Variant::Bech32).map_err(|_| MulEcdsaError::GenerateAddressFailed)
+}
+
+/// EIP-55 checksummed Ethereum address: last 20 bytes of Keccak256 over the
+/// uncompressed key without its 0x04 prefix.
+pub fn ethereum_address(public_key: &ProjectivePoint) -> String {
+    let uncompressed = public_key_sec1(public_key, false);
+    let hash = Keccak256::digest(&uncompressed[1..]);
+    let lower = hex::encode(&hash[12..]);
+
+    let checksum = Keccak256::digest(lower.as_bytes());
+    let mut address = String::from("0x");
+    for (i, c) in lower.chars().enumerate() {
+        let nibble = (checksum[i / 2] >> (4 * (1 - i % 2))) & 0x0f;
+        if nibble >= 8 {
+            address.push(c.to_ascii_uppercase());
+        } else {
+            address.push(c);
+        }
+    }
+    address
+}
+
+#[test]
+fn address_test() {
+    // secret key 1, i.e. the generator
+    let g = ProjectivePoint::GENERATOR;
+    assert_eq!(
+        compressed_sec1_hex(&g),
+        "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798"
+    );
+    assert_eq!(
+        p2wpkh_address(&g, "bc").unwrap(),
+        "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4"
+    );
+    assert_eq!(
+        ethereum_address(&g),
+        "0x7E5F4552091A69125d5DfCb7b8C2659029395Bdf"
+    );
+}
+
+#[test]
+fn base58check_test() {
+    // the legacy P2PKH address of the generator
+    let g = ProjectivePoint::GENERATOR;
+    let mut payload = vec![0u8];
+    payload.extend_from_slice(&hash160(&g));
+    let address = base58check_encode(&payload);
+    assert_eq!(address, "1BgGZ9tcN4rm9KBzDn7KprQz87SZ26SAMH");
+    assert_eq!(base58check_decode(&address).unwrap(), payload);
+
+    let id = fingerprint_base58check(&g);
+    assert_eq!(base58check_decode(&id).unwrap(), fingerprint(&g));
+    assert_eq!(base58check_decode(&base58check_encode(&[0, 0, 1])).unwrap(), [0, 0, 1]);
+    assert_eq!(base58check_decode(&base58check_encode(&[])).unwrap(), []);
+
+    let mut typo = address.into_bytes();
+    typo[10] = if typo[10] == b'a' { b'b' } else { b'a' };
+    assert_eq!(
+        base58check_decode(std::str::from_utf8(&typo).unwrap()),
+        Err(MulEcdsaError::InvalidInput)
+    );
+    for bad in ["", "1", "0OIl", "1BgGZ9tcN4rm9KBzDn7KprQz87SZ26SAM"] {
+        assert_eq!(base58check_decode(bad), Err(MulEcdsaError::InvalidInput), "{}", bad);
+    }
+}
@@ -0,0 +1,95 @@
+//! Serde representation of `BigInt` fields, for use with
+//! `#[serde(with = "crate::utilities::bigint_serde")]`. Human-readable
+//! formats get a hex string (`-` prefixed when negative), binary formats
+//! the sign and the big-endian magnitude as bytes. The default `BigInt`
+//! encoding is a sign and a sequence of `u32` digits, which bincode writes
+//! as one varint per digit.
+
+use num_bigint::{BigInt, Sign};
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+pub fn serialize<S: Serializer>(value: &BigInt, serializer: S) -> Result<S::Ok, S::Error> {
+    if serializer.is_human_readable() {
+        return serializer.serialize_str(&value.to_str_radix(16));
+    }
+    let (sign, bytes) = value.to_bytes_be();
+    let sign: i8 = match sign {
+        Sign::Minus => -1,
+        Sign::NoSign => 0,
+        Sign::Plus => 1,
+    };
+    (sign, bytes).serialize(serializer)
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BigInt, D::Error> {
+    if deserializer.is_human_readable() {
+        let hex = <std::borrow::Cow<'de, str>>::deserialize(deserializer)?;
+        let (negative, digits) = match hex.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, &hex[..]),
+        };
+        // parse_bytes also takes a leading '+' and '_' separators
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(D::Error::custom("invalid hex integer"));
+        }
+        let value = BigInt::parse_bytes(digits.as_bytes(), 16)
+            .ok_or_else(|| D::Error::custom("invalid hex integer"))?;
+        return Ok(if negative { -value } else { value });
+    }
+    let (sign, bytes): (i8, Vec<u8>) = Deserialize::deserialize(deserializer)?;
+    let sign = match sign {
+        -1 => Sign::Minus,
+        0 => Sign::NoSign,
+        1 => Sign::Plus,
+        _ => return Err(D::Error::custom("invalid sign")),
+    };
+    if (sign == Sign::NoSign) != bytes.iter().all(|b| *b == 0) {
+        return Err(D::Error::custom("sign does not match magnitude"));
+    }
+    Ok(BigInt::from_bytes_be(sign, &bytes))
+}
+
+/// Borrowing wrapper for hand-written `Serialize` impls.
+pub struct Ser<'a>(pub &'a BigInt);
+
+impl Serialize for Ser<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize(self.0, serializer)
+    }
+}
+
+#[test]
+fn bigint_serde_test() {
+    use bincode::config::standard;
+    use serde::de::value::{Error as ValueError, StrDeserializer};
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Wrapped(#[serde(with = "crate::utilities::bigint_serde")] BigInt);
+
+    let values = [
+        BigInt::from(0),
+        BigInt::from(-255),
+        BigInt::from(1) << 300u32,
+    ];
+    for value in values {
+        let bytes = bincode::serde::encode_to_vec(Wrapped(value.clone()), standard()).unwrap();
+        let (decoded, _): (Wrapped, usize) =
+            bincode::serde::decode_from_slice(&bytes, standard()).unwrap();
+        assert_eq!(decoded.0, value);
+    }
+    let large = (BigInt::from(1) << 300u32) - 1;
+    let default = bincode::serde::encode_to_vec(&large, standard()).unwrap();
+    let wrapped = bincode::serde::encode_to_vec(Wrapped(large), standard()).unwrap();
+    assert!(wrapped.len() < default.len());
+
+    // a zero sign with a non-zero magnitude
+    let forged = bincode::serde::encode_to_vec((0i8, vec![1u8]), standard()).unwrap();
+    assert!(bincode::serde::decode_from_slice::<Wrapped, _>(&forged, standard()).is_err());
+
+    let hex = |s| deserialize(StrDeserializer::<ValueError>::new(s));
+    assert_eq!(hex("-ff").unwrap(), BigInt::from(-255));
+    assert!(hex("+ff").is_err());
+    assert!(hex("f_f").is_err());
+    assert!(hex("").is_err());
+}
@@ -0,0 +1,127 @@
+use crate::utilities::class_group::*;
+use crate::utilities::error::MulEcdsaError;
+use crate::utilities::fixed_mpz::{self, EXPONENT_RESPONSE_BYTES};
+use crate::utilities::sigma::{self, SigmaProtocol, Transcript};
+#[cfg(not(feature = "verify-only"))]
+use crate::utilities::sigma::SigmaProver;
+use classgroup::gmp::mpz::Mpz;
+use classgroup::gmp_classgroup::*;
+use classgroup::ClassGroup;
+use num_bigint::BigInt;
+use serde::{Deserialize, Serialize};
+
+/// Knowledge of a CL secret key: `pk = gq^sk`.
+pub struct ClKeyProtocol<'a> {
+    pub group: &'a CLGroup,
+}
+
+/// Non-interactive `ClKeyProtocol` proof.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ClKeyProof {
+    pub t: GmpClassGroup,
+    #[serde(
+        serialize_with = "fixed_mpz::serialize::<EXPONENT_RESPONSE_BYTES, _>",
+        deserialize_with = "fixed_mpz::deserialize::<EXPONENT_RESPONSE_BYTES, _>"
+    )]
+    pub z: Mpz,
+}
+
+impl ClKeyProtocol<'_> {
+    /// Bound for the prover randomness, hiding k * sk statistically.
+    fn randomness_bound(&self) -> BigInt {
+        let params = &self.group.params;
+        let hiding =
+            BigInt::from(2i32).pow((params.computational_security + params.slack_bits) as u32);
+        params.secret_key_bound(&mpz_to_bigint(self.group.stilde.clone())) * hiding
+    }
+}
+
+impl SigmaProtocol for ClKeyProtocol<'_> {
+    type Statement = GmpClassGroup;
+    type Commitment = GmpClassGroup;
+    type Response = Mpz;
+
+    fn challenge_bytes(&self) -> usize {
+        self.group.params.challenge_bytes()
+    }
+
+    fn check(&self, pk: &GmpClassGroup, t: &GmpClassGroup, k: &BigInt, z: &Mpz) -> bool {
+        let bound = self.randomness_bound();
+        let slack = BigInt::from(2i32).pow(self.group.params.slack_bits as u32);
+        if *z < Mpz::from(0) || *z > bigint_to_mpz(&bound + &bound / &slack) {
+            return false;
+        }
+        let mut pk_k = pk.clone();
+        pk_k.pow(bigint_to_mpz(k.clone()));
+        self.group.pk_for_sk(z.clone()) == t.clone() * pk_k
+    }
+
+    fn absorb_statement(&self, pk: &GmpClassGroup, transcript: &mut Transcript) {
+        transcript.append_class_group(b"gq", &self.group.gq);
+        transcript.append_class_group(b"pk", pk);
+    }
+
+    fn absorb_commitment(&self, t: &GmpClassGroup, transcript: &mut Transcript) {
+        transcript.append_class_group(b"t", t);
+    }
+}
+
+#[cfg(not(feature = "verify-only"))]
+impl SigmaProver for ClKeyProtocol<'_> {
+    type Witness = Mpz;
+    type Randomness = Mpz;
+
+    fn sample(&self) -> Mpz {
+        bigint_to_mpz(sample_below(&self.randomness_bound()))
+    }
+
+    fn commit(&self, _: &GmpClassGroup, r: &Mpz) -> GmpClassGroup {
+        self.group.pk_for_sk(r.clone())
+    }
+
+    fn respond(&self, sk: &Mpz, r: &Mpz, k: &BigInt) -> Mpz {
+        r.clone() + &bigint_to_mpz(k.clone()) * sk
+    }
+}
+
+impl ClKeyProof {
+    #[cfg(not(feature = "verify-only"))]
+    pub fn prove(group: &CLGroup, sk: &Mpz, pk: &GmpClassGroup) -> Self {
+        let (t, z) = sigma::prove(
+            &ClKeyProtocol { group },
+            &Transcript::new(b"ClKeyProof"),
+            pk,
+            sk,
+        );
+        Self { t, z }
+    }
+
+    pub fn verify(&self, group: &CLGroup, pk: &GmpClassGroup) -> Result<(), MulEcdsaError> {
+        group.check_elements(&[pk, &self.t])?;
+        match sigma::verify(
+            &ClKeyProtocol { group },
+            &Transcript::new(b"ClKeyProof"),
+            pk,
+            &self.t,
+            &self.z,
+        ) {
+            true => Ok(()),
+            false => Err(MulEcdsaError::VrfyCLKeyProofFailed),
+        }
+    }
+}
+
+#[cfg(not(feature = "verify-only"))]
+#[test]
+fn cl_key_proof_test() {
+    let group = CLGroup::new();
+    let (sk, pk) = group.keygen();
+    let proof = ClKeyProof::prove(&group, &sk, &pk);
+    proof.verify(&group, &pk).unwrap();
+
+    let (_, other_pk) = group.keygen();
+    assert_eq!(
+        proof.verify(&group, &other_pk),
+        Err(MulEcdsaError::VrfyCLKeyProofFailed)
+    );
+}
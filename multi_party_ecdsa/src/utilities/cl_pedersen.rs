@@ -0,0 +1,256 @@
+use crate::utilities::class_group::*;
+use crate::utilities::error::MulEcdsaError;
+use crate::utilities::fixed_mpz::{self, EXPONENT_RESPONSE_BYTES, ZQ_RESPONSE_BYTES};
+use crate::utilities::sigma::{self, SigmaProtocol, Transcript};
+#[cfg(not(feature = "verify-only"))]
+use crate::utilities::sigma::SigmaProver;
+use classgroup::gmp::mpz::Mpz;
+use classgroup::gmp_classgroup::*;
+use classgroup::ClassGroup;
+use k256::Scalar;
+use num_bigint::BigInt;
+use serde::{Deserialize, Serialize};
+
+/// Pedersen commitments C = g^x h^r in the class group, where g and h have
+/// unknown order and the committer must not know log_g(h).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ClPedersen {
+    pub g: GmpClassGroup,
+    pub h: GmpClassGroup,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PedersenCommitment {
+    pub c: GmpClassGroup,
+}
+
+#[derive(Clone, Debug)]
+pub struct PedersenOpening {
+    pub x: Scalar,
+    pub r: Mpz,
+}
+
+/// Proof of knowledge of an opening (x, r) of a commitment.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PedersenProof {
+    pub t: GmpClassGroup,
+    #[serde(
+        serialize_with = "fixed_mpz::serialize::<ZQ_RESPONSE_BYTES, _>",
+        deserialize_with = "fixed_mpz::deserialize::<ZQ_RESPONSE_BYTES, _>"
+    )]
+    pub zx: Mpz,
+    #[serde(
+        serialize_with = "fixed_mpz::serialize::<EXPONENT_RESPONSE_BYTES, _>",
+        deserialize_with = "fixed_mpz::deserialize::<EXPONENT_RESPONSE_BYTES, _>"
+    )]
+    pub zr: Mpz,
+}
+
+impl ClPedersen {
+    /// Derives h = g^alpha for a fresh alpha that is dropped immediately.
+    /// This must be run by the party that verifies the commitments, never by
+    /// the committer.
+    #[cfg(not(feature = "verify-only"))]
+    pub fn setup(group: &CLGroup) -> Self {
+        let (_, h) = group.keygen();
+        Self {
+            g: group.gq.clone(),
+            h,
+        }
+    }
+
+    pub fn from_generators(g: GmpClassGroup, h: GmpClassGroup) -> Self {
+        Self { g, h }
+    }
+
+    #[cfg(not(feature = "verify-only"))]
+    pub fn commit(&self, group: &CLGroup, x: &Scalar) -> (PedersenCommitment, PedersenOpening) {
+        let upper = group
+            .params
+            .secret_key_bound(&mpz_to_bigint(group.stilde.clone()));
+        let r = bigint_to_mpz(sample_below(&upper));
+        let c = self.commit_with_randomness(x, &r);
+        (c, PedersenOpening { x: *x, r })
+    }
+
+    pub fn commit_with_randomness(&self, x: &Scalar, r: &Mpz) -> PedersenCommitment {
+        let mut gx = self.g.clone();
+        gx.pow(scalar_to_mpz(x));
+        let mut hr = self.h.clone();
+        hr.pow(r.clone());
+        PedersenCommitment { c: gx * hr }
+    }
+
+    pub fn verify_opening(
+        &self,
+        commitment: &PedersenCommitment,
+        opening: &PedersenOpening,
+    ) -> Result<(), MulEcdsaError> {
+        if opening.r < Mpz::from(0)
+            || self.commit_with_randomness(&opening.x, &opening.r) != *commitment
+        {
+            return Err(MulEcdsaError::OpenPedersenCommFailed);
+        }
+        Ok(())
+    }
+}
+
+/// Knowledge of an opening of a commitment under `pedersen`.
+pub struct PedersenOpeningProtocol<'a> {
+    pub group: &'a CLGroup,
+    pub pedersen: &'a ClPedersen,
+}
+
+impl PedersenOpeningProtocol<'_> {
+    /// Bounds for the prover randomness of x (mod q) and r.
+    fn randomness_bounds(&self) -> (BigInt, BigInt) {
+        let params = &self.group.params;
+        let hiding =
+            BigInt::from(2i32).pow((params.computational_security + params.slack_bits) as u32);
+        let x_bound = mpz_to_bigint(q()) * &hiding;
+        let r_bound = params.secret_key_bound(&mpz_to_bigint(self.group.stilde.clone())) * &hiding;
+        (x_bound, r_bound)
+    }
+}
+
+impl SigmaProtocol for PedersenOpeningProtocol<'_> {
+    type Statement = PedersenCommitment;
+    type Commitment = GmpClassGroup;
+    type Response = (Mpz, Mpz);
+
+    fn challenge_bytes(&self) -> usize {
+        self.group.params.challenge_bytes()
+    }
+
+    fn check(
+        &self,
+        commitment: &PedersenCommitment,
+        t: &GmpClassGroup,
+        k: &BigInt,
+        (zx, zr): &Self::Response,
+    ) -> bool {
+        // responses of an honest prover stay below bound * (2^slack + 1) / 2^slack
+        let (x_bound, r_bound) = self.randomness_bounds();
+        let slack = BigInt::from(2i32).pow(self.group.params.slack_bits as u32);
+        let zx_max = bigint_to_mpz(&x_bound + &x_bound / &slack);
+        let zr_max = bigint_to_mpz(&r_bound + &r_bound / &slack);
+        if *zx < Mpz::from(0) || *zx > zx_max || *zr < Mpz::from(0) || *zr > zr_max {
+            return false;
+        }
+
+        let mut g_zx = self.pedersen.g.clone();
+        g_zx.pow(zx.clone());
+        let mut h_zr = self.pedersen.h.clone();
+        h_zr.pow(zr.clone());
+        let mut ck = commitment.c.clone();
+        ck.pow(bigint_to_mpz(k.clone()));
+        g_zx * h_zr == t.clone() * ck
+    }
+
+    fn absorb_statement(&self, commitment: &PedersenCommitment, transcript: &mut Transcript) {
+        transcript.append_class_group(b"g", &self.pedersen.g);
+        transcript.append_class_group(b"h", &self.pedersen.h);
+        transcript.append_class_group(b"c", &commitment.c);
+    }
+
+    fn absorb_commitment(&self, t: &GmpClassGroup, transcript: &mut Transcript) {
+        transcript.append_class_group(b"t", t);
+    }
+}
+
+#[cfg(not(feature = "verify-only"))]
+impl SigmaProver for PedersenOpeningProtocol<'_> {
+    type Witness = PedersenOpening;
+    type Randomness = (Mpz, Mpz);
+
+    fn sample(&self) -> Self::Randomness {
+        let (x_bound, r_bound) = self.randomness_bounds();
+        (
+            bigint_to_mpz(sample_below(&x_bound)),
+            bigint_to_mpz(sample_below(&r_bound)),
+        )
+    }
+
+    fn commit(&self, _: &PedersenCommitment, randomness: &Self::Randomness) -> GmpClassGroup {
+        let mut g_rho = self.pedersen.g.clone();
+        g_rho.pow(randomness.0.clone());
+        let mut h_rho = self.pedersen.h.clone();
+        h_rho.pow(randomness.1.clone());
+        g_rho * h_rho
+    }
+
+    fn respond(
+        &self,
+        opening: &PedersenOpening,
+        randomness: &Self::Randomness,
+        k: &BigInt,
+    ) -> Self::Response {
+        let k = bigint_to_mpz(k.clone());
+        let zx = randomness.0.clone() + &k * &scalar_to_mpz(&opening.x);
+        let zr = randomness.1.clone() + &k * &opening.r;
+        (zx, zr)
+    }
+}
+
+impl PedersenProof {
+    #[cfg(not(feature = "verify-only"))]
+    pub fn prove(
+        group: &CLGroup,
+        pedersen: &ClPedersen,
+        commitment: &PedersenCommitment,
+        opening: &PedersenOpening,
+    ) -> Self {
+        let protocol = PedersenOpeningProtocol { group, pedersen };
+        let (t, (zx, zr)) = sigma::prove(
+            &protocol,
+            &Transcript::new(b"PedersenProof"),
+            commitment,
+            opening,
+        );
+        Self { t, zx, zr }
+    }
+
+    pub fn verify(
+        &self,
+        group: &CLGroup,
+        pedersen: &ClPedersen,
+        commitment: &PedersenCommitment,
+    ) -> Result<(), MulEcdsaError> {
+        let protocol = PedersenOpeningProtocol { group, pedersen };
+        let response = (self.zx.clone(), self.zr.clone());
+        match sigma::verify(
+            &protocol,
+            &Transcript::new(b"PedersenProof"),
+            commitment,
+            &self.t,
+            &response,
+        ) {
+            true => Ok(()),
+            false => Err(MulEcdsaError::VrfyPedersenProofFailed),
+        }
+    }
+}
+
+#[cfg(not(feature = "verify-only"))]
+#[test]
+fn cl_pedersen_test() {
+    use k256::elliptic_curve::Field;
+    use crate::utilities::rng::ProtocolRng;
+
+    let group = CLGroup::new();
+    let pedersen = ClPedersen::setup(&group);
+    let x = Scalar::random(&mut ProtocolRng);
+    let (commitment, opening) = pedersen.commit(&group, &x);
+    pedersen.verify_opening(&commitment, &opening).unwrap();
+
+    let proof = PedersenProof::prove(&group, &pedersen, &commitment, &opening);
+    proof.verify(&group, &pedersen, &commitment).unwrap();
+
+    let wrong = PedersenOpening {
+        x: x + Scalar::ONE,
+        r: opening.r.clone(),
+    };
+    assert!(pedersen.verify_opening(&commitment, &wrong).is_err());
+    let (other, _) = pedersen.commit(&group, &x);
+    assert!(proof.verify(&group, &pedersen, &other).is_err());
+}
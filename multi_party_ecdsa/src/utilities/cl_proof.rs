@@ -1,14 +1,19 @@
 use crate::utilities::class_group::*;
 use crate::utilities::error::MulEcdsaError;
-use crate::utilities::SECURITY_PARAMETER;
+use crate::utilities::fixed_mpz::{self, EXPONENT_RESPONSE_BYTES, SCALAR_BYTES};
+use crate::utilities::sigma::{self, SigmaProtocol, Transcript};
+#[cfg(not(feature = "verify-only"))]
+use crate::utilities::sigma::SigmaProver;
+use crate::utilities::typed_int::*;
 use classgroup::gmp::mpz::Mpz;
 use classgroup::gmp_classgroup::*;
 use classgroup::ClassGroup;
+use num_bigint::BigInt;
 use k256::Scalar;
+#[cfg(not(feature = "verify-only"))]
 use k256::elliptic_curve::Field; 
-use num_bigint::{BigInt, Sign};
-use sha2::{Sha256, Digest};
-use rand::rngs::OsRng;
+#[cfg(not(feature = "verify-only"))]
+use crate::utilities::rng::ProtocolRng;
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -33,114 +38,224 @@ pub struct CLWit {
 pub struct CLProof {
     pub t1: GmpClassGroup,
     pub t2: GmpClassGroup,
+    #[serde(
+        serialize_with = "fixed_mpz::serialize::<EXPONENT_RESPONSE_BYTES, _>",
+        deserialize_with = "fixed_mpz::deserialize::<EXPONENT_RESPONSE_BYTES, _>"
+    )]
     pub u1: Mpz,
+    #[serde(
+        serialize_with = "fixed_mpz::serialize::<SCALAR_BYTES, _>",
+        deserialize_with = "fixed_mpz::deserialize::<SCALAR_BYTES, _>"
+    )]
     pub u2: Mpz,
 }
 
+/// Knowledge of (x, r) such that `cipher` is the CL encryption of x with
+/// randomness r under `cl_pub_key`.
+pub struct ClEncProtocol<'a> {
+    pub group: &'a CLGroup,
+}
+
+impl SigmaProtocol for ClEncProtocol<'_> {
+    type Statement = CLState;
+    type Commitment = (GmpClassGroup, GmpClassGroup);
+    type Response = (Mpz, Mpz);
+
+    fn challenge_bytes(&self) -> usize {
+        self.group.params.challenge_bytes()
+    }
+
+    fn check(
+        &self,
+        statement: &CLState,
+        commitment: &Self::Commitment,
+        k: &BigInt,
+        response: &Self::Response,
+    ) -> bool {
+        let (t1, t2) = commitment;
+        let group = self.group;
+
+        let sample_size = group
+            .params
+            .proof_response_bound(&mpz_to_bigint(group.stilde.clone()));
+        let u1 = match BoundedWitness::new(response.0.clone(), &bigint_to_mpz(sample_size)) {
+            Some(u1) => GroupExponent::new(u1.as_mpz().clone()),
+            None => return false,
+        };
+        let u2 = match ZqScalar::new(response.1.clone()) {
+            Some(u2) => u2,
+            None => return false,
+        };
+        let k = GroupExponent::new(bigint_to_mpz(k.clone()));
+
+        if t1.clone() * k.pow(&statement.cipher.c1) != u1.pow(&group.gq) {
+            return false;
+        };
+
+        let fu2 = expo_f(&q(), group.gq.discriminant(), u2.as_mpz());
+        t2.clone() * k.pow(&statement.cipher.c2) == u1.pow(&statement.cl_pub_key) * fu2
+    }
+
+    fn absorb_statement(&self, statement: &CLState, transcript: &mut Transcript) {
+        transcript.append_class_group(b"c1", &statement.cipher.c1);
+        transcript.append_class_group(b"c2", &statement.cipher.c2);
+        transcript.append_class_group(b"cl_pub_key", &statement.cl_pub_key);
+    }
+
+    fn absorb_commitment(&self, commitment: &Self::Commitment, transcript: &mut Transcript) {
+        transcript.append_class_group(b"t1", &commitment.0);
+        transcript.append_class_group(b"t2", &commitment.1);
+    }
+}
+
+#[cfg(not(feature = "verify-only"))]
+impl SigmaProver for ClEncProtocol<'_> {
+    type Witness = CLWit;
+    type Randomness = (GroupExponent, ZqScalar);
+
+    fn sample(&self) -> Self::Randomness {
+        let upper = self
+            .group
+            .params
+            .proof_randomness_bound(&mpz_to_bigint(self.group.stilde.clone()));
+        let r1 = GroupExponent::new(bigint_to_mpz(sample_below(&upper)));
+        let r2 = ZqScalar::from_scalar(&Scalar::random(&mut ProtocolRng));
+        (r1, r2)
+    }
+
+    fn commit(&self, statement: &CLState, randomness: &Self::Randomness) -> Self::Commitment {
+        let (r1, r2) = randomness;
+        let fr2 = expo_f(&q(), self.group.gq.discriminant(), r2.as_mpz());
+        let t2 = fr2 * self.group.pow(&statement.cl_pub_key, r1.as_mpz());
+        let t1 = self.group.pow(&self.group.gq, r1.as_mpz());
+        (t1, t2)
+    }
+
+    fn respond(
+        &self,
+        witness: &CLWit,
+        randomness: &Self::Randomness,
+        k: &BigInt,
+    ) -> Self::Response {
+        let (r1, r2) = randomness;
+        let u1 = r1.mul_add(k, &GroupExponent::new(witness.r.clone()));
+        let u2 = r2.mul_add(k, &ZqScalar::from_scalar(&witness.x));
+        (u1.into_mpz(), u2.into_mpz())
+    }
+}
+
 impl CLProof {
+    #[cfg(not(feature = "verify-only"))]
     pub fn prove(group: &CLGroup, witness: CLWit, statement: CLState) -> Self {
-        let upper = &mpz_to_bigint(group.stilde.clone())
-            * BigInt::from(2i32).pow(40)
-            * BigInt::from(2i32).pow(SECURITY_PARAMETER as u32)
-            * BigInt::from(2i32).pow(40);
-        let r1 = sample_below(&upper);
-        let r1_mpz = bigint_to_mpz(r1);
-        let r2_fe = Scalar::random(&mut OsRng);
-        let r2 = into_mpz(&r2_fe);
-        let fr2 = expo_f(&q(), &group.gq.discriminant(), &r2);
-        let mut pkr1 = statement.cl_pub_key.clone();
-        pkr1.pow(r1_mpz.clone());
-        let t2 = fr2 * pkr1;
-        let mut t1 = group.gq.clone();
-        t1.pow(r1_mpz.clone());
-        let k = Self::challenge(
-            &statement.cl_pub_key,
-            t1.clone(),
-            t2.clone(),
-            &statement.cipher,
-        );
-        let u1 = r1_mpz + &bigint_to_mpz(k.clone()) * &witness.r;
-        let q_bigint = mpz_to_bigint(q());
-        let u2 = mod_add(
-            &mpz_to_bigint(r2),
-            &(&k * scalar_to_bigint(&witness.x)),
-            &q_bigint,
+        let ((t1, t2), (u1, u2)) = sigma::prove(
+            &ClEncProtocol { group },
+            &Transcript::new(b"CLProof"),
+            &statement,
+            &witness,
         );
-
-        Self {
-            t1,
-            t2,
-            u1,
-            u2: bigint_to_mpz(u2),
-        }
+        Self { t1, t2, u1, u2 }
     }
 
-    /// Compute the Fiat-Shamir challenge for the proof.
-    pub fn challenge(
-        public_key: &GmpClassGroup,
-        t1: GmpClassGroup,
-        t2: GmpClassGroup,
-        ciphertext: &Ciphertext,
-    ) -> BigInt {
-        let mut hasher = Sha256::new();
-        hasher.update(<Vec<u8> as AsRef<[u8]>>::as_ref(&ciphertext.c1.to_bytes()));
-        hasher.update(<Vec<u8> as AsRef<[u8]>>::as_ref(&ciphertext.c2.to_bytes()));
-        hasher.update(<Vec<u8> as AsRef<[u8]>>::as_ref(&public_key.to_bytes()));
-        hasher.update(<Vec<u8> as AsRef<[u8]>>::as_ref(&t1.to_bytes()));
-        hasher.update(<Vec<u8> as AsRef<[u8]>>::as_ref(&t2.to_bytes()));
-        let hash256 = hasher.finalize();
-
-        let hash128 = &hash256[..SECURITY_PARAMETER / 8];
-        BigInt::from_bytes_be(Sign::Plus, hash128)
+    /// One proof per recipient of `CLGroup::encrypt_multi`, each against
+    /// that recipient's view of the ciphertext.
+    #[cfg(not(feature = "verify-only"))]
+    pub fn prove_multi(
+        group: &CLGroup,
+        witness: CLWit,
+        public_keys: &[GmpClassGroup],
+        cipher: &MultiCiphertext,
+    ) -> Vec<Self> {
+        public_keys
+            .iter()
+            .enumerate()
+            .map(|(i, cl_pub_key)| {
+                let statement = CLState {
+                    cipher: cipher.ciphertext(i).unwrap(),
+                    cl_pub_key: cl_pub_key.clone(),
+                };
+                Self::prove(group, witness.clone(), statement)
+            })
+            .collect()
     }
 
     pub fn verify(&self, group: &CLGroup, statement: CLState) -> Result<(), MulEcdsaError> {
-        let mut flag = true;
-
-        // reconstruct k
-        let k = Self::challenge(
-            &statement.cl_pub_key,
-            self.t1.clone(),
-            self.t2.clone(),
-            &statement.cipher,
-        );
+        let CLState { cipher, cl_pub_key } = &statement;
+        group.check_elements(&[&cipher.c1, &cipher.c2, cl_pub_key, &self.t1, &self.t2])?;
+        let commitment = (self.t1.clone(), self.t2.clone());
+        let response = (self.u1.clone(), self.u2.clone());
+        match sigma::verify(
+            &ClEncProtocol { group },
+            &Transcript::new(b"CLProof"),
+            &statement,
+            &commitment,
+            &response,
+        ) {
+            true => Ok(()),
+            false => Err(MulEcdsaError::VrfyCLProofFailed),
+        }
+    }
+}
 
-        let sample_size = &mpz_to_bigint(group.stilde.clone())
-            * BigInt::from(2i32).pow(40)
-            * BigInt::from(2i32).pow(SECURITY_PARAMETER as u32)
-            * (BigInt::from(2i32).pow(40) + BigInt::from(1));
+#[cfg(not(feature = "verify-only"))]
+#[test]
+fn cl_proof_params_test() {
+    use crate::utilities::ProtocolParams;
 
-        //length test u1:
-        if &self.u1 > &bigint_to_mpz(sample_size) || &self.u1 < &Mpz::from(0) {
-            flag = false;
-        }
-        // length test u2:
-        if &self.u2 > &q() || &self.u2 < &Mpz::from(0) {
-            flag = false;
-        }
+    let params = ProtocolParams::with_security_level(192).unwrap();
+    let group = CLGroup::new_with_params(params);
+    let (_, cl_pub_key) = group.keygen();
+    let x = Scalar::random(&mut ProtocolRng);
+    let (cipher, r) = CLGroup::encrypt(&group, &cl_pub_key, x);
+    let statement = CLState { cipher, cl_pub_key };
+    let proof = CLProof::prove(&group, CLWit { x, r }, statement.clone());
+    proof.verify(&group, statement.clone()).unwrap();
+
+    // a verifier expecting a different challenge length rejects the proof
+    let default_group = CLGroup::new();
+    assert!(proof.verify(&default_group, statement).is_err());
+}
 
-        let mut c1k = statement.cipher.c1;
-        c1k.pow(bigint_to_mpz(k.clone()));
-        let t1c1k = self.t1.clone() * c1k;
-        let mut gqu1 = group.gq.clone();
-        gqu1.pow(self.u1.clone());
-        if t1c1k != gqu1 {
-            flag = false;
+#[cfg(not(feature = "verify-only"))]
+#[test]
+fn cl_proof_multi_test() {
+    let group = CLGroup::new();
+    let public_keys: Vec<_> = (0..2).map(|_| group.keygen().1).collect();
+    let x = Scalar::random(&mut ProtocolRng);
+    let (cipher, r) = CLGroup::encrypt_multi(&group, &public_keys, x);
+    let proofs = CLProof::prove_multi(&group, CLWit { x, r }, &public_keys, &cipher);
+    for (i, proof) in proofs.iter().enumerate() {
+        let statement = CLState {
+            cipher: cipher.ciphertext(i).unwrap(),
+            cl_pub_key: public_keys[i].clone(),
         };
+        proof.verify(&group, statement).unwrap();
+    }
+    let swapped = CLState {
+        cipher: cipher.ciphertext(0).unwrap(),
+        cl_pub_key: public_keys[1].clone(),
+    };
+    assert!(proofs[0].verify(&group, swapped).is_err());
+}
 
-        let mut pku1 = statement.cl_pub_key;
-        pku1.pow(self.u1.clone());
-        let fu2 = expo_f(&q(), &group.gq.discriminant(), &self.u2);
-        let mut c2k = statement.cipher.c2;
-        c2k.pow(bigint_to_mpz(k));
-        let t2c2k = self.t2.clone() * c2k;
-        let pku1fu2 = pku1 * fu2;
-        if t2c2k != pku1fu2 {
-            flag = false;
-        }
-        match flag {
-            true => Ok(()),
-            false => Err(MulEcdsaError::VrfyCLProofFailed),
-        }
+#[cfg(not(feature = "verify-only"))]
+#[test]
+fn cl_proof_fixed_size_test() {
+    use bincode::config::standard;
+
+    let group = CLGroup::new();
+    let (_, cl_pub_key) = group.keygen();
+    for x in [Scalar::ZERO, Scalar::random(&mut ProtocolRng)] {
+        let (cipher, r) = CLGroup::encrypt(&group, &cl_pub_key, x);
+        let statement = CLState {
+            cipher,
+            cl_pub_key: cl_pub_key.clone(),
+        };
+        let proof = CLProof::prove(&group, CLWit { x, r }, statement.clone());
+        let bytes = bincode::serde::encode_to_vec(&proof, standard()).unwrap();
+        let forms = bincode::serde::encode_to_vec((&proof.t1, &proof.t2), standard()).unwrap();
+        assert_eq!(bytes.len() - forms.len(), EXPONENT_RESPONSE_BYTES + SCALAR_BYTES);
+        let (decoded, _): (CLProof, usize) =
+            bincode::serde::decode_from_slice(&bytes, standard()).unwrap();
+        decoded.verify(&group, statement).unwrap();
     }
 }
@@ -1,14 +1,22 @@
-use classgroup::gmp::mpz::Mpz;
+use crate::utilities::error::MulEcdsaError;
+use crate::utilities::k256_helpers::{curve_order, scalar_mod_order};
+use crate::utilities::ProtocolParams;
+use classgroup::gmp::mpz::{Mpz, ProbabPrimeResult};
 use classgroup::gmp_classgroup::*;
-use classgroup::ClassGroup;
+use classgroup::{estimate_class_number, BigNumExt, ClassGroup};
 use k256::Scalar;
 use k256::elliptic_curve::PrimeField;
-use num_bigint::{BigInt, Sign, RandBigInt};
+use num_bigint::{BigInt, Sign};
+use std::collections::HashMap;
 use std::str::FromStr;
-use rand::rngs::OsRng;
+use std::sync::{Arc, Mutex, OnceLock};
+#[cfg(not(feature = "verify-only"))]
+use crate::utilities::rng::ProtocolRng;
+#[cfg(not(feature = "verify-only"))]
+use rand::RngCore;
 use serde::{Deserialize, Serialize};
 
-#[cfg(test)]
+#[cfg(all(test, not(feature = "verify-only")))]
 use k256::elliptic_curve::Field;
 
 #[derive(Clone, Debug)]
@@ -16,6 +24,105 @@ pub struct CLGroup {
     pub delta_k: Mpz,
     pub gq: GmpClassGroup,
     pub stilde: Mpz,
+    pub params: ProtocolParams,
+    pub options: ClOptions,
+    /// Power tables shared by the proofs and encryptions of one session;
+    /// `None` exponentiates from scratch every time.
+    pub prover_context: Option<ProverContext>,
+}
+
+/// `base^(2^i)` for every bit of the exponents it serves, so a power of
+/// `base` costs only the multiplications of square-and-multiply.
+#[derive(Clone, Debug)]
+pub struct PowTable {
+    powers: Vec<GmpClassGroup>,
+}
+
+impl PowTable {
+    pub fn new(base: &GmpClassGroup, bits: usize) -> Self {
+        let mut powers = Vec::with_capacity(bits);
+        let mut power = base.clone();
+        for _ in 0..bits {
+            powers.push(power.clone());
+            power.square();
+        }
+        Self { powers }
+    }
+
+    pub fn bits(&self) -> usize {
+        self.powers.len()
+    }
+
+    /// `None` when `exponent` is negative or wider than the table.
+    pub fn pow(&self, exponent: &Mpz) -> Option<GmpClassGroup> {
+        let base = self.powers.first()?;
+        if *exponent < Mpz::zero() || exponent.bit_length() > self.bits() {
+            return None;
+        }
+        let mut result = base.identity();
+        for (i, power) in self.powers.iter().enumerate() {
+            if exponent.tstbit(i) {
+                result *= power;
+            }
+        }
+        Some(result)
+    }
+}
+
+/// Session-level cache of `PowTable`s for the bases a prover raises again
+/// and again: `gq` and the CL public key, across `CLProof`, `CLDLProof`,
+/// `ClKeyProof` and the encryptions they prove. Clones share the cache.
+#[derive(Clone, Debug, Default)]
+pub struct ProverContext {
+    tables: Arc<Mutex<HashMap<GmpClassGroup, Arc<PowTable>>>>,
+}
+
+impl ProverContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `base^exponent`, building the table of `base` with `bits` entries on
+    /// first use. Exponents the table cannot serve are raised directly.
+    pub fn pow(&self, base: &GmpClassGroup, exponent: &Mpz, bits: usize) -> GmpClassGroup {
+        let table = self
+            .tables
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .entry(base.clone())
+            .or_insert_with(|| Arc::new(PowTable::new(base, bits)))
+            .clone();
+        table.pow(exponent).unwrap_or_else(|| {
+            let mut result = base.clone();
+            result.pow_signed(exponent.clone());
+            result
+        })
+    }
+
+    /// Drops the table of `base`, e.g. a public key that was rotated out.
+    pub fn forget(&self, base: &GmpClassGroup) {
+        self.tables
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .remove(base);
+    }
+
+    /// Number of bases with a table.
+    pub fn cached_bases(&self) -> usize {
+        self.tables
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .len()
+    }
+}
+
+/// Implementation choices that do not change the protocol.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ClOptions {
+    /// Re-randomize the ciphertext with a fresh encryption of zero before
+    /// exponentiating by the secret key, so that exponentiation never runs
+    /// on an attacker-chosen c1. Costs two extra exponentiations.
+    pub blind_decryption: bool,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -24,8 +131,79 @@ pub struct Ciphertext {
     pub c2: GmpClassGroup,
 }
 
+/// Both forms abbreviated; `{:#?}` gives the full values.
+impl std::fmt::Display for Ciphertext {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Ciphertext(c1={}, c2={})", self.c1, self.c2)
+    }
+}
+
+/// A CL plaintext, always in `[0, q)`: values outside are either rejected
+/// by `new` or reduced by `reduce`, never silently wrapped by the
+/// encryption. Scalars convert directly.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Plaintext(Mpz);
+
+impl Plaintext {
+    pub fn new(m: Mpz) -> Result<Self, MulEcdsaError> {
+        match m >= Mpz::zero() && m < q() {
+            true => Ok(Self(m)),
+            false => Err(MulEcdsaError::PlaintextOutOfRange),
+        }
+    }
+
+    /// `m mod q`, for callers that mean to reduce.
+    pub fn reduce(m: &Mpz) -> Self {
+        Self(m.mod_floor(&q()))
+    }
+
+    pub fn as_mpz(&self) -> &Mpz {
+        &self.0
+    }
+}
+
+impl From<&Scalar> for Plaintext {
+    fn from(m: &Scalar) -> Self {
+        Self(scalar_to_mpz(m))
+    }
+}
+
+impl From<Scalar> for Plaintext {
+    fn from(m: Scalar) -> Self {
+        Self::from(&m)
+    }
+}
+
+impl From<Plaintext> for Scalar {
+    fn from(m: Plaintext) -> Self {
+        mpz_to_scalar(&m.0)
+    }
+}
+
+/// One plaintext encrypted to several public keys, sharing `c1 = gq^r`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MultiCiphertext {
+    pub c1: GmpClassGroup,
+    /// One per recipient, in the order of the public keys.
+    pub c2: Vec<GmpClassGroup>,
+}
+
+impl MultiCiphertext {
+    /// The ordinary ciphertext recipient `i` decrypts and proves against.
+    pub fn ciphertext(&self, i: usize) -> Option<Ciphertext> {
+        self.c2.get(i).map(|c2| Ciphertext {
+            c1: self.c1.clone(),
+            c2: c2.clone(),
+        })
+    }
+}
+
 impl CLGroup {
     pub fn new() -> Self {
+        Self::new_with_params(ProtocolParams::default())
+    }
+
+    pub fn new_with_params(params: ProtocolParams) -> Self {
         let delta_k = Mpz::from_str("-5612960460354297586496608465355436736175385121665162536528003724349027131555226649274328061478036486426974235182817460231858406454328229705097433539599357659030732986212902896965288623752937699627896244889952350312271535460213196686033784826094098560791044370859682930856242386198578254852455887200105136848768296981731378965699234956909793269449142655809687632817484368532297652832818925682445449730939672558315001010323704348812542103398759340104715127787089082447127193712577594846384285770469931817870736146192486488946997648500323172668328291265422577316785106221217309556660122713505680384876843920057653776862871100907889289236674725514431").unwrap();
         let a = Mpz::from_str("3379933361837959750444281267886081834476751587152191195702130129876229099797314884670653751744957540137083102210369145718831424083421213040698452363387299065826090566614550509104171596193940708452801446727936908797340323098201338663853170233065328696856790082422069275092967399794413723895514088363951458374936750806184395472544267780653575123461655052057240595359404437943529185106860238910043016082").unwrap();
         let b = Mpz::from_str("58358596530709071629230628954813789065094567413901151732504604054459961302465715041370372364950254062052414177175583619344532154277172761099891464143583046235404103174114873829883081661462607082144282568946995469931366172071928031362252538721358169137643386731728896321136677327778862260030176007687015790858390775199286445826383171957023481318023285705914617463624817890014105071550499557399120835").unwrap();
@@ -36,6 +214,49 @@ impl CLGroup {
             delta_k,
             gq,
             stilde,
+            params,
+            options: ClOptions::default(),
+            prover_context: None,
+        }
+    }
+
+    pub fn with_options(self, options: ClOptions) -> Self {
+        Self { options, ..self }
+    }
+
+    pub fn with_prover_context(self, prover_context: ProverContext) -> Self {
+        Self {
+            prover_context: Some(prover_context),
+            ..self
+        }
+    }
+
+    /// Estimated bit size of the class number of `delta_k`, checked against
+    /// `2 * computational_security` so generic order-finding stays out of
+    /// reach. A sanity check for discriminants that are not the built-in one.
+    pub fn check_class_number(&self) -> Result<usize, MulEcdsaError> {
+        match estimate_class_number(&self.delta_k) {
+            Some(bits) if bits >= 2 * self.params.computational_security => Ok(bits),
+            _ => Err(MulEcdsaError::ClassGroupTooSmall),
+        }
+    }
+
+    /// `base^exponent` for the prover side, through the prover context when
+    /// there is one. Tables cover the widest prover randomness.
+    pub fn pow(&self, base: &GmpClassGroup, exponent: &Mpz) -> GmpClassGroup {
+        match &self.prover_context {
+            Some(context) => {
+                let bits = self
+                    .params
+                    .proof_randomness_bound(&mpz_to_bigint(self.stilde.clone()))
+                    .bits() as usize;
+                context.pow(base, exponent, bits)
+            }
+            None => {
+                let mut result = base.clone();
+                result.pow_signed(exponent.clone());
+                result
+            }
         }
     }
 
@@ -47,25 +268,27 @@ impl CLGroup {
             delta_k: group.delta_k.clone(),
             gq: gq_new,
             stilde: group.stilde.clone(),
+            params: group.params,
+            options: group.options,
+            prover_context: group.prover_context.clone(),
         }
     }
 
+    #[cfg(not(feature = "verify-only"))]
     pub fn keygen(&self) -> (Mpz, GmpClassGroup) {
-        let upper = &(mpz_to_bigint(self.stilde.clone())) * BigInt::from(2i32).pow(40);
+        let upper = self.params.secret_key_bound(&mpz_to_bigint(self.stilde.clone()));
         let sk = bigint_to_mpz(sample_below(&upper));
-        let mut generator = self.gq.clone();
-        generator.pow(sk.clone());
-        let pk = generator;
+        let pk = self.pow(&self.gq, &sk);
         (sk, pk)
     }
 
-    pub fn encrypt(group: &CLGroup, public_key: &GmpClassGroup, m: &Scalar) -> (Ciphertext, Mpz) {
-        let k = into_mpz(m);
+    #[cfg(not(feature = "verify-only"))]
+    pub fn encrypt<M: Into<Plaintext>>(group: &CLGroup, public_key: &GmpClassGroup, m: M) -> (Ciphertext, Mpz) {
+        let k = m.into();
         let (r, r_big) = group.keygen();
         let delta = group.gq.discriminant().clone();
-        let exp_f = expo_f(&q(), &delta, &k);
-        let mut h_exp_r = public_key.clone();
-        h_exp_r.pow(r.clone());
+        let exp_f = expo_f(&q(), &delta, k.as_mpz());
+        let h_exp_r = group.pow(public_key, &r);
 
         (
             Ciphertext {
@@ -76,23 +299,64 @@ impl CLGroup {
         )
     }
 
-    pub fn decrypt(group: &CLGroup, secret_key: &Mpz, c: &Ciphertext) -> Scalar {
-        let mut c1_x_inv = c.c1.clone();
-        c1_x_inv.pow(secret_key.clone());
-        c1_x_inv.inverse();
-        let tmp = c.c2.clone() * &c1_x_inv;
-        let plaintext = discrete_log_f(&q(), &group.gq.discriminant(), &tmp);
-        debug_assert!(plaintext < q());
-        let plaintext_big = mpz_to_bigint(plaintext);
-        scalar_from_bigint(&plaintext_big)
+    /// Encrypts `m` to every key in `public_keys` with a single randomness,
+    /// so `gq^r` and `f^m` are computed once. The keys must be independent,
+    /// e.g. each checked with a `ClKeyProof`.
+    #[cfg(not(feature = "verify-only"))]
+    pub fn encrypt_multi<M: Into<Plaintext>>(
+        group: &CLGroup,
+        public_keys: &[GmpClassGroup],
+        m: M,
+    ) -> (MultiCiphertext, Mpz) {
+        let (r, c1) = group.keygen();
+        let exp_f = expo_f(&q(), group.gq.discriminant(), m.into().as_mpz());
+        let c2 = public_keys
+            .iter()
+            .map(|public_key| group.pow(public_key, &r) * &exp_f)
+            .collect();
+        (MultiCiphertext { c1, c2 }, r)
+    }
+
+    #[cfg(not(feature = "verify-only"))]
+    pub fn decrypt_batch(
+        group: &CLGroup,
+        secret_key: &Mpz,
+        ciphertexts: &[Ciphertext],
+    ) -> Result<Vec<Scalar>, MulEcdsaError> {
+        ciphertexts
+            .iter()
+            .map(|c| CLGroup::decrypt(group, secret_key, c))
+            .collect()
+    }
+
+    #[cfg(not(feature = "verify-only"))]
+    pub fn decrypt(group: &CLGroup, secret_key: &Mpz, c: &Ciphertext) -> Result<Scalar, MulEcdsaError> {
+        CLGroup::decrypt_plaintext(group, secret_key, c).map(Scalar::from)
     }
 
-    pub fn encrypt_without_r(group: &CLGroup, m: &Scalar) -> (Ciphertext, Mpz) {
+    #[cfg(not(feature = "verify-only"))]
+    pub fn decrypt_plaintext(group: &CLGroup, secret_key: &Mpz, c: &Ciphertext) -> Result<Plaintext, MulEcdsaError> {
+        group.check_elements(&[&c.c1, &c.c2])?;
+        let tmp = if group.options.blind_decryption {
+            // c * Enc(0; r) = (c1 * g^r, c2 * pk^r), with pk^r = (g^r)^sk
+            let (_, g_r) = group.keygen();
+            let mut pk_r = g_r.clone();
+            pk_r.pow(secret_key.clone());
+            let mut c1_x_inv = c.c1.clone() * &g_r;
+            c1_x_inv.pow_signed(-secret_key.clone());
+            c.c2.clone() * &pk_r * &c1_x_inv
+        } else {
+            let mut c1_x_inv = c.c1.clone();
+            c1_x_inv.pow_signed(-secret_key.clone());
+            c.c2.clone() * &c1_x_inv
+        };
+        Plaintext::new(discrete_log_f(&q(), group.gq.discriminant(), &tmp)?)
+    }
+
+    pub fn encrypt_without_r<M: Into<Plaintext>>(group: &CLGroup, m: M) -> (Ciphertext, Mpz) {
         let r = Mpz::from(0);
         let r_big = group.pk_for_sk(r.clone());
-        let m_bigint = scalar_to_bigint(m);
-        let m_mpz = Mpz::from_str(&m_bigint.to_str_radix(10)).unwrap();
-        let exp_f = expo_f(&q(), &group.gq.discriminant(), &m_mpz);
+        let exp_f = expo_f(&q(), group.gq.discriminant(), m.into().as_mpz());
 
         (
             Ciphertext {
@@ -103,10 +367,60 @@ impl CLGroup {
         )
     }
 
+    /// Rejects elements a peer sent unless each is a reduced form of this
+    /// group's discriminant, which the class group arithmetic assumes.
+    pub fn check_elements(&self, elements: &[&GmpClassGroup]) -> Result<(), MulEcdsaError> {
+        match elements
+            .iter()
+            .all(|element| element.discriminant() == self.gq.discriminant() && element.is_reduced_form())
+        {
+            true => Ok(()),
+            false => Err(MulEcdsaError::VrfyClassGroupFailed),
+        }
+    }
+
     pub fn pk_for_sk(&self, sk: Mpz) -> GmpClassGroup {
-        let mut group_element = self.gq.clone();
-        group_element.pow(sk);
-        group_element
+        self.pow(&self.gq, &sk)
+    }
+
+    /// `c^a * Enc(b; rho)`: an encryption of `a * m + b` under `public_key`
+    /// with randomness `a * r + rho`. The exponents are used as given.
+    pub fn eval_affine_with(
+        group: &CLGroup,
+        public_key: &GmpClassGroup,
+        c: &Ciphertext,
+        a: &Mpz,
+        b: &Mpz,
+        rho: &Mpz,
+    ) -> Ciphertext {
+        let mut c1_a = c.c1.clone();
+        c1_a.pow(a.clone());
+        let g_rho = group.pow(&group.gq, rho);
+
+        let mut c2_a = c.c2.clone();
+        c2_a.pow(a.clone());
+        let pk_rho = group.pow(public_key, rho);
+        let f_b = expo_f(&q(), group.gq.discriminant(), b);
+        Ciphertext {
+            c1: c1_a * g_rho,
+            c2: c2_a * (pk_rho * f_b),
+        }
+    }
+
+    /// Enc(a * m + b) from `c` = Enc(m), re-randomized with a fresh `rho`,
+    /// which is returned alongside.
+    #[cfg(not(feature = "verify-only"))]
+    pub fn eval_affine(
+        group: &CLGroup,
+        c: &Ciphertext,
+        a: &Scalar,
+        b: &Scalar,
+        public_key: &GmpClassGroup,
+    ) -> (Ciphertext, Mpz) {
+        let upper = group.params.secret_key_bound(&mpz_to_bigint(group.stilde.clone()));
+        let rho = bigint_to_mpz(sample_below(&upper));
+        let cipher = CLGroup::eval_affine_with(group, public_key, c, &scalar_to_mpz(a), &scalar_to_mpz(b), &rho);
+        (cipher, rho)
     }
 
     pub fn eval_scal(c: &Ciphertext, val: Mpz) -> Ciphertext {
@@ -127,10 +441,55 @@ impl CLGroup {
     }
 }
 
+/// Size bounds on `|delta_k|`: the recommended size for 112-bit security
+/// below, and a cap on the cost of exponentiations a peer can impose above.
+pub const MIN_DELTA_K_BITS: usize = 1348;
+pub const MAX_DELTA_K_BITS: usize = 8192;
+/// Trial division bound for the cofactor `p` of `delta_k = -q * p`.
+const SMALL_FACTOR_BOUND: u32 = 1 << 12;
+
+/// Cheap structural checks on a group description received from a peer,
+/// before anything is encrypted or proven in it. `delta_k` must be
+/// `-q * p` with `p` a probable prime, `p = 3 mod 4` and free of small
+/// factors, which makes it a fundamental discriminant and rules out
+/// class groups built with a known composite structure; `gq` must be a
+/// reduced form of `delta_k * q^2`.
+pub fn validate_peer_group(group: &CLGroup) -> Result<(), MulEcdsaError> {
+    let q = q();
+    let delta_k = &group.delta_k;
+    let bits = delta_k.bit_length();
+    if *delta_k >= Mpz::zero()
+        || delta_k.frem_u32(4) != 1
+        || !(MIN_DELTA_K_BITS..=MAX_DELTA_K_BITS).contains(&bits)
+        || delta_k.mod_floor(&q) != Mpz::zero()
+    {
+        return Err(MulEcdsaError::VrfyClassGroupFailed);
+    }
+    let p = -delta_k / &q;
+    if p.frem_u32(4) != 3
+        || p == q
+        || (3..SMALL_FACTOR_BOUND)
+            .step_by(2)
+            .any(|d| p.frem_u32(d) == 0)
+        || p.probab_prime(25) == ProbabPrimeResult::NotPrime
+    {
+        return Err(MulEcdsaError::VrfyClassGroupFailed);
+    }
+    let gq = &group.gq;
+    if *gq.discriminant() != delta_k * &q * &q
+        || !gq.is_reduced_form()
+        || *gq == gq.identity()
+        || group.stilde <= Mpz::zero()
+    {
+        return Err(MulEcdsaError::VrfyClassGroupFailed);
+    }
+    Ok(())
+}
+
 pub fn q() -> Mpz {
     // secp256k1 order
-    let q = Mpz::from_str("115792089237316195423570985008687907852837564279074904382605163141518161494337").unwrap();
-    q
+    static Q: OnceLock<Mpz> = OnceLock::new();
+    Q.get_or_init(|| bigint_to_mpz(curve_order().clone())).clone()
 }
 
 pub fn scalar_to_bigint(s: &Scalar) -> BigInt {
@@ -138,28 +497,31 @@ pub fn scalar_to_bigint(s: &Scalar) -> BigInt {
     BigInt::from_bytes_be(Sign::Plus, &bytes)
 }
 
-pub fn scalar_from_bigint(b: &BigInt) -> Scalar {
-    let (sign, mut bytes) = b.to_bytes_be();
-    if sign == Sign::Minus {
-        // For negative numbers, we need to compute modulo the curve order
-        // For now, just wrap around
-        let q = BigInt::from_str("115792089237316195423570985008687907852837564279074904382605163141518161494337").unwrap();
-        let positive = (b % &q + &q) % &q;
-        let (_, pos_bytes) = positive.to_bytes_be();
-        bytes = pos_bytes;
+/// `b` mod the curve order, for non-negative `b` such as a digest or a
+/// coordinate. Inputs of any length are reduced rather than rejected;
+/// negative ones are refused.
+pub fn scalar_from_bigint(b: &BigInt) -> Result<Scalar, MulEcdsaError> {
+    match b.sign() {
+        Sign::Minus => Err(MulEcdsaError::NegativeScalar),
+        _ => Ok(scalar_mod_order(b)),
     }
-    
-    let mut arr = [0u8; 32];
-    let len = std::cmp::min(bytes.len(), 32);
-    if len > 0 {
-        arr[32-len..].copy_from_slice(&bytes[bytes.len()-len..]);
-    }
-    Scalar::from_repr(arr.into()).unwrap_or(Scalar::ZERO)
 }
 
+/// Uniform in `[0, upper)`. Every attempt draws the bound's full byte
+/// length, so the randomness consumed does not depend on the result.
+#[cfg(not(feature = "verify-only"))]
 pub fn sample_below(upper: &BigInt) -> BigInt {
-    let mut rng = OsRng;
-    rng.gen_bigint_range(&BigInt::from(0), upper)
+    assert!(upper.sign() == Sign::Plus);
+    let bits = upper.bits() as usize;
+    let mut bytes = vec![0u8; bits.div_ceil(8)];
+    loop {
+        ProtocolRng.fill_bytes(&mut bytes);
+        bytes[0] &= 0xff >> (bytes.len() * 8 - bits);
+        let candidate = BigInt::from_bytes_be(Sign::Plus, &bytes);
+        if candidate < *upper {
+            return candidate;
+        }
+    }
 }
 
 pub fn mod_add(a: &BigInt, b: &BigInt, modulus: &BigInt) -> BigInt {
@@ -178,8 +540,13 @@ pub fn from_discriminant(delta: &Mpz) -> GmpClassGroup {
     ClassGroup::from_ab_discriminant(a, b, (*delta).clone())
 }
 
+/// Computes f^k in the subgroup of order p without any group operations:
+/// f^k is the reduced form (p^2, L(k) p) where L(k) is an odd representative
+/// of k^{-1} mod p. `k` is taken mod p, so out-of-range or negative inputs
+/// are handled rather than panicking.
 pub fn expo_f(p: &Mpz, delta: &Mpz, k: &Mpz) -> GmpClassGroup {
-    if k == &Mpz::from(0) {
+    let k = k.mod_floor(p);
+    if k == Mpz::from(0) {
         let group = from_discriminant(delta);
         return group;
     }
@@ -192,59 +559,270 @@ pub fn expo_f(p: &Mpz, delta: &Mpz, k: &Mpz) -> GmpClassGroup {
     qf
 }
 
-pub fn discrete_log_f(p: &Mpz, delta: &Mpz, c: &GmpClassGroup) -> Mpz {
+/// Solves the discrete log of `c` to the base f, in closed form: a reduced
+/// element of F other than the identity is (p^2, L p), and the exponent is
+/// L^{-1} mod p. Elements outside F (a malformed ciphertext or a wrong key)
+/// are rejected instead of yielding an arbitrary plaintext.
+pub fn discrete_log_f(p: &Mpz, delta: &Mpz, c: &GmpClassGroup) -> Result<Mpz, MulEcdsaError> {
     let principal_qf = from_discriminant(delta);
     if c == &principal_qf {
-        return Mpz::from(0);
-    } else {
-        let lk = c.b.div_floor(p);
-        let lk_inv = lk.invert(p).unwrap();
-        return lk_inv;
+        return Ok(Mpz::from(0));
+    }
+    if c.a != p * p || !c.b.mod_floor(p).is_zero() {
+        return Err(MulEcdsaError::CLDecryptFailed);
     }
+    let lk = c.b.div_floor(p);
+    lk.invert(p).ok_or(MulEcdsaError::CLDecryptFailed)
 }
 
 pub fn mpz_to_bigint(value: Mpz) -> BigInt {
-    let hex_str = value.to_str_radix(16);
-    let is_negative = hex_str.starts_with('-');
-    let hex_part = hex_str.trim_start_matches('-');
-    
-    // Pad with leading zero if odd length
-    let padded_hex = if hex_part.len() % 2 == 1 {
-        format!("0{}", hex_part)
-    } else {
-        hex_part.to_string()
+    let sign = match value.sign() {
+        classgroup::gmp::sign::Sign::Negative => Sign::Minus,
+        _ => Sign::Plus,
     };
-    
-    let bytes = hex::decode(&padded_hex).unwrap();
-    let sign = if is_negative { Sign::Minus } else { Sign::Plus };
-    BigInt::from_bytes_be(sign, &bytes)
+    BigInt::from_slice(sign, &value.to_u32_digits())
 }
 
 pub fn bigint_to_mpz(value: BigInt) -> Mpz {
-    let (sign, bytes) = value.to_bytes_be();
-    let hex_str = if sign == Sign::Minus {
-        format!("-{}", hex::encode(&bytes))
-    } else {
-        hex::encode(&bytes)
-    };
-    Mpz::from_str_radix(&hex_str, 16).unwrap()
+    let (sign, digits) = value.to_u32_digits();
+    let magnitude = Mpz::from_u32_digits(&digits);
+    match sign {
+        Sign::Minus => -magnitude,
+        _ => magnitude,
+    }
+}
+
+pub fn scalar_to_mpz(f: &Scalar) -> Mpz {
+    Mpz::from(&f.to_bytes()[..])
 }
 
-pub fn into_mpz(f: &Scalar) -> Mpz {
-    let bigint = scalar_to_bigint(f);
-    Mpz::from_str(&bigint.to_str_radix(10)).unwrap()
+/// Reduces `value` mod the curve order.
+pub fn mpz_to_scalar(value: &Mpz) -> Scalar {
+    let bytes = Vec::<u8>::from(&value.mod_floor(&q()));
+    let mut repr = [0u8; 32];
+    repr[32 - bytes.len()..].copy_from_slice(&bytes);
+    Scalar::from_repr(repr.into()).unwrap()
 }
 
+#[cfg(not(feature = "verify-only"))]
 #[test]
 pub fn test_encrypt_decrypt() {
     let group = CLGroup::new();
-    let m = Scalar::random(&mut OsRng);
+    let m = Scalar::random(&mut ProtocolRng);
     let (sk, pk) = group.keygen();
-    let c = CLGroup::encrypt(&group, &pk, &m);
-    let m_new = CLGroup::decrypt(&group, &sk, &c.0);
+    let c = CLGroup::encrypt(&group, &pk, m);
+    let m_new = CLGroup::decrypt(&group, &sk, &c.0).unwrap();
     assert_eq!(scalar_to_bigint(&m), scalar_to_bigint(&m_new));
+
+    // decrypting under the wrong key lands outside F
+    let (wrong_sk, _) = group.keygen();
+    assert_eq!(
+        CLGroup::decrypt(&group, &wrong_sk, &c.0),
+        Err(MulEcdsaError::CLDecryptFailed)
+    );
+
+    let blinded = group.with_options(ClOptions {
+        blind_decryption: true,
+    });
+    let m_blinded = CLGroup::decrypt(&blinded, &sk, &c.0).unwrap();
+    assert_eq!(m, m_blinded);
+    assert_eq!(
+        CLGroup::decrypt(&blinded, &wrong_sk, &c.0),
+        Err(MulEcdsaError::CLDecryptFailed)
+    );
 }
 
+#[cfg(not(feature = "verify-only"))]
+#[test]
+fn test_expo_f() {
+    let group = CLGroup::new();
+    let delta = group.gq.discriminant().clone();
+    let m = scalar_to_mpz(&Scalar::random(&mut ProtocolRng));
+
+    // the closed form is a homomorphism: g^r f^a * f^b == g^r f^(a + b)
+    let a = scalar_to_mpz(&Scalar::random(&mut ProtocolRng));
+    let gr = group.pk_for_sk(Mpz::from(12345));
+    let lhs = gr.clone() * expo_f(&q(), &delta, &a) * expo_f(&q(), &delta, &m);
+    assert_eq!(lhs, gr * expo_f(&q(), &delta, &(a + &m)));
+
+    // inputs are taken mod q
+    assert_eq!(expo_f(&q(), &delta, &q()), from_discriminant(&delta));
+    assert_eq!(expo_f(&q(), &delta, &(m.clone() + &q())), expo_f(&q(), &delta, &m));
+    assert_eq!(expo_f(&q(), &delta, &-m.clone()), {
+        let mut inv = expo_f(&q(), &delta, &m);
+        inv.inverse();
+        inv.reduce();
+        inv
+    });
+}
+
+#[cfg(not(feature = "verify-only"))]
+#[test]
+fn plaintext_test() {
+    let q = q();
+    assert_eq!(Plaintext::new(q.clone()), Err(MulEcdsaError::PlaintextOutOfRange));
+    assert_eq!(Plaintext::new(Mpz::from(-1)), Err(MulEcdsaError::PlaintextOutOfRange));
+    assert_eq!(Plaintext::reduce(&(q.clone() + Mpz::from(5))), Plaintext::new(Mpz::from(5)).unwrap());
+    assert_eq!(Plaintext::reduce(&Mpz::from(-1)), Plaintext::from(-Scalar::ONE));
+
+    let group = CLGroup::new();
+    let (sk, pk) = group.keygen();
+    let (c, _) = CLGroup::encrypt(&group, &pk, Plaintext::reduce(&(q + Mpz::from(5))));
+    assert_eq!(CLGroup::decrypt_plaintext(&group, &sk, &c).unwrap().as_mpz(), &Mpz::from(5));
+    assert_eq!(CLGroup::decrypt(&group, &sk, &c).unwrap(), Scalar::from(5u64));
+}
+
+#[cfg(not(feature = "verify-only"))]
+#[test]
+fn encrypt_multi_test() {
+    let group = CLGroup::new();
+    let m = Scalar::random(&mut ProtocolRng);
+    let keys: Vec<_> = (0..3).map(|_| group.keygen()).collect();
+    let public_keys: Vec<_> = keys.iter().map(|(_, pk)| pk.clone()).collect();
+    let (multi, _) = CLGroup::encrypt_multi(&group, &public_keys, m);
+    assert_eq!(multi.c2.len(), 3);
+    for (i, (sk, _)) in keys.iter().enumerate() {
+        let c = multi.ciphertext(i).unwrap();
+        assert_eq!(CLGroup::decrypt(&group, sk, &c).unwrap(), m);
+    }
+    assert!(multi.ciphertext(3).is_none());
+
+    let (sk, pk) = &keys[0];
+    let m2 = Scalar::random(&mut ProtocolRng);
+    let batch = [
+        multi.ciphertext(0).unwrap(),
+        CLGroup::encrypt(&group, pk, m2).0,
+    ];
+    assert_eq!(CLGroup::decrypt_batch(&group, sk, &batch).unwrap(), vec![m, m2]);
+}
+
+#[cfg(not(feature = "verify-only"))]
+#[test]
+fn eval_affine_test() {
+    let group = CLGroup::new();
+    let (sk, pk) = group.keygen();
+    let (m, a, b) = (
+        Scalar::random(&mut ProtocolRng),
+        Scalar::random(&mut ProtocolRng),
+        Scalar::random(&mut ProtocolRng),
+    );
+    let (c, r) = CLGroup::encrypt(&group, &pk, m);
+    let (c_affine, rho) = CLGroup::eval_affine(&group, &c, &a, &b, &pk);
+    assert_eq!(CLGroup::decrypt(&group, &sk, &c_affine).unwrap(), a * m + b);
+
+    // the randomness of the result is a * r + rho
+    let mut c1 = group.gq.clone();
+    c1.pow(scalar_to_mpz(&a) * &r + &rho);
+    assert_eq!(c_affine.c1, c1);
+}
+
+#[test]
+fn pow_signed_test() {
+    let group = CLGroup::new();
+    let e = Mpz::from(123456789u64);
+    let mut negative = group.gq.clone();
+    negative.pow_signed(-e.clone());
+    let mut positive = group.gq.clone();
+    positive.pow_signed(e);
+    let mut product = negative * &positive;
+    product.reduce();
+    assert_eq!(product, group.gq.identity());
+
+    let mut zero = group.gq.clone();
+    zero.pow_signed(Mpz::from(0));
+    assert_eq!(zero, group.gq.identity());
+}
+
+#[cfg(not(feature = "verify-only"))]
+#[test]
+fn prover_context_test() {
+    let context = ProverContext::new();
+    let group = CLGroup::new().with_prover_context(context.clone());
+    let plain = CLGroup::new();
+    let (sk, pk) = group.keygen();
+    assert_eq!(pk, plain.pk_for_sk(sk.clone()));
+    let m = Scalar::random(&mut ProtocolRng);
+    let (c, r) = CLGroup::encrypt(&group, &pk, m);
+    assert_eq!(c.c2, plain.pow(&pk, &r) * expo_f(&q(), group.gq.discriminant(), &scalar_to_mpz(&m)));
+    assert_eq!(CLGroup::decrypt(&plain, &sk, &c).unwrap(), m);
+    assert_eq!(context.cached_bases(), 2);
+
+    // exponents the table cannot serve fall back to plain exponentiation
+    let table = PowTable::new(&group.gq, 8);
+    assert_eq!(table.pow(&Mpz::from(255)), Some(plain.pow(&group.gq, &Mpz::from(255))));
+    assert_eq!(table.pow(&Mpz::from(256)), None);
+    assert_eq!(table.pow(&Mpz::from(-1)), None);
+    assert_eq!(context.pow(&group.gq, &Mpz::from(-3), 8), plain.pow(&group.gq, &Mpz::from(-3)));
+}
+
+#[test]
+fn check_class_number_test() {
+    let group = CLGroup::new();
+    let bits = group.check_class_number().unwrap();
+    // h(delta_k) is about sqrt(|delta_k|), and stilde bounds it
+    assert!(bits.abs_diff(group.delta_k.bit_length() / 2) <= 2);
+    assert!(bits <= group.stilde.bit_length() + 1);
+
+    let small = CLGroup {
+        delta_k: Mpz::from(-10000007),
+        ..CLGroup::new()
+    };
+    assert_eq!(small.check_class_number(), Err(MulEcdsaError::ClassGroupTooSmall));
+}
+
+#[test]
+fn validate_peer_group_test() {
+    let group = CLGroup::new();
+    validate_peer_group(&group).unwrap();
+
+    let q = q();
+    let rejected = |group: CLGroup| {
+        assert_eq!(validate_peer_group(&group), Err(MulEcdsaError::VrfyClassGroupFailed));
+    };
+    // p = 3 mod 4 but with a small factor: -q * 3 * p'
+    let p = -&group.delta_k / &q;
+    rejected(CLGroup {
+        delta_k: -(&q * &(p.clone() * &Mpz::from(9u64))),
+        ..group.clone()
+    });
+    // p = 1 mod 4
+    rejected(CLGroup {
+        delta_k: -(&q * &(p.clone() + 2u64)),
+        ..group.clone()
+    });
+    // too short
+    rejected(CLGroup {
+        delta_k: Mpz::from(-7) * &q,
+        ..group.clone()
+    });
+    // gq of another discriminant
+    rejected(CLGroup {
+        gq: from_discriminant(&group.delta_k),
+        ..group.clone()
+    });
+    let mut identity = group.gq.identity();
+    identity.reduce();
+    rejected(CLGroup {
+        gq: identity,
+        ..group
+    });
+}
+
+#[test]
+fn pow_mod_order_hint_test() {
+    let group = CLGroup::new();
+    // only the reduction is checked here; 1000 is not a multiple of the
+    // order of gq, which is unknown
+    let modulus = Mpz::from(1000);
+    let e = Mpz::from(3007);
+    for (exponent, reduced) in [(e.clone(), 7), (-e, 993)] {
+        let mut expected = group.gq.clone();
+        expected.pow(Mpz::from(reduced));
+        let mut hinted = group.gq.clone();
+        hinted.pow_mod_order_hint(exponent, Some(&modulus));
+        assert_eq!(hinted, expected);
+    }
+}
 
 #[test]
 pub fn pow_a() {
@@ -265,3 +843,46 @@ fn test_big_to_mpz() {
     let duration = start.elapsed();
     println!("duration = {:?}", duration);
 }
+
+#[test]
+fn mpz_bigint_conversion_test() {
+    let big = BigInt::from(2i32).pow(1000) + BigInt::from(12345);
+    for value in [BigInt::from(0), BigInt::from(1), -BigInt::from(1), big.clone(), -big] {
+        let mpz = bigint_to_mpz(value.clone());
+        assert_eq!(mpz.to_str_radix(16), value.to_str_radix(16));
+        assert_eq!(mpz_to_bigint(mpz), value);
+    }
+}
+
+#[cfg(not(feature = "verify-only"))]
+#[test]
+fn scalar_mpz_conversion_test() {
+    let s = Scalar::random(&mut ProtocolRng);
+    let m = scalar_to_mpz(&s);
+    assert_eq!(mpz_to_bigint(m.clone()), scalar_to_bigint(&s));
+    assert_eq!(mpz_to_scalar(&m), s);
+    assert_eq!(mpz_to_scalar(&(m.clone() + &q())), s);
+    assert_eq!(mpz_to_scalar(&-m), -s);
+    assert_eq!(mpz_to_scalar(&Mpz::from(0)), Scalar::ZERO);
+
+    // digests at or above the order wrap around instead of becoming zero
+    let above = curve_order() + BigInt::from(5);
+    assert_eq!(scalar_from_bigint(&above), Ok(Scalar::from(5u64)));
+    assert_eq!(scalar_from_bigint(&BigInt::from(0)), Ok(Scalar::ZERO));
+    assert_eq!(
+        scalar_from_bigint(&BigInt::from(-5)),
+        Err(MulEcdsaError::NegativeScalar)
+    );
+}
+
+#[cfg(not(feature = "verify-only"))]
+#[test]
+fn ciphertext_format_test() {
+    let group = CLGroup::new();
+    let (_, pk) = group.keygen();
+    let (c, _) = CLGroup::encrypt(&group, &pk, Scalar::ONE);
+    let short = c.to_string();
+    assert!(short.starts_with("Ciphertext(c1=(a≈") && short.len() < 160, "{}", short);
+    assert!(format!("{:?}", c).len() < 200);
+    assert!(format!("{:#?}", c).contains(&c.c2.a.to_string()));
+}
@@ -1,9 +1,11 @@
+use crate::utilities::bigint_serde;
 use crate::utilities::error::MulEcdsaError;
-use crate::utilities::SECURITY_BITS;
+use crate::utilities::ProtocolParams;
 use crate::utilities::k256_helpers::*;
 use k256::{ProjectivePoint, Scalar};
 use num_bigint::BigInt;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
 #[derive(Clone, Debug)]
 pub struct DlogCommitment {
@@ -21,11 +23,38 @@ pub struct DlogCommitmentOpen {
 pub struct DLComZK {
     pub commitments: DLCommitments,
     pub witness: CommWitness,
+    /// See `session_context`.
+    pub context: [u8; 32],
+}
+
+/// Binds `DLComZK` commitments to one signing session, so a commitment
+/// cannot be replayed into a session with another id or message.
+pub fn session_context(session_id: &[u8], message_hash: &[u8]) -> [u8; 32] {
+    hash_session_parts(&[session_id, message_hash])
+}
+
+/// `session_context` that also binds a caller's label, such as the
+/// business operation a signature belongs to. Both parties must use the
+/// same label or party one fails to open party two's nonce commitment.
+pub fn labeled_session_context(session_id: &[u8], message_hash: &[u8], label: &str) -> [u8; 32] {
+    hash_session_parts(&[session_id, message_hash, label.as_bytes()])
+}
+
+fn hash_session_parts(parts: &[&[u8]]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(b"DLComZKSession");
+    for part in parts {
+        hasher.update((part.len() as u64).to_be_bytes());
+        hasher.update(part);
+    }
+    hasher.finalize().into()
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct DLCommitments {
+    #[serde(with = "crate::utilities::bigint_serde")]
     pub pk_commitment: BigInt,
+    #[serde(with = "crate::utilities::bigint_serde")]
     pub zk_pok_commitment: BigInt,
 }
 
@@ -45,8 +74,8 @@ impl serde::Serialize for CommWitness {
         use serde::ser::SerializeStruct;
         use k256::elliptic_curve::sec1::ToEncodedPoint;
         let mut state = serializer.serialize_struct("CommWitness", 5)?;
-        state.serialize_field("pk_commitment_blind_factor", &self.pk_commitment_blind_factor)?;
-        state.serialize_field("zk_pok_blind_factor", &self.zk_pok_blind_factor)?;
+        state.serialize_field("pk_commitment_blind_factor", &bigint_serde::Ser(&self.pk_commitment_blind_factor))?;
+        state.serialize_field("zk_pok_blind_factor", &bigint_serde::Ser(&self.zk_pok_blind_factor))?;
         // Serialize public_share as compressed bytes
         let public_share_bytes = self.public_share.to_affine().to_encoded_point(true);
         state.serialize_field("public_share", public_share_bytes.as_bytes())?;
@@ -69,7 +98,9 @@ impl<'de> serde::Deserialize<'de> for CommWitness {
         
         #[derive(serde::Deserialize)]
         struct Helper {
+            #[serde(with = "crate::utilities::bigint_serde")]
             pk_commitment_blind_factor: BigInt,
+            #[serde(with = "crate::utilities::bigint_serde")]
             zk_pok_blind_factor: BigInt,
             public_share: Vec<u8>,
             pk_t_rand_commitment: Vec<u8>,
@@ -112,8 +143,14 @@ impl<'de> serde::Deserialize<'de> for CommWitness {
 
 
 impl DlogCommitment {
+    #[cfg(not(feature = "verify-only"))]
     pub fn new(public_share: &ProjectivePoint) -> Self {
-        let blind_factor = sample_bigint(SECURITY_BITS);
+        Self::new_with_params(public_share, &ProtocolParams::default())
+    }
+
+    #[cfg(not(feature = "verify-only"))]
+    pub fn new_with_params(public_share: &ProjectivePoint, params: &ProtocolParams) -> Self {
+        let blind_factor = sample_bigint(params.blind_bits());
         let commitment = create_hash_commitment(
             &public_share.bytes_compressed_to_big_int(),
             &blind_factor,
@@ -129,11 +166,13 @@ impl DlogCommitment {
     }
 
     pub fn verify(&self) -> Result<(), MulEcdsaError> {
-        if create_hash_commitment(
-            &self.open.public_share.bytes_compressed_to_big_int(),
-            &self.open.blind_factor,
-        ) != self.commitment
-        {
+        if !hash_commitment_eq(
+            &create_hash_commitment(
+                &self.open.public_share.bytes_compressed_to_big_int(),
+                &self.open.blind_factor,
+            ),
+            &self.commitment,
+        ) {
             return Err(MulEcdsaError::OpenDLCommFailed);
         }
 
@@ -144,11 +183,13 @@ impl DlogCommitment {
         commitment: &BigInt,
         open: &DlogCommitmentOpen,
     ) -> Result<(), MulEcdsaError> {
-        if create_hash_commitment(
-            &open.public_share.bytes_compressed_to_big_int(),
-            &open.blind_factor,
-        ) != *commitment
-        {
+        if !hash_commitment_eq(
+            &create_hash_commitment(
+                &open.public_share.bytes_compressed_to_big_int(),
+                &open.blind_factor,
+            ),
+            commitment,
+        ) {
             return Err(MulEcdsaError::OpenDLCommFailed);
         }
 
@@ -161,17 +202,30 @@ impl DlogCommitment {
 }
 
 impl DLComZK {
-    pub fn new(secret_share: &Scalar, public_share: &ProjectivePoint) -> Self {
+    #[cfg(not(feature = "verify-only"))]
+    pub fn new(secret_share: &Scalar, public_share: &ProjectivePoint, context: &[u8; 32]) -> Self {
+        Self::new_with_params(secret_share, public_share, context, &ProtocolParams::default())
+    }
+
+    #[cfg(not(feature = "verify-only"))]
+    pub fn new_with_params(
+        secret_share: &Scalar,
+        public_share: &ProjectivePoint,
+        context: &[u8; 32],
+        params: &ProtocolParams,
+    ) -> Self {
         let d_log_proof = DLogProof::<ProjectivePoint>::prove(secret_share);
         // we use hash based commitment
-        let pk_commitment_blind_factor = sample_bigint(SECURITY_BITS);
-        let pk_commitment = create_hash_commitment(
+        let pk_commitment_blind_factor = sample_bigint(params.blind_bits());
+        let pk_commitment = create_bound_hash_commitment(
+            context,
             &public_share.bytes_compressed_to_big_int(),
             &pk_commitment_blind_factor,
         );
 
-        let zk_pok_blind_factor = sample_bigint(SECURITY_BITS);
-        let zk_pok_commitment = create_hash_commitment(
+        let zk_pok_blind_factor = sample_bigint(params.blind_bits());
+        let zk_pok_commitment = create_bound_hash_commitment(
+            context,
             &d_log_proof
                 .pk_t_rand_commitment
                 .bytes_compressed_to_big_int(),
@@ -193,29 +247,36 @@ impl DLComZK {
         Self {
             commitments,
             witness,
+            context: *context,
         }
     }
 
     pub fn verify_commitments_and_dlog_proof(&self) -> Result<(), MulEcdsaError> {
         // Verify the commitment of DL
-        if create_hash_commitment(
-            &self.witness.public_share.bytes_compressed_to_big_int(),
-            &self.witness.pk_commitment_blind_factor,
-        ) != self.commitments.pk_commitment
-        {
+        if !hash_commitment_eq(
+            &create_bound_hash_commitment(
+                &self.context,
+                &self.witness.public_share.bytes_compressed_to_big_int(),
+                &self.witness.pk_commitment_blind_factor,
+            ),
+            &self.commitments.pk_commitment,
+        ) {
             return Err(MulEcdsaError::OpenDLCommFailed);
         }
 
         // Verify the commitment of proof
-        if create_hash_commitment(
-            &self
-                .witness
-                .d_log_proof
-                .pk_t_rand_commitment
-                .bytes_compressed_to_big_int(),
-            &self.witness.zk_pok_blind_factor,
-        ) != self.commitments.zk_pok_commitment
-        {
+        if !hash_commitment_eq(
+            &create_bound_hash_commitment(
+                &self.context,
+                &self
+                    .witness
+                    .d_log_proof
+                    .pk_t_rand_commitment
+                    .bytes_compressed_to_big_int(),
+                &self.witness.zk_pok_blind_factor,
+            ),
+            &self.commitments.zk_pok_commitment,
+        ) {
             return Err(MulEcdsaError::OpenCommZKFailed);
         }
 
@@ -224,25 +285,50 @@ impl DLComZK {
         Ok(())
     }
 
-    pub fn verify(commitment: &DLCommitments, witness: &CommWitness) -> Result<(), MulEcdsaError> {
-        // Verify the commitment of DL
-        if create_hash_commitment(
-            &witness.public_share.bytes_compressed_to_big_int(),
-            &witness.pk_commitment_blind_factor,
-        ) != commitment.pk_commitment
+    pub fn verify(
+        commitment: &DLCommitments,
+        witness: &CommWitness,
+        context: &[u8; 32],
+    ) -> Result<(), MulEcdsaError> {
+        Self::verify_with_params(commitment, witness, context, &ProtocolParams::default())
+    }
+
+    /// Rejects blinds outside `params.blind_in_range` before opening.
+    pub fn verify_with_params(
+        commitment: &DLCommitments,
+        witness: &CommWitness,
+        context: &[u8; 32],
+        params: &ProtocolParams,
+    ) -> Result<(), MulEcdsaError> {
+        if !params.blind_in_range(&witness.pk_commitment_blind_factor)
+            || !params.blind_in_range(&witness.zk_pok_blind_factor)
         {
+            return Err(MulEcdsaError::InvalidBlindFactor);
+        }
+        // Verify the commitment of DL
+        if !hash_commitment_eq(
+            &create_bound_hash_commitment(
+                context,
+                &witness.public_share.bytes_compressed_to_big_int(),
+                &witness.pk_commitment_blind_factor,
+            ),
+            &commitment.pk_commitment,
+        ) {
             return Err(MulEcdsaError::OpenDLCommFailed);
         }
 
         // Verify the commitment of proof
-        if create_hash_commitment(
-            &witness
-                .d_log_proof
-                .pk_t_rand_commitment
-                .bytes_compressed_to_big_int(),
-            &witness.zk_pok_blind_factor,
-        ) != commitment.zk_pok_commitment
-        {
+        if !hash_commitment_eq(
+            &create_bound_hash_commitment(
+                context,
+                &witness
+                    .d_log_proof
+                    .pk_t_rand_commitment
+                    .bytes_compressed_to_big_int(),
+                &witness.zk_pok_blind_factor,
+            ),
+            &commitment.zk_pok_commitment,
+        ) {
             return Err(MulEcdsaError::OpenCommZKFailed);
         }
 
@@ -271,15 +357,52 @@ impl Default for DLCommitments {
     }
 }
 
+#[cfg(not(feature = "verify-only"))]
 #[test]
 fn dl_com_zk_test() {
     use k256::elliptic_curve::Field;
-    use rand::rngs::OsRng;
+    use crate::utilities::rng::ProtocolRng;
     
-    let secret_share = Scalar::random(&mut OsRng);
+    let secret_share = Scalar::random(&mut ProtocolRng);
     let public_share = ProjectivePoint::GENERATOR * secret_share;
 
-    let dl_com_zk = DLComZK::new(&secret_share, &public_share);
+    let context = session_context(b"session", &[1u8; 32]);
+    let dl_com_zk = DLComZK::new(&secret_share, &public_share, &context);
 
     dl_com_zk.verify_commitments_and_dlog_proof().unwrap();
+    DLComZK::verify(&dl_com_zk.commitments, &dl_com_zk.witness, &context).unwrap();
+    for other in [
+        session_context(b"other session", &[1u8; 32]),
+        session_context(b"session", &[2u8; 32]),
+    ] {
+        assert_eq!(
+            DLComZK::verify(&dl_com_zk.commitments, &dl_com_zk.witness, &other),
+            Err(MulEcdsaError::OpenDLCommFailed)
+        );
+    }
+
+    // a well-formed opening with a degenerate blind
+    let mut witness = dl_com_zk.witness.clone();
+    let mut commitments = dl_com_zk.commitments.clone();
+    for blind in [BigInt::ZERO, BigInt::from(1), BigInt::from(1) << 300u32] {
+        witness.pk_commitment_blind_factor = blind.clone();
+        commitments.pk_commitment = create_bound_hash_commitment(
+            &context,
+            &public_share.bytes_compressed_to_big_int(),
+            &blind,
+        );
+        assert_eq!(
+            DLComZK::verify(&commitments, &witness, &context),
+            Err(MulEcdsaError::InvalidBlindFactor)
+        );
+    }
+
+    let params = ProtocolParams::with_security_level(192).unwrap();
+    let dl_com_zk = DLComZK::new_with_params(&secret_share, &public_share, &context, &params);
+    DLComZK::verify_with_params(&dl_com_zk.commitments, &dl_com_zk.witness, &context, &params)
+        .unwrap();
+    assert_eq!(
+        DLComZK::verify(&dl_com_zk.commitments, &dl_com_zk.witness, &context),
+        Err(MulEcdsaError::InvalidBlindFactor)
+    );
 }
@@ -0,0 +1,246 @@
+use crate::utilities::cl_proof::{CLState, ClEncProtocol};
+#[cfg(not(feature = "verify-only"))]
+use crate::utilities::cl_proof::CLWit;
+use crate::utilities::class_group::*;
+use crate::utilities::error::MulEcdsaError;
+use crate::utilities::fixed_mpz::{self, Fixed, EXPONENT_RESPONSE_BYTES, SCALAR_BYTES};
+use crate::utilities::k256_helpers::scalar_mod_order;
+use crate::utilities::sigma::{self, SigmaProtocol, Transcript};
+#[cfg(not(feature = "verify-only"))]
+use crate::utilities::sigma::SigmaProver;
+use crate::utilities::typed_int::*;
+use classgroup::gmp::mpz::Mpz;
+use classgroup::gmp_classgroup::*;
+use k256::{ProjectivePoint, Scalar};
+use num_bigint::BigInt;
+use serde::Deserialize;
+
+/// Statement: `cipher` encrypts x under `cl_pub_key` and `point = x * base`.
+#[derive(Clone, Debug)]
+pub struct EqualityState {
+    pub cipher: Ciphertext,
+    pub cl_pub_key: GmpClassGroup,
+    pub base: ProjectivePoint,
+    pub point: ProjectivePoint,
+}
+
+#[derive(Clone, Debug)]
+pub struct EqualityWit {
+    pub x: Scalar,
+    pub r: Mpz,
+}
+
+#[derive(Clone, Debug)]
+pub struct EqualityProof {
+    pub t1: GmpClassGroup,
+    pub t2: GmpClassGroup,
+    pub t3: ProjectivePoint,
+    pub u1: Mpz,
+    pub u2: Mpz,
+}
+
+impl EqualityState {
+    fn cl_state(&self) -> CLState {
+        CLState {
+            cipher: self.cipher.clone(),
+            cl_pub_key: self.cl_pub_key.clone(),
+        }
+    }
+}
+
+/// `ClEncProtocol` extended with x * base, sharing the response for x.
+pub struct EqualityProtocol<'a> {
+    pub enc: ClEncProtocol<'a>,
+}
+
+impl SigmaProtocol for EqualityProtocol<'_> {
+    type Statement = EqualityState;
+    type Commitment = ((GmpClassGroup, GmpClassGroup), ProjectivePoint);
+    type Response = (Mpz, Mpz);
+
+    fn challenge_bytes(&self) -> usize {
+        self.enc.challenge_bytes()
+    }
+
+    fn check(
+        &self,
+        statement: &EqualityState,
+        commitment: &Self::Commitment,
+        k: &BigInt,
+        response: &Self::Response,
+    ) -> bool {
+        let u2 = match ZqScalar::new(response.1.clone()) {
+            Some(u2) => u2.to_scalar(),
+            None => return false,
+        };
+        // t3 + k * point == u2 * base
+        if commitment.1 + statement.point * scalar_mod_order(k) != statement.base * u2 {
+            return false;
+        }
+        self.enc
+            .check(&statement.cl_state(), &commitment.0, k, response)
+    }
+
+    fn absorb_statement(&self, statement: &EqualityState, transcript: &mut Transcript) {
+        transcript.append_point(b"base", &statement.base);
+        transcript.append_point(b"point", &statement.point);
+        self.enc.absorb_statement(&statement.cl_state(), transcript);
+    }
+
+    fn absorb_commitment(&self, commitment: &Self::Commitment, transcript: &mut Transcript) {
+        self.enc.absorb_commitment(&commitment.0, transcript);
+        transcript.append_point(b"t3", &commitment.1);
+    }
+}
+
+#[cfg(not(feature = "verify-only"))]
+impl SigmaProver for EqualityProtocol<'_> {
+    type Witness = EqualityWit;
+    type Randomness = (GroupExponent, ZqScalar);
+
+    fn sample(&self) -> Self::Randomness {
+        self.enc.sample()
+    }
+
+    fn commit(&self, statement: &EqualityState, randomness: &Self::Randomness) -> Self::Commitment {
+        let t3 = statement.base * randomness.1.to_scalar();
+        (self.enc.commit(&statement.cl_state(), randomness), t3)
+    }
+
+    fn respond(
+        &self,
+        witness: &EqualityWit,
+        randomness: &Self::Randomness,
+        k: &BigInt,
+    ) -> Self::Response {
+        let witness = CLWit {
+            x: witness.x,
+            r: witness.r.clone(),
+        };
+        self.enc.respond(&witness, randomness, k)
+    }
+}
+
+impl EqualityProof {
+    #[cfg(not(feature = "verify-only"))]
+    pub fn prove(group: &CLGroup, witness: &EqualityWit, statement: &EqualityState) -> Self {
+        let protocol = EqualityProtocol {
+            enc: ClEncProtocol { group },
+        };
+        let (((t1, t2), t3), (u1, u2)) = sigma::prove(
+            &protocol,
+            &Transcript::new(b"EqualityProof"),
+            statement,
+            witness,
+        );
+        Self { t1, t2, t3, u1, u2 }
+    }
+
+    pub fn verify(&self, group: &CLGroup, statement: &EqualityState) -> Result<(), MulEcdsaError> {
+        let EqualityState { cipher, cl_pub_key, .. } = statement;
+        group.check_elements(&[&cipher.c1, &cipher.c2, cl_pub_key, &self.t1, &self.t2])?;
+        let protocol = EqualityProtocol {
+            enc: ClEncProtocol { group },
+        };
+        let commitment = ((self.t1.clone(), self.t2.clone()), self.t3);
+        let response = (self.u1.clone(), self.u2.clone());
+        match sigma::verify(
+            &protocol,
+            &Transcript::new(b"EqualityProof"),
+            statement,
+            &commitment,
+            &response,
+        ) {
+            true => Ok(()),
+            false => Err(MulEcdsaError::VrfyEqualityProofFailed),
+        }
+    }
+}
+
+impl serde::Serialize for EqualityProof {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use k256::elliptic_curve::sec1::ToEncodedPoint;
+        use serde::ser::SerializeStruct;
+        let mut state = serializer.serialize_struct("EqualityProof", 5)?;
+        state.serialize_field("t1", &self.t1)?;
+        state.serialize_field("t2", &self.t2)?;
+        // Serialize t3 as compressed bytes
+        let t3_bytes = self.t3.to_affine().to_encoded_point(true);
+        state.serialize_field("t3", t3_bytes.as_bytes())?;
+        state.serialize_field("u1", &Fixed::<EXPONENT_RESPONSE_BYTES>(&self.u1))?;
+        state.serialize_field("u2", &Fixed::<SCALAR_BYTES>(&self.u2))?;
+        state.end()
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for EqualityProof {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        use k256::elliptic_curve::sec1::FromEncodedPoint;
+        use k256::EncodedPoint;
+        use serde::de;
+
+        #[derive(Deserialize)]
+        struct Helper {
+            t1: GmpClassGroup,
+            t2: GmpClassGroup,
+            t3: Vec<u8>,
+            #[serde(deserialize_with = "fixed_mpz::deserialize::<EXPONENT_RESPONSE_BYTES, _>")]
+            u1: Mpz,
+            #[serde(deserialize_with = "fixed_mpz::deserialize::<SCALAR_BYTES, _>")]
+            u2: Mpz,
+        }
+
+        let helper = Helper::deserialize(deserializer)?;
+        let t3_encoded = EncodedPoint::from_bytes(&helper.t3).map_err(de::Error::custom)?;
+        let t3_affine = k256::AffinePoint::from_encoded_point(&t3_encoded);
+        let t3 = if t3_affine.is_some().into() {
+            ProjectivePoint::from(t3_affine.unwrap())
+        } else {
+            return Err(de::Error::custom("invalid t3 point encoding"));
+        };
+
+        Ok(EqualityProof {
+            t1: helper.t1,
+            t2: helper.t2,
+            t3,
+            u1: helper.u1,
+            u2: helper.u2,
+        })
+    }
+}
+
+#[cfg(not(feature = "verify-only"))]
+#[test]
+fn equality_proof_test() {
+    use k256::elliptic_curve::Field;
+    use crate::utilities::rng::ProtocolRng;
+
+    let group = CLGroup::new();
+    let (_, cl_pub_key) = group.keygen();
+    let x = Scalar::random(&mut ProtocolRng);
+    let base = ProjectivePoint::GENERATOR * Scalar::random(&mut ProtocolRng);
+    let (cipher, r) = CLGroup::encrypt(&group, &cl_pub_key, x);
+    let statement = EqualityState {
+        cipher,
+        cl_pub_key,
+        base,
+        point: base * x,
+    };
+    let proof = EqualityProof::prove(&group, &EqualityWit { x, r }, &statement);
+    proof.verify(&group, &statement).unwrap();
+
+    let wrong = EqualityState {
+        point: ProjectivePoint::GENERATOR * x,
+        ..statement
+    };
+    assert_eq!(
+        proof.verify(&group, &wrong),
+        Err(MulEcdsaError::VrfyEqualityProofFailed)
+    );
+}
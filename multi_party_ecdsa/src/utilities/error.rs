@@ -1,8 +1,10 @@
+use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::error::Error;
 
-/// Represents errors.
-#[derive(Clone, Debug, Eq, PartialEq)]
+/// Represents errors. Sent to the counterparty in `AbortMsg` as the variant
+/// index, so new variants go at the end.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub enum MulEcdsaError {
     OpenDLCommFailed,
     OpenCommZKFailed,
@@ -34,8 +36,50 @@ pub enum MulEcdsaError {
     FromHexFailed,
     GenerateJsonStringFailed,
     MissingMsg,
+    GenerateAddressFailed,
+    InvalidProtocolParams,
+    CLDecryptFailed,
+    OpenPedersenCommFailed,
+    VrfyPedersenProofFailed,
+    VrfyMtAwcProofFailed,
+    MissingMtAwcProof,
+    VrfyEqualityProofFailed,
+    InvalidKeyStore,
+    CounterpartyMismatch,
+    VrfyCLKeyProofFailed,
+    VrfyMtaConsistencyFailed,
+    MessageTooLarge,
+    NonceReuse,
+    NonceRegistryFailed,
     InvertZero,
     GeneralError,
+    PeerAborted,
+    InvalidBlindFactor,
+    MissingSessionBinding,
+    MissingNonceRegistry,
+    NegativeScalar,
+    IdentityPoint,
+    ZeroScalar,
+    ClassGroupTooSmall,
+    RefreshRequired,
+    UnknownKey,
+    Unauthorized,
+    TransportFailed,
+    PresignatureUnavailable,
+    PresignaturePoolFailed,
+    InvalidInput,
+    PlaintextOutOfRange,
+    InvalidCeremonyReport,
+    InvalidTweak,
+    SignatureConflict,
+    ClockSkew,
+    SessionExpired,
+    Overloaded,
+    UnknownSession,
+    Cancelled,
+    PeerCancelled,
+    RequestInProgress,
+    RequestIdReused,
 }
 
 impl fmt::Display for MulEcdsaError {
@@ -71,8 +115,50 @@ impl fmt::Display for MulEcdsaError {
             MulEcdsaError::FromHexFailed => write!(f, "From Hex Failed"),
             MulEcdsaError::GenerateJsonStringFailed => write!(f, "Generate Result Json String Failed"),
             MulEcdsaError::MissingMsg => write!(f, "Missing message to sign"),
+            MulEcdsaError::GenerateAddressFailed => write!(f, "Generate address failed"),
+            MulEcdsaError::InvalidProtocolParams => write!(f, "Invalid protocol security parameters"),
+            MulEcdsaError::CLDecryptFailed => write!(f, "CL decryption failed: plaintext not in the subgroup F"),
+            MulEcdsaError::OpenPedersenCommFailed => write!(f, "Open class group Pedersen commitment failed"),
+            MulEcdsaError::VrfyPedersenProofFailed => write!(f, "Verify class group Pedersen proof failed"),
+            MulEcdsaError::VrfyMtAwcProofFailed => write!(f, "Verify MtAwc proof failed"),
+            MulEcdsaError::MissingMtAwcProof => write!(f, "Strict mode requires an MtAwc proof"),
+            MulEcdsaError::VrfyEqualityProofFailed => write!(f, "Verify ciphertext/point equality proof failed"),
+            MulEcdsaError::InvalidKeyStore => write!(f, "Key store shares do not match the joint public key"),
+            MulEcdsaError::CounterpartyMismatch => write!(f, "Counterparty does not match the key store"),
+            MulEcdsaError::VrfyCLKeyProofFailed => write!(f, "Verify CL key proof failed"),
+            MulEcdsaError::VrfyMtaConsistencyFailed => write!(f, "Verify MtA consistency message failed"),
+            MulEcdsaError::MessageTooLarge => write!(f, "Message exceeds the size cap for its kind"),
+            MulEcdsaError::NonceReuse => write!(f, "Nonce point was already used with this key"),
+            MulEcdsaError::NonceRegistryFailed => write!(f, "Read or write nonce registry failed"),
             MulEcdsaError::InvertZero => write!(f, "Invert a zero element"),
             MulEcdsaError::GeneralError => write!(f, "General error"),
+            MulEcdsaError::PeerAborted => write!(f, "Counterparty aborted the session"),
+            MulEcdsaError::InvalidBlindFactor => write!(f, "Commitment blind factor out of range"),
+            MulEcdsaError::MissingSessionBinding => write!(f, "Strict profile requires a session-bound nonce commitment"),
+            MulEcdsaError::MissingNonceRegistry => write!(f, "Strict profile requires a nonce registry"),
+            MulEcdsaError::NegativeScalar => write!(f, "Negative integer where a scalar was expected"),
+            MulEcdsaError::IdentityPoint => write!(f, "Received point is the identity"),
+            MulEcdsaError::ZeroScalar => write!(f, "Received scalar is zero"),
+            MulEcdsaError::ClassGroupTooSmall => write!(f, "Class group too small for the security level"),
+            MulEcdsaError::RefreshRequired => write!(f, "Key must be refreshed before it signs again"),
+            MulEcdsaError::UnknownKey => write!(f, "No key with this id"),
+            MulEcdsaError::Unauthorized => write!(f, "Signing request not authorized by the approval key"),
+            MulEcdsaError::TransportFailed => write!(f, "Message could not be read or written"),
+            MulEcdsaError::PresignatureUnavailable => write!(f, "Presignature missing, consumed, expired or from before a refresh"),
+            MulEcdsaError::PresignaturePoolFailed => write!(f, "Presignature pool could not be read or written"),
+            MulEcdsaError::InvalidInput => write!(f, "Input could not be parsed"),
+            MulEcdsaError::PlaintextOutOfRange => write!(f, "CL plaintext not in [0, q)"),
+            MulEcdsaError::InvalidCeremonyReport => write!(f, "Ceremony report malformed, unsigned or for another key"),
+            MulEcdsaError::InvalidTweak => write!(f, "Tweak not below the group order"),
+            MulEcdsaError::SignatureConflict => write!(f, "Another co-signer's session already released this message's signature"),
+            MulEcdsaError::ClockSkew => write!(f, "Peer's clock too far from ours"),
+            MulEcdsaError::SessionExpired => write!(f, "Session expired"),
+            MulEcdsaError::Overloaded => write!(f, "Too busy, try again later"),
+            MulEcdsaError::UnknownSession => write!(f, "No such session"),
+            MulEcdsaError::Cancelled => write!(f, "Session cancelled"),
+            MulEcdsaError::PeerCancelled => write!(f, "Session cancelled by the counterparty"),
+            MulEcdsaError::RequestInProgress => write!(f, "A session for this request id is still running"),
+            MulEcdsaError::RequestIdReused => write!(f, "Request id already used for another message"),
         }
     }
 }
@@ -0,0 +1,120 @@
+//! Fixed-width serde representation of proof responses. With the default
+//! `Mpz` encoding the length of a response such as `u1 = r1 + k * r` shows
+//! its bit length, and with it something about `k * r`; here every response
+//! of a kind takes exactly `LEN` big-endian bytes (hex of `2 * LEN` digits in
+//! human-readable formats). Use on `Mpz` fields with
+//! `#[serde(serialize_with = "fixed_mpz::serialize::<LEN, _>")]` and the
+//! matching `deserialize_with`. Negative values and values wider than `LEN`
+//! do not serialize.
+
+use classgroup::gmp::mpz::Mpz;
+use serde::de::{Error, SeqAccess, Visitor};
+use serde::ser::SerializeTuple;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+/// Class group exponent responses (`u1`, `z`, `z_rho`, `zr`): room for
+/// `stilde * 2^(stat + comp + slack + 2)` with the built-in discriminant,
+/// `computational_security` up to 256 and 40 statistical and slack bits.
+pub const EXPONENT_RESPONSE_BYTES: usize = 192;
+/// Responses over a mod-q witness (`z_a`, `zx`): room for
+/// `q * 2^(comp + slack + 1)` with the same parameters.
+pub const ZQ_RESPONSE_BYTES: usize = 80;
+/// Responses reduced mod q (`u2`, `z_alpha`).
+pub const SCALAR_BYTES: usize = 32;
+
+pub fn serialize<const LEN: usize, S: Serializer>(
+    value: &Mpz,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    use serde::ser::Error;
+    if *value < Mpz::zero() {
+        return Err(S::Error::custom("negative fixed-width integer"));
+    }
+    let bytes: Vec<u8> = value.into();
+    let bytes = &bytes[bytes.iter().take_while(|b| **b == 0).count()..];
+    if bytes.len() > LEN {
+        return Err(S::Error::custom("integer wider than its fixed width"));
+    }
+    let mut padded = vec![0u8; LEN];
+    padded[LEN - bytes.len()..].copy_from_slice(bytes);
+    if serializer.is_human_readable() {
+        return serializer.serialize_str(&hex::encode(padded));
+    }
+    let mut tuple = serializer.serialize_tuple(LEN)?;
+    for byte in &padded {
+        tuple.serialize_element(byte)?;
+    }
+    tuple.end()
+}
+
+pub fn deserialize<'de, const LEN: usize, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Mpz, D::Error> {
+    if deserializer.is_human_readable() {
+        let hex = <std::borrow::Cow<'de, str>>::deserialize(deserializer)?;
+        if hex.len() != 2 * LEN {
+            return Err(D::Error::invalid_length(hex.len() / 2, &"a fixed-width integer"));
+        }
+        let bytes = hex::decode(&*hex).map_err(|_| D::Error::custom("invalid hex integer"))?;
+        return Ok(Mpz::from(&bytes[..]));
+    }
+
+    struct FixedVisitor<const LEN: usize>;
+
+    impl<'de, const LEN: usize> Visitor<'de> for FixedVisitor<LEN> {
+        type Value = Mpz;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            write!(formatter, "{} big-endian bytes", LEN)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Mpz, A::Error> {
+            let mut bytes = Vec::with_capacity(LEN);
+            for i in 0..LEN {
+                let byte: u8 = seq
+                    .next_element()?
+                    .ok_or_else(|| A::Error::invalid_length(i, &self))?;
+                bytes.push(byte);
+            }
+            Ok(Mpz::from(&bytes[..]))
+        }
+    }
+
+    deserializer.deserialize_tuple(LEN, FixedVisitor::<LEN>)
+}
+
+/// Borrowing wrapper for hand-written `Serialize` impls.
+pub struct Fixed<'a, const LEN: usize>(pub &'a Mpz);
+
+impl<const LEN: usize> Serialize for Fixed<'_, LEN> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize::<LEN, S>(self.0, serializer)
+    }
+}
+
+#[test]
+fn fixed_mpz_test() {
+    use bincode::config::standard;
+
+    #[derive(Serialize, Deserialize)]
+    struct Wrapped(
+        #[serde(
+            serialize_with = "serialize::<4, _>",
+            deserialize_with = "deserialize::<4, _>"
+        )]
+        Mpz,
+    );
+
+    for value in [Mpz::zero(), Mpz::from(5), Mpz::from(0xffff_ffffu64)] {
+        let bytes = bincode::serde::encode_to_vec(Fixed::<4>(&value), standard()).unwrap();
+        assert_eq!(bytes.len(), 4);
+        let (decoded, _): (Wrapped, usize) =
+            bincode::serde::decode_from_slice(&bytes, standard()).unwrap();
+        assert_eq!(decoded.0, value);
+    }
+    assert!(bincode::serde::encode_to_vec(Fixed::<4>(&Mpz::from(1u64 << 32)), standard()).is_err());
+    assert!(bincode::serde::encode_to_vec(Fixed::<4>(&Mpz::from(-1)), standard()).is_err());
+    // a short input does not decode
+    assert!(bincode::serde::decode_from_slice::<Wrapped, _>(&[0u8; 3], standard()).is_err());
+}
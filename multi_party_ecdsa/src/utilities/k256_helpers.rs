@@ -1,9 +1,80 @@
 use k256::{ProjectivePoint, AffinePoint, Scalar};
 use k256::elliptic_curve::sec1::ToEncodedPoint;
-use k256::elliptic_curve::{Field, PrimeField};
-use num_bigint::{BigInt, Sign, RandBigInt};
+use k256::elliptic_curve::PrimeField;
+use num_bigint::{BigInt, Sign};
 use sha2::{Sha256, Digest};
-use rand::rngs::OsRng;
+use std::sync::OnceLock;
+use crate::utilities::sigma::{self, SigmaProtocol, Transcript};
+#[cfg(not(feature = "verify-only"))]
+use crate::utilities::{rng::ProtocolRng, sigma::SigmaProver};
+#[cfg(not(feature = "verify-only"))]
+use num_bigint::RandBigInt;
+#[cfg(not(feature = "verify-only"))]
+use rand::RngCore;
+use crate::utilities::error::MulEcdsaError;
+
+/// The secp256k1 group order `q`, big-endian.
+pub const ORDER: [u8; 32] = [
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe,
+    0xba, 0xae, 0xdc, 0xe6, 0xaf, 0x48, 0xa0, 0x3b, 0xbf, 0xd2, 0x5e, 0x8c, 0xd0, 0x36, 0x41, 0x41,
+];
+
+/// `q / 2` rounded down, big-endian: the largest low `s`.
+pub const HALF_ORDER: [u8; 32] = [
+    0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0x5d, 0x57, 0x6e, 0x73, 0x57, 0xa4, 0x50, 0x1d, 0xdf, 0xe9, 0x2f, 0x46, 0x68, 0x1b, 0x20, 0xa0,
+];
+
+/// The secp256k1 group order, computed once.
+pub fn curve_order() -> &'static BigInt {
+    static Q: OnceLock<BigInt> = OnceLock::new();
+    Q.get_or_init(|| BigInt::from_bytes_be(Sign::Plus, &ORDER))
+}
+
+/// Reduce an arbitrary (possibly negative) integer mod the curve order.
+pub fn scalar_mod_order(value: &BigInt) -> Scalar {
+    let q = curve_order();
+    let reduced = ((value % q) + q) % q;
+    let (_, bytes) = reduced.to_bytes_be();
+    let mut repr = [0u8; 32];
+    repr[32 - bytes.len()..].copy_from_slice(&bytes);
+    Scalar::from_repr(repr.into()).unwrap()
+}
+
+/// Rejects the identity, for points received from the counterparty.
+pub fn check_not_identity(point: &ProjectivePoint) -> Result<(), MulEcdsaError> {
+    match *point == ProjectivePoint::IDENTITY {
+        true => Err(MulEcdsaError::IdentityPoint),
+        false => Ok(()),
+    }
+}
+
+/// Rejects zero, for scalars received from the counterparty.
+pub fn check_not_zero(s: &Scalar) -> Result<(), MulEcdsaError> {
+    match bool::from(s.is_zero()) {
+        true => Err(MulEcdsaError::ZeroScalar),
+        false => Ok(()),
+    }
+}
+
+/// Big-endian `bytes` of any length, such as a digest or `x(R)`, reduced
+/// mod `q`, and whether the value was `q` or more: for `x(R)`, bit 1 of the
+/// recovery id.
+pub fn reduce_bytes_to_scalar(bytes: &[u8]) -> (Scalar, bool) {
+    let value = BigInt::from_bytes_be(Sign::Plus, bytes);
+    let overflow = value >= *curve_order();
+    (scalar_mod_order(&value), overflow)
+}
+
+/// Whether `s` is above `HALF_ORDER`, the `s` BIP-62 rules out.
+pub fn is_high_s(s: &Scalar) -> bool {
+    s.to_bytes().as_slice() > &HALF_ORDER[..]
+}
+
+/// Whether `s` lies in the lower half of the scalar field (BIP-62 low-s).
+pub fn is_low_s(s: &Scalar) -> bool {
+    !is_high_s(s)
+}
 
 // Serialization helpers for Scalar
 
@@ -26,66 +97,257 @@ pub struct DLogProof<P> {
     pub challenge_response: Scalar,
 }
 
+/// Knowledge of x with X = x * base.
+#[derive(Clone, Debug)]
+pub struct DlogProtocol {
+    pub base: ProjectivePoint,
+}
+
+impl DlogProtocol {
+    pub fn generator() -> Self {
+        Self {
+            base: ProjectivePoint::GENERATOR,
+        }
+    }
+}
+
+impl SigmaProtocol for DlogProtocol {
+    type Statement = ProjectivePoint;
+    type Commitment = ProjectivePoint;
+    type Response = Scalar;
+
+    fn challenge_bytes(&self) -> usize {
+        32
+    }
+
+    fn check(
+        &self,
+        statement: &ProjectivePoint,
+        commitment: &ProjectivePoint,
+        challenge: &BigInt,
+        response: &Scalar,
+    ) -> bool {
+        // base^response == commitment * public_key^challenge
+        self.base * response == commitment + &(*statement * scalar_mod_order(challenge))
+    }
+
+    fn absorb_statement(&self, statement: &ProjectivePoint, transcript: &mut Transcript) {
+        transcript.append_point(b"base", &self.base);
+        transcript.append_point(b"public_key", statement);
+    }
+
+    fn absorb_commitment(&self, commitment: &ProjectivePoint, transcript: &mut Transcript) {
+        transcript.append_point(b"commitment", commitment);
+    }
+}
+
+#[cfg(not(feature = "verify-only"))]
+impl SigmaProver for DlogProtocol {
+    type Witness = Scalar;
+    type Randomness = Scalar;
+
+    fn sample(&self) -> Scalar {
+        sample_scalar()
+    }
+
+    fn commit(&self, _: &ProjectivePoint, randomness: &Scalar) -> ProjectivePoint {
+        self.base * randomness
+    }
+
+    fn respond(&self, witness: &Scalar, randomness: &Scalar, challenge: &BigInt) -> Scalar {
+        // Response: r + challenge * secret
+        randomness + scalar_mod_order(challenge) * witness
+    }
+}
+
+fn dlog_transcript(context: Option<&[u8; 32]>) -> Transcript {
+    let mut transcript = Transcript::new(b"DLogProof");
+    if let Some(context) = context {
+        transcript.append(b"context", context);
+    }
+    transcript
+}
+
+#[cfg(not(feature = "verify-only"))]
 impl DLogProof<ProjectivePoint> {
     pub fn prove(secret: &Scalar) -> Self {
-        let random = Scalar::random(&mut OsRng);
-        let pk_t_rand_commitment = ProjectivePoint::GENERATOR * random;
-        
-        // Fiat-Shamir challenge
+        Self::prove_with(secret, None)
+    }
+
+    /// A proof that only verifies under the same `context`, e.g. a
+    /// `session_context`.
+    pub fn prove_in_context(secret: &Scalar, context: &[u8; 32]) -> Self {
+        Self::prove_with(secret, Some(context))
+    }
+
+    fn prove_with(secret: &Scalar, context: Option<&[u8; 32]>) -> Self {
         let public_key = ProjectivePoint::GENERATOR * secret;
-        let challenge = Self::compute_challenge(&public_key, &pk_t_rand_commitment);
-        
-        // Response: r + challenge * secret
-        let challenge_response = random + challenge * secret;
+        let (pk_t_rand_commitment, challenge_response) = sigma::prove(
+            &DlogProtocol::generator(),
+            &dlog_transcript(context),
+            &public_key,
+            secret,
+        );
         
         Self {
             pk_t_rand_commitment,
             challenge_response,
         }
     }
-    
+}
+
+impl DLogProof<ProjectivePoint> {
     pub fn verify(&self, public_key: &ProjectivePoint) -> Result<(), String> {
-        let challenge = Self::compute_challenge(public_key, &self.pk_t_rand_commitment);
-        
-        // Verify: g^response == commitment * public_key^challenge
-        let lhs = ProjectivePoint::GENERATOR * self.challenge_response;
-        let rhs = self.pk_t_rand_commitment + (*public_key * challenge);
-        
-        if lhs == rhs {
+        self.verify_with(public_key, None)
+    }
+
+    pub fn verify_in_context(&self, public_key: &ProjectivePoint, context: &[u8; 32]) -> Result<(), String> {
+        self.verify_with(public_key, Some(context))
+    }
+
+    fn verify_with(&self, public_key: &ProjectivePoint, context: Option<&[u8; 32]>) -> Result<(), String> {
+        if sigma::verify(
+            &DlogProtocol::generator(),
+            &dlog_transcript(context),
+            public_key,
+            &self.pk_t_rand_commitment,
+            &self.challenge_response,
+        ) {
             Ok(())
         } else {
             Err("DLog proof verification failed".to_string())
         }
     }
-    
-    fn compute_challenge(public_key: &ProjectivePoint, commitment: &ProjectivePoint) -> Scalar {
-        let mut hasher = Sha256::new();
-        let pk_bytes = public_key.bytes_compressed_to_big_int();
-        let (_, pk_be) = pk_bytes.to_bytes_be();
-        hasher.update(&pk_be);
-        let comm_bytes = commitment.bytes_compressed_to_big_int();
-        let (_, comm_be) = comm_bytes.to_bytes_be();
-        hasher.update(&comm_be);
-        let hash = hasher.finalize();
-        
-        let mut scalar_bytes = [0u8; 32];
-        scalar_bytes.copy_from_slice(&hash[..32]);
-        Scalar::from_repr(scalar_bytes.into()).unwrap_or(Scalar::ZERO)
-    }
 }
 
 // Hash commitment helper
 pub fn create_hash_commitment(message: &BigInt, blind_factor: &BigInt) -> BigInt {
-    let mut hasher = Sha256::new();
+    create_hash_commitment_with::<Sha256>(message, blind_factor)
+}
+
+/// `create_hash_commitment` over any digest, e.g. `sha3::Sha3_256`.
+pub fn create_hash_commitment_with<H: Digest>(message: &BigInt, blind_factor: &BigInt) -> BigInt {
+    let mut hasher = H::new();
     let (_, msg_bytes) = message.to_bytes_be();
     hasher.update(&msg_bytes);
     let (_, blind_bytes) = blind_factor.to_bytes_be();
     hasher.update(&blind_bytes);
-    let hash = hasher.finalize();
-    BigInt::from_bytes_be(Sign::Plus, &hash)
+    BigInt::from_bytes_be(Sign::Plus, &hasher.finalize())
 }
 
+/// `create_hash_commitment` with a fixed-length context hashed first.
+pub fn create_bound_hash_commitment(
+    context: &[u8; 32],
+    message: &BigInt,
+    blind_factor: &BigInt,
+) -> BigInt {
+    create_bound_hash_commitment_with::<Sha256>(context, message, blind_factor)
+}
+
+pub fn create_bound_hash_commitment_with<H: Digest>(
+    context: &[u8; 32],
+    message: &BigInt,
+    blind_factor: &BigInt,
+) -> BigInt {
+    let mut hasher = H::new();
+    hasher.update(context);
+    let (_, msg_bytes) = message.to_bytes_be();
+    hasher.update(&msg_bytes);
+    let (_, blind_bytes) = blind_factor.to_bytes_be();
+    hasher.update(&blind_bytes);
+    BigInt::from_bytes_be(Sign::Plus, &hasher.finalize())
+}
+
+/// Constant-time equality of two hash commitments. Values that do not fit
+/// a SHA-256 digest compare unequal; only their length can leak.
+pub fn hash_commitment_eq(a: &BigInt, b: &BigInt) -> bool {
+    hash_commitment_eq_with::<Sha256>(a, b)
+}
+
+/// `hash_commitment_eq` for commitments made with digest `H`.
+pub fn hash_commitment_eq_with<H: Digest>(a: &BigInt, b: &BigInt) -> bool {
+    use k256::elliptic_curve::subtle::ConstantTimeEq;
+    let len = <H as Digest>::output_size();
+    let to_digest = |value: &BigInt| {
+        let (sign, bytes) = value.to_bytes_be();
+        if sign == Sign::Minus || bytes.len() > len {
+            return None;
+        }
+        let mut digest = vec![0u8; len];
+        digest[len - bytes.len()..].copy_from_slice(&bytes);
+        Some(digest)
+    };
+    match (to_digest(a), to_digest(b)) {
+        (Some(a), Some(b)) => a.ct_eq(&b).into(),
+        _ => false,
+    }
+}
+
+/// Uniform in `[0, 2^bits)`.
+#[cfg(not(feature = "verify-only"))]
 pub fn sample_bigint(bits: usize) -> BigInt {
-    let mut rng = OsRng;
-    rng.gen_bigint(bits as u64)
+    let mut rng = ProtocolRng;
+    rng.gen_biguint(bits as u64).into()
+}
+
+/// Uniform in `[0, q)`: 32 random bytes, redrawn while they are not below
+/// the curve order, so no value is favoured by a reduction.
+#[cfg(not(feature = "verify-only"))]
+pub fn sample_scalar() -> Scalar {
+    loop {
+        let mut repr = [0u8; 32];
+        ProtocolRng.fill_bytes(&mut repr);
+        if let Some(s) = Option::<Scalar>::from(Scalar::from_repr(repr.into())) {
+            return s;
+        }
+    }
+}
+
+#[test]
+fn curve_order_test() {
+    let q = BigInt::parse_bytes(b"115792089237316195423570985008687907852837564279074904382605163141518161494337", 10).unwrap();
+    assert_eq!(curve_order(), &q);
+    assert_eq!(scalar_mod_order(&BigInt::from(-1)), -Scalar::ONE);
+    assert_eq!(scalar_mod_order(&(q + 5)), Scalar::from(5u64));
+    assert!(is_low_s(&Scalar::ONE));
+    assert!(!is_low_s(&-Scalar::ONE));
+    use k256::elliptic_curve::IsHigh;
+    let half = Scalar::from_repr(HALF_ORDER.into()).unwrap();
+    for s in [half, half + Scalar::ONE, -half, Scalar::ZERO] {
+        assert_eq!(is_high_s(&s), bool::from(s.is_high()));
+    }
+    assert!(!is_high_s(&half) && is_high_s(&(half + Scalar::ONE)));
+    assert_eq!(reduce_bytes_to_scalar(&ORDER), (Scalar::ZERO, true));
+    assert_eq!(reduce_bytes_to_scalar(&HALF_ORDER), (half, false));
+    assert!(reduce_bytes_to_scalar(&[0xff; 32]).1);
+    assert_eq!(reduce_bytes_to_scalar(&[]), (Scalar::ZERO, false));
+
+    let digest = create_hash_commitment(&BigInt::from(1), &BigInt::from(2));
+    assert!(hash_commitment_eq(&digest, &digest.clone()));
+    assert!(!hash_commitment_eq(&digest, &(&digest + 1)));
+    assert!(!hash_commitment_eq(&(BigInt::from(1) << 256u32), &(BigInt::from(1) << 256u32)));
+}
+
+#[cfg(not(feature = "verify-only"))]
+#[test]
+fn hash_commitment_digest_test() {
+    use sha3::Sha3_256;
+
+    let (message, blind) = (BigInt::from(1), sample_bigint(256));
+    let sha2 = create_hash_commitment(&message, &blind);
+    let sha3 = create_hash_commitment_with::<Sha3_256>(&message, &blind);
+    assert_ne!(sha2, sha3);
+    assert_eq!(sha3, create_hash_commitment_with::<Sha3_256>(&message, &blind));
+    assert!(hash_commitment_eq_with::<Sha3_256>(&sha3, &sha3.clone()));
+    assert_ne!(
+        create_bound_hash_commitment_with::<Sha3_256>(&[0; 32], &message, &blind),
+        create_bound_hash_commitment(&[0; 32], &message, &blind)
+    );
+    // SHA-512 commitments are wider than the default comparison allows
+    let wide = create_hash_commitment_with::<sha2::Sha512>(&message, &blind);
+    assert!(hash_commitment_eq_with::<sha2::Sha512>(&wide, &wide.clone()));
+    assert!(!hash_commitment_eq(&wide, &wide.clone()));
+
+    let s = sample_scalar();
+    assert_ne!(s, sample_scalar());
 }
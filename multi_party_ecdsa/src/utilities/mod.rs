@@ -1,9 +1,132 @@
+use crate::utilities::error::MulEcdsaError;
+use num_bigint::BigInt;
+
 pub const SECURITY_BITS: usize = 256;
 pub const SECURITY_PARAMETER: usize = 128;
+pub const STATISTICAL_SECURITY: usize = 40;
+pub const SLACK_BITS: usize = 40;
 
+pub mod address;
+pub mod bigint_serde;
 pub mod class_group;
+pub mod cl_pedersen;
 pub mod dl_com_zk;
 pub mod error;
+pub mod fixed_mpz;
+pub mod sigma;
+pub mod shamir;
 pub mod signature;
 pub mod cl_proof;
+pub mod cl_dl_proof;
+pub mod cl_key_proof;
+pub mod equality_proof;
 pub mod k256_helpers;
+pub mod mta_wc_proof;
+pub mod parse;
+pub mod point_serde;
+#[cfg(not(feature = "verify-only"))]
+pub mod rng;
+#[cfg(all(any(test, feature = "test-utils"), not(feature = "verify-only")))]
+pub mod test_vectors;
+pub mod typed_int;
+
+/// Security parameters shared by the CL proofs and the hash commitments.
+///
+/// `computational_security` is the Fiat-Shamir challenge length (and half the
+/// commitment blind length), `statistical_security` bounds the distance of CL
+/// secret keys / randomness from uniform, and `slack_bits` is the extra room
+/// given to the proof randomness so responses hide the witness.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProtocolParams {
+    pub statistical_security: usize,
+    pub computational_security: usize,
+    pub slack_bits: usize,
+}
+
+impl ProtocolParams {
+    pub fn new(
+        statistical_security: usize,
+        computational_security: usize,
+        slack_bits: usize,
+    ) -> Result<Self, MulEcdsaError> {
+        let params = Self {
+            statistical_security,
+            computational_security,
+            slack_bits,
+        };
+        params.validate()?;
+        Ok(params)
+    }
+
+    /// Parameters for a 112, 128 or 192-bit computational security level.
+    pub fn with_security_level(computational_security: usize) -> Result<Self, MulEcdsaError> {
+        match computational_security {
+            112 | 128 | 192 => Self::new(STATISTICAL_SECURITY, computational_security, SLACK_BITS),
+            _ => Err(MulEcdsaError::InvalidProtocolParams),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), MulEcdsaError> {
+        // the challenge is a truncated SHA-256 digest
+        if self.computational_security == 0
+            || self.computational_security > 256
+            || !self.computational_security.is_multiple_of(8)
+            || self.statistical_security == 0
+        {
+            return Err(MulEcdsaError::InvalidProtocolParams);
+        }
+        Ok(())
+    }
+
+    pub fn challenge_bytes(&self) -> usize {
+        self.computational_security / 8
+    }
+
+    pub fn blind_bits(&self) -> usize {
+        2 * self.computational_security
+    }
+
+    /// Shortest blind accepted in an opening. An honest `blind_bits()` blind
+    /// falls below it with probability 2^-(computational_security / 2).
+    pub fn min_blind_bits(&self) -> usize {
+        self.blind_bits() - self.computational_security / 2
+    }
+
+    /// Whether a received commitment blind is positive and between
+    /// `min_blind_bits()` and `blind_bits()` long.
+    pub fn blind_in_range(&self, blind: &BigInt) -> bool {
+        let bits = blind.bits() as usize;
+        blind.sign() == num_bigint::Sign::Plus
+            && bits >= self.min_blind_bits()
+            && bits <= self.blind_bits()
+    }
+
+    /// Bound for CL secret keys and encryption randomness: stilde * 2^stat.
+    pub fn secret_key_bound(&self, stilde: &BigInt) -> BigInt {
+        stilde * BigInt::from(2i32).pow(self.statistical_security as u32)
+    }
+
+    /// Bound for the prover randomness r1: stilde * 2^stat * 2^comp * 2^slack.
+    pub fn proof_randomness_bound(&self, stilde: &BigInt) -> BigInt {
+        self.secret_key_bound(stilde)
+            * BigInt::from(2i32).pow(self.computational_security as u32)
+            * BigInt::from(2i32).pow(self.slack_bits as u32)
+    }
+
+    /// Bound on an honest response u1 = r1 + k * r: stilde * 2^stat * 2^comp * (2^slack + 1).
+    pub fn proof_response_bound(&self, stilde: &BigInt) -> BigInt {
+        self.secret_key_bound(stilde)
+            * BigInt::from(2i32).pow(self.computational_security as u32)
+            * (BigInt::from(2i32).pow(self.slack_bits as u32) + BigInt::from(1))
+    }
+}
+
+impl Default for ProtocolParams {
+    fn default() -> Self {
+        Self {
+            statistical_security: STATISTICAL_SECURITY,
+            computational_security: SECURITY_PARAMETER,
+            slack_bits: SLACK_BITS,
+        }
+    }
+}
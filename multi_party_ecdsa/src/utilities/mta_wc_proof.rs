@@ -0,0 +1,282 @@
+use crate::utilities::class_group::*;
+use crate::utilities::error::MulEcdsaError;
+use crate::utilities::fixed_mpz::{self, Fixed, EXPONENT_RESPONSE_BYTES, SCALAR_BYTES, ZQ_RESPONSE_BYTES};
+use crate::utilities::k256_helpers::scalar_mod_order;
+use crate::utilities::sigma::{self, SigmaProtocol, Transcript};
+#[cfg(not(feature = "verify-only"))]
+use crate::utilities::sigma::SigmaProver;
+use classgroup::gmp::mpz::Mpz;
+use classgroup::gmp_classgroup::*;
+use classgroup::ClassGroup;
+#[cfg(not(feature = "verify-only"))]
+use k256::elliptic_curve::Field;
+use k256::{ProjectivePoint, Scalar};
+use num_bigint::BigInt;
+#[cfg(not(feature = "verify-only"))]
+use crate::utilities::rng::ProtocolRng;
+use serde::Deserialize;
+
+/// Statement of the MtA-with-check proof: `c_a = c_b^a * Enc(alpha; rho)`
+/// under `cl_pub_key`, and `a_pub = a * G`.
+#[derive(Clone, Debug)]
+pub struct MtAwcState {
+    pub c_b: Ciphertext,
+    pub c_a: Ciphertext,
+    pub cl_pub_key: GmpClassGroup,
+    pub a_pub: ProjectivePoint,
+}
+
+#[derive(Clone, Debug)]
+pub struct MtAwcWit {
+    pub a: Scalar,
+    pub alpha: Scalar,
+    pub rho: Mpz,
+}
+
+#[derive(Clone, Debug)]
+pub struct MtAwcProof {
+    pub t1: GmpClassGroup,
+    pub t2: GmpClassGroup,
+    pub t3: ProjectivePoint,
+    pub z_a: Mpz,
+    pub z_alpha: Mpz,
+    pub z_rho: Mpz,
+}
+
+pub struct MtAwcProtocol<'a> {
+    pub group: &'a CLGroup,
+}
+
+impl MtAwcProtocol<'_> {
+    /// Bounds for the prover randomness of a (mod q) and rho.
+    fn randomness_bounds(&self) -> (BigInt, BigInt) {
+        let params = &self.group.params;
+        let hiding =
+            BigInt::from(2i32).pow((params.computational_security + params.slack_bits) as u32);
+        let a_bound = mpz_to_bigint(q()) * &hiding;
+        let rho_bound =
+            params.secret_key_bound(&mpz_to_bigint(self.group.stilde.clone())) * &hiding;
+        (a_bound, rho_bound)
+    }
+}
+
+impl SigmaProtocol for MtAwcProtocol<'_> {
+    type Statement = MtAwcState;
+    type Commitment = (GmpClassGroup, GmpClassGroup, ProjectivePoint);
+
+    /// (z_a, z_alpha, z_rho)
+    type Response = (Mpz, Mpz, Mpz);
+
+    fn challenge_bytes(&self) -> usize {
+        self.group.params.challenge_bytes()
+    }
+
+    fn check(
+        &self,
+        statement: &MtAwcState,
+        (t1, t2, t3): &Self::Commitment,
+        k: &BigInt,
+        (z_a, z_alpha, z_rho): &Self::Response,
+    ) -> bool {
+        let (a_bound, rho_bound) = self.randomness_bounds();
+        let slack = BigInt::from(2i32).pow(self.group.params.slack_bits as u32);
+        let z_a_max = bigint_to_mpz(&a_bound + &a_bound / &slack);
+        let z_rho_max = bigint_to_mpz(&rho_bound + &rho_bound / &slack);
+        if *z_a < Mpz::from(0)
+            || *z_a > z_a_max
+            || *z_rho < Mpz::from(0)
+            || *z_rho > z_rho_max
+            || *z_alpha < Mpz::from(0)
+            || *z_alpha >= q()
+        {
+            return false;
+        }
+
+        // c_b^z_a * Enc(z_alpha; z_rho) == (t1, t2) * c_a^k
+        let Ciphertext { c1: lhs1, c2: lhs2 } = CLGroup::eval_affine_with(
+            self.group,
+            &statement.cl_pub_key,
+            &statement.c_b,
+            z_a,
+            z_alpha,
+            z_rho,
+        );
+        let k_mpz = bigint_to_mpz(k.clone());
+        let mut ca1_k = statement.c_a.c1.clone();
+        ca1_k.pow(k_mpz.clone());
+        let mut ca2_k = statement.c_a.c2.clone();
+        ca2_k.pow(k_mpz);
+        if lhs1 != t1.clone() * ca1_k || lhs2 != t2.clone() * ca2_k {
+            return false;
+        }
+
+        // z_a * G == t3 + k * a_pub
+        let z_a_fe = mpz_to_scalar(z_a);
+        ProjectivePoint::GENERATOR * z_a_fe == *t3 + statement.a_pub * scalar_mod_order(k)
+    }
+
+    fn absorb_statement(&self, statement: &MtAwcState, transcript: &mut Transcript) {
+        transcript.append_class_group(b"c_b.c1", &statement.c_b.c1);
+        transcript.append_class_group(b"c_b.c2", &statement.c_b.c2);
+        transcript.append_class_group(b"c_a.c1", &statement.c_a.c1);
+        transcript.append_class_group(b"c_a.c2", &statement.c_a.c2);
+        transcript.append_class_group(b"cl_pub_key", &statement.cl_pub_key);
+        transcript.append_point(b"a_pub", &statement.a_pub);
+    }
+
+    fn absorb_commitment(&self, (t1, t2, t3): &Self::Commitment, transcript: &mut Transcript) {
+        transcript.append_class_group(b"t1", t1);
+        transcript.append_class_group(b"t2", t2);
+        transcript.append_point(b"t3", t3);
+    }
+}
+
+#[cfg(not(feature = "verify-only"))]
+impl SigmaProver for MtAwcProtocol<'_> {
+    type Witness = MtAwcWit;
+
+    /// (s_a, s_alpha, s_rho)
+    type Randomness = (Mpz, Scalar, Mpz);
+
+    fn sample(&self) -> Self::Randomness {
+        let (a_bound, rho_bound) = self.randomness_bounds();
+        (
+            bigint_to_mpz(sample_below(&a_bound)),
+            Scalar::random(&mut ProtocolRng),
+            bigint_to_mpz(sample_below(&rho_bound)),
+        )
+    }
+
+    fn commit(
+        &self,
+        statement: &MtAwcState,
+        (s_a, s_alpha, s_rho): &Self::Randomness,
+    ) -> Self::Commitment {
+        let Ciphertext { c1: t1, c2: t2 } = CLGroup::eval_affine_with(
+            self.group,
+            &statement.cl_pub_key,
+            &statement.c_b,
+            s_a,
+            &scalar_to_mpz(s_alpha),
+            s_rho,
+        );
+        let t3 = ProjectivePoint::GENERATOR * mpz_to_scalar(s_a);
+        (t1, t2, t3)
+    }
+
+    fn respond(
+        &self,
+        witness: &MtAwcWit,
+        (s_a, s_alpha, s_rho): &Self::Randomness,
+        k: &BigInt,
+    ) -> Self::Response {
+        let k_mpz = bigint_to_mpz(k.clone());
+        let z_a = s_a.clone() + &k_mpz * &scalar_to_mpz(&witness.a);
+        let z_rho = s_rho.clone() + &k_mpz * &witness.rho;
+        let z_alpha = mod_add(
+            &scalar_to_bigint(s_alpha),
+            &(k * scalar_to_bigint(&witness.alpha)),
+            &mpz_to_bigint(q()),
+        );
+        (z_a, bigint_to_mpz(z_alpha), z_rho)
+    }
+}
+
+impl MtAwcProof {
+    #[cfg(not(feature = "verify-only"))]
+    pub fn prove(group: &CLGroup, witness: &MtAwcWit, statement: &MtAwcState) -> Self {
+        let ((t1, t2, t3), (z_a, z_alpha, z_rho)) = sigma::prove(
+            &MtAwcProtocol { group },
+            &Transcript::new(b"MtAwcProof"),
+            statement,
+            witness,
+        );
+        Self {
+            t1,
+            t2,
+            t3,
+            z_a,
+            z_alpha,
+            z_rho,
+        }
+    }
+
+    pub fn verify(&self, group: &CLGroup, statement: &MtAwcState) -> Result<(), MulEcdsaError> {
+        let MtAwcState { c_b, c_a, cl_pub_key, .. } = statement;
+        group.check_elements(&[&c_b.c1, &c_b.c2, &c_a.c1, &c_a.c2, cl_pub_key, &self.t1, &self.t2])?;
+        let commitment = (self.t1.clone(), self.t2.clone(), self.t3);
+        let response = (self.z_a.clone(), self.z_alpha.clone(), self.z_rho.clone());
+        match sigma::verify(
+            &MtAwcProtocol { group },
+            &Transcript::new(b"MtAwcProof"),
+            statement,
+            &commitment,
+            &response,
+        ) {
+            true => Ok(()),
+            false => Err(MulEcdsaError::VrfyMtAwcProofFailed),
+        }
+    }
+}
+
+impl serde::Serialize for MtAwcProof {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use k256::elliptic_curve::sec1::ToEncodedPoint;
+        use serde::ser::SerializeStruct;
+        let mut state = serializer.serialize_struct("MtAwcProof", 6)?;
+        state.serialize_field("t1", &self.t1)?;
+        state.serialize_field("t2", &self.t2)?;
+        // Serialize t3 as compressed bytes
+        let t3_bytes = self.t3.to_affine().to_encoded_point(true);
+        state.serialize_field("t3", t3_bytes.as_bytes())?;
+        state.serialize_field("z_a", &Fixed::<ZQ_RESPONSE_BYTES>(&self.z_a))?;
+        state.serialize_field("z_alpha", &Fixed::<SCALAR_BYTES>(&self.z_alpha))?;
+        state.serialize_field("z_rho", &Fixed::<EXPONENT_RESPONSE_BYTES>(&self.z_rho))?;
+        state.end()
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for MtAwcProof {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        use k256::elliptic_curve::sec1::FromEncodedPoint;
+        use k256::EncodedPoint;
+        use serde::de;
+
+        #[derive(Deserialize)]
+        struct Helper {
+            t1: GmpClassGroup,
+            t2: GmpClassGroup,
+            t3: Vec<u8>,
+            #[serde(deserialize_with = "fixed_mpz::deserialize::<ZQ_RESPONSE_BYTES, _>")]
+            z_a: Mpz,
+            #[serde(deserialize_with = "fixed_mpz::deserialize::<SCALAR_BYTES, _>")]
+            z_alpha: Mpz,
+            #[serde(deserialize_with = "fixed_mpz::deserialize::<EXPONENT_RESPONSE_BYTES, _>")]
+            z_rho: Mpz,
+        }
+
+        let helper = Helper::deserialize(deserializer)?;
+        let t3_encoded = EncodedPoint::from_bytes(&helper.t3).map_err(de::Error::custom)?;
+        let t3_affine = k256::AffinePoint::from_encoded_point(&t3_encoded);
+        let t3 = if t3_affine.is_some().into() {
+            ProjectivePoint::from(t3_affine.unwrap())
+        } else {
+            return Err(de::Error::custom("invalid t3 point encoding"));
+        };
+
+        Ok(MtAwcProof {
+            t1: helper.t1,
+            t2: helper.t2,
+            t3,
+            z_a: helper.z_a,
+            z_alpha: helper.z_alpha,
+            z_rho: helper.z_rho,
+        })
+    }
+}
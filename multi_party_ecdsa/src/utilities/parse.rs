@@ -0,0 +1,200 @@
+//! Strict parsing of scalars, digests and points given as text by a user
+//! or a config file, for tools built on the crate. Lengths are fixed,
+//! scalars must be below the group order, decimals have no leading zeros
+//! and base64 must be padded; nothing is truncated or reduced. Errors say
+//! which rule the input broke.
+
+use crate::utilities::error::MulEcdsaError;
+use crate::utilities::k256_helpers::curve_order;
+use base64ct::{Base64, Encoding as _};
+use k256::elliptic_curve::sec1::FromEncodedPoint;
+use k256::elliptic_curve::PrimeField;
+use k256::{AffinePoint, EncodedPoint, ProjectivePoint, Scalar};
+use num_bigint::BigInt;
+use std::convert::TryInto;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    /// Either case, with an optional `0x` prefix.
+    Hex,
+    /// Standard alphabet, padded.
+    Base64,
+    /// Scalars only.
+    Decimal,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    Empty,
+    /// Byte offset of the first character outside the encoding's alphabet.
+    InvalidCharacter(usize),
+    /// Odd number of hex digits or wrong base64 padding.
+    Malformed,
+    Length { expected: &'static str, found: usize },
+    /// A scalar not below the group order, or a decimal with leading zeros.
+    NonCanonical,
+    /// Not on secp256k1, or the identity.
+    InvalidPoint,
+    UnsupportedEncoding(Encoding),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Empty => write!(f, "empty input"),
+            ParseError::InvalidCharacter(i) => write!(f, "invalid character at offset {}", i),
+            ParseError::Malformed => write!(f, "odd number of hex digits or bad base64 padding"),
+            ParseError::Length { expected, found } => write!(f, "expected {}, found {} bytes", expected, found),
+            ParseError::NonCanonical => write!(f, "not the canonical encoding of a scalar below the group order"),
+            ParseError::InvalidPoint => write!(f, "not a point on secp256k1"),
+            ParseError::UnsupportedEncoding(encoding) => write!(f, "{:?} is not accepted for this value", encoding),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+impl From<ParseError> for MulEcdsaError {
+    fn from(_: ParseError) -> Self {
+        MulEcdsaError::InvalidInput
+    }
+}
+
+/// Hex or base64 into bytes.
+pub fn parse_bytes(input: &str, encoding: Encoding) -> Result<Vec<u8>, ParseError> {
+    if input.is_empty() {
+        return Err(ParseError::Empty);
+    }
+    match encoding {
+        Encoding::Hex => {
+            let (offset, digits) = match input.strip_prefix("0x") {
+                Some(digits) => (2, digits),
+                None => (0, input),
+            };
+            if let Some(i) = digits.find(|c: char| !c.is_ascii_hexdigit()) {
+                return Err(ParseError::InvalidCharacter(offset + i));
+            }
+            hex::decode(digits).map_err(|_| ParseError::Malformed)
+        }
+        Encoding::Base64 => {
+            let alphabet = |c: char| c.is_ascii_alphanumeric() || c == '+' || c == '/' || c == '=';
+            if let Some(i) = input.find(|c: char| !alphabet(c)) {
+                return Err(ParseError::InvalidCharacter(i));
+            }
+            Base64::decode_vec(input).map_err(|_| ParseError::Malformed)
+        }
+        Encoding::Decimal => Err(ParseError::UnsupportedEncoding(encoding)),
+    }
+}
+
+fn parse_array<const N: usize>(input: &str, encoding: Encoding, expected: &'static str) -> Result<[u8; N], ParseError> {
+    let bytes = parse_bytes(input, encoding)?;
+    let found = bytes.len();
+    bytes.try_into().map_err(|_| ParseError::Length { expected, found })
+}
+
+/// A 32-byte message digest.
+pub fn parse_digest(input: &str, encoding: Encoding) -> Result<[u8; 32], ParseError> {
+    parse_array(input, encoding, "a 32-byte digest")
+}
+
+/// 32 big-endian bytes, or decimal, below the group order.
+pub fn parse_scalar(input: &str, encoding: Encoding) -> Result<Scalar, ParseError> {
+    let repr = match encoding {
+        Encoding::Decimal => decimal_repr(input)?,
+        _ => parse_array(input, encoding, "a 32-byte scalar")?,
+    };
+    Option::from(Scalar::from_repr(repr.into())).ok_or(ParseError::NonCanonical)
+}
+
+fn decimal_repr(input: &str) -> Result<[u8; 32], ParseError> {
+    if input.is_empty() {
+        return Err(ParseError::Empty);
+    }
+    if let Some(i) = input.find(|c: char| !c.is_ascii_digit()) {
+        return Err(ParseError::InvalidCharacter(i));
+    }
+    if input.len() > 1 && input.starts_with('0') {
+        return Err(ParseError::NonCanonical);
+    }
+    let value = BigInt::parse_bytes(input.as_bytes(), 10).ok_or(ParseError::Malformed)?;
+    if value >= *curve_order() {
+        return Err(ParseError::NonCanonical);
+    }
+    let (_, bytes) = value.to_bytes_be();
+    let mut repr = [0u8; 32];
+    repr[32 - bytes.len()..].copy_from_slice(&bytes);
+    Ok(repr)
+}
+
+/// Compressed or uncompressed SEC1, not the identity.
+pub fn parse_point(input: &str, encoding: Encoding) -> Result<ProjectivePoint, ParseError> {
+    let bytes = parse_bytes(input, encoding)?;
+    if bytes.len() != 33 && bytes.len() != 65 {
+        return Err(ParseError::Length {
+            expected: "a 33- or 65-byte SEC1 point",
+            found: bytes.len(),
+        });
+    }
+    let encoded = EncodedPoint::from_bytes(&bytes).map_err(|_| ParseError::InvalidPoint)?;
+    Option::<AffinePoint>::from(AffinePoint::from_encoded_point(&encoded))
+        .map(ProjectivePoint::from)
+        .ok_or(ParseError::InvalidPoint)
+}
+
+#[test]
+fn parse_test() {
+    use k256::elliptic_curve::sec1::ToEncodedPoint;
+
+    let two = "0000000000000000000000000000000000000000000000000000000000000002";
+    assert_eq!(parse_scalar(two, Encoding::Hex), Ok(Scalar::from(2u64)));
+    assert_eq!(parse_scalar(&format!("0x{}", two.to_uppercase()), Encoding::Hex), Ok(Scalar::from(2u64)));
+    assert_eq!(parse_scalar("2", Encoding::Decimal), Ok(Scalar::from(2u64)));
+    assert_eq!(
+        parse_scalar("AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAI=", Encoding::Base64),
+        Ok(Scalar::from(2u64))
+    );
+
+    let order = curve_order().to_string();
+    let q_minus_one = (curve_order() - 1u8).to_string();
+    assert_eq!(parse_scalar(&q_minus_one, Encoding::Decimal), Ok(-Scalar::ONE));
+    for (input, encoding, err) in [
+        ("", Encoding::Hex, ParseError::Empty),
+        (&two[1..], Encoding::Hex, ParseError::Malformed),
+        (&two[2..], Encoding::Hex, ParseError::Length { expected: "a 32-byte scalar", found: 31 }),
+        (" 2", Encoding::Decimal, ParseError::InvalidCharacter(0)),
+        ("02", Encoding::Decimal, ParseError::NonCanonical),
+        ("-2", Encoding::Decimal, ParseError::InvalidCharacter(0)),
+        (&order, Encoding::Decimal, ParseError::NonCanonical),
+        ("fffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd0364141", Encoding::Hex, ParseError::NonCanonical),
+        ("AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAI", Encoding::Base64, ParseError::Malformed),
+        ("AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA-=", Encoding::Base64, ParseError::InvalidCharacter(42)),
+    ] {
+        assert_eq!(parse_scalar(input, encoding), Err(err), "{:?}", input);
+    }
+    let mut with_gap = two.to_string();
+    with_gap.insert(10, ' ');
+    assert_eq!(parse_scalar(&with_gap, Encoding::Hex), Err(ParseError::InvalidCharacter(10)));
+
+    assert_eq!(parse_digest(&"ab".repeat(32), Encoding::Hex), Ok([0xab; 32]));
+    assert_eq!(
+        parse_digest("1", Encoding::Decimal),
+        Err(ParseError::UnsupportedEncoding(Encoding::Decimal))
+    );
+
+    let g = ProjectivePoint::GENERATOR.to_affine();
+    for compress in [true, false] {
+        let encoded = hex::encode(g.to_encoded_point(compress).as_bytes());
+        assert_eq!(parse_point(&encoded, Encoding::Hex), Ok(ProjectivePoint::GENERATOR));
+    }
+    let mut off_curve = g.to_encoded_point(false).as_bytes().to_vec();
+    off_curve[64] ^= 1;
+    assert_eq!(parse_point(&hex::encode(off_curve), Encoding::Hex), Err(ParseError::InvalidPoint));
+    assert_eq!(
+        parse_point("00", Encoding::Hex),
+        Err(ParseError::Length { expected: "a 33- or 65-byte SEC1 point", found: 1 })
+    );
+    assert_eq!(MulEcdsaError::from(ParseError::Empty), MulEcdsaError::InvalidInput);
+}
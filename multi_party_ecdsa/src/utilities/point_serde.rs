@@ -0,0 +1,20 @@
+//! Serde representation of `ProjectivePoint` fields, for use with
+//! `#[serde(with = "crate::utilities::point_serde")]`: the compressed SEC1
+//! bytes, as the hand-written message impls write them.
+
+use k256::elliptic_curve::sec1::{FromEncodedPoint, ToEncodedPoint};
+use k256::{AffinePoint, EncodedPoint, ProjectivePoint};
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serializer};
+
+pub fn serialize<S: Serializer>(point: &ProjectivePoint, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_bytes(point.to_affine().to_encoded_point(true).as_bytes())
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<ProjectivePoint, D::Error> {
+    let bytes = Vec::<u8>::deserialize(deserializer)?;
+    let encoded = EncodedPoint::from_bytes(&bytes).map_err(D::Error::custom)?;
+    Option::<AffinePoint>::from(AffinePoint::from_encoded_point(&encoded))
+        .map(ProjectivePoint::from)
+        .ok_or_else(|| D::Error::custom("invalid point encoding"))
+}
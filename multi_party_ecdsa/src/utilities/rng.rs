@@ -0,0 +1,114 @@
+//! The randomness source of every party, proof and encryption. It is
+//! `OsRng`, except inside a `SeededRun`, where the calling thread draws
+//! from a ChaCha20 stream instead so a failing run can be replayed from its
+//! seed. Seeding is only compiled into tests and the `test-utils` feature.
+
+use rand::rngs::OsRng;
+use rand::{CryptoRng, RngCore};
+
+#[cfg(any(test, feature = "test-utils"))]
+use rand::SeedableRng;
+#[cfg(any(test, feature = "test-utils"))]
+use rand_chacha::ChaCha20Rng;
+#[cfg(any(test, feature = "test-utils"))]
+use std::cell::RefCell;
+
+#[cfg(any(test, feature = "test-utils"))]
+thread_local! {
+    static SEEDED: RefCell<Option<ChaCha20Rng>> = const { RefCell::new(None) };
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ProtocolRng;
+
+impl ProtocolRng {
+    #[cfg(any(test, feature = "test-utils"))]
+    fn with<T>(f: impl FnOnce(&mut dyn RngCore) -> T) -> T {
+        SEEDED.with(|seeded| match seeded.borrow_mut().as_mut() {
+            Some(rng) => f(rng),
+            None => f(&mut OsRng),
+        })
+    }
+
+    #[cfg(not(any(test, feature = "test-utils")))]
+    fn with<T>(f: impl FnOnce(&mut dyn RngCore) -> T) -> T {
+        f(&mut OsRng)
+    }
+}
+
+impl RngCore for ProtocolRng {
+    fn next_u32(&mut self) -> u32 {
+        Self::with(|rng| rng.next_u32())
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        Self::with(|rng| rng.next_u64())
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        Self::with(|rng| rng.fill_bytes(dest))
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        Self::with(|rng| rng.try_fill_bytes(dest))
+    }
+}
+
+impl CryptoRng for ProtocolRng {}
+
+/// Runs closures with `ProtocolRng` seeded on the calling thread. Threads
+/// spawned inside keep using `OsRng`. The seed is printed to stderr, so a
+/// failing test shows it; set `SEEDED_RUN_SEED` to rerun with it.
+#[cfg(any(test, feature = "test-utils"))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SeededRun {
+    pub seed: u64,
+}
+
+#[cfg(any(test, feature = "test-utils"))]
+impl SeededRun {
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    /// The seed from `SEEDED_RUN_SEED`, or a fresh one.
+    pub fn from_env() -> Self {
+        let seed = std::env::var("SEEDED_RUN_SEED")
+            .ok()
+            .and_then(|seed| seed.parse().ok())
+            .unwrap_or_else(|| OsRng.next_u64());
+        Self::new(seed)
+    }
+
+    pub fn run<T>(&self, f: impl FnOnce() -> T) -> T {
+        struct Reset(Option<ChaCha20Rng>);
+        impl Drop for Reset {
+            fn drop(&mut self) {
+                SEEDED.with(|seeded| *seeded.borrow_mut() = self.0.take());
+            }
+        }
+
+        eprintln!("SeededRun seed: {}", self.seed);
+        let previous = SEEDED.with(|seeded| {
+            seeded
+                .borrow_mut()
+                .replace(ChaCha20Rng::seed_from_u64(self.seed))
+        });
+        let _reset = Reset(previous);
+        f()
+    }
+}
+
+#[test]
+fn seeded_run_test() {
+    let draw = || ProtocolRng.next_u64();
+    let run = SeededRun::new(3);
+    assert_eq!(run.run(draw), run.run(draw));
+    assert_ne!(run.run(draw), SeededRun::new(4).run(draw));
+    // nested runs restore the outer stream
+    let outer = run.run(|| {
+        SeededRun::new(4).run(draw);
+        draw()
+    });
+    assert_eq!(outer, run.run(draw));
+}
@@ -0,0 +1,160 @@
+//! Conversion between the additive two-party shares and t-of-n Shamir
+//! shares of the same key. Each additive holder deals its share with
+//! Feldman VSS and every new party adds up what it received; any t Shamir
+//! holders turn their shares back into additive ones with Lagrange
+//! coefficients. The joint public key is unchanged either way.
+
+use crate::utilities::error::MulEcdsaError;
+#[cfg(not(feature = "verify-only"))]
+use k256::elliptic_curve::Field;
+use k256::{ProjectivePoint, Scalar};
+#[cfg(not(feature = "verify-only"))]
+use crate::utilities::rng::ProtocolRng;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ShamirShare {
+    /// Evaluation point, `1..=n`.
+    pub index: u32,
+    pub value: Scalar,
+}
+
+/// One additive holder's dealing: `subshares[j - 1]` goes to party `j`,
+/// `commitments` (`G` times each coefficient) go to everyone.
+#[derive(Clone, Debug)]
+pub struct Dealing {
+    pub subshares: Vec<Scalar>,
+    pub commitments: Vec<ProjectivePoint>,
+}
+
+/// Shares `additive_share` with a random polynomial of degree `t - 1`.
+#[cfg(not(feature = "verify-only"))]
+pub fn deal(additive_share: &Scalar, t: usize, n: usize) -> Result<Dealing, MulEcdsaError> {
+    if t == 0 || t > n {
+        return Err(MulEcdsaError::PartyLessThanThreshold);
+    }
+    let mut coefficients = vec![*additive_share];
+    coefficients.extend((1..t).map(|_| Scalar::random(&mut ProtocolRng)));
+    let subshares = (1..=n as u32)
+        .map(|j| evaluate(&coefficients, &Scalar::from(j)))
+        .collect();
+    Ok(Dealing {
+        subshares,
+        commitments: coefficients
+            .iter()
+            .map(|c| ProjectivePoint::GENERATOR * c)
+            .collect(),
+    })
+}
+
+#[cfg(not(feature = "verify-only"))]
+fn evaluate(coefficients: &[Scalar], x: &Scalar) -> Scalar {
+    coefficients
+        .iter()
+        .rev()
+        .fold(Scalar::ZERO, |acc, c| acc * x + c)
+}
+
+/// Feldman check of the subshare for party `index`.
+pub fn verify_subshare(
+    index: u32,
+    subshare: &Scalar,
+    commitments: &[ProjectivePoint],
+) -> Result<(), MulEcdsaError> {
+    let x = Scalar::from(index);
+    let expected = commitments
+        .iter()
+        .rev()
+        .fold(ProjectivePoint::IDENTITY, |acc, c| acc * x + c);
+    match ProjectivePoint::GENERATOR * subshare == expected {
+        true => Ok(()),
+        false => Err(MulEcdsaError::VrfyVSSFailed),
+    }
+}
+
+/// Party `index`'s Shamir share: checks each dealer's subshare against its
+/// commitments and that the dealt secrets add up to `public_key`.
+pub fn combine(
+    index: u32,
+    received: &[(Scalar, Vec<ProjectivePoint>)],
+    public_key: &ProjectivePoint,
+) -> Result<ShamirShare, MulEcdsaError> {
+    let mut value = Scalar::ZERO;
+    let mut dealt_key = ProjectivePoint::IDENTITY;
+    for (subshare, commitments) in received {
+        verify_subshare(index, subshare, commitments)?;
+        value += subshare;
+        dealt_key += commitments.first().ok_or(MulEcdsaError::VrfyVSSFailed)?;
+    }
+    if dealt_key != *public_key {
+        return Err(MulEcdsaError::VrfyVSSFailed);
+    }
+    Ok(ShamirShare { index, value })
+}
+
+/// Lagrange coefficient at zero of `index` among `signers`.
+pub fn lagrange_coefficient(index: u32, signers: &[u32]) -> Result<Scalar, MulEcdsaError> {
+    let x_i = Scalar::from(index);
+    let (mut num, mut den) = (Scalar::ONE, Scalar::ONE);
+    for &j in signers.iter().filter(|&&j| j != index) {
+        let x_j = Scalar::from(j);
+        num *= x_j;
+        den *= x_j - x_i;
+    }
+    Option::from(den.invert())
+        .map(|inv: Scalar| num * inv)
+        .ok_or(MulEcdsaError::InvertZero)
+}
+
+/// The additive share of `share` among `signers`; the signers' additive
+/// shares add up to the key. `signers` must hold distinct indices,
+/// including `share.index`.
+pub fn to_additive(share: &ShamirShare, signers: &[u32]) -> Result<Scalar, MulEcdsaError> {
+    if !signers.contains(&share.index) {
+        return Err(MulEcdsaError::GetIndexFailed);
+    }
+    Ok(lagrange_coefficient(share.index, signers)? * share.value)
+}
+
+#[cfg(not(feature = "verify-only"))]
+#[test]
+fn shamir_conversion_test() {
+    let x1 = Scalar::random(&mut ProtocolRng);
+    let x2 = Scalar::random(&mut ProtocolRng);
+    let public_key = ProjectivePoint::GENERATOR * (x1 + x2);
+    let dealings = [deal(&x1, 2, 3).unwrap(), deal(&x2, 2, 3).unwrap()];
+    let shares: Vec<ShamirShare> = (1..=3u32)
+        .map(|j| {
+            let received: Vec<_> = dealings
+                .iter()
+                .map(|d| (d.subshares[j as usize - 1], d.commitments.clone()))
+                .collect();
+            combine(j, &received, &public_key).unwrap()
+        })
+        .collect();
+
+    for signers in [[1u32, 2], [1, 3], [2, 3]] {
+        let sum = signers.iter().fold(Scalar::ZERO, |acc, &j| {
+            acc + to_additive(&shares[j as usize - 1], &signers).unwrap()
+        });
+        assert_eq!(sum, x1 + x2);
+    }
+
+    let mut tampered = dealings[0].subshares[0] + Scalar::ONE;
+    assert_eq!(
+        verify_subshare(1, &tampered, &dealings[0].commitments),
+        Err(MulEcdsaError::VrfyVSSFailed)
+    );
+    // a dealer sharing something other than its additive share
+    tampered = x1 + Scalar::ONE;
+    let wrong = deal(&tampered, 2, 3).unwrap();
+    let received = [
+        (wrong.subshares[0], wrong.commitments),
+        (dealings[1].subshares[0], dealings[1].commitments.clone()),
+    ];
+    assert_eq!(
+        combine(1, &received, &public_key),
+        Err(MulEcdsaError::VrfyVSSFailed)
+    );
+    assert_eq!(to_additive(&shares[0], &[2, 3]), Err(MulEcdsaError::GetIndexFailed));
+    assert_eq!(deal(&x1, 4, 3).unwrap_err(), MulEcdsaError::PartyLessThanThreshold);
+}
@@ -0,0 +1,335 @@
+use crate::utilities::k256_helpers::ProjectivePointExt;
+use classgroup::gmp_classgroup::GmpClassGroup;
+use classgroup::ClassGroup;
+use k256::ProjectivePoint;
+use num_bigint::{BigInt, Sign};
+use sha2::{Digest, Sha256};
+
+/// Fiat-Shamir transcript: a domain-separated SHA-256 absorber where every
+/// item is length-prefixed, so distinct item sequences never collide.
+#[derive(Clone)]
+pub struct Transcript {
+    hasher: Sha256,
+}
+
+impl Transcript {
+    pub fn new(domain: &[u8]) -> Self {
+        let mut transcript = Self {
+            hasher: Sha256::new(),
+        };
+        transcript.append(b"domain", domain);
+        transcript
+    }
+
+    pub fn append(&mut self, label: &[u8], bytes: &[u8]) {
+        self.append_label(label, bytes.len());
+        self.hasher.update(bytes);
+    }
+
+    fn append_label(&mut self, label: &[u8], item_len: usize) {
+        self.hasher.update((label.len() as u64).to_be_bytes());
+        self.hasher.update(label);
+        self.hasher.update((item_len as u64).to_be_bytes());
+    }
+
+    pub fn append_point(&mut self, label: &[u8], point: &ProjectivePoint) {
+        let (_, bytes) = point.bytes_compressed_to_big_int().to_bytes_be();
+        self.append(label, &bytes);
+    }
+
+    /// Same encoding as `append(label, &element.to_bytes())`, streamed into
+    /// the hasher.
+    pub fn append_class_group(&mut self, label: &[u8], element: &GmpClassGroup) {
+        self.append_label(label, element.to_bytes_len());
+        element
+            .hash_into(&mut self.hasher)
+            .expect("writing to a hasher cannot fail");
+    }
+
+    /// The first `n_bytes` (at most 32) of the digest as a non-negative integer.
+    pub fn challenge(&self, n_bytes: usize) -> BigInt {
+        let digest = self.hasher.clone().finalize();
+        BigInt::from_bytes_be(Sign::Plus, &digest[..n_bytes])
+    }
+}
+
+/// A three-move public-coin proof of knowledge, as the verifier sees it.
+/// Implementors hold whatever public context they need (group parameters,
+/// bases) in `self`.
+pub trait SigmaProtocol {
+    type Statement;
+    type Commitment;
+    type Response;
+
+    fn challenge_bytes(&self) -> usize;
+
+    /// Checks the verification equations, including any range checks on
+    /// the response.
+    fn check(
+        &self,
+        statement: &Self::Statement,
+        commitment: &Self::Commitment,
+        challenge: &BigInt,
+        response: &Self::Response,
+    ) -> bool;
+
+    fn absorb_statement(&self, statement: &Self::Statement, transcript: &mut Transcript);
+
+    fn absorb_commitment(&self, commitment: &Self::Commitment, transcript: &mut Transcript);
+}
+
+/// The prover's moves, which need a witness and fresh randomness.
+#[cfg(not(feature = "verify-only"))]
+pub trait SigmaProver: SigmaProtocol {
+    type Witness;
+    /// The prover's secret randomness for the first move.
+    type Randomness;
+
+    fn sample(&self) -> Self::Randomness;
+
+    fn commit(
+        &self,
+        statement: &Self::Statement,
+        randomness: &Self::Randomness,
+    ) -> Self::Commitment;
+
+    fn respond(
+        &self,
+        witness: &Self::Witness,
+        randomness: &Self::Randomness,
+        challenge: &BigInt,
+    ) -> Self::Response;
+}
+
+pub fn challenge<P: SigmaProtocol>(
+    protocol: &P,
+    transcript: &Transcript,
+    statement: &P::Statement,
+    commitment: &P::Commitment,
+) -> BigInt {
+    let mut transcript = transcript.clone();
+    protocol.absorb_statement(statement, &mut transcript);
+    protocol.absorb_commitment(commitment, &mut transcript);
+    transcript.challenge(protocol.challenge_bytes())
+}
+
+/// Non-interactive proof via Fiat-Shamir over `transcript`.
+#[cfg(not(feature = "verify-only"))]
+pub fn prove<P: SigmaProver>(
+    protocol: &P,
+    transcript: &Transcript,
+    statement: &P::Statement,
+    witness: &P::Witness,
+) -> (P::Commitment, P::Response) {
+    let randomness = protocol.sample();
+    let commitment = protocol.commit(statement, &randomness);
+    let k = challenge(protocol, transcript, statement, &commitment);
+    let response = protocol.respond(witness, &randomness, &k);
+    (commitment, response)
+}
+
+pub fn verify<P: SigmaProtocol>(
+    protocol: &P,
+    transcript: &Transcript,
+    statement: &P::Statement,
+    commitment: &P::Commitment,
+    response: &P::Response,
+) -> bool {
+    let k = challenge(protocol, transcript, statement, commitment);
+    protocol.check(statement, commitment, &k, response)
+}
+
+/// Conjunction: both statements proven under one challenge.
+pub struct And<A, B>(pub A, pub B);
+
+impl<A: SigmaProtocol, B: SigmaProtocol> SigmaProtocol for And<A, B> {
+    type Statement = (A::Statement, B::Statement);
+    type Commitment = (A::Commitment, B::Commitment);
+    type Response = (A::Response, B::Response);
+
+    fn challenge_bytes(&self) -> usize {
+        self.0.challenge_bytes().min(self.1.challenge_bytes())
+    }
+
+    fn check(
+        &self,
+        statement: &Self::Statement,
+        commitment: &Self::Commitment,
+        challenge: &BigInt,
+        response: &Self::Response,
+    ) -> bool {
+        self.0
+            .check(&statement.0, &commitment.0, challenge, &response.0)
+            && self
+                .1
+                .check(&statement.1, &commitment.1, challenge, &response.1)
+    }
+
+    fn absorb_statement(&self, statement: &Self::Statement, transcript: &mut Transcript) {
+        self.0.absorb_statement(&statement.0, transcript);
+        self.1.absorb_statement(&statement.1, transcript);
+    }
+
+    fn absorb_commitment(&self, commitment: &Self::Commitment, transcript: &mut Transcript) {
+        self.0.absorb_commitment(&commitment.0, transcript);
+        self.1.absorb_commitment(&commitment.1, transcript);
+    }
+}
+
+#[cfg(not(feature = "verify-only"))]
+impl<A: SigmaProver, B: SigmaProver> SigmaProver for And<A, B> {
+    type Witness = (A::Witness, B::Witness);
+    type Randomness = (A::Randomness, B::Randomness);
+
+    fn sample(&self) -> Self::Randomness {
+        (self.0.sample(), self.1.sample())
+    }
+
+    fn commit(
+        &self,
+        statement: &Self::Statement,
+        randomness: &Self::Randomness,
+    ) -> Self::Commitment {
+        (
+            self.0.commit(&statement.0, &randomness.0),
+            self.1.commit(&statement.1, &randomness.1),
+        )
+    }
+
+    fn respond(
+        &self,
+        witness: &Self::Witness,
+        randomness: &Self::Randomness,
+        challenge: &BigInt,
+    ) -> Self::Response {
+        (
+            self.0.respond(&witness.0, &randomness.0, challenge),
+            self.1.respond(&witness.1, &randomness.1, challenge),
+        )
+    }
+}
+
+/// Equality: the same witness for both statements, shown by reusing one set
+/// of randomness and a single response.
+pub struct Eq<A, B>(pub A, pub B);
+
+impl<A, B> SigmaProtocol for Eq<A, B>
+where
+    A: SigmaProtocol,
+    B: SigmaProtocol<Response = A::Response>,
+{
+    type Statement = (A::Statement, B::Statement);
+    type Commitment = (A::Commitment, B::Commitment);
+    type Response = A::Response;
+
+    fn challenge_bytes(&self) -> usize {
+        self.0.challenge_bytes().min(self.1.challenge_bytes())
+    }
+
+    fn check(
+        &self,
+        statement: &Self::Statement,
+        commitment: &Self::Commitment,
+        challenge: &BigInt,
+        response: &Self::Response,
+    ) -> bool {
+        self.0
+            .check(&statement.0, &commitment.0, challenge, response)
+            && self
+                .1
+                .check(&statement.1, &commitment.1, challenge, response)
+    }
+
+    fn absorb_statement(&self, statement: &Self::Statement, transcript: &mut Transcript) {
+        self.0.absorb_statement(&statement.0, transcript);
+        self.1.absorb_statement(&statement.1, transcript);
+    }
+
+    fn absorb_commitment(&self, commitment: &Self::Commitment, transcript: &mut Transcript) {
+        self.0.absorb_commitment(&commitment.0, transcript);
+        self.1.absorb_commitment(&commitment.1, transcript);
+    }
+}
+
+#[cfg(not(feature = "verify-only"))]
+impl<A, B> SigmaProver for Eq<A, B>
+where
+    A: SigmaProver,
+    B: SigmaProver<Witness = A::Witness, Randomness = A::Randomness, Response = A::Response>,
+{
+    type Witness = A::Witness;
+    type Randomness = A::Randomness;
+
+    fn sample(&self) -> Self::Randomness {
+        self.0.sample()
+    }
+
+    fn commit(
+        &self,
+        statement: &Self::Statement,
+        randomness: &Self::Randomness,
+    ) -> Self::Commitment {
+        (
+            self.0.commit(&statement.0, randomness),
+            self.1.commit(&statement.1, randomness),
+        )
+    }
+
+    fn respond(
+        &self,
+        witness: &Self::Witness,
+        randomness: &Self::Randomness,
+        challenge: &BigInt,
+    ) -> Self::Response {
+        self.0.respond(witness, randomness, challenge)
+    }
+}
+
+#[cfg(not(feature = "verify-only"))]
+#[test]
+fn sigma_composition_test() {
+    use crate::utilities::k256_helpers::DlogProtocol;
+    use k256::elliptic_curve::Field;
+    use k256::Scalar;
+    use crate::utilities::rng::ProtocolRng;
+
+    let transcript = Transcript::new(b"sigma_composition_test");
+    let x = Scalar::random(&mut ProtocolRng);
+    let y = Scalar::random(&mut ProtocolRng);
+    let h = ProjectivePoint::GENERATOR * Scalar::random(&mut ProtocolRng);
+    let on_g = DlogProtocol::generator();
+    let on_h = DlogProtocol { base: h };
+
+    let and = And(on_g.clone(), on_h.clone());
+    let statement = (ProjectivePoint::GENERATOR * x, h * y);
+    let (commitment, response) = prove(&and, &transcript, &statement, &(x, y));
+    assert!(verify(
+        &and,
+        &transcript,
+        &statement,
+        &commitment,
+        &response
+    ));
+
+    // DLEQ: log_G(X) == log_H(Y)
+    let eq = Eq(on_g, on_h);
+    let statement = (ProjectivePoint::GENERATOR * x, h * x);
+    let (commitment, response) = prove(&eq, &transcript, &statement, &x);
+    assert!(verify(&eq, &transcript, &statement, &commitment, &response));
+    let bad_statement = (ProjectivePoint::GENERATOR * x, h * y);
+    let (commitment, response) = prove(&eq, &transcript, &bad_statement, &x);
+    assert!(!verify(
+        &eq,
+        &transcript,
+        &bad_statement,
+        &commitment,
+        &response
+    ));
+    assert!(!verify(
+        &eq,
+        &Transcript::new(b"other"),
+        &statement,
+        &commitment,
+        &response
+    ));
+}
@@ -1,40 +1,324 @@
 use crate::utilities::error::MulEcdsaError;
-use crate::utilities::class_group::scalar_to_bigint;
+use crate::utilities::k256_helpers::reduce_bytes_to_scalar;
+#[cfg(any(not(feature = "verify-only"), feature = "cross-check-verify"))]
+use crate::utilities::k256_helpers::is_low_s;
+#[cfg(not(feature = "verify-only"))]
+use crate::utilities::rng::ProtocolRng;
+use k256::ecdsa::signature::hazmat::PrehashVerifier;
+use k256::ecdsa::VerifyingKey;
 use k256::{Scalar, ProjectivePoint};
 use k256::elliptic_curve::sec1::ToEncodedPoint;
-use num_bigint::{BigInt, Sign};
+#[cfg(not(feature = "verify-only"))]
+use k256::elliptic_curve::Field;
+use sha2::Digest;
 
-#[derive(Debug)]
+/// How a raw message is hashed into the 32-byte digest that is signed.
+/// The parties' `online_sign` take the digest itself.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MessageDigest {
+    Sha256,
+    /// As used by Ethereum.
+    Keccak256,
+}
+
+impl MessageDigest {
+    pub fn digest(&self, msg: &[u8]) -> [u8; 32] {
+        match self {
+            MessageDigest::Sha256 => sha2::Sha256::digest(msg).into(),
+            MessageDigest::Keccak256 => sha3::Keccak256::digest(msg).into(),
+        }
+    }
+}
+
+/// Whether party one's `online_sign` moves `s` into the lower half of the
+/// field, as Bitcoin (BIP-62) and Ethereum require.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LowSPolicy {
+    #[default]
+    Normalize,
+    /// `s` as computed, for verifiers that take either. `verify` accepts
+    /// only low s, so a high-s result fails it.
+    Preserve,
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct Signature {
     pub s: Scalar,
     pub r: Scalar,
+    /// Nonce point R with x(R) = r, matching the final (low) s.
+    pub r_point: ProjectivePoint,
+}
+
+/// `(s, r, R)` with R compressed.
+impl serde::Serialize for Signature {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let r_point = self.r_point.to_affine().to_encoded_point(true);
+        serde::Serialize::serialize(&(self.s, self.r, r_point.as_bytes()), serializer)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Signature {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        use k256::elliptic_curve::sec1::FromEncodedPoint;
+        use serde::de::Error;
+        let (s, r, r_point): (Scalar, Scalar, Vec<u8>) =
+            serde::Deserialize::deserialize(deserializer)?;
+        let encoded = k256::EncodedPoint::from_bytes(&r_point).map_err(D::Error::custom)?;
+        let affine: Option<k256::AffinePoint> =
+            k256::AffinePoint::from_encoded_point(&encoded).into();
+        let r_point = affine.ok_or_else(|| D::Error::custom("invalid R point encoding"))?;
+        Ok(Signature {
+            s,
+            r,
+            r_point: r_point.into(),
+        })
+    }
 }
 
 impl Signature {
+    pub fn r_y_is_odd(&self) -> bool {
+        self.r_point.to_affine().to_encoded_point(true).as_bytes()[0] == 0x03
+    }
+
+    /// Recovery id: bit 0 is the parity of R.y, bit 1 is set when R.x >= q.
+    pub fn recovery_id(&self) -> u8 {
+        let encoded = self.r_point.to_affine().to_encoded_point(false);
+        let x_overflow = encoded.x().is_some_and(|x| reduce_bytes_to_scalar(x).1);
+        (self.r_y_is_odd() as u8) | ((x_overflow as u8) << 1)
+    }
+
+    /// Standard low-s ECDSA verification of `message` (already reduced to a
+    /// scalar) under `pubkey`, by k256's verifier. With the
+    /// `cross-check-verify` feature the result is also compared against a
+    /// hand-written check.
     pub fn verify(&self, pubkey: &ProjectivePoint, message: &Scalar) -> Result<(), MulEcdsaError> {
-        // secp256k1 order
-        let q = BigInt::parse_bytes(b"115792089237316195423570985008687907852837564279074904382605163141518161494337", 10).unwrap();
+        let result = self.verify_native(pubkey, message);
+        #[cfg(feature = "cross-check-verify")]
+        assert_eq!(
+            result,
+            self.verify_manual(pubkey, message),
+            "k256 and manual ECDSA verification disagree"
+        );
+        result
+    }
+
+    /// Verifies against a 32-byte digest, such as the `message_hash` the
+    /// parties signed.
+    pub fn verify_prehashed(
+        &self,
+        pubkey: &ProjectivePoint,
+        digest: &[u8; 32],
+    ) -> Result<(), MulEcdsaError> {
+        self.verify(pubkey, &reduce_bytes_to_scalar(digest).0)
+    }
+
+    /// Verifies against a raw message, hashing it with `digest` first.
+    pub fn verify_msg(
+        &self,
+        pubkey: &ProjectivePoint,
+        msg: &[u8],
+        digest: MessageDigest,
+    ) -> Result<(), MulEcdsaError> {
+        self.verify_prehashed(pubkey, &digest.digest(msg))
+    }
+
+    /// Checks all of `items` at once, see `BatchVerifier`.
+    #[cfg(not(feature = "verify-only"))]
+    pub fn batch_verify(items: &[(VerifyingKey, [u8; 32], Signature)]) -> Result<(), MulEcdsaError> {
+        let mut batch = BatchVerifier::new();
+        for (key, digest, signature) in items {
+            batch.add(key, digest, signature)?;
+        }
+        batch.verify()
+    }
+
+    /// `r = x(R) mod q` with `R` not the identity and `s` low and non-zero,
+    /// the checks that leave only `s R = m G + r P` to batch.
+    #[cfg(not(feature = "verify-only"))]
+    fn check_r_point(&self) -> Result<(), MulEcdsaError> {
+        let encoded = self.r_point.to_affine().to_encoded_point(false);
+        let x_bytes = encoded.x().ok_or(MulEcdsaError::VrfyMultiECDSAFailed)?;
+        let r = reduce_bytes_to_scalar(x_bytes).0;
+        if bool::from(self.r.is_zero()) || self.r != r || bool::from(self.s.is_zero()) || !is_low_s(&self.s) {
+            return Err(MulEcdsaError::VrfyMultiECDSAFailed);
+        }
+        Ok(())
+    }
+
+    fn verify_native(&self, pubkey: &ProjectivePoint, message: &Scalar) -> Result<(), MulEcdsaError> {
+        let signature = k256::ecdsa::Signature::from_scalars(self.r.to_bytes(), self.s.to_bytes())
+            .map_err(|_| MulEcdsaError::VrfyMultiECDSAFailed)?;
+        let key = VerifyingKey::from_encoded_point(&pubkey.to_affine().to_encoded_point(false))
+            .map_err(|_| MulEcdsaError::InvalidPublicKey)?;
+        key.verify_prehash(&message.to_bytes(), &signature)
+            .map_err(|_| MulEcdsaError::VrfyMultiECDSAFailed)
+    }
 
-        let s_inv = self.s.invert().unwrap_or(Scalar::ZERO);
+    #[cfg(feature = "cross-check-verify")]
+    fn verify_manual(&self, pubkey: &ProjectivePoint, message: &Scalar) -> Result<(), MulEcdsaError> {
+        if *pubkey == ProjectivePoint::IDENTITY {
+            return Err(MulEcdsaError::InvalidPublicKey);
+        }
+        let s_inv = Option::<Scalar>::from(self.s.invert())
+            .ok_or(MulEcdsaError::VrfyMultiECDSAFailed)?;
         let u1 = ProjectivePoint::GENERATOR * (*message * s_inv);
         let u2 = *pubkey * (self.r * s_inv);
 
-        // Get x-coordinate of u1 + u2
+        // Get x-coordinate of u1 + u2, reduced mod q
         let u1_plus_u2_point = u1 + u2;
         let affine = u1_plus_u2_point.to_affine();
         let encoded = affine.to_encoded_point(false);
         let x_bytes = encoded.x().ok_or(MulEcdsaError::VrfyMultiECDSAFailed)?;
-        let u1_plus_u2_x = BigInt::from_bytes_be(Sign::Plus, x_bytes);
+        let u1_plus_u2_x = reduce_bytes_to_scalar(x_bytes).0;
 
-        let r_bigint = scalar_to_bigint(&self.r);
-        let s_bigint = scalar_to_bigint(&self.s);
-        
         // second condition is against malleability
-        if r_bigint == u1_plus_u2_x && s_bigint < &q - &s_bigint
-        {
+        if !bool::from(self.r.is_zero()) && self.r == u1_plus_u2_x && is_low_s(&self.s) {
             Ok(())
         } else {
             Err(MulEcdsaError::VrfyMultiECDSAFailed)
         }
     }
 }
+
+/// Verifies signatures as they are produced by checking one random linear
+/// combination of their equations `s R - m G - r P = 0`, which the `R` a
+/// `Signature` carries makes possible without a square root per signature.
+/// A forged signature passes with probability about 2^-128. `verify` says
+/// only whether all of them hold; `Signature::verify` finds the bad ones.
+#[cfg(not(feature = "verify-only"))]
+#[derive(Clone, Debug)]
+pub struct BatchVerifier {
+    /// Sum of `z (s R - r P)` over the signatures added, `z` random.
+    sum: ProjectivePoint,
+    /// Sum of `z m`, the multiple of `G` that `sum` has to equal.
+    generator_scalar: Scalar,
+    pub count: usize,
+}
+
+#[cfg(not(feature = "verify-only"))]
+impl Default for BatchVerifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(not(feature = "verify-only"))]
+impl BatchVerifier {
+    pub fn new() -> Self {
+        Self {
+            sum: ProjectivePoint::IDENTITY,
+            generator_scalar: Scalar::ZERO,
+            count: 0,
+        }
+    }
+
+    /// Fails right away on a signature malformed on its own.
+    pub fn add(&mut self, key: &VerifyingKey, digest: &[u8; 32], signature: &Signature) -> Result<(), MulEcdsaError> {
+        signature.check_r_point()?;
+        let pubkey = ProjectivePoint::from(key);
+        let message = reduce_bytes_to_scalar(digest).0;
+        let z = Scalar::random(&mut ProtocolRng);
+        self.sum += signature.r_point * (z * signature.s) - pubkey * (z * signature.r);
+        self.generator_scalar += z * message;
+        self.count += 1;
+        Ok(())
+    }
+
+    pub fn verify(&self) -> Result<(), MulEcdsaError> {
+        match self.sum == ProjectivePoint::GENERATOR * self.generator_scalar {
+            true => Ok(()),
+            false => Err(MulEcdsaError::VrfyMultiECDSAFailed),
+        }
+    }
+}
+
+#[cfg(not(feature = "verify-only"))]
+#[test]
+fn signature_verify_test() {
+    let x = Scalar::random(&mut ProtocolRng);
+    let k = Scalar::random(&mut ProtocolRng);
+    let m = Scalar::random(&mut ProtocolRng);
+    let pubkey = ProjectivePoint::GENERATOR * x;
+    let r_point = ProjectivePoint::GENERATOR * k;
+    let encoded = r_point.to_affine().to_encoded_point(false);
+    let r = reduce_bytes_to_scalar(encoded.x().unwrap()).0;
+    let s = k.invert().unwrap() * (m + r * x);
+    let (s, r_point) = if is_low_s(&s) { (s, r_point) } else { (-s, -r_point) };
+    let signature = Signature { s, r, r_point };
+
+    signature.verify(&pubkey, &m).unwrap();
+    let wrong = [
+        (pubkey, m + Scalar::ONE),
+        (pubkey + ProjectivePoint::GENERATOR, m),
+    ];
+    for (pubkey, m) in wrong {
+        assert_eq!(signature.verify(&pubkey, &m), Err(MulEcdsaError::VrfyMultiECDSAFailed));
+    }
+    assert_eq!(
+        signature.verify(&ProjectivePoint::IDENTITY, &m),
+        Err(MulEcdsaError::InvalidPublicKey)
+    );
+    let high_s = Signature { s: -s, ..signature.clone() };
+    assert_eq!(high_s.verify(&pubkey, &m), Err(MulEcdsaError::VrfyMultiECDSAFailed));
+    let zero_s = Signature { s: Scalar::ZERO, ..signature };
+    assert_eq!(zero_s.verify(&pubkey, &m), Err(MulEcdsaError::VrfyMultiECDSAFailed));
+}
+
+#[cfg(not(feature = "verify-only"))]
+#[test]
+fn batch_verify_test() {
+    let sign = |x: &Scalar, digest: &[u8; 32]| {
+        let k = Scalar::random(&mut ProtocolRng);
+        let m = reduce_bytes_to_scalar(digest).0;
+        let r_point = ProjectivePoint::GENERATOR * k;
+        let encoded = r_point.to_affine().to_encoded_point(false);
+        let r = reduce_bytes_to_scalar(encoded.x().unwrap()).0;
+        let s = k.invert().unwrap() * (m + r * x);
+        let (s, r_point) = if is_low_s(&s) { (s, r_point) } else { (-s, -r_point) };
+        Signature { s, r, r_point }
+    };
+    let mut items: Vec<(VerifyingKey, [u8; 32], Signature)> = (0..8u8)
+        .map(|i| {
+            let x = Scalar::random(&mut ProtocolRng);
+            let key = VerifyingKey::from_encoded_point(
+                &(ProjectivePoint::GENERATOR * x).to_affine().to_encoded_point(true),
+            )
+            .unwrap();
+            let digest = [i; 32];
+            (key, digest, sign(&x, &digest))
+        })
+        .collect();
+    Signature::batch_verify(&items).unwrap();
+    Signature::batch_verify(&[]).unwrap();
+
+    // a wrong digest, a signature moved to another key, a negated R
+    items[3].1[0] ^= 1;
+    assert_eq!(Signature::batch_verify(&items), Err(MulEcdsaError::VrfyMultiECDSAFailed));
+    items[3].1[0] ^= 1;
+    items.swap(0, 1);
+    items[0].1 = items[1].1;
+    let (key, _, signature) = items[1].clone();
+    items[0].2 = signature.clone();
+    assert_eq!(Signature::batch_verify(&items[..2]), Err(MulEcdsaError::VrfyMultiECDSAFailed));
+    items[0].0 = key;
+    items[0].2.r_point = -signature.r_point;
+    assert_eq!(Signature::batch_verify(&items[..2]), Err(MulEcdsaError::VrfyMultiECDSAFailed));
+}
+
+#[test]
+fn message_digest_test() {
+    assert_eq!(
+        hex::encode(MessageDigest::Sha256.digest(b"")),
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    );
+    assert_eq!(
+        hex::encode(MessageDigest::Keccak256.digest(b"")),
+        "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+    );
+}
@@ -0,0 +1,179 @@
+//! Fixed-seed vectors for `CLProof` and `CLDLProof` with every
+//! intermediate value, for checking other implementations against this
+//! crate's Fiat-Shamir conventions. The vectors are shipped in
+//! `test_vectors/`; `test_vectors_test` checks they still match, and
+//! `REGENERATE_TEST_VECTORS=1 cargo test test_vectors` rewrites them.
+//!
+//! Format: `name = value` lines. Integers are hex, big-endian, with a `-`
+//! for negative values; scalars are 32-byte big-endian hex; points are
+//! compressed SEC1. A class group element is given as `a` and `b` of its
+//! reduced form, plus `bytes`, its encoding in the transcript, for the
+//! elements absorbed there.
+
+use crate::utilities::cl_dl_proof::{CLDLProof, CLDLState};
+use crate::utilities::cl_proof::{CLProof, CLState, CLWit, ClEncProtocol};
+use crate::utilities::class_group::CLGroup;
+use crate::utilities::equality_proof::{EqualityProof, EqualityProtocol, EqualityWit};
+use crate::utilities::k256_helpers::ProjectivePointExt;
+use crate::utilities::rng::{ProtocolRng, SeededRun};
+use crate::utilities::sigma::{self, SigmaProver, Transcript};
+use classgroup::gmp::mpz::Mpz;
+use classgroup::gmp_classgroup::GmpClassGroup;
+use classgroup::ClassGroup;
+use k256::elliptic_curve::Field;
+use k256::{ProjectivePoint, Scalar};
+use num_bigint::BigInt;
+use std::fmt::Write;
+
+pub const CL_PROOF_SEED: u64 = 1;
+pub const CLDL_PROOF_SEED: u64 = 2;
+
+#[derive(Default)]
+struct Vector(String);
+
+impl Vector {
+    fn line(&mut self, name: &str, value: impl std::fmt::Display) {
+        writeln!(self.0, "{} = {}", name, value).unwrap();
+    }
+
+    fn mpz(&mut self, name: &str, value: &Mpz) {
+        self.line(name, value.to_str_radix(16));
+    }
+
+    fn element(&mut self, name: &str, element: &GmpClassGroup, absorbed: bool) {
+        let (a, b) = element.to_ab_hex();
+        self.line(&format!("{}.a", name), a);
+        self.line(&format!("{}.b", name), b);
+        if absorbed {
+            self.line(&format!("{}.bytes", name), hex::encode(element.to_bytes()));
+        }
+    }
+
+    fn scalar(&mut self, name: &str, value: &Scalar) {
+        self.line(name, hex::encode(value.to_bytes()));
+    }
+
+    fn point(&mut self, name: &str, point: &ProjectivePoint) {
+        let (_, bytes) = point.bytes_compressed_to_big_int().to_bytes_be();
+        self.line(name, hex::encode(bytes));
+    }
+
+    fn bigint(&mut self, name: &str, value: &BigInt) {
+        self.line(name, format!("{:x}", value));
+    }
+
+    /// The group and the encryption shared by both proofs.
+    fn setup(&mut self, title: &str, seed: u64, domain: &[u8]) -> (CLGroup, CLState, CLWit) {
+        let group = CLGroup::new();
+        let (_, cl_pub_key) = group.keygen();
+        let x = Scalar::random(&mut ProtocolRng);
+        let (cipher, r) = CLGroup::encrypt(&group, &cl_pub_key, x);
+        writeln!(self.0, "# {} test vector, seed {}", title, seed).unwrap();
+        self.line("transcript.domain", String::from_utf8_lossy(domain));
+        self.line("challenge_bytes", group.params.challenge_bytes());
+        self.mpz("discriminant", group.gq.discriminant());
+        self.element("gq", &group.gq, false);
+        self.element("cl_pub_key", &cl_pub_key, true);
+        self.element("c1", &cipher.c1, true);
+        self.element("c2", &cipher.c2, true);
+        self.scalar("x", &x);
+        self.mpz("r", &r);
+        (group, CLState { cipher, cl_pub_key }, CLWit { x, r })
+    }
+}
+
+pub fn cl_proof_vector(seed: u64) -> String {
+    SeededRun::new(seed).run(|| {
+        let mut vector = Vector::default();
+        let (group, statement, witness) = vector.setup("CLProof", seed, b"CLProof");
+        let protocol = ClEncProtocol { group: &group };
+        let (r1, r2) = protocol.sample();
+        let (t1, t2) = protocol.commit(&statement, &(r1.clone(), r2.clone()));
+        let k = sigma::challenge(&protocol, &Transcript::new(b"CLProof"), &statement, &(t1.clone(), t2.clone()));
+        let (u1, u2) = protocol.respond(&witness, &(r1.clone(), r2.clone()), &k);
+        CLProof {
+            t1: t1.clone(),
+            t2: t2.clone(),
+            u1: u1.clone(),
+            u2: u2.clone(),
+        }
+        .verify(&group, statement)
+        .expect("generated CLProof verifies");
+
+        vector.mpz("r1", r1.as_mpz());
+        vector.mpz("r2", r2.as_mpz());
+        vector.element("t1", &t1, true);
+        vector.element("t2", &t2, true);
+        vector.bigint("k", &k);
+        vector.mpz("u1", &u1);
+        vector.mpz("u2", &u2);
+        vector.0
+    })
+}
+
+/// The CLDL proof is an `EqualityProof` with base G, whose point `X = x G`
+/// and commitment `t3 = r2 G` are absorbed too.
+pub fn cl_dl_proof_vector(seed: u64) -> String {
+    SeededRun::new(seed).run(|| {
+        let mut vector = Vector::default();
+        let (group, cl_state, witness) = vector.setup("CLDLProof", seed, b"EqualityProof");
+        let dl_pub = ProjectivePoint::GENERATOR * witness.x;
+        let statement = CLDLState {
+            cipher: cl_state.cipher,
+            cl_pub_key: cl_state.cl_pub_key,
+            dl_pub,
+        };
+        let protocol = EqualityProtocol {
+            enc: ClEncProtocol { group: &group },
+        };
+        let equality_statement = statement.clone().into();
+        let witness = EqualityWit {
+            x: witness.x,
+            r: witness.r,
+        };
+        let (r1, r2) = protocol.sample();
+        let ((t1, t2), t3) = protocol.commit(&equality_statement, &(r1.clone(), r2.clone()));
+        let commitment = ((t1.clone(), t2.clone()), t3);
+        let k = sigma::challenge(&protocol, &Transcript::new(b"EqualityProof"), &equality_statement, &commitment);
+        let (u1, u2) = protocol.respond(&witness, &(r1.clone(), r2.clone()), &k);
+        CLDLProof {
+            proof: EqualityProof {
+                t1: t1.clone(),
+                t2: t2.clone(),
+                t3,
+                u1: u1.clone(),
+                u2: u2.clone(),
+            },
+        }
+        .verify(&group, statement)
+        .expect("generated CLDLProof verifies");
+
+        vector.point("dl_pub", &dl_pub);
+        vector.mpz("r1", r1.as_mpz());
+        vector.mpz("r2", r2.as_mpz());
+        vector.element("t1", &t1, true);
+        vector.element("t2", &t2, true);
+        vector.point("t3", &t3);
+        vector.bigint("k", &k);
+        vector.mpz("u1", &u1);
+        vector.mpz("u2", &u2);
+        vector.0
+    })
+}
+
+#[test]
+fn test_vectors_test() {
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("test_vectors");
+    for (file, vector) in [
+        ("cl_proof.txt", cl_proof_vector(CL_PROOF_SEED)),
+        ("cl_dl_proof.txt", cl_dl_proof_vector(CLDL_PROOF_SEED)),
+    ] {
+        let path = dir.join(file);
+        if std::env::var_os("REGENERATE_TEST_VECTORS").is_some() {
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(&path, &vector).unwrap();
+        }
+        let shipped = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(shipped, vector, "{} is out of date", file);
+    }
+}
@@ -0,0 +1,112 @@
+//! Newtypes for the three kinds of integer the CL proofs mix: values mod
+//! the curve order, prover responses that must stay under a bound, and
+//! class group exponents with no modulus at all.
+
+use crate::utilities::class_group::*;
+use classgroup::gmp::mpz::Mpz;
+use classgroup::gmp_classgroup::GmpClassGroup;
+use classgroup::ClassGroup;
+use k256::Scalar;
+use num_bigint::BigInt;
+
+/// An integer in `[0, q)`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ZqScalar(Mpz);
+
+impl ZqScalar {
+    /// `None` unless `0 <= value < q`.
+    pub fn new(value: Mpz) -> Option<Self> {
+        match value >= Mpz::from(0) && value < q() {
+            true => Some(Self(value)),
+            false => None,
+        }
+    }
+
+    pub fn from_scalar(s: &Scalar) -> Self {
+        Self(scalar_to_mpz(s))
+    }
+
+    /// `self + k * other mod q`.
+    pub fn mul_add(&self, k: &BigInt, other: &ZqScalar) -> Self {
+        Self((self.0.clone() + &bigint_to_mpz(k.clone()) * &other.0).mod_floor(&q()))
+    }
+
+    pub fn as_mpz(&self) -> &Mpz {
+        &self.0
+    }
+
+    pub fn into_mpz(self) -> Mpz {
+        self.0
+    }
+
+    pub fn to_scalar(&self) -> Scalar {
+        mpz_to_scalar(&self.0)
+    }
+}
+
+/// A non-negative integer at most the bound it was checked against.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BoundedWitness(Mpz);
+
+impl BoundedWitness {
+    /// `None` unless `0 <= value <= bound`.
+    pub fn new(value: Mpz, bound: &Mpz) -> Option<Self> {
+        match value >= Mpz::from(0) && value <= *bound {
+            true => Some(Self(value)),
+            false => None,
+        }
+    }
+
+    pub fn as_mpz(&self) -> &Mpz {
+        &self.0
+    }
+}
+
+/// An integer used only as an exponent in the class group, such as a CL
+/// secret key or encryption randomness. Never reduced.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GroupExponent(Mpz);
+
+impl GroupExponent {
+    pub fn new(value: Mpz) -> Self {
+        Self(value)
+    }
+
+    /// `base^self`.
+    pub fn pow(&self, base: &GmpClassGroup) -> GmpClassGroup {
+        let mut result = base.clone();
+        result.pow_signed(self.0.clone());
+        result
+    }
+
+    /// `self + k * other` over the integers.
+    pub fn mul_add(&self, k: &BigInt, other: &GroupExponent) -> Self {
+        Self(self.0.clone() + &bigint_to_mpz(k.clone()) * &other.0)
+    }
+
+    pub fn as_mpz(&self) -> &Mpz {
+        &self.0
+    }
+
+    pub fn into_mpz(self) -> Mpz {
+        self.0
+    }
+}
+
+#[test]
+fn typed_int_test() {
+    assert!(ZqScalar::new(q()).is_none());
+    assert!(ZqScalar::new(Mpz::from(-1)).is_none());
+    let top = ZqScalar::new(q() - 1u64).unwrap();
+    let one = ZqScalar::new(Mpz::from(1)).unwrap();
+    assert_eq!(top.mul_add(&BigInt::from(1), &one).into_mpz(), Mpz::from(0));
+
+    let bound = Mpz::from(100);
+    assert!(BoundedWitness::new(Mpz::from(100), &bound).is_some());
+    assert!(BoundedWitness::new(Mpz::from(101), &bound).is_none());
+    assert!(BoundedWitness::new(Mpz::from(-1), &bound).is_none());
+
+    let e = GroupExponent::new(Mpz::from(5));
+    let big = e.mul_add(&BigInt::from(3), &GroupExponent::new(q()));
+    assert_eq!(big.as_mpz(), &(q() * &Mpz::from(3) + &Mpz::from(5)));
+}
@@ -0,0 +1,222 @@
+use crate::clock::{Clock, SystemClock};
+use crate::mta::MTASecondRoundMsg;
+use crate::shared::*;
+use crate::utilities::cl_proof::MTAFirstRoundMsg;
+use crate::utilities::class_group::{scalar_from_bigint, CLGroup};
+use crate::utilities::dl_com_zk::*;
+use crate::utilities::error::MulEcdsaError;
+use crate::utilities::signature::Signature;
+use crate::utilities::ProtocolParams;
+use k256::ProjectivePoint;
+use num_bigint::BigInt;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// The protocol step an audit entry refers to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuditEvent {
+    NonceCommitment,
+    MtaFirstRound,
+    MtaSecondRound,
+    MtaConsistency,
+    PartyOneNonce,
+    PartyTwoNonceOpening,
+    Signature,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AuditEntry {
+    pub event: AuditEvent,
+    pub outcome: Result<(), MulEcdsaError>,
+    /// When the watcher saw the message, by its clock.
+    pub at: u64,
+}
+
+/// Every message of one signing session, in the order a `Watcher` saw
+/// them. Each party sees all of them, so either can keep one by running a
+/// watcher over what it sends and receives; `verify` replays it later.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SigningTranscript {
+    /// See `party_two::Sign::for_session`.
+    pub session_context: [u8; 32],
+    /// See `party_two::Sign::for_labeled_session`.
+    pub label: Option<String>,
+    pub nonce_com: Option<DLCommitments>,
+    pub mta_first_round: Option<MTAFirstRoundMsg>,
+    pub mta_second_round: Option<MTASecondRoundMsg>,
+    pub mta_consistency: Option<MtaConsistencyMsg>,
+    pub party_one_nonce: Option<NonceKEMsg>,
+    pub party_two_nonce: Option<CommWitness>,
+    pub message_hash: Option<Vec<u8>>,
+    pub signature: Option<Signature>,
+}
+
+impl SigningTranscript {
+    /// Replays the session through a fresh watcher; every message must be
+    /// present and pass.
+    pub fn verify(
+        &self,
+        party_one_share: ProjectivePoint,
+        party_two_share: ProjectivePoint,
+    ) -> Result<(), MulEcdsaError> {
+        self.verify_with_params(party_one_share, party_two_share, ProtocolParams::default())
+    }
+
+    /// `verify` for a session run at `params`.
+    pub fn verify_with_params(
+        &self,
+        party_one_share: ProjectivePoint,
+        party_two_share: ProjectivePoint,
+        params: ProtocolParams,
+    ) -> Result<(), MulEcdsaError> {
+        fn get<T>(msg: &Option<T>) -> Result<&T, MulEcdsaError> {
+            msg.as_ref().ok_or(MulEcdsaError::MissingMsg)
+        }
+        let mut watcher = Watcher::new(party_one_share, party_two_share).with_params(params);
+        watcher.transcript.session_context = self.session_context;
+        watcher.transcript.label = self.label.clone();
+        watcher.observe_nonce_com(get(&self.nonce_com)?)?;
+        watcher.observe_mta_first_round(get(&self.mta_first_round)?)?;
+        watcher.observe_mta_second_round(get(&self.mta_second_round)?)?;
+        watcher.observe_mta_consistency(get(&self.mta_consistency)?)?;
+        watcher.observe_party_one_nonce(get(&self.party_one_nonce)?)?;
+        watcher.observe_party_two_nonce(get(&self.party_two_nonce)?)?;
+        watcher.observe_signature(get(&self.signature)?, get(&self.message_hash)?)
+    }
+}
+
+/// Passive third role: sees every message of a signing session, checks all
+/// publicly verifiable parts and records the outcome. Holds no secrets.
+#[derive(Clone, Debug)]
+pub struct Watcher {
+    pub party_one_share: ProjectivePoint,
+    pub party_two_share: ProjectivePoint,
+    pub public_signing_key: ProjectivePoint,
+    pub audit_log: Vec<AuditEntry>,
+    pub transcript: SigningTranscript,
+    /// Stamps the audit entries.
+    pub clock: Arc<dyn Clock>,
+    /// The parties' `ProtocolParams`, for checking their CL proofs.
+    pub params: ProtocolParams,
+}
+
+impl Watcher {
+    pub fn new(party_one_share: ProjectivePoint, party_two_share: ProjectivePoint) -> Self {
+        Self {
+            party_one_share,
+            party_two_share,
+            public_signing_key: party_one_share + party_two_share,
+            audit_log: Vec::new(),
+            transcript: SigningTranscript::default(),
+            clock: Arc::new(SystemClock),
+            params: ProtocolParams::default(),
+        }
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn with_params(mut self, params: ProtocolParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    pub fn for_session(mut self, session_id: &[u8], message_hash: &[u8]) -> Self {
+        self.transcript.session_context = session_context(session_id, message_hash);
+        self
+    }
+
+    /// Expects a session opened with `for_labeled_session` by both parties;
+    /// the label is kept with the transcript.
+    pub fn for_labeled_session(mut self, session_id: &[u8], message_hash: &[u8], label: &str) -> Self {
+        self.transcript.session_context = labeled_session_context(session_id, message_hash, label);
+        self.transcript.label = Some(label.to_string());
+        self
+    }
+
+    fn record(
+        &mut self,
+        event: AuditEvent,
+        outcome: Result<(), MulEcdsaError>,
+    ) -> Result<(), MulEcdsaError> {
+        self.audit_log.push(AuditEntry {
+            event,
+            outcome: outcome.clone(),
+            at: self.clock.now(),
+        });
+        outcome
+    }
+
+    /// Whether every observed step verified.
+    pub fn is_clean(&self) -> bool {
+        self.audit_log.iter().all(|entry| entry.outcome.is_ok())
+    }
+
+    pub fn observe_nonce_com(&mut self, msg: &DLCommitments) -> Result<(), MulEcdsaError> {
+        self.transcript.nonce_com = Some(msg.clone());
+        self.record(AuditEvent::NonceCommitment, Ok(()))
+    }
+
+    pub fn observe_mta_first_round(&mut self, msg: &MTAFirstRoundMsg) -> Result<(), MulEcdsaError> {
+        let outcome = msg.proof.verify(&CLGroup::new_with_params(self.params), msg.state.clone());
+        self.transcript.mta_first_round = Some(msg.clone());
+        self.record(AuditEvent::MtaFirstRound, outcome)
+    }
+
+    /// The MtAwc proof refers to party two's nonce point, so it is checked
+    /// once the nonce commitment is opened.
+    pub fn observe_mta_second_round(&mut self, msg: &MTASecondRoundMsg) -> Result<(), MulEcdsaError> {
+        self.transcript.mta_second_round = Some(msg.clone());
+        self.record(AuditEvent::MtaSecondRound, Ok(()))
+    }
+
+    pub fn observe_mta_consistency(&mut self, msg: &MtaConsistencyMsg) -> Result<(), MulEcdsaError> {
+        self.transcript.mta_consistency = Some(msg.clone());
+        let outcome = match msg.public_key == self.party_one_share {
+            true => Ok(()),
+            false => Err(MulEcdsaError::CounterpartyMismatch),
+        };
+        self.record(AuditEvent::MtaConsistency, outcome)
+    }
+
+    pub fn observe_party_one_nonce(&mut self, msg: &NonceKEMsg) -> Result<(), MulEcdsaError> {
+        self.transcript.party_one_nonce = Some(msg.clone());
+        let outcome = msg
+            .dl_proof
+            .verify_in_context(&msg.nonce_public_key, &self.transcript.session_context)
+            .map_err(|_| MulEcdsaError::VrfyDlogFailed);
+        self.record(AuditEvent::PartyOneNonce, outcome)
+    }
+
+    pub fn observe_party_two_nonce(&mut self, witness: &CommWitness) -> Result<(), MulEcdsaError> {
+        self.transcript.party_two_nonce = Some(witness.clone());
+        let transcript = &self.transcript;
+        let outcome = match &transcript.nonce_com {
+            Some(com) => DLComZK::verify(com, witness, &transcript.session_context).and_then(|_| {
+                match (&transcript.mta_first_round, &transcript.mta_second_round) {
+                    (Some(first), Some(second)) => {
+                        second.verify(&CLGroup::new_with_params(self.params), &first.state, &witness.public_share)
+                    }
+                    _ => Ok(()),
+                }
+            }),
+            None => Err(MulEcdsaError::MissingMsg),
+        };
+        self.record(AuditEvent::PartyTwoNonceOpening, outcome)
+    }
+
+    pub fn observe_signature(
+        &mut self,
+        signature: &Signature,
+        message_bytes: &[u8],
+    ) -> Result<(), MulEcdsaError> {
+        self.transcript.message_hash = Some(message_bytes.to_vec());
+        self.transcript.signature = Some(signature.clone());
+        let message_bigint = BigInt::from_bytes_be(num_bigint::Sign::Plus, message_bytes);
+        let outcome = scalar_from_bigint(&message_bigint)
+            .and_then(|message| signature.verify(&self.public_signing_key, &message));
+        self.record(AuditEvent::Signature, outcome)
+    }
+}